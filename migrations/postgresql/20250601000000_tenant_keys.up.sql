@@ -0,0 +1,20 @@
+CREATE TABLE "tenant_keys" (
+	"id" SERIAL PRIMARY KEY,
+	"tenant_id" BIGINT NOT NULL,
+	"version" BIGINT NOT NULL,
+	"state" TEXT NOT NULL DEFAULT 'next',
+	"algorithm" TEXT NOT NULL,
+	"public_key" TEXT,
+	"private_key" TEXT NOT NULL,
+	"expires_at" BIGINT,
+	"updated_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+	"created_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  FOREIGN KEY ("tenant_id") REFERENCES "tenants" ("id") ON DELETE CASCADE
+);
+CREATE UNIQUE INDEX "tenant_keys_tenant_id_version_unique_idx" ON "tenant_keys" ("tenant_id", "version");
+
+ALTER TABLE "tenants" ADD COLUMN "key_version" BIGINT NOT NULL DEFAULT 1;
+
+INSERT INTO "tenant_keys"
+  ("tenant_id", "version", "state", "algorithm", "public_key", "private_key")
+  SELECT "id", 1, 'active', "algorithm", "public_key", "private_key" FROM "tenants";
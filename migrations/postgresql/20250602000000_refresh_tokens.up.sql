@@ -0,0 +1,23 @@
+CREATE TABLE "refresh_token_families" (
+  "id" SERIAL PRIMARY KEY,
+  "tenant_id" BIGINT NOT NULL,
+  "sub_type" TEXT NOT NULL,
+  "sub" BIGINT NOT NULL,
+  "revoked_at" BIGINT,
+  "updated_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  "created_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  FOREIGN KEY ("tenant_id") REFERENCES "tenants" ("id") ON DELETE CASCADE
+);
+CREATE INDEX "refresh_token_families_tenant_id_sub_type_sub_idx" ON "refresh_token_families" ("tenant_id", "sub_type", "sub");
+
+CREATE TABLE "refresh_tokens" (
+  "id" SERIAL PRIMARY KEY,
+  "refresh_token_family_id" BIGINT NOT NULL,
+  "jti" TEXT NOT NULL,
+  "used_at" BIGINT,
+  "expires_at" BIGINT NOT NULL,
+  "created_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  FOREIGN KEY ("refresh_token_family_id") REFERENCES "refresh_token_families" ("id") ON DELETE CASCADE
+);
+CREATE UNIQUE INDEX "refresh_tokens_jti_unique_idx" ON "refresh_tokens" ("jti");
+CREATE INDEX "refresh_tokens_refresh_token_family_id_idx" ON "refresh_tokens" ("refresh_token_family_id");
@@ -0,0 +1,12 @@
+CREATE TABLE "token_revocations" (
+  "id" SERIAL PRIMARY KEY,
+  "application_id" BIGINT NOT NULL,
+  "jti" TEXT,
+  "sub_type" TEXT,
+  "sub" BIGINT,
+  "revoked_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  "created_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  FOREIGN KEY ("application_id") REFERENCES "applications" ("id") ON DELETE CASCADE
+);
+CREATE INDEX "token_revocations_jti_idx" ON "token_revocations" ("jti");
+CREATE INDEX "token_revocations_application_id_sub_type_sub_idx" ON "token_revocations" ("application_id", "sub_type", "sub");
@@ -0,0 +1,14 @@
+CREATE TABLE "oauth2_clients" (
+  "id" SERIAL PRIMARY KEY,
+  "tenant_id" BIGINT NOT NULL,
+  "client_id" VARCHAR(36) NOT NULL,
+  "encrypted_client_secret" TEXT,
+  "name" TEXT NOT NULL,
+  "redirect_uris" TEXT NOT NULL,
+  "active" SMALLINT NOT NULL DEFAULT 1,
+  "updated_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  "created_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  FOREIGN KEY ("tenant_id") REFERENCES "tenants" ("id") ON DELETE CASCADE
+);
+CREATE UNIQUE INDEX "oauth2_clients_client_id_unique_idx" ON "oauth2_clients" ("client_id");
+CREATE INDEX "oauth2_clients_tenant_id_idx" ON "oauth2_clients" ("tenant_id");
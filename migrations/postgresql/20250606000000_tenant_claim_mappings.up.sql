@@ -0,0 +1,13 @@
+CREATE TABLE "tenant_claim_mappings" (
+  "id" SERIAL PRIMARY KEY,
+  "tenant_id" BIGINT NOT NULL,
+  "claim" TEXT NOT NULL,
+  "source" TEXT NOT NULL,
+  "value" TEXT NOT NULL,
+  "access_token" SMALLINT NOT NULL DEFAULT 1,
+  "id_token" SMALLINT NOT NULL DEFAULT 1,
+  "updated_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  "created_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  FOREIGN KEY ("tenant_id") REFERENCES "tenants" ("id") ON DELETE CASCADE
+);
+CREATE UNIQUE INDEX "tenant_claim_mappings_tenant_id_claim_unique_idx" ON "tenant_claim_mappings" ("tenant_id", "claim");
@@ -0,0 +1,21 @@
+CREATE TABLE "user_sessions" (
+  "id" SERIAL PRIMARY KEY,
+  "tenant_id" BIGINT NOT NULL,
+  "user_id" BIGINT NOT NULL,
+  "refresh_token_family_id" BIGINT NOT NULL,
+  "sid" VARCHAR(36) NOT NULL,
+  "grant_type" TEXT,
+  "ip_address" TEXT,
+  "user_agent" TEXT,
+  "expires_at" BIGINT NOT NULL,
+  "revoked_at" BIGINT,
+  "last_seen_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  "updated_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  "created_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  FOREIGN KEY ("tenant_id") REFERENCES "tenants" ("id") ON DELETE CASCADE,
+  FOREIGN KEY ("user_id") REFERENCES "users" ("id") ON DELETE CASCADE,
+  FOREIGN KEY ("refresh_token_family_id") REFERENCES "refresh_token_families" ("id") ON DELETE CASCADE
+);
+CREATE UNIQUE INDEX "user_sessions_sid_unique_idx" ON "user_sessions" ("sid");
+CREATE INDEX "user_sessions_user_id_idx" ON "user_sessions" ("user_id");
+CREATE INDEX "user_sessions_refresh_token_family_id_idx" ON "user_sessions" ("refresh_token_family_id");
@@ -0,0 +1,3 @@
+ALTER TABLE "oauth2_clients" ADD COLUMN "post_logout_redirect_uris" TEXT;
+ALTER TABLE "oauth2_clients" ADD COLUMN "backchannel_logout_uri" TEXT;
+ALTER TABLE "user_sessions" ADD COLUMN "oauth2_client_id" BIGINT REFERENCES "oauth2_clients" ("id") ON DELETE SET NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE "refresh_token_families" DROP COLUMN "mfa_validated";
+ALTER TABLE "refresh_token_families" DROP COLUMN "expires_at";
@@ -0,0 +1,6 @@
+ALTER TABLE "refresh_token_families" ADD COLUMN "expires_at" BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE "refresh_token_families" ADD COLUMN "mfa_validated" SMALLINT NOT NULL DEFAULT 0;
+UPDATE "refresh_token_families" SET "expires_at" = COALESCE(
+  (SELECT MAX(rt."expires_at") FROM "refresh_tokens" rt WHERE rt."refresh_token_family_id" = "refresh_token_families"."id"),
+  "updated_at"
+);
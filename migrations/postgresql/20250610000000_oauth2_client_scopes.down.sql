@@ -0,0 +1 @@
+ALTER TABLE "oauth2_clients" DROP COLUMN "scopes";
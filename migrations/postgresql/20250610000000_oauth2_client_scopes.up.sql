@@ -0,0 +1,2 @@
+ALTER TABLE "oauth2_clients" ADD COLUMN "scopes" TEXT NOT NULL DEFAULT '';
+UPDATE "oauth2_clients" SET "scopes" = 'openid profile email phone address';
@@ -0,0 +1,2 @@
+UPDATE "service_accounts" SET "scopes" = 'applications:read applications:write tenants:read tenants:admin users:read users:write users:impersonate service-accounts:read service-accounts:write tokens:sign tokens:introspect' WHERE "scopes" IS NULL AND "admin" = 1;
+UPDATE "service_accounts" SET "scopes" = 'applications:read tenants:read users:read users:write service-accounts:read tokens:introspect' WHERE ("scopes" IS NULL OR "scopes" = 'applications:read applications:write tenants:read tenants:admin users:read users:write users:impersonate service-accounts:read service-accounts:write tokens:sign tokens:introspect') AND "admin" = 0;
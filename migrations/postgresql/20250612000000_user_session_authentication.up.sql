@@ -0,0 +1,3 @@
+ALTER TABLE "user_sessions" ADD COLUMN "amr" TEXT;
+ALTER TABLE "user_sessions" ADD COLUMN "auth_time" BIGINT;
+UPDATE "user_sessions" SET "auth_time" = "created_at";
@@ -0,0 +1,2 @@
+ALTER TABLE "tenants" DROP COLUMN "encryption_secret";
+ALTER TABLE "tenants" DROP COLUMN "encryption_public_key";
@@ -0,0 +1,14 @@
+CREATE TABLE "user_webauthn_credentials" (
+  "id" SERIAL PRIMARY KEY,
+  "user_id" BIGINT NOT NULL,
+  "credential_id" TEXT NOT NULL,
+  "public_key" TEXT NOT NULL,
+  "sign_count" BIGINT NOT NULL DEFAULT 0,
+  "name" TEXT NOT NULL,
+  "last_used_at" BIGINT,
+  "updated_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  "created_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  CONSTRAINT "user_webauthn_credentials_user_id_fk" FOREIGN KEY("user_id") REFERENCES "users" ("id") ON DELETE CASCADE
+);
+CREATE UNIQUE INDEX "user_webauthn_credentials_credential_id_unique_idx" ON "user_webauthn_credentials" ("credential_id");
+CREATE INDEX "user_webauthn_credentials_user_id_idx" ON "user_webauthn_credentials" ("user_id");
@@ -0,0 +1,9 @@
+CREATE TABLE "user_recovery_codes" (
+  "id" SERIAL PRIMARY KEY,
+  "user_id" BIGINT NOT NULL,
+  "code" TEXT NOT NULL,
+  "used_at" BIGINT,
+  "created_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  CONSTRAINT "user_recovery_codes_user_id_fk" FOREIGN KEY("user_id") REFERENCES "users" ("id") ON DELETE CASCADE
+);
+CREATE INDEX "user_recovery_codes_user_id_idx" ON "user_recovery_codes" ("user_id");
@@ -0,0 +1,11 @@
+CREATE TABLE "user_hotps"(
+  "user_id" BIGINT NOT NULL PRIMARY KEY,
+  "algorithm" TEXT NOT NULL DEFAULT 'SHA1',
+  "digits" BIGINT NOT NULL DEFAULT 6,
+  "counter" BIGINT NOT NULL DEFAULT 0,
+  "secret" TEXT NOT NULL,
+  "updated_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  "created_at" BIGINT NOT NULL DEFAULT extract(epoch from now() at time zone 'utc'),
+  CONSTRAINT "user_hotps_user_id_fk" FOREIGN KEY("user_id") REFERENCES "users" ("id") ON DELETE CASCADE
+);
+CREATE UNIQUE INDEX "user_hotps_user_id_unique_idx" ON "user_hotps" ("user_id");
@@ -0,0 +1,2 @@
+ALTER TABLE "tenants" DROP COLUMN "previous_encryption_secret";
+ALTER TABLE "tenants" DROP COLUMN "encrypt_access_tokens";
@@ -0,0 +1,2 @@
+ALTER TABLE "tenants" ADD COLUMN "encrypt_access_tokens" SMALLINT NOT NULL DEFAULT 0;
+ALTER TABLE "tenants" ADD COLUMN "previous_encryption_secret" TEXT;
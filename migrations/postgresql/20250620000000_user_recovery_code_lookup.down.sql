@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS "user_recovery_codes_user_id_lookup_idx";
+ALTER TABLE "user_recovery_codes" DROP COLUMN "lookup";
@@ -0,0 +1,2 @@
+ALTER TABLE "user_recovery_codes" ADD COLUMN "lookup" TEXT;
+CREATE INDEX "user_recovery_codes_user_id_lookup_idx" ON "user_recovery_codes" ("user_id", "lookup");
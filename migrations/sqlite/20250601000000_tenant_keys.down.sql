@@ -0,0 +1,3 @@
+ALTER TABLE "tenants" DROP COLUMN "key_version";
+
+DROP TABLE IF EXISTS "tenant_keys";
@@ -0,0 +1,22 @@
+CREATE TABLE "tenant_keys" (
+  "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  "tenant_id" INTEGER NOT NULL,
+  "version" INTEGER NOT NULL,
+  "state" TEXT NOT NULL DEFAULT 'next',
+	"algorithm" TEXT NOT NULL,
+	"public_key" TEXT,
+	"private_key" TEXT NOT NULL,
+  "expires_at" INTEGER,
+	"updated_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+	"created_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  FOREIGN KEY ("tenant_id") REFERENCES "tenants" ("id") ON DELETE CASCADE
+) STRICT;
+CREATE UNIQUE INDEX "tenant_keys_id_unique_idx" ON "tenant_keys" ("id");
+CREATE UNIQUE INDEX "tenant_keys_tenant_id_version_unique_idx" ON "tenant_keys" ("tenant_id", "version");
+CREATE INDEX "tenant_keys_tenant_id_idx" ON "tenant_keys" ("tenant_id");
+
+ALTER TABLE "tenants" ADD COLUMN "key_version" INTEGER NOT NULL DEFAULT 1;
+
+INSERT INTO "tenant_keys"
+  ("tenant_id", "version", "state", "algorithm", "public_key", "private_key")
+  SELECT "id", 1, 'active', "algorithm", "public_key", "private_key" FROM "tenants";
@@ -0,0 +1,25 @@
+CREATE TABLE "refresh_token_families" (
+  "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  "tenant_id" INTEGER NOT NULL,
+  "sub_type" TEXT NOT NULL,
+  "sub" INTEGER NOT NULL,
+  "revoked_at" INTEGER,
+  "updated_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  "created_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  FOREIGN KEY ("tenant_id") REFERENCES "tenants" ("id") ON DELETE CASCADE
+) STRICT;
+CREATE UNIQUE INDEX "refresh_token_families_id_unique_idx" ON "refresh_token_families" ("id");
+CREATE INDEX "refresh_token_families_tenant_id_sub_type_sub_idx" ON "refresh_token_families" ("tenant_id", "sub_type", "sub");
+
+CREATE TABLE "refresh_tokens" (
+  "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  "refresh_token_family_id" INTEGER NOT NULL,
+  "jti" TEXT NOT NULL,
+  "used_at" INTEGER,
+  "expires_at" INTEGER NOT NULL,
+  "created_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  FOREIGN KEY ("refresh_token_family_id") REFERENCES "refresh_token_families" ("id") ON DELETE CASCADE
+) STRICT;
+CREATE UNIQUE INDEX "refresh_tokens_id_unique_idx" ON "refresh_tokens" ("id");
+CREATE UNIQUE INDEX "refresh_tokens_jti_unique_idx" ON "refresh_tokens" ("jti");
+CREATE INDEX "refresh_tokens_refresh_token_family_id_idx" ON "refresh_tokens" ("refresh_token_family_id");
@@ -0,0 +1,13 @@
+CREATE TABLE "token_revocations" (
+  "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  "application_id" INTEGER NOT NULL,
+  "jti" TEXT,
+  "sub_type" TEXT,
+  "sub" INTEGER,
+  "revoked_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  "created_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  FOREIGN KEY ("application_id") REFERENCES "applications" ("id") ON DELETE CASCADE
+) STRICT;
+CREATE UNIQUE INDEX "token_revocations_id_unique_idx" ON "token_revocations" ("id");
+CREATE INDEX "token_revocations_jti_idx" ON "token_revocations" ("jti");
+CREATE INDEX "token_revocations_application_id_sub_type_sub_idx" ON "token_revocations" ("application_id", "sub_type", "sub");
@@ -0,0 +1,15 @@
+CREATE TABLE "oauth2_clients" (
+  "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  "tenant_id" INTEGER NOT NULL,
+  "client_id" TEXT NOT NULL,
+  "encrypted_client_secret" TEXT,
+  "name" TEXT NOT NULL,
+  "redirect_uris" TEXT NOT NULL,
+  "active" INTEGER NOT NULL DEFAULT 1,
+  "updated_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  "created_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  FOREIGN KEY ("tenant_id") REFERENCES "tenants" ("id") ON DELETE CASCADE
+) STRICT;
+CREATE UNIQUE INDEX "oauth2_clients_id_unique_idx" ON "oauth2_clients" ("id");
+CREATE UNIQUE INDEX "oauth2_clients_client_id_unique_idx" ON "oauth2_clients" ("client_id");
+CREATE INDEX "oauth2_clients_tenant_id_idx" ON "oauth2_clients" ("tenant_id");
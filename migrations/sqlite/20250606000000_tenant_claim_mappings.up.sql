@@ -0,0 +1,14 @@
+CREATE TABLE "tenant_claim_mappings" (
+  "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  "tenant_id" INTEGER NOT NULL,
+  "claim" TEXT NOT NULL,
+  "source" TEXT NOT NULL,
+  "value" TEXT NOT NULL,
+  "access_token" INTEGER NOT NULL DEFAULT 1,
+  "id_token" INTEGER NOT NULL DEFAULT 1,
+  "updated_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  "created_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  FOREIGN KEY ("tenant_id") REFERENCES "tenants" ("id") ON DELETE CASCADE
+) STRICT;
+CREATE UNIQUE INDEX "tenant_claim_mappings_id_unique_idx" ON "tenant_claim_mappings" ("id");
+CREATE UNIQUE INDEX "tenant_claim_mappings_tenant_id_claim_unique_idx" ON "tenant_claim_mappings" ("tenant_id", "claim");
@@ -0,0 +1,22 @@
+CREATE TABLE "user_sessions" (
+  "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  "tenant_id" INTEGER NOT NULL,
+  "user_id" INTEGER NOT NULL,
+  "refresh_token_family_id" INTEGER NOT NULL,
+  "sid" TEXT NOT NULL,
+  "grant_type" TEXT,
+  "ip_address" TEXT,
+  "user_agent" TEXT,
+  "expires_at" INTEGER NOT NULL,
+  "revoked_at" INTEGER,
+  "last_seen_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  "updated_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  "created_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  FOREIGN KEY ("tenant_id") REFERENCES "tenants" ("id") ON DELETE CASCADE,
+  FOREIGN KEY ("user_id") REFERENCES "users" ("id") ON DELETE CASCADE,
+  FOREIGN KEY ("refresh_token_family_id") REFERENCES "refresh_token_families" ("id") ON DELETE CASCADE
+) STRICT;
+CREATE UNIQUE INDEX "user_sessions_id_unique_idx" ON "user_sessions" ("id");
+CREATE UNIQUE INDEX "user_sessions_sid_unique_idx" ON "user_sessions" ("sid");
+CREATE INDEX "user_sessions_user_id_idx" ON "user_sessions" ("user_id");
+CREATE INDEX "user_sessions_refresh_token_family_id_idx" ON "user_sessions" ("refresh_token_family_id");
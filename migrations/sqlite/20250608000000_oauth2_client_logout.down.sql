@@ -0,0 +1,3 @@
+ALTER TABLE "user_sessions" DROP COLUMN "oauth2_client_id";
+ALTER TABLE "oauth2_clients" DROP COLUMN "backchannel_logout_uri";
+ALTER TABLE "oauth2_clients" DROP COLUMN "post_logout_redirect_uris";
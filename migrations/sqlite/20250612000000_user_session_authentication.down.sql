@@ -0,0 +1,2 @@
+ALTER TABLE "user_sessions" DROP COLUMN "auth_time";
+ALTER TABLE "user_sessions" DROP COLUMN "amr";
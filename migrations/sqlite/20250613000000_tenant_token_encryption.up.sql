@@ -0,0 +1,2 @@
+ALTER TABLE "tenants" ADD COLUMN "encryption_public_key" TEXT;
+ALTER TABLE "tenants" ADD COLUMN "encryption_secret" TEXT;
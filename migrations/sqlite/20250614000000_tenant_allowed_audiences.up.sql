@@ -0,0 +1 @@
+ALTER TABLE "tenants" ADD COLUMN "allowed_audiences" TEXT;
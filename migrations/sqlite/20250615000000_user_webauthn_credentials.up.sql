@@ -0,0 +1,15 @@
+CREATE TABLE "user_webauthn_credentials" (
+  "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  "user_id" INTEGER NOT NULL,
+  "credential_id" TEXT NOT NULL,
+  "public_key" TEXT NOT NULL,
+  "sign_count" INTEGER NOT NULL DEFAULT 0,
+  "name" TEXT NOT NULL,
+  "last_used_at" INTEGER,
+  "updated_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  "created_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  FOREIGN KEY ("user_id") REFERENCES "users" ("id") ON DELETE CASCADE
+) STRICT;
+CREATE UNIQUE INDEX "user_webauthn_credentials_id_unique_idx" ON "user_webauthn_credentials" ("id");
+CREATE UNIQUE INDEX "user_webauthn_credentials_credential_id_unique_idx" ON "user_webauthn_credentials" ("credential_id");
+CREATE INDEX "user_webauthn_credentials_user_id_idx" ON "user_webauthn_credentials" ("user_id");
@@ -0,0 +1,10 @@
+CREATE TABLE "user_recovery_codes" (
+  "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  "user_id" INTEGER NOT NULL,
+  "code" TEXT NOT NULL,
+  "used_at" INTEGER,
+  "created_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  FOREIGN KEY ("user_id") REFERENCES "users" ("id") ON DELETE CASCADE
+) STRICT;
+CREATE UNIQUE INDEX "user_recovery_codes_id_unique_idx" ON "user_recovery_codes" ("id");
+CREATE INDEX "user_recovery_codes_user_id_idx" ON "user_recovery_codes" ("user_id");
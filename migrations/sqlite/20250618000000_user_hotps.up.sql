@@ -0,0 +1,11 @@
+CREATE TABLE "user_hotps"(
+  "user_id" INTEGER NOT NULL PRIMARY KEY,
+  "algorithm" TEXT NOT NULL DEFAULT 'SHA1',
+  "digits" INTEGER NOT NULL DEFAULT 6,
+  "counter" INTEGER NOT NULL DEFAULT 0,
+  "secret" TEXT NOT NULL,
+  "updated_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  "created_at" INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+  FOREIGN KEY ("user_id") REFERENCES "users" ("id") ON DELETE CASCADE
+) STRICT;
+CREATE UNIQUE INDEX "user_hotps_user_id_unique_idx" ON "user_hotps" ("user_id");
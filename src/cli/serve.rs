@@ -3,6 +3,7 @@ use std::{net::SocketAddr, sync::Arc};
 use crate::{
   core::{config::Config, error::InternalError},
   router::{create_router, RouterState},
+  service::{mail::create_mail_sender, sms::create_sms_sender},
 };
 use tokio_util::sync::CancellationToken;
 
@@ -12,6 +13,8 @@ pub async fn serve(
   cancellation_token: CancellationToken,
 ) -> Result<(), InternalError> {
   let router = create_router(RouterState {
+    mail_sender: create_mail_sender(&config.mail)?,
+    sms_sender: create_sms_sender(&config.sms)?,
     config: config.clone(),
     pool: pool.clone(),
   });
@@ -53,15 +53,12 @@ pub async fn init_pool(config: &Config) -> Result<sqlx::AnyPool, sqlx::Error> {
     .max_lifetime(Duration::from_secs(config.database.max_lifetime))
     .after_connect(|conn, _meta| {
       Box::pin(async move {
-        match conn.backend_name().to_lowercase().as_str() {
-          "sqlite" => {
-            conn
-              .execute(
-                "PRAGMA journal_mode = wal; PRAGMA synchronous = normal; PRAGMA foreign_keys = on;",
-              )
-              .await?;
-          }
-          _ => (),
+        if conn.backend_name().to_lowercase().as_str() == "sqlite" {
+          conn
+            .execute(
+              "PRAGMA journal_mode = wal; PRAGMA synchronous = normal; PRAGMA foreign_keys = on;",
+            )
+            .await?;
         }
         Ok(())
       })
@@ -117,15 +114,12 @@ pub async fn close_pool() -> Result<(), sqlx::Error> {
   let pool = POOL.take(Ordering::SeqCst).expect("Pool not initialized");
   {
     let conn = pool.acquire().await?;
-    match conn.backend_name().to_lowercase().as_str() {
-      "sqlite" => {
-        log::info!("Optimizing database");
-        pool
-          .execute("PRAGMA analysis_limit=400; PRAGMA optimize;")
-          .await?;
-        log::info!("Optimized database");
-      }
-      _ => {}
+    if conn.backend_name().to_lowercase().as_str() == "sqlite" {
+      log::info!("Optimizing database");
+      pool
+        .execute("PRAGMA analysis_limit=400; PRAGMA optimize;")
+        .await?;
+      log::info!("Optimized database");
     }
   }
   pool.close().await;
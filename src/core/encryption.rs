@@ -1,7 +1,35 @@
+use base64::{prelude::BASE64_STANDARD, Engine};
+use jsonwebtoken::{
+  errors::{Error, ErrorKind},
+  Algorithm,
+};
 use rand::Rng;
+use rsa::{
+  pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey},
+  pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey, LineEnding},
+  rand_core::OsRng,
+};
 
 use super::config::Config;
 
+pub const RSA_KEY_BITS: usize = 2048;
+
+/// RFC 7518 JWE key management algorithm for ID tokens encrypted to the client
+pub const JWE_ALGORITHM_RSA_OAEP_256: &str = "RSA-OAEP-256";
+/// RFC 7518 JWE content encryption, the only one supported
+pub const JWE_ENCRYPTION_A256GCM: &str = "A256GCM";
+/// The content type of a JWE wrapping a signed token
+pub const JWE_CONTENT_TYPE_JWT: &str = "JWT";
+
+const A256GCM_KEY_BYTES: usize = 32;
+
+pub struct JweHeader {
+  pub alg: String,
+  pub enc: String,
+  pub kid: Option<String>,
+  pub cty: Option<String>,
+}
+
 pub fn random_bytes(size: usize) -> Vec<u8> {
   let mut bytes = vec![0; size];
   rand::rng().fill(bytes.as_mut_slice());
@@ -11,7 +39,7 @@ pub fn random_bytes(size: usize) -> Vec<u8> {
 pub fn encrypt_password(config: &Config, input: &str) -> argon2::Result<String> {
   argon2::hash_encoded(
     input.as_bytes(),
-    random_bytes(config.password.salt_length.into()).as_slice(),
+    random_bytes(config.password.salt_length).as_slice(),
     &argon2_config(config),
   )
 }
@@ -20,13 +48,257 @@ pub fn verify_password(input: &str, encrypted_password: &str) -> argon2::Result<
   argon2::verify_encoded(encrypted_password, input.as_bytes())
 }
 
+/// Generates a new signing key for the algorithm, returns the PEM encoded public key and the
+/// private key, HMAC algorithms only have a base64 encoded secret.
+pub fn generate_key_pair(algorithm: Algorithm) -> Result<(Option<String>, String), Error> {
+  match algorithm {
+    Algorithm::HS256 => Ok((None, BASE64_STANDARD.encode(random_bytes(256)))),
+    Algorithm::HS384 => Ok((None, BASE64_STANDARD.encode(random_bytes(384)))),
+    Algorithm::HS512 => Ok((None, BASE64_STANDARD.encode(random_bytes(512)))),
+    Algorithm::RS256
+    | Algorithm::RS384
+    | Algorithm::RS512
+    | Algorithm::PS256
+    | Algorithm::PS384
+    | Algorithm::PS512 => {
+      let private_key = rsa::RsaPrivateKey::new(&mut OsRng, RSA_KEY_BITS)
+        .map_err(|e| Error::from(ErrorKind::InvalidRsaKey(e.to_string())))?;
+      let (public_key, private_key) = rsa_key_pair_pem(&private_key)?;
+      Ok((Some(public_key), private_key))
+    }
+    Algorithm::ES256 => {
+      let (public_key, private_key) = p256_key_pair_pem(&p256::SecretKey::random(&mut OsRng))?;
+      Ok((Some(public_key), private_key))
+    }
+    Algorithm::ES384 => {
+      let (public_key, private_key) = p384_key_pair_pem(&p384::SecretKey::random(&mut OsRng))?;
+      Ok((Some(public_key), private_key))
+    }
+    Algorithm::EdDSA => {
+      let (public_key, private_key) =
+        ed25519_key_pair_pem(&ed25519_dalek::SigningKey::generate(&mut OsRng))?;
+      Ok((Some(public_key), private_key))
+    }
+  }
+}
+
+/// Parses a PEM encoded private key (PKCS#8, or PKCS#1/SEC1 for RSA/EC keys) and returns the
+/// PEM encoded public key along with the private key re-encoded as PKCS#8.
+pub fn key_pair_from_private_key(
+  algorithm: Algorithm,
+  private_key: &str,
+) -> Result<(String, String), Error> {
+  match algorithm {
+    Algorithm::RS256
+    | Algorithm::RS384
+    | Algorithm::RS512
+    | Algorithm::PS256
+    | Algorithm::PS384
+    | Algorithm::PS512 => {
+      let private_key = rsa::RsaPrivateKey::from_pkcs8_pem(private_key)
+        .or_else(|_| rsa::RsaPrivateKey::from_pkcs1_pem(private_key))
+        .map_err(|e| Error::from(ErrorKind::InvalidRsaKey(e.to_string())))?;
+      rsa_key_pair_pem(&private_key)
+    }
+    Algorithm::ES256 => {
+      let private_key = p256::SecretKey::from_pkcs8_pem(private_key)
+        .or_else(|_| p256::SecretKey::from_sec1_pem(private_key))
+        .map_err(|_| Error::from(ErrorKind::InvalidEcdsaKey))?;
+      p256_key_pair_pem(&private_key)
+    }
+    Algorithm::ES384 => {
+      let private_key = p384::SecretKey::from_pkcs8_pem(private_key)
+        .or_else(|_| p384::SecretKey::from_sec1_pem(private_key))
+        .map_err(|_| Error::from(ErrorKind::InvalidEcdsaKey))?;
+      p384_key_pair_pem(&private_key)
+    }
+    Algorithm::EdDSA => {
+      let private_key = ed25519_dalek::SigningKey::from_pkcs8_pem(private_key)
+        .map_err(|_| Error::from(ErrorKind::InvalidKeyFormat))?;
+      ed25519_key_pair_pem(&private_key)
+    }
+    Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+      Err(Error::from(ErrorKind::InvalidAlgorithm))
+    }
+  }
+}
+
+/// Parses a PEM encoded public key (SPKI, or PKCS#1 for RSA keys) and returns it re-encoded as
+/// SPKI, matching the public keys returned by `key_pair_from_private_key`.
+pub fn public_key_pem(algorithm: Algorithm, public_key: &str) -> Result<String, Error> {
+  match algorithm {
+    Algorithm::RS256
+    | Algorithm::RS384
+    | Algorithm::RS512
+    | Algorithm::PS256
+    | Algorithm::PS384
+    | Algorithm::PS512 => rsa::RsaPublicKey::from_public_key_pem(public_key)
+      .or_else(|_| rsa::RsaPublicKey::from_pkcs1_pem(public_key))
+      .map_err(|e| Error::from(ErrorKind::InvalidRsaKey(e.to_string())))?
+      .to_public_key_pem(LineEnding::LF)
+      .map_err(|e| Error::from(ErrorKind::InvalidRsaKey(e.to_string()))),
+    Algorithm::ES256 => p256::PublicKey::from_public_key_pem(public_key)
+      .map_err(|_| Error::from(ErrorKind::InvalidEcdsaKey))?
+      .to_public_key_pem(LineEnding::LF)
+      .map_err(|_| Error::from(ErrorKind::InvalidEcdsaKey)),
+    Algorithm::ES384 => p384::PublicKey::from_public_key_pem(public_key)
+      .map_err(|_| Error::from(ErrorKind::InvalidEcdsaKey))?
+      .to_public_key_pem(LineEnding::LF)
+      .map_err(|_| Error::from(ErrorKind::InvalidEcdsaKey)),
+    Algorithm::EdDSA => ed25519_dalek::VerifyingKey::from_public_key_pem(public_key)
+      .map_err(|_| Error::from(ErrorKind::InvalidKeyFormat))?
+      .to_public_key_pem(LineEnding::LF)
+      .map_err(|_| Error::from(ErrorKind::InvalidKeyFormat)),
+    Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+      Err(Error::from(ErrorKind::InvalidAlgorithm))
+    }
+  }
+}
+
+/// Returns true when the token is a JWE rather than a signed token.
+pub fn is_jwe(token: &str) -> bool {
+  josekit::jwt::decode_header(token).is_ok_and(|header| header.claim("enc").is_some())
+}
+
+pub fn decode_jwe_header(token: &str) -> Result<JweHeader, Error> {
+  let header = josekit::jwt::decode_header(token).map_err(jose_error)?;
+  let claim = |name: &str| {
+    header
+      .claim(name)
+      .and_then(serde_json::Value::as_str)
+      .map(ToOwned::to_owned)
+  };
+  Ok(JweHeader {
+    alg: claim("alg").ok_or(ErrorKind::InvalidToken)?,
+    enc: claim("enc").ok_or(ErrorKind::InvalidToken)?,
+    kid: claim("kid"),
+    cty: claim("cty"),
+  })
+}
+
+/// Generates a base64 encoded secret for [`encrypt_jwe_dir`].
+pub fn generate_jwe_secret() -> String {
+  BASE64_STANDARD.encode(random_bytes(A256GCM_KEY_BYTES))
+}
+
+/// Encrypts the payload with a new content key that is wrapped with the PEM encoded RSA public
+/// key, only the holder of the private key can decrypt it.
+pub fn encrypt_jwe_rsa_oaep_256(
+  payload: &str,
+  public_key: &str,
+  kid: Option<String>,
+) -> Result<String, Error> {
+  let encrypter = josekit::jwe::RSA_OAEP_256
+    .encrypter_from_pem(public_key)
+    .map_err(jose_error)?;
+  josekit::jwe::serialize_compact(payload.as_bytes(), &jwe_header(kid), &encrypter)
+    .map_err(jose_error)
+}
+
+pub fn decrypt_jwe_rsa_oaep_256(token: &str, private_key: &str) -> Result<String, Error> {
+  let decrypter = josekit::jwe::RSA_OAEP_256
+    .decrypter_from_pem(private_key)
+    .map_err(jose_error)?;
+  open_jwe(token, &decrypter)
+}
+
+/// Encrypts the payload directly with a secret from [`generate_jwe_secret`].
+pub fn encrypt_jwe_dir(payload: &str, secret: &str, kid: Option<String>) -> Result<String, Error> {
+  let encrypter = josekit::jwe::Dir
+    .encrypter_from_bytes(BASE64_STANDARD.decode(secret)?)
+    .map_err(jose_error)?;
+  josekit::jwe::serialize_compact(payload.as_bytes(), &jwe_header(kid), &encrypter)
+    .map_err(jose_error)
+}
+
+pub fn decrypt_jwe_dir(token: &str, secret: &str) -> Result<String, Error> {
+  let decrypter = josekit::jwe::Dir
+    .decrypter_from_bytes(BASE64_STANDARD.decode(secret)?)
+    .map_err(jose_error)?;
+  open_jwe(token, &decrypter)
+}
+
+fn jwe_header(kid: Option<String>) -> josekit::jwe::JweHeader {
+  let mut header = josekit::jwe::JweHeader::new();
+  header.set_content_encryption(JWE_ENCRYPTION_A256GCM);
+  header.set_content_type(JWE_CONTENT_TYPE_JWT);
+  if let Some(kid) = kid {
+    header.set_key_id(kid);
+  }
+  header
+}
+
+/// Only A256GCM content is accepted, the decrypter checks the key management algorithm.
+fn open_jwe(token: &str, decrypter: &dyn josekit::jwe::JweDecrypter) -> Result<String, Error> {
+  let (payload, header) =
+    josekit::jwe::deserialize_compact(token, decrypter).map_err(jose_error)?;
+  if header.content_encryption() != Some(JWE_ENCRYPTION_A256GCM) {
+    return Err(ErrorKind::InvalidAlgorithm.into());
+  }
+  String::from_utf8(payload).map_err(|e| ErrorKind::Utf8(e).into())
+}
+
+fn jose_error(error: josekit::JoseError) -> Error {
+  match error {
+    josekit::JoseError::InvalidKeyFormat(e) => ErrorKind::InvalidRsaKey(e.to_string()).into(),
+    josekit::JoseError::UnsupportedSignatureAlgorithm(_) => ErrorKind::InvalidAlgorithm.into(),
+    _ => ErrorKind::InvalidToken.into(),
+  }
+}
+
+fn rsa_key_pair_pem(private_key: &rsa::RsaPrivateKey) -> Result<(String, String), Error> {
+  let public_key = private_key
+    .to_public_key()
+    .to_public_key_pem(LineEnding::LF)
+    .map_err(|e| Error::from(ErrorKind::InvalidRsaKey(e.to_string())))?;
+  let private_key = private_key
+    .to_pkcs8_pem(LineEnding::LF)
+    .map_err(|e| Error::from(ErrorKind::InvalidRsaKey(e.to_string())))?;
+  Ok((public_key, private_key.to_string()))
+}
+
+fn p256_key_pair_pem(private_key: &p256::SecretKey) -> Result<(String, String), Error> {
+  let public_key = private_key
+    .public_key()
+    .to_public_key_pem(LineEnding::LF)
+    .map_err(|_| Error::from(ErrorKind::InvalidEcdsaKey))?;
+  let private_key = private_key
+    .to_pkcs8_pem(LineEnding::LF)
+    .map_err(|_| Error::from(ErrorKind::InvalidEcdsaKey))?;
+  Ok((public_key, private_key.to_string()))
+}
+
+fn p384_key_pair_pem(private_key: &p384::SecretKey) -> Result<(String, String), Error> {
+  let public_key = private_key
+    .public_key()
+    .to_public_key_pem(LineEnding::LF)
+    .map_err(|_| Error::from(ErrorKind::InvalidEcdsaKey))?;
+  let private_key = private_key
+    .to_pkcs8_pem(LineEnding::LF)
+    .map_err(|_| Error::from(ErrorKind::InvalidEcdsaKey))?;
+  Ok((public_key, private_key.to_string()))
+}
+
+fn ed25519_key_pair_pem(
+  private_key: &ed25519_dalek::SigningKey,
+) -> Result<(String, String), Error> {
+  let public_key = private_key
+    .verifying_key()
+    .to_public_key_pem(LineEnding::LF)
+    .map_err(|_| Error::from(ErrorKind::InvalidKeyFormat))?;
+  let private_key = private_key
+    .to_pkcs8_pem(LineEnding::LF)
+    .map_err(|_| Error::from(ErrorKind::InvalidKeyFormat))?;
+  Ok((public_key, private_key.to_string()))
+}
+
 fn argon2_config<'a>(config: &Config) -> argon2::Config<'a> {
-  return argon2::Config {
+  argon2::Config {
     variant: argon2::Variant::Argon2id,
     hash_length: config.password.hash_length,
     lanes: config.password.parallelism,
     mem_cost: config.password.memory_mib * 1024,
     time_cost: config.password.iterations,
     ..Default::default()
-  };
+  }
 }
@@ -21,6 +21,7 @@ pub const DATEBASE_ERROR: &str = "database";
 pub const NOT_ALLOWED_ERROR: &str = "not-allowed";
 pub const ALREADY_USED_ERROR: &str = "already-used";
 pub const ALREADY_EXISTS_ERROR: &str = "already-exists";
+pub const INSUFFICIENT_AUTHENTICATION_ERROR: &str = "insufficient-authentication";
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ErrorMessage {
@@ -28,6 +29,12 @@ pub struct ErrorMessage {
   parameters: HashMap<String, Value>,
 }
 
+impl ErrorMessage {
+  pub fn code(&self) -> &str {
+    &self.code
+  }
+}
+
 impl<'a> From<&'a ValidationError> for ErrorMessage {
   fn from(error: &'a ValidationError) -> Self {
     Self::from((
@@ -87,6 +94,10 @@ impl ErrorMessages {
     self.0.push(msg.into());
     self
   }
+
+  pub fn first(&self) -> Option<&ErrorMessage> {
+    self.0.first()
+  }
 }
 
 pub type Errors = HashMap<String, ErrorMessages>;
@@ -121,7 +132,7 @@ impl From<StatusCode> for InternalError {
 impl From<ValidationErrors> for InternalError {
   fn from(validation_errors: ValidationErrors) -> Self {
     let mut new = Self::bad_request();
-    handle_validation_errors(&mut new, &mut String::new(), &validation_errors);
+    handle_validation_errors(&mut new, "", &validation_errors);
     new
   }
 }
@@ -184,6 +195,7 @@ impl InternalError {
     Self::from(StatusCode::BAD_REQUEST)
   }
 
+  #[allow(clippy::self_named_constructors)]
   pub fn internal_error() -> Self {
     Self::from(StatusCode::INTERNAL_SERVER_ERROR)
   }
@@ -214,7 +226,7 @@ impl InternalError {
     self
       .messages
       .entry(name.into())
-      .or_insert_with(Default::default)
+      .or_default()
       .error(msg);
     self
   }
@@ -270,7 +282,7 @@ fn handle_validation_errors_kind(
       for (index, e) in validation_errors {
         let mut name = current_name.to_owned();
         name.push_str(&format!("[{}]", index));
-        handle_validation_errors(errors, &mut name, e);
+        handle_validation_errors(errors, &name, e);
       }
     }
     ValidationErrorsKind::Field(validation_errors) => {
@@ -3,3 +3,4 @@ pub mod database;
 pub mod encryption;
 pub mod error;
 pub mod openapi;
+pub mod webauthn;
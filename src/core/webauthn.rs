@@ -0,0 +1,266 @@
+use std::io;
+
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use ciborium::Value;
+use p256::ecdsa::signature::Verifier;
+use rsa::{BigUint, Pkcs1v15Sign, RsaPublicKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::config::WebAuthnConfig;
+
+/// COSE algorithms of the credential public keys that can be verified
+pub const COSE_ALGORITHM_ES256: i64 = -7;
+pub const COSE_ALGORITHM_EDDSA: i64 = -8;
+pub const COSE_ALGORITHM_RS256: i64 = -257;
+pub const COSE_ALGORITHMS: [i64; 3] = [
+  COSE_ALGORITHM_ES256,
+  COSE_ALGORITHM_EDDSA,
+  COSE_ALGORITHM_RS256,
+];
+
+pub const CLIENT_DATA_TYPE_CREATE: &str = "webauthn.create";
+pub const CLIENT_DATA_TYPE_GET: &str = "webauthn.get";
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+const COSE_KEY_ALGORITHM: i64 = 3;
+const COSE_KEY_RSA_N: i64 = -1;
+const COSE_KEY_RSA_E: i64 = -2;
+const COSE_KEY_X: i64 = -2;
+const COSE_KEY_Y: i64 = -3;
+
+#[derive(Deserialize)]
+struct ClientData {
+  r#type: String,
+  challenge: String,
+  origin: String,
+}
+
+pub struct AuthenticatorData {
+  pub flags: u8,
+  pub sign_count: u32,
+  /// The credential id and COSE public key of a newly registered credential
+  pub credential: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl AuthenticatorData {
+  pub fn is_user_verified(&self) -> bool {
+    self.flags & FLAG_USER_VERIFIED != 0
+  }
+}
+
+pub fn generate_challenge() -> String {
+  BASE64_URL_SAFE_NO_PAD.encode(super::encryption::random_bytes(32))
+}
+
+/// The base64url challenge the client signed, used to find the stored challenge before verifying
+pub fn client_data_challenge(client_data_json: &[u8]) -> io::Result<String> {
+  Ok(serde_json::from_slice::<ClientData>(client_data_json)?.challenge)
+}
+
+/// Verifies a `navigator.credentials.create` response for the challenge and returns the new
+/// credential. Attestation statements are not verified, like relying parties requesting `none`
+/// attestation.
+pub fn verify_registration(
+  config: &WebAuthnConfig,
+  challenge: &str,
+  client_data_json: &[u8],
+  attestation_object: &[u8],
+) -> io::Result<AuthenticatorData> {
+  verify_client_data(config, CLIENT_DATA_TYPE_CREATE, challenge, client_data_json)?;
+  let attestation: Value = ciborium::from_reader(attestation_object).map_err(io::Error::other)?;
+  let auth_data = attestation
+    .as_map()
+    .and_then(|map| {
+      map
+        .iter()
+        .find(|(key, _)| key.as_text() == Some("authData"))
+    })
+    .and_then(|(_, value)| value.as_bytes())
+    .ok_or_else(|| io::Error::other("attestation object is missing authData"))?;
+  let authenticator_data = parse_authenticator_data(config, auth_data)?;
+  let Some((_, public_key)) = authenticator_data.credential.as_ref() else {
+    return Err(io::Error::other(
+      "authenticator data is missing the credential",
+    ));
+  };
+  let algorithm = cose_key_algorithm(&cose_key(public_key)?)?;
+  if !COSE_ALGORITHMS.contains(&algorithm) {
+    return Err(io::Error::other(format!(
+      "unsupported credential algorithm {algorithm}"
+    )));
+  }
+  Ok(authenticator_data)
+}
+
+/// Verifies a `navigator.credentials.get` response for the challenge, signed by the credential's
+/// COSE public key.
+pub fn verify_assertion(
+  config: &WebAuthnConfig,
+  challenge: &str,
+  public_key: &[u8],
+  client_data_json: &[u8],
+  authenticator_data: &[u8],
+  signature: &[u8],
+) -> io::Result<AuthenticatorData> {
+  verify_client_data(config, CLIENT_DATA_TYPE_GET, challenge, client_data_json)?;
+  let parsed_authenticator_data = parse_authenticator_data(config, authenticator_data)?;
+  let mut signed = authenticator_data.to_vec();
+  signed.extend_from_slice(&Sha256::digest(client_data_json));
+  verify_signature(&cose_key(public_key)?, &signed, signature)?;
+  Ok(parsed_authenticator_data)
+}
+
+fn verify_client_data(
+  config: &WebAuthnConfig,
+  r#type: &str,
+  challenge: &str,
+  client_data_json: &[u8],
+) -> io::Result<()> {
+  let client_data: ClientData = serde_json::from_slice(client_data_json)?;
+  if client_data.r#type != r#type {
+    return Err(io::Error::other(format!(
+      "invalid client data type {}",
+      client_data.r#type
+    )));
+  }
+  if client_data.challenge != challenge {
+    return Err(io::Error::other("invalid client data challenge"));
+  }
+  if !config.origins.contains(&client_data.origin) {
+    return Err(io::Error::other(format!(
+      "invalid client data origin {}",
+      client_data.origin
+    )));
+  }
+  Ok(())
+}
+
+fn parse_authenticator_data(
+  config: &WebAuthnConfig,
+  authenticator_data: &[u8],
+) -> io::Result<AuthenticatorData> {
+  if authenticator_data.len() < 37 {
+    return Err(io::Error::other("authenticator data is too short"));
+  }
+  if authenticator_data[..32] != Sha256::digest(config.rp_id.as_bytes())[..] {
+    return Err(io::Error::other("invalid authenticator data rp id hash"));
+  }
+  let flags = authenticator_data[32];
+  if flags & FLAG_USER_PRESENT == 0 {
+    return Err(io::Error::other("user was not present"));
+  }
+  let sign_count = u32::from_be_bytes([
+    authenticator_data[33],
+    authenticator_data[34],
+    authenticator_data[35],
+    authenticator_data[36],
+  ]);
+  let mut credential = None;
+  if flags & FLAG_ATTESTED_CREDENTIAL_DATA != 0 {
+    // 16 byte AAGUID, then the 2 byte credential id length
+    let id_start = 37 + 16 + 2;
+    if authenticator_data.len() < id_start {
+      return Err(io::Error::other("attested credential data is too short"));
+    }
+    let id_length = u16::from_be_bytes([
+      authenticator_data[id_start - 2],
+      authenticator_data[id_start - 1],
+    ]) as usize;
+    let key_start = id_start + id_length;
+    if authenticator_data.len() <= key_start {
+      return Err(io::Error::other("attested credential data is too short"));
+    }
+    let mut rest = &authenticator_data[key_start..];
+    ciborium::from_reader::<Value, _>(&mut rest).map_err(io::Error::other)?;
+    let key_end = authenticator_data.len() - rest.len();
+    credential = Some((
+      authenticator_data[id_start..key_start].to_vec(),
+      authenticator_data[key_start..key_end].to_vec(),
+    ));
+  }
+  Ok(AuthenticatorData {
+    flags,
+    sign_count,
+    credential,
+  })
+}
+
+fn cose_key(public_key: &[u8]) -> io::Result<Vec<(Value, Value)>> {
+  match ciborium::from_reader(public_key).map_err(io::Error::other)? {
+    Value::Map(entries) => Ok(entries),
+    _ => Err(io::Error::other("credential public key is not a COSE key")),
+  }
+}
+
+fn cose_key_value(key: &[(Value, Value)], label: i64) -> Option<&Value> {
+  key
+    .iter()
+    .find(|(entry_label, _)| {
+      entry_label
+        .as_integer()
+        .and_then(|entry_label| i64::try_from(entry_label).ok())
+        == Some(label)
+    })
+    .map(|(_, value)| value)
+}
+
+fn cose_key_bytes(key: &[(Value, Value)], label: i64) -> io::Result<&[u8]> {
+  cose_key_value(key, label)
+    .and_then(Value::as_bytes)
+    .map(Vec::as_slice)
+    .ok_or_else(|| io::Error::other(format!("COSE key is missing {label}")))
+}
+
+fn cose_key_algorithm(key: &[(Value, Value)]) -> io::Result<i64> {
+  cose_key_value(key, COSE_KEY_ALGORITHM)
+    .and_then(Value::as_integer)
+    .and_then(|algorithm| i64::try_from(algorithm).ok())
+    .ok_or_else(|| io::Error::other("COSE key is missing its algorithm"))
+}
+
+fn verify_signature(key: &[(Value, Value)], data: &[u8], signature: &[u8]) -> io::Result<()> {
+  match cose_key_algorithm(key)? {
+    COSE_ALGORITHM_ES256 => {
+      let mut point = vec![0x04];
+      point.extend_from_slice(cose_key_bytes(key, COSE_KEY_X)?);
+      point.extend_from_slice(cose_key_bytes(key, COSE_KEY_Y)?);
+      let verifying_key =
+        p256::ecdsa::VerifyingKey::from_sec1_bytes(&point).map_err(io::Error::other)?;
+      let signature = p256::ecdsa::Signature::from_der(signature).map_err(io::Error::other)?;
+      verifying_key
+        .verify(data, &signature)
+        .map_err(io::Error::other)
+    }
+    COSE_ALGORITHM_EDDSA => {
+      let x: [u8; 32] = cose_key_bytes(key, COSE_KEY_X)?
+        .try_into()
+        .map_err(io::Error::other)?;
+      let verifying_key = ed25519_dalek::VerifyingKey::from_bytes(&x).map_err(io::Error::other)?;
+      let signature = ed25519_dalek::Signature::from_slice(signature).map_err(io::Error::other)?;
+      verifying_key
+        .verify(data, &signature)
+        .map_err(io::Error::other)
+    }
+    COSE_ALGORITHM_RS256 => {
+      let verifying_key = RsaPublicKey::new(
+        BigUint::from_bytes_be(cose_key_bytes(key, COSE_KEY_RSA_N)?),
+        BigUint::from_bytes_be(cose_key_bytes(key, COSE_KEY_RSA_E)?),
+      )
+      .map_err(io::Error::other)?;
+      verifying_key
+        .verify(
+          Pkcs1v15Sign::new::<Sha256>(),
+          &Sha256::digest(data),
+          signature,
+        )
+        .map_err(io::Error::other)
+    }
+    algorithm => Err(io::Error::other(format!(
+      "unsupported credential algorithm {algorithm}"
+    ))),
+  }
+}
@@ -2,15 +2,20 @@ use std::str::FromStr;
 
 use axum::extract::{FromRef, FromRequestParts};
 use http::request::Parts;
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Deserialize};
 
 use super::claims::{parse_jwt, parse_jwt_no_validation, BasicClaims, TOKEN_TYPE_BEARER};
 use crate::{
   core::{
+    encryption::{decode_jwe_header, is_jwe},
     error::{InternalError, INVALID_ERROR, PARSE_ERROR, REQUIRED_ERROR},
     openapi::AUTHORIZATION_HEADER,
   },
-  repository::tenant::{get_tenant_by_id, TenantRow},
+  repository::{
+    tenant::{get_tenant_by_id, TenantRow},
+    token_revocation::is_token_revoked,
+    user_session::is_user_session_revoked,
+  },
   router::RouterState,
 };
 
@@ -65,8 +70,14 @@ pub async fn parse_authorization<T>(
 where
   T: DeserializeOwned,
 {
-  let maybe_invalid_token = match parse_jwt_no_validation::<T>(authorization_string) {
-    Ok(maybe_invalid_token) => maybe_invalid_token,
+  // encrypted tokens name the tenant in their JWE header, the signed token is inside
+  let kid = if is_jwe(authorization_string) {
+    decode_jwe_header(authorization_string).map(|header| header.kid)
+  } else {
+    parse_jwt_no_validation::<T>(authorization_string).map(|token| token.header.kid)
+  };
+  let kid = match kid {
+    Ok(kid) => kid,
     Err(e) => {
       log::error!("invalid authorization failed to check header: {}", e);
       return Err(InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR));
@@ -75,13 +86,8 @@ where
   let ApplicationIdTenantId {
     application_id,
     tenant_id,
-  } = match maybe_invalid_token
-    .header
-    .kid
-    .as_ref()
-    .map(String::as_str)
-    .map(FromStr::from_str)
-  {
+    ..
+  } = match kid.as_deref().map(FromStr::from_str) {
     Some(Ok(application_id_tenant_id)) => application_id_tenant_id,
     Some(Err(e)) => {
       log::error!("invalid authorization failed to parse kid: {}", e);
@@ -103,24 +109,106 @@ where
       return Err(InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR));
     }
   };
-  let token_data = match parse_jwt::<T>(authorization_string, &tenant) {
+  // the claims are validated once and then read as both the requested and the revocation claims
+  let token_data = match parse_jwt::<serde_json::Value>(pool, authorization_string, &tenant).await {
     Ok(token_data) => token_data,
     Err(e) => {
       log::error!("invalid authorization failed to parse claims: {}", e);
       return Err(InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR));
     }
   };
+  let revocation_claims = match RevocationClaims::deserialize(&token_data.claims) {
+    Ok(revocation_claims) => revocation_claims,
+    Err(e) => {
+      log::error!("invalid authorization failed to parse claims: {}", e);
+      return Err(InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR));
+    }
+  };
+  let token_data = match T::deserialize(token_data.claims) {
+    Ok(claims) => jsonwebtoken::TokenData {
+      header: token_data.header,
+      claims,
+    },
+    Err(e) => {
+      log::error!("invalid authorization failed to parse claims: {}", e);
+      return Err(InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR));
+    }
+  };
+  if let (Some(sub_type), Some(sub)) = (&revocation_claims.sub_type, revocation_claims.sub) {
+    match is_token_revoked(
+      pool,
+      tenant.application_id,
+      revocation_claims.jti.as_deref(),
+      sub_type,
+      sub,
+      revocation_claims.iat.unwrap_or_default(),
+    )
+    .await
+    {
+      Ok(false) => {}
+      Ok(true) => {
+        log::error!("invalid authorization token has been revoked");
+        return Err(InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR));
+      }
+      Err(e) => {
+        log::error!("invalid authorization failed to check revocation: {}", e);
+        return Err(InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR));
+      }
+    }
+  }
+  if let Some(sid) = revocation_claims.sid.as_deref() {
+    match is_user_session_revoked(pool, sid).await {
+      Ok(false) => {}
+      Ok(true) => {
+        log::error!("invalid authorization session has been revoked");
+        return Err(InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR));
+      }
+      Err(e) => {
+        log::error!(
+          "invalid authorization failed to check session revocation: {}",
+          e
+        );
+        return Err(InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR));
+      }
+    }
+  }
   Ok((tenant, token_data))
 }
 
+/// The claims used to check a token against the revocation list, tokens minted through
+/// `POST /jwt` may not have any of them.
+#[derive(Deserialize)]
+struct RevocationClaims {
+  jti: Option<String>,
+  sub_type: Option<String>,
+  sub: Option<i64>,
+  iat: Option<i64>,
+  sid: Option<String>,
+}
+
+/// The `kid` of tokens signed by a tenant, `{application_id}-{tenant_id}-{key_version}`. Tokens
+/// signed before keys were versioned have no key version and were signed with version `1`.
 pub struct ApplicationIdTenantId {
   application_id: i64,
   tenant_id: i64,
+  key_version: i64,
 }
 
 impl ApplicationIdTenantId {
-  pub fn new_kid(application_id: i64, tenant_id: i64) -> String {
-    format!("{}-{}", application_id, tenant_id)
+  pub fn new_kid(application_id: i64, tenant_id: i64, key_version: i64) -> String {
+    format!("{}-{}-{}", application_id, tenant_id, key_version)
+  }
+
+  pub fn application_id(&self) -> i64 {
+    self.application_id
+  }
+
+  pub fn tenant_id(&self) -> i64 {
+    self.tenant_id
+  }
+
+  pub fn key_version(&self) -> i64 {
+    self.key_version
   }
 }
 
@@ -128,16 +216,24 @@ impl FromStr for ApplicationIdTenantId {
   type Err = InternalError;
 
   fn from_str(s: &str) -> Result<Self, Self::Err> {
-    match s.split_once('-') {
-      Some((application_id, tenant_id)) => Ok(Self {
-        application_id: application_id
-          .parse()
-          .map_err(|_| InternalError::not_found().with_error("kid", PARSE_ERROR))?,
-        tenant_id: tenant_id
-          .parse()
-          .map_err(|_| InternalError::not_found().with_error("kid", PARSE_ERROR))?,
+    let mut parts = s.splitn(3, '-');
+    let mut next_part = || -> Result<Option<i64>, Self::Err> {
+      parts
+        .next()
+        .map(|part| {
+          part
+            .parse()
+            .map_err(|_| InternalError::not_found().with_error("kid", PARSE_ERROR))
+        })
+        .transpose()
+    };
+    match (next_part()?, next_part()?, next_part()?) {
+      (Some(application_id), Some(tenant_id), key_version) => Ok(Self {
+        application_id,
+        tenant_id,
+        key_version: key_version.unwrap_or(1),
       }),
-      None => Err(InternalError::not_found().with_error("kid", PARSE_ERROR)),
+      _ => Err(InternalError::not_found().with_error("kid", PARSE_ERROR)),
     }
   }
 }
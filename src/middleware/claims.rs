@@ -1,8 +1,12 @@
-use std::str::FromStr;
+use std::{borrow::Cow, str::FromStr};
 
+use jsonwebtoken::errors::ErrorKind;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::repository::tenant::TenantRow;
+use crate::{
+  core::encryption::{decrypt_jwe_dir, encrypt_jwe_dir, encrypt_jwe_rsa_oaep_256, is_jwe},
+  repository::{tenant::TenantRow, tenant_key::get_tenant_key_by_version},
+};
 
 use super::authorization::ApplicationIdTenantId;
 
@@ -16,6 +20,38 @@ pub const TOKEN_TYPE_ID: &str = "id";
 pub const TOKEN_SUB_TYPE_USER: &str = "user";
 pub const TOKEN_SUB_TYPE_SERVICE_ACCOUNT: &str = "service-account";
 
+/// RFC 8176 authentication method references of a user's login
+pub const AMR_PASSWORD: &str = "pwd";
+pub const AMR_OTP: &str = "otp";
+pub const AMR_MFA: &str = "mfa";
+pub const AMR_FEDERATED: &str = "fed";
+pub const AMR_HARDWARE_KEY: &str = "hwk";
+
+/// Authentication context classes, logins with a second factor have the higher class
+pub const ACR_SINGLE_FACTOR: &str = "1";
+pub const ACR_MULTI_FACTOR: &str = "2";
+
+/// Claims set by the server, tenant claim mappings can not replace them
+pub const RESERVED_CLAIMS: [&str; 17] = [
+  "type",
+  "exp",
+  "iat",
+  "nbf",
+  "iss",
+  "aud",
+  "sub_type",
+  "sub",
+  "app",
+  "scopes",
+  "jti",
+  "sid",
+  "act",
+  "nonce",
+  "auth_time",
+  "amr",
+  "acr",
+];
+
 pub trait Claims: Serialize + DeserializeOwned {
   fn r#type(&self) -> &String;
   fn exp(&self) -> i64;
@@ -35,12 +71,67 @@ pub trait Claims: Serialize + DeserializeOwned {
     header.kid = Some(ApplicationIdTenantId::new_kid(
       tenant.application_id,
       tenant.id,
+      tenant.key_version,
     ));
 
     let key = tenant_encoding_key(tenant, algorithm)?;
 
-    jsonwebtoken::encode(&header, self, &key)
+    let jwt = jsonwebtoken::encode(&header, self, &key)?;
+    encrypt_tenant_jwt(tenant, jwt, self.r#type())
+  }
+}
+
+/// Encrypts a signed token when the tenant encrypts it. ID tokens are read by the client so
+/// they are encrypted to its public key, access and refresh tokens only when the tenant opted in
+/// since resource servers can no longer verify them against the JWKS.
+pub fn encrypt_tenant_jwt(
+  tenant: &TenantRow,
+  jwt: String,
+  r#type: &str,
+) -> Result<String, jsonwebtoken::errors::Error> {
+  if r#type == TOKEN_TYPE_ID {
+    return match &tenant.encryption_public_key {
+      Some(public_key) => encrypt_jwe_rsa_oaep_256(&jwt, public_key, None),
+      None => Ok(jwt),
+    };
+  }
+  if !tenant.encrypts_access_tokens() {
+    return Ok(jwt);
+  }
+  let secret = tenant
+    .encryption_secret
+    .as_deref()
+    .ok_or(ErrorKind::InvalidKeyFormat)?;
+  encrypt_jwe_dir(
+    &jwt,
+    secret,
+    Some(ApplicationIdTenantId::new_kid(
+      tenant.application_id,
+      tenant.id,
+      tenant.key_version,
+    )),
+  )
+}
+
+/// Decrypts a token encrypted by [`encrypt_tenant_jwt`] with the tenant's secret, or the secret
+/// it replaced so tokens issued before a rotation stay readable. Signed tokens are returned as
+/// they are.
+pub fn decrypt_tenant_jwt<'a>(
+  tenant: &TenantRow,
+  jwt: &'a str,
+) -> Result<Cow<'a, str>, jsonwebtoken::errors::Error> {
+  if !is_jwe(jwt) {
+    return Ok(Cow::Borrowed(jwt));
   }
+  [
+    tenant.encryption_secret.as_deref(),
+    tenant.previous_encryption_secret.as_deref(),
+  ]
+  .into_iter()
+  .flatten()
+  .find_map(|secret| decrypt_jwe_dir(jwt, secret).ok())
+  .map(Cow::Owned)
+  .ok_or_else(|| ErrorKind::InvalidToken.into())
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
@@ -56,6 +147,35 @@ pub struct BasicClaims {
   pub sub: i64,
   pub app: i64,
   pub scopes: Vec<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub jti: Option<String>,
+  /// The user session the token belongs to
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub sid: Option<String>,
+  /// When the user logged in
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub auth_time: Option<i64>,
+  /// How the user logged in, see the `AMR_` constants
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub amr: Vec<String>,
+  /// The authentication context class of the login, see the `ACR_` constants
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub acr: Option<String>,
+  /// RFC 8693 actor of a delegated or impersonated token
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub act: Option<ActorClaims>,
+  /// Claims added by the tenant's claim mappings
+  #[serde(flatten)]
+  pub custom: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ActorClaims {
+  pub sub_type: String,
+  pub sub: i64,
+  /// The previous actor when the subject token was already delegated
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub act: Option<Box<ActorClaims>>,
 }
 
 impl Claims for BasicClaims {
@@ -91,25 +211,132 @@ impl Claims for BasicClaims {
   }
 }
 
-pub fn parse_jwt<T>(
+impl BasicClaims {
+  /// Whether the user logged in at most `max_age` seconds ago, tokens without an `auth_time`
+  /// were not issued for a login.
+  pub fn is_authenticated_within(&self, max_age: i64) -> bool {
+    self
+      .auth_time
+      .is_some_and(|auth_time| chrono::Utc::now().timestamp() - auth_time <= max_age)
+  }
+
+  pub fn is_multi_factor(&self) -> bool {
+    self.amr.iter().any(|amr| amr == AMR_MFA)
+  }
+}
+
+/// The authentication context class of a login with the `amr` methods.
+pub fn acr_from_amr(amr: &[String]) -> Option<String> {
+  if amr.is_empty() {
+    None
+  } else if amr.iter().any(|amr| amr == AMR_MFA) {
+    Some(ACR_MULTI_FACTOR.to_owned())
+  } else {
+    Some(ACR_SINGLE_FACTOR.to_owned())
+  }
+}
+
+/// Whether a login of class `acr` satisfies one of the space separated `acr_values` requested
+/// by a client, the multi-factor class also satisfies requests for the single-factor class.
+pub fn acr_satisfies(acr: Option<&str>, acr_values: &str) -> bool {
+  acr_values.split_whitespace().any(|acr_value| {
+    Some(acr_value) == acr || (acr_value == ACR_SINGLE_FACTOR && acr == Some(ACR_MULTI_FACTOR))
+  })
+}
+
+/// Parses and validates a token signed by any of the tenant's verifiable keys, the key is
+/// selected by the version in the `kid` header.
+pub async fn parse_jwt<T>(
+  pool: &sqlx::AnyPool,
+  jwt: &str,
+  tenant: &TenantRow,
+) -> Result<jsonwebtoken::TokenData<T>, jsonwebtoken::errors::Error>
+where
+  T: DeserializeOwned,
+{
+  parse_jwt_with_validation(pool, jwt, tenant, true).await
+}
+
+/// Like [`parse_jwt`] but accepts expired tokens, for hints like the `id_token_hint` of a logout.
+pub async fn parse_jwt_allow_expired<T>(
+  pool: &sqlx::AnyPool,
   jwt: &str,
   tenant: &TenantRow,
 ) -> Result<jsonwebtoken::TokenData<T>, jsonwebtoken::errors::Error>
 where
   T: DeserializeOwned,
 {
+  parse_jwt_with_validation(pool, jwt, tenant, false).await
+}
+
+async fn parse_jwt_with_validation<T>(
+  pool: &sqlx::AnyPool,
+  jwt: &str,
+  tenant: &TenantRow,
+  validate_exp: bool,
+) -> Result<jsonwebtoken::TokenData<T>, jsonwebtoken::errors::Error>
+where
+  T: DeserializeOwned,
+{
+  let jwt = decrypt_tenant_jwt(tenant, jwt)?;
+  let jwt = jwt.as_ref();
+  let key_version = match jsonwebtoken::decode_header(jwt)?.kid.as_deref() {
+    Some(kid) => ApplicationIdTenantId::from_str(kid)
+      .map_err(|_| jsonwebtoken::errors::Error::from(ErrorKind::InvalidToken))?
+      .key_version(),
+    None => tenant.key_version,
+  };
+  let (algorithm, key) = if key_version == tenant.key_version {
+    let algorithm = jsonwebtoken::Algorithm::from_str(&tenant.algorithm)?;
+    (algorithm, tenant_decoding_key(tenant, algorithm)?)
+  } else {
+    let tenant_key = match get_tenant_key_by_version(pool, tenant.id, key_version).await {
+      Ok(Some(tenant_key)) if tenant_key.is_verifiable() => tenant_key,
+      Ok(_) => return Err(ErrorKind::InvalidSignature.into()),
+      Err(e) => {
+        log::error!("error getting tenant key: {}", e);
+        return Err(ErrorKind::InvalidToken.into());
+      }
+    };
+    let algorithm = jsonwebtoken::Algorithm::from_str(&tenant_key.algorithm)?;
+    let key = decoding_key(
+      algorithm,
+      tenant_key.public_key.as_deref(),
+      &tenant_key.private_key,
+    )?;
+    (algorithm, key)
+  };
+  let mut validation = tenant_validation(tenant, algorithm);
+  validation.validate_exp = validate_exp;
+  jsonwebtoken::decode(jwt, &key, &validation)
+}
+
+/// Parses and validates a token signed by the tenant's active key.
+pub fn parse_jwt_with_tenant_key<T>(
+  jwt: &str,
+  tenant: &TenantRow,
+) -> Result<jsonwebtoken::TokenData<T>, jsonwebtoken::errors::Error>
+where
+  T: DeserializeOwned,
+{
+  let jwt = decrypt_tenant_jwt(tenant, jwt)?;
   let algorithm = jsonwebtoken::Algorithm::from_str(&tenant.algorithm)?;
+  let key = tenant_decoding_key(tenant, algorithm)?;
+  jsonwebtoken::decode(&jwt, &key, &tenant_validation(tenant, algorithm))
+}
 
+fn tenant_validation(
+  tenant: &TenantRow,
+  algorithm: jsonwebtoken::Algorithm,
+) -> jsonwebtoken::Validation {
   let mut validation = jsonwebtoken::Validation::new(algorithm);
   validation.validate_nbf = true;
   validation.set_issuer(&[&tenant.issuer]);
-  if let Some(audience) = &tenant.audience {
-    validation.set_audience(&[audience]);
+  let audiences = tenant.audiences();
+  if !audiences.is_empty() {
+    validation.set_audience(&audiences);
   }
-
-  let key = tenant_decoding_key(tenant, algorithm)?;
-
-  jsonwebtoken::decode(jwt, &key, &validation)
+  validation
 }
 
 pub fn parse_jwt_no_validation<T>(
@@ -134,34 +361,68 @@ pub fn tenant_decoding_key(
   tenant: &TenantRow,
   algorithm: jsonwebtoken::Algorithm,
 ) -> Result<jsonwebtoken::DecodingKey, jsonwebtoken::errors::Error> {
+  decoding_key(algorithm, tenant.public_key.as_deref(), &tenant.private_key)
+}
+
+pub fn tenant_encoding_key(
+  tenant: &TenantRow,
+  algorithm: jsonwebtoken::Algorithm,
+) -> Result<jsonwebtoken::EncodingKey, jsonwebtoken::errors::Error> {
+  encoding_key(algorithm, &tenant.private_key)
+}
+
+pub fn decoding_key(
+  algorithm: jsonwebtoken::Algorithm,
+  public_key: Option<&str>,
+  private_key: &str,
+) -> Result<jsonwebtoken::DecodingKey, jsonwebtoken::errors::Error> {
+  if is_hmac_algorithm(algorithm) {
+    return Ok(jsonwebtoken::DecodingKey::from_secret(
+      private_key.as_bytes(),
+    ));
+  }
+  let public_key = public_key.ok_or(ErrorKind::InvalidKeyFormat)?;
   match &algorithm {
-    jsonwebtoken::Algorithm::HS256
-    | jsonwebtoken::Algorithm::HS384
-    | jsonwebtoken::Algorithm::HS512 => Ok(jsonwebtoken::DecodingKey::from_secret(
-      tenant.private_key.as_bytes(),
-    )),
-    _ => {
-      return Err(jsonwebtoken::errors::Error::from(
-        jsonwebtoken::errors::ErrorKind::InvalidAlgorithm,
-      ));
+    jsonwebtoken::Algorithm::ES256 | jsonwebtoken::Algorithm::ES384 => {
+      jsonwebtoken::DecodingKey::from_ec_pem(public_key.as_bytes())
     }
+    jsonwebtoken::Algorithm::EdDSA => jsonwebtoken::DecodingKey::from_ed_pem(public_key.as_bytes()),
+    _ => jsonwebtoken::DecodingKey::from_rsa_pem(public_key.as_bytes()),
   }
 }
 
-pub fn tenant_encoding_key(
-  tenant: &TenantRow,
+pub fn encoding_key(
   algorithm: jsonwebtoken::Algorithm,
+  private_key: &str,
 ) -> Result<jsonwebtoken::EncodingKey, jsonwebtoken::errors::Error> {
   match &algorithm {
     jsonwebtoken::Algorithm::HS256
     | jsonwebtoken::Algorithm::HS384
     | jsonwebtoken::Algorithm::HS512 => Ok(jsonwebtoken::EncodingKey::from_secret(
-      tenant.private_key.as_bytes(),
+      private_key.as_bytes(),
     )),
-    _ => {
-      return Err(jsonwebtoken::errors::Error::from(
-        jsonwebtoken::errors::ErrorKind::InvalidAlgorithm,
-      ));
+    jsonwebtoken::Algorithm::ES256 | jsonwebtoken::Algorithm::ES384 => {
+      jsonwebtoken::EncodingKey::from_ec_pem(private_key.as_bytes())
+    }
+    jsonwebtoken::Algorithm::EdDSA => {
+      jsonwebtoken::EncodingKey::from_ed_pem(private_key.as_bytes())
+    }
+    jsonwebtoken::Algorithm::RS256
+    | jsonwebtoken::Algorithm::RS384
+    | jsonwebtoken::Algorithm::RS512
+    | jsonwebtoken::Algorithm::PS256
+    | jsonwebtoken::Algorithm::PS384
+    | jsonwebtoken::Algorithm::PS512 => {
+      jsonwebtoken::EncodingKey::from_rsa_pem(private_key.as_bytes())
     }
   }
 }
+
+pub fn is_hmac_algorithm(algorithm: jsonwebtoken::Algorithm) -> bool {
+  matches!(
+    algorithm,
+    jsonwebtoken::Algorithm::HS256
+      | jsonwebtoken::Algorithm::HS384
+      | jsonwebtoken::Algorithm::HS512
+  )
+}
@@ -0,0 +1,77 @@
+use std::{
+  convert::Infallible,
+  net::{IpAddr, SocketAddr},
+};
+
+use axum::extract::{ConnectInfo, FromRef, FromRequestParts};
+use http::{header::USER_AGENT, request::Parts, HeaderMap};
+
+use crate::router::RouterState;
+
+pub const X_FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+pub const X_REAL_IP_HEADER: &str = "X-Real-IP";
+
+/// The IP address and user agent of the client making a request, recorded on the sessions it
+/// logs in to.
+#[derive(Clone, Default)]
+pub struct ClientInfo {
+  pub ip_address: Option<String>,
+  pub user_agent: Option<String>,
+}
+
+impl<S> FromRequestParts<S> for ClientInfo
+where
+  RouterState: FromRef<S>,
+  S: Send + Sync,
+{
+  type Rejection = Infallible;
+
+  async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+    let router_state = RouterState::from_ref(state);
+    let trusted_proxies = &router_state.config.server.trusted_proxies;
+
+    let peer_address = parts
+      .extensions
+      .get::<ConnectInfo<SocketAddr>>()
+      .map(|ConnectInfo(address)| address.ip());
+    // forwarding headers are only believed when they were set by one of our own proxies
+    let ip_address = match peer_address {
+      Some(peer_address) if trusted_proxies.contains(&peer_address) => {
+        Some(forwarded_ip_address(&parts.headers, trusted_proxies).unwrap_or(peer_address))
+      }
+      peer_address => peer_address,
+    };
+    let user_agent = header(&parts.headers, USER_AGENT.as_str()).map(ToOwned::to_owned);
+    Ok(Self {
+      ip_address: ip_address.map(|ip_address| ip_address.to_string()),
+      user_agent,
+    })
+  }
+}
+
+/// The client address reported by trusted proxies, the right most X-Forwarded-For address that
+/// is not a trusted proxy itself, or X-Real-IP.
+fn forwarded_ip_address(headers: &HeaderMap, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+  if let Some(forwarded_for) = header(headers, X_FORWARDED_FOR_HEADER) {
+    let client_ip_address = forwarded_for
+      .split(',')
+      .map(|ip_address| ip_address.trim().parse::<IpAddr>())
+      .rev()
+      .find(
+        |ip_address| !matches!(ip_address, Ok(ip_address) if trusted_proxies.contains(ip_address)),
+      )
+      .and_then(Result::ok);
+    if client_ip_address.is_some() {
+      return client_ip_address;
+    }
+  }
+  header(headers, X_REAL_IP_HEADER).and_then(|real_ip| real_ip.parse().ok())
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+  headers
+    .get(name)
+    .and_then(|value| value.to_str().ok())
+    .map(str::trim)
+    .filter(|value| !value.is_empty())
+}
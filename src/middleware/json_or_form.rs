@@ -0,0 +1,48 @@
+use axum::extract::{
+  rejection::{FormRejection, JsonRejection},
+  FromRequest, Request,
+};
+use http::{header::CONTENT_TYPE, HeaderMap};
+
+use crate::core::error::{InternalError, REQUEST_BODY};
+
+pub const FORM_URL_ENCODED_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+
+pub fn is_form_url_encoded(headers: &HeaderMap) -> bool {
+  headers
+    .get(CONTENT_TYPE)
+    .and_then(|content_type| content_type.to_str().ok())
+    .map(|content_type| content_type.starts_with(FORM_URL_ENCODED_CONTENT_TYPE))
+    .unwrap_or(false)
+}
+
+/// Accepts a JSON body, or a form encoded body when the request's content type is
+/// `application/x-www-form-urlencoded` as required by the OAuth2 RFCs.
+pub struct JsonOrForm<T>(pub T);
+
+impl<S, T> FromRequest<S> for JsonOrForm<T>
+where
+  axum::Json<T>: FromRequest<S, Rejection = JsonRejection>,
+  axum::Form<T>: FromRequest<S, Rejection = FormRejection>,
+  S: Send + Sync,
+{
+  type Rejection = InternalError;
+
+  async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+    if is_form_url_encoded(request.headers()) {
+      match axum::Form::<T>::from_request(request, state).await {
+        Ok(axum::Form(value)) => Ok(Self(value)),
+        Err(rejection) => {
+          Err(InternalError::bad_request().with_error(REQUEST_BODY, rejection.to_string()))
+        }
+      }
+    } else {
+      match axum::Json::<T>::from_request(request, state).await {
+        Ok(axum::Json(value)) => Ok(Self(value)),
+        Err(rejection) => {
+          Err(InternalError::bad_request().with_error(REQUEST_BODY, rejection.to_string()))
+        }
+      }
+    }
+  }
+}
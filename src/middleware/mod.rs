@@ -1,6 +1,8 @@
 pub mod authorization;
 pub mod claims;
+pub mod client_info;
 pub mod json;
+pub mod json_or_form;
 pub mod openid_claims;
 pub mod service_account_authorization;
 pub mod tenant_id;
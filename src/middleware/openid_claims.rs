@@ -1,5 +1,6 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use super::claims::{BasicClaims, Claims};
 
@@ -9,7 +10,29 @@ pub const SCOPE_EMAIL: &str = "email";
 pub const SCOPE_PHONE: &str = "phone";
 pub const SCOPE_ADDRESS: &str = "address";
 
-#[derive(Serialize, Deserialize, Default, Clone)]
+/// The claims of [`OpenIdProfile`] and the nonce, which are never custom claims of an id token
+const OPENID_CLAIMS: [&str; 18] = [
+  "name",
+  "given_name",
+  "family_name",
+  "middle_name",
+  "nickname",
+  "preferred_username",
+  "profile_picture",
+  "website",
+  "email",
+  "email_verified",
+  "gender",
+  "birthdate",
+  "zone_info",
+  "locale",
+  "phone_number",
+  "phone_number_verified",
+  "address",
+  "nonce",
+];
+
+#[derive(Serialize, Deserialize, Default, Clone, ToSchema)]
 pub struct OpenIdProfile {
   #[serde(skip_serializing_if = "Option::is_none")]
   pub name: Option<String>,
@@ -48,11 +71,39 @@ pub struct OpenIdProfile {
 }
 
 #[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(from = "FlattenedOpenIdClaims")]
 pub struct OpenIdClaims {
   #[serde(flatten)]
   pub claims: BasicClaims,
   #[serde(flatten)]
   pub profile: OpenIdProfile,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub nonce: Option<String>,
+}
+
+/// Deserialized form of [`OpenIdClaims`], the flattened custom claims of [`BasicClaims`] also
+/// receive the profile claims and the nonce.
+#[derive(Deserialize)]
+struct FlattenedOpenIdClaims {
+  #[serde(flatten)]
+  claims: BasicClaims,
+  #[serde(flatten)]
+  profile: OpenIdProfile,
+  nonce: Option<String>,
+}
+
+impl From<FlattenedOpenIdClaims> for OpenIdClaims {
+  fn from(flattened: FlattenedOpenIdClaims) -> Self {
+    let mut claims = flattened.claims;
+    claims
+      .custom
+      .retain(|claim, _| !OPENID_CLAIMS.contains(&claim.as_str()));
+    Self {
+      claims,
+      profile: flattened.profile,
+      nonce: flattened.nonce,
+    }
+  }
 }
 
 unsafe impl Send for OpenIdClaims {}
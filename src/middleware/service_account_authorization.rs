@@ -1,13 +1,14 @@
 use axum::extract::{FromRef, FromRequestParts};
-use http::request::Parts;
+use http::{request::Parts, StatusCode};
 
 use super::{
   authorization::Authorization,
   claims::{TOKEN_SUB_TYPE_SERVICE_ACCOUNT, TOKEN_TYPE_BEARER},
+  openid_claims::{SCOPE_ADDRESS, SCOPE_EMAIL, SCOPE_OPENID, SCOPE_PHONE, SCOPE_PROFILE},
 };
 use crate::{
   core::{
-    error::{InternalError, INVALID_ERROR},
+    error::{InternalError, INVALID_ERROR, NOT_ALLOWED_ERROR},
     openapi::AUTHORIZATION_HEADER,
   },
   repository::{
@@ -17,6 +18,44 @@ use crate::{
   router::RouterState,
 };
 
+pub const SCOPE_APPLICATIONS_READ: &str = "applications:read";
+pub const SCOPE_APPLICATIONS_WRITE: &str = "applications:write";
+pub const SCOPE_TENANTS_READ: &str = "tenants:read";
+/// Creating, updating and deleting tenants, their signing keys, OAuth2 providers and clients
+pub const SCOPE_TENANTS_ADMIN: &str = "tenants:admin";
+pub const SCOPE_USERS_READ: &str = "users:read";
+pub const SCOPE_USERS_WRITE: &str = "users:write";
+/// Exchanging the service account's token for a token of a user
+pub const SCOPE_USERS_IMPERSONATE: &str = "users:impersonate";
+pub const SCOPE_SERVICE_ACCOUNTS_READ: &str = "service-accounts:read";
+pub const SCOPE_SERVICE_ACCOUNTS_WRITE: &str = "service-accounts:write";
+/// Signing arbitrary claims with `POST /jwt`
+pub const SCOPE_TOKENS_SIGN: &str = "tokens:sign";
+pub const SCOPE_TOKENS_INTROSPECT: &str = "tokens:introspect";
+
+pub const SERVICE_ACCOUNT_SCOPES: [&str; 11] = [
+  SCOPE_APPLICATIONS_READ,
+  SCOPE_APPLICATIONS_WRITE,
+  SCOPE_TENANTS_READ,
+  SCOPE_TENANTS_ADMIN,
+  SCOPE_USERS_READ,
+  SCOPE_USERS_WRITE,
+  SCOPE_USERS_IMPERSONATE,
+  SCOPE_SERVICE_ACCOUNTS_READ,
+  SCOPE_SERVICE_ACCOUNTS_WRITE,
+  SCOPE_TOKENS_SIGN,
+  SCOPE_TOKENS_INTROSPECT,
+];
+
+/// The user scopes a service account can be granted for the tokens of the users it impersonates
+pub const IMPERSONATION_SCOPES: [&str; 5] = [
+  SCOPE_OPENID,
+  SCOPE_PROFILE,
+  SCOPE_EMAIL,
+  SCOPE_PHONE,
+  SCOPE_ADDRESS,
+];
+
 pub struct ServiceAccountAuthorization {
   pub service_account: ServiceAccountRow,
   pub tenant: TenantRow,
@@ -56,22 +95,35 @@ where
             InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR),
           );
         }
-        return Ok(Self {
+        Ok(Self {
           service_account,
           tenant: authorization.tenant,
           scopes: authorization.claims.scopes,
-        });
+        })
       }
       Ok(None) => {
-        return Err(InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR));
+        Err(InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR))
       }
       Err(e) => {
         log::error!(
           "invalid authorization service_account not found for sub: {}",
           e
         );
-        return Err(InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR));
+        Err(InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR))
       }
     }
   }
 }
+
+/// Fails unless the token was granted `scope` and the service account is still allowed it.
+pub fn require_service_account_scope(
+  service_account: &ServiceAccountRow,
+  scopes: &[String],
+  scope: &str,
+) -> Result<(), InternalError> {
+  if service_account.allows_scope(scope) && scopes.iter().any(|granted| granted == scope) {
+    Ok(())
+  } else {
+    Err(InternalError::from(StatusCode::FORBIDDEN).with_error(scope, NOT_ALLOWED_ERROR))
+  }
+}
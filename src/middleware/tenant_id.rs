@@ -10,6 +10,8 @@ use crate::{
   router::RouterState,
 };
 
+pub const CLIENT_ID_QUERY_PARAM: &str = "client_id";
+
 pub struct TenantId(pub TenantRow);
 
 impl<S> FromRequestParts<S> for TenantId
@@ -24,25 +26,11 @@ where
 
     if let Some(id_header_value) = parts.headers.get(TENENT_ID_HEADER) {
       match id_header_value.to_str() {
-        Ok(id_string) => match id_string.parse::<uuid::Uuid>() {
-          Ok(client_id) => {
-            match get_tenant_by_client_id(&router_state.pool, &client_id.to_string()).await {
-              Ok(Some(tenant)) => Ok(TenantId(tenant)),
-              Ok(None) => {
-                log::error!("invalid tenant id: {}", id_string);
-                Err(InternalError::bad_request().with_error(TENENT_ID_HEADER, INVALID_ERROR))
-              }
-              Err(e) => {
-                log::error!("invalid tenant id: {}", e);
-                Err(InternalError::bad_request().with_error(TENENT_ID_HEADER, INVALID_ERROR))
-              }
-            }
-          }
-          Err(e) => {
-            log::error!("invalid tenant id: {}", e);
-            Err(InternalError::bad_request().with_error(TENENT_ID_HEADER, INVALID_ERROR))
-          }
-        },
+        Ok(id_string) => {
+          get_tenant_by_client_id_string(&router_state.pool, TENENT_ID_HEADER, id_string)
+            .await
+            .map(TenantId)
+        }
         Err(e) => {
           log::error!("invalid tenant id: {}", e);
           Err(InternalError::bad_request().with_error(TENENT_ID_HEADER, PARSE_ERROR))
@@ -54,3 +42,64 @@ where
     }
   }
 }
+
+/// Tenant from the Tenant-ID header or, when the header is missing, the `client_id` query
+/// parameter, for public endpoints that clients fetch without custom headers.
+pub struct TenantIdOrClientId(pub TenantRow);
+
+impl<S> FromRequestParts<S> for TenantIdOrClientId
+where
+  RouterState: FromRef<S>,
+  S: Send + Sync,
+{
+  type Rejection = InternalError;
+
+  async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+    if parts.headers.contains_key(TENENT_ID_HEADER) {
+      let TenantId(tenant) = TenantId::from_request_parts(parts, state).await?;
+      return Ok(Self(tenant));
+    }
+    let router_state = RouterState::from_ref(state);
+
+    let client_id = parts.uri.query().and_then(|query| {
+      form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == CLIENT_ID_QUERY_PARAM)
+        .map(|(_, value)| value.into_owned())
+    });
+    match client_id {
+      Some(client_id) => {
+        get_tenant_by_client_id_string(&router_state.pool, CLIENT_ID_QUERY_PARAM, &client_id)
+          .await
+          .map(Self)
+      }
+      None => {
+        log::error!("missing tenant id or client id");
+        Err(InternalError::bad_request().with_error(TENENT_ID_HEADER, REQUIRED_ERROR))
+      }
+    }
+  }
+}
+
+async fn get_tenant_by_client_id_string(
+  pool: &sqlx::AnyPool,
+  name: &str,
+  id_string: &str,
+) -> Result<TenantRow, InternalError> {
+  match id_string.parse::<uuid::Uuid>() {
+    Ok(client_id) => match get_tenant_by_client_id(pool, &client_id.to_string()).await {
+      Ok(Some(tenant)) => Ok(tenant),
+      Ok(None) => {
+        log::error!("invalid tenant id: {}", id_string);
+        Err(InternalError::bad_request().with_error(name, INVALID_ERROR))
+      }
+      Err(e) => {
+        log::error!("invalid tenant id: {}", e);
+        Err(InternalError::bad_request().with_error(name, INVALID_ERROR))
+      }
+    },
+    Err(e) => {
+      log::error!("invalid tenant id: {}", e);
+      Err(InternalError::bad_request().with_error(name, INVALID_ERROR))
+    }
+  }
+}
@@ -1,18 +1,23 @@
+use std::collections::HashMap;
+
 use axum::extract::{FromRef, FromRequestParts};
 use http::request::Parts;
+use serde_json::json;
 
 use super::{
   authorization::Authorization,
-  claims::{TOKEN_SUB_TYPE_USER, TOKEN_TYPE_BEARER},
+  claims::{BasicClaims, ACR_MULTI_FACTOR, TOKEN_SUB_TYPE_USER, TOKEN_TYPE_BEARER},
 };
 use crate::{
   core::{
-    error::{InternalError, INVALID_ERROR},
+    config::Config,
+    error::{InternalError, INSUFFICIENT_AUTHENTICATION_ERROR, INTERNAL_ERROR, INVALID_ERROR},
     openapi::AUTHORIZATION_HEADER,
   },
   repository::{
     tenant::TenantRow,
     user::{get_user_by_id, UserRow},
+    user_config::get_user_config_by_user_id,
   },
   router::RouterState,
 };
@@ -20,7 +25,7 @@ use crate::{
 pub struct UserAuthorization {
   pub user: UserRow,
   pub tenant: TenantRow,
-  pub scopes: Vec<String>,
+  pub claims: BasicClaims,
 }
 
 impl<S> FromRequestParts<S> for UserAuthorization
@@ -56,19 +61,51 @@ where
             InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR),
           );
         }
-        return Ok(Self {
+        Ok(Self {
           user,
           tenant: authorization.tenant,
-          scopes: authorization.claims.scopes,
-        });
+          claims: authorization.claims,
+        })
       }
       Ok(None) => {
-        return Err(InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR));
+        Err(InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR))
       }
       Err(e) => {
         log::error!("invalid authorization user not found for sub: {}", e);
-        return Err(InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR));
+        Err(InternalError::unauthorized().with_error(AUTHORIZATION_HEADER, INVALID_ERROR))
       }
     }
   }
 }
+
+/// Step-up authentication for sensitive operations, the user's token must come from a login
+/// within `user.step_up_max_age_in_seconds` that passed multi-factor authentication when the
+/// user has it enabled. Otherwise the user has to log in again, the error's parameters tell
+/// clients the `max_age` and `acr_values` to log in with.
+pub async fn require_step_up(
+  pool: &sqlx::AnyPool,
+  config: &Config,
+  claims: &BasicClaims,
+) -> Result<(), InternalError> {
+  let max_age = config.user.step_up_max_age_in_seconds;
+  let requires_mfa = match get_user_config_by_user_id(pool, claims.sub).await {
+    Ok(user_config) => user_config
+      .and_then(|user_config| user_config.mfa_type)
+      .is_some_and(|mfa_type| mfa_type != "none"),
+    Err(e) => {
+      log::error!("error getting user config: {}", e);
+      return Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR));
+    }
+  };
+  if claims.is_authenticated_within(max_age) && (!requires_mfa || claims.is_multi_factor()) {
+    return Ok(());
+  }
+  let mut parameters = HashMap::from([("max_age".to_owned(), json!(max_age))]);
+  if requires_mfa {
+    parameters.insert("acr_values".to_owned(), json!(ACR_MULTI_FACTOR));
+  }
+  Err(InternalError::unauthorized().with_error(
+    AUTHORIZATION_HEADER,
+    (INSUFFICIENT_AUTHENTICATION_ERROR, parameters),
+  ))
+}
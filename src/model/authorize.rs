@@ -0,0 +1,107 @@
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::{IntoParams, ToSchema};
+
+pub const RESPONSE_TYPE_CODE: &str = "code";
+
+pub const CODE_CHALLENGE_METHOD_S256: &str = "S256";
+
+/// The browser session of the authorize endpoint, holds a random secret that is never put in a
+/// token, only its hash is stored
+pub const AUTHORIZE_SESSION_COOKIE: &str = "authorize_session";
+
+#[derive(Deserialize, IntoParams)]
+pub struct AuthorizeQuery {
+  #[param(example = "code")]
+  pub response_type: String,
+  /// The registered OAuth2 client's client id
+  pub client_id: String,
+  /// Must be one of the client's redirect uris, required when it has more than one
+  pub redirect_uri: Option<String>,
+  #[param(example = "openid")]
+  pub scope: Option<String>,
+  pub state: Option<String>,
+  /// Returned as the `nonce` claim of the id token
+  pub nonce: Option<String>,
+  /// PKCE code challenge, required for public clients
+  pub code_challenge: Option<String>,
+  /// Only `S256` is supported, required with a code challenge
+  #[param(example = "S256")]
+  pub code_challenge_method: Option<String>,
+  /// The oldest login in seconds the client accepts, older logins must log in again
+  pub max_age: Option<i64>,
+  /// Space separated authentication context classes the client accepts, `2` requires a login
+  /// with a second factor
+  #[param(example = "2")]
+  pub acr_values: Option<String>,
+}
+
+/// Sent by the login page once the user logged in, starts the browser session used by the
+/// authorize endpoint
+#[derive(Deserialize, ToSchema)]
+pub struct AuthorizeSessionRequest {
+  /// An access token of the user's login, its session becomes the browser session
+  pub access_token: String,
+  /// The authorize request to continue, must be a url of the authorize endpoint
+  pub return_to: String,
+}
+
+/// Stored behind the hash of a browser session cookie's secret
+#[derive(Serialize, Deserialize)]
+pub struct AuthorizeSession {
+  /// The user session the browser logged in with
+  pub sid: String,
+}
+
+impl AuthorizeSession {
+  pub fn kv_key(secret: &str) -> String {
+    format!(
+      "authorize-session:{}",
+      BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(secret.as_bytes()))
+    )
+  }
+}
+
+/// Stored behind a single-use authorization code until it is redeemed at the token endpoint
+#[derive(Serialize, Deserialize)]
+pub struct AuthorizationCodeGrant {
+  pub application_id: i64,
+  pub tenant_id: i64,
+  pub oauth2_client_id: i64,
+  pub user_id: i64,
+  /// The redirect uri sent to the authorize endpoint, the token request must send the same one
+  pub redirect_uri: Option<String>,
+  pub scope: Option<String>,
+  pub nonce: Option<String>,
+  /// S256 PKCE code challenge
+  pub code_challenge: Option<String>,
+  /// How the user logged in
+  #[serde(default)]
+  pub amr: Vec<String>,
+  /// When the user logged in
+  #[serde(default)]
+  pub auth_time: Option<i64>,
+}
+
+impl AuthorizationCodeGrant {
+  pub fn kv_key(code: &str) -> String {
+    format!("authorization-code:{code}")
+  }
+
+  /// Verifies the PKCE `code_verifier`, grants without a code challenge need no verifier.
+  pub fn verify_code_verifier(&self, code_verifier: Option<&str>) -> bool {
+    let Some(code_challenge) = self.code_challenge.as_deref() else {
+      return true;
+    };
+    // RFC 7636 verifiers are 43 to 128 characters long, anything else can never match
+    let Some(code_verifier) = code_verifier.filter(|v| (43..=128).contains(&v.len())) else {
+      return false;
+    };
+    oauth2::PkceCodeChallenge::from_code_verifier_sha256(&oauth2::PkceCodeVerifier::new(
+      code_verifier.to_owned(),
+    ))
+    .as_str()
+      == code_challenge
+  }
+}
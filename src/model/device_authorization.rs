@@ -0,0 +1,106 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// User code characters without vowels or easily confused characters, RFC 8628 section 6.1
+const USER_CODE_CHARACTERS: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+const USER_CODE_LENGTH: usize = 8;
+
+#[derive(Deserialize, ToSchema)]
+pub struct DeviceAuthorizationRequest {
+  /// The registered OAuth2 client's client id
+  pub client_id: String,
+  /// Required for confidential clients
+  pub client_secret: Option<String>,
+  #[schema(example = "openid")]
+  pub scope: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeviceAuthorization {
+  pub device_code: String,
+  #[schema(example = "WDJB-MJHT")]
+  pub user_code: String,
+  pub verification_uri: String,
+  pub verification_uri_complete: String,
+  pub expires_in: i64,
+  /// Seconds the device must wait between token requests
+  pub interval: i64,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct PendingDeviceAuthorizationQuery {
+  #[param(example = "WDJB-MJHT")]
+  pub user_code: String,
+}
+
+/// What the user is asked to approve for a user code
+#[derive(Serialize, ToSchema)]
+pub struct PendingDeviceAuthorization {
+  /// The requesting OAuth2 client's client id
+  pub client_id: String,
+  pub client_name: String,
+  pub scopes: Vec<String>,
+  pub expires_in: i64,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ApproveDeviceAuthorization {
+  #[schema(example = "WDJB-MJHT")]
+  pub user_code: String,
+  /// Denies the device instead of approving it
+  pub deny: Option<bool>,
+}
+
+/// Stored behind a device code until the device redeems it at the token endpoint
+#[derive(Serialize, Deserialize)]
+pub struct DeviceCodeGrant {
+  pub application_id: i64,
+  pub tenant_id: i64,
+  pub oauth2_client_id: i64,
+  pub scope: Option<String>,
+  pub expires_at: i64,
+  /// Set once a user approves the user code
+  pub user_id: Option<i64>,
+  pub denied: bool,
+  /// How the approving user logged in
+  #[serde(default)]
+  pub amr: Vec<String>,
+  /// When the approving user logged in
+  #[serde(default)]
+  pub auth_time: Option<i64>,
+}
+
+impl DeviceCodeGrant {
+  pub fn kv_key(device_code: &str) -> String {
+    format!("device-code:{device_code}")
+  }
+
+  /// The time of the device's last token request, kept apart from the grant so polling never
+  /// overwrites an approval.
+  pub fn polled_at_kv_key(device_code: &str) -> String {
+    format!("device-code-polled-at:{device_code}")
+  }
+
+  /// The device code of a user code, users may type the code in any case and without the dash.
+  pub fn user_code_kv_key(user_code: &str) -> String {
+    let user_code = user_code
+      .chars()
+      .filter(char::is_ascii_alphanumeric)
+      .map(|c| c.to_ascii_uppercase())
+      .collect::<String>();
+    format!("device-user-code:{user_code}")
+  }
+
+  pub fn new_user_code() -> String {
+    let mut rng = rand::rng();
+    let mut user_code = String::with_capacity(USER_CODE_LENGTH + 1);
+    for i in 0..USER_CODE_LENGTH {
+      if i == USER_CODE_LENGTH / 2 {
+        user_code.push('-');
+      }
+      user_code.push(USER_CODE_CHARACTERS[rng.random_range(0..USER_CODE_CHARACTERS.len())] as char);
+    }
+    user_code
+  }
+}
@@ -0,0 +1,78 @@
+use std::{collections::HashMap, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+  middleware::{authorization::ApplicationIdTenantId, claims::tenant_encoding_key},
+  repository::tenant::TenantRow,
+};
+
+pub const BACKCHANNEL_LOGOUT_EVENT: &str = "http://schemas.openid.net/event/backchannel-logout";
+pub const LOGOUT_TOKEN_TYPE: &str = "logout+jwt";
+/// Logout tokens are delivered right away, so they are short lived
+pub const LOGOUT_TOKEN_EXPIRES_IN_SECONDS: i64 = 120;
+
+/// OpenID Connect RP-Initiated Logout request
+#[derive(Deserialize, IntoParams, ToSchema)]
+pub struct EndSessionRequest {
+  /// An id token issued to the user, it may have expired. An id token encrypted to the client
+  /// is sent decrypted
+  pub id_token_hint: String,
+  /// Must be one of the client's post logout redirect uris, requires the `client_id`
+  pub post_logout_redirect_uri: Option<String>,
+  /// The registered OAuth2 client's client id
+  pub client_id: Option<String>,
+  /// Returned to the `post_logout_redirect_uri`
+  pub state: Option<String>,
+}
+
+/// OpenID Connect Back-Channel Logout token, sent to the client's back-channel logout uri for
+/// each of the user's ended sessions.
+#[derive(Serialize, Deserialize)]
+pub struct LogoutClaims {
+  pub iss: String,
+  pub sub: String,
+  /// The client id of the notified client
+  pub aud: String,
+  pub iat: i64,
+  pub exp: i64,
+  pub jti: String,
+  pub sid: String,
+  pub events: HashMap<String, serde_json::Value>,
+}
+
+impl LogoutClaims {
+  pub fn new(tenant: &TenantRow, sub: i64, client_id: String, sid: String) -> Self {
+    let iat = chrono::Utc::now().timestamp();
+    Self {
+      iss: tenant.issuer.clone(),
+      sub: sub.to_string(),
+      aud: client_id,
+      iat,
+      exp: iat + LOGOUT_TOKEN_EXPIRES_IN_SECONDS,
+      jti: uuid::Uuid::new_v4().to_string(),
+      sid,
+      events: HashMap::from([(
+        BACKCHANNEL_LOGOUT_EVENT.to_owned(),
+        serde_json::Value::Object(serde_json::Map::new()),
+      )]),
+    }
+  }
+
+  pub fn encode(&self, tenant: &TenantRow) -> Result<String, jsonwebtoken::errors::Error> {
+    let algorithm = jsonwebtoken::Algorithm::from_str(&tenant.algorithm)?;
+
+    let mut header = jsonwebtoken::Header::new(algorithm);
+    header.typ = Some(LOGOUT_TOKEN_TYPE.to_owned());
+    header.kid = Some(ApplicationIdTenantId::new_kid(
+      tenant.application_id,
+      tenant.id,
+      tenant.key_version,
+    ));
+
+    let key = tenant_encoding_key(tenant, algorithm)?;
+
+    jsonwebtoken::encode(&header, self, &key)
+  }
+}
@@ -0,0 +1,49 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::repository::user_hotp::UserHOTPRow;
+
+#[derive(Serialize, ToSchema)]
+pub struct UserHOTP {
+  pub algorithm: String,
+  pub digits: i64,
+  pub counter: i64,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<UserHOTPRow> for UserHOTP {
+  fn from(row: UserHOTPRow) -> Self {
+    Self {
+      algorithm: row.algorithm,
+      digits: row.digits,
+      counter: row.counter,
+      updated_at: DateTime::<Utc>::from_timestamp(row.updated_at, 0).unwrap_or_default(),
+      created_at: DateTime::<Utc>::from_timestamp(row.created_at, 0).unwrap_or_default(),
+    }
+  }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ServiceAccountCreateUserHOTP {
+  /// The token's seed, base32 encoded, at least 128 bits
+  #[schema(example = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ")]
+  pub secret: String,
+  #[schema(example = "SHA1")]
+  pub algorithm: Option<String>,
+  #[schema(example = "6")]
+  pub digits: Option<i64>,
+  /// The counter of the token's next code
+  #[schema(example = "0")]
+  pub counter: Option<i64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ServiceAccountResyncUserHOTP {
+  #[schema(example = "755224")]
+  pub code: String,
+  /// The code the token produces right after `code`
+  #[schema(example = "287082")]
+  pub next_code: String,
+}
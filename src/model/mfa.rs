@@ -1,13 +1,59 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+use super::webauthn::WebAuthnAssertion;
+
+/// How long a mailed or texted one-time code can be used
+pub const OTP_EXPIRES_IN_SECONDS: i64 = 60 * 10;
+/// Wrong codes allowed before the challenge is discarded and a new login is required, TOTP, HOTP
+/// and recovery codes are instead refused for the rest of the [`OTP_EXPIRES_IN_SECONDS`] window
+pub const OTP_MAX_ATTEMPTS: u32 = 5;
+
 #[derive(Deserialize, ToSchema)]
 #[serde(tag = "type")]
 pub enum MFARequest {
   #[serde(rename = "totp")]
   #[schema(title = "MFARequestTOTP")]
   TOTP { code: String },
+  #[serde(rename = "hotp")]
+  #[schema(title = "MFARequestHOTP")]
+  HOTP { code: String },
+  #[serde(rename = "email")]
+  #[schema(title = "MFARequestEmail")]
+  Email { code: String },
+  #[serde(rename = "text")]
+  #[schema(title = "MFARequestText")]
+  Text { code: String },
+  #[serde(rename = "webauthn")]
+  #[schema(title = "MFARequestWebAuthn")]
+  WebAuthn(WebAuthnAssertion),
+  /// One of the user's recovery codes, accepted in place of any MFA type
+  #[serde(rename = "recovery-code")]
+  #[schema(title = "MFARequestRecoveryCode")]
+  RecoveryCode { code: String },
   #[serde(rename = "service-account")]
   #[schema(title = "MFARequestServiceAccount")]
   ServiceAccount { code: String },
 }
+
+/// The one-time code mailed or texted for an `mfa-email` or `mfa-text` token, stored under the
+/// token's `jti`
+#[derive(Serialize, Deserialize)]
+pub struct OTPChallenge {
+  pub user_id: i64,
+  pub code: String,
+  pub attempts: u32,
+  pub expires_at: i64,
+}
+
+impl OTPChallenge {
+  pub fn kv_key(mfa_type: &str, jti: &str) -> String {
+    format!("mfa-{mfa_type}:{jti}")
+  }
+}
+
+/// Attempts at a user's TOTP, HOTP or recovery code within the [`OTP_EXPIRES_IN_SECONDS`]
+/// window, codes do not change between logins so they are counted per user rather than per token
+pub fn mfa_attempts_kv_key(mfa_type: &str, user_id: i64) -> String {
+  format!("mfa-{mfa_type}-attempts:{user_id}")
+}
@@ -1,12 +1,23 @@
 pub mod application;
+pub mod authorize;
 pub mod current_user;
+pub mod device_authorization;
+pub mod end_session;
+pub mod hotp;
 pub mod mfa;
 pub mod oauth2;
+pub mod oauth2_client;
+pub mod recovery_code;
 pub mod register;
 pub mod service_account;
 pub mod tenant;
+pub mod tenant_claim_mapping;
+pub mod tenant_key;
 pub mod tenant_oauth2_provider;
 pub mod token;
 pub mod totp;
 pub mod user;
+pub mod userinfo;
 pub mod util;
+pub mod webauthn;
+pub mod well_known;
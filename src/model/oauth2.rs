@@ -63,6 +63,7 @@ impl OAuth2State {
     header.kid = Some(ApplicationIdTenantId::new_kid(
       tenant.application_id,
       tenant.id,
+      tenant.key_version,
     ));
 
     let key = tenant_encoding_key(tenant, algorithm)?;
@@ -0,0 +1,81 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::repository::oauth2_client::OAuth2ClientRow;
+
+#[derive(Serialize, ToSchema)]
+pub struct OAuth2Client {
+  pub id: i64,
+  pub tenant_id: i64,
+  pub client_id: uuid::Uuid,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub client_secret: Option<uuid::Uuid>,
+  pub name: String,
+  pub redirect_uris: Vec<String>,
+  pub post_logout_redirect_uris: Vec<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub backchannel_logout_uri: Option<String>,
+  pub scopes: Vec<String>,
+  pub public: bool,
+  pub active: bool,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<OAuth2ClientRow> for OAuth2Client {
+  fn from(row: OAuth2ClientRow) -> Self {
+    let active = row.is_active();
+    let public = row.is_public();
+    let redirect_uris = row.redirect_uris().map(ToOwned::to_owned).collect();
+    let post_logout_redirect_uris = row
+      .post_logout_redirect_uris()
+      .map(ToOwned::to_owned)
+      .collect();
+    let backchannel_logout_uri = row.backchannel_logout_uri().map(ToOwned::to_owned);
+    let scopes = row.scopes().map(ToOwned::to_owned).collect();
+    Self {
+      id: row.id,
+      tenant_id: row.tenant_id,
+      client_id: uuid::Uuid::parse_str(&row.client_id).unwrap_or_default(),
+      client_secret: None,
+      name: row.name,
+      redirect_uris,
+      post_logout_redirect_uris,
+      backchannel_logout_uri,
+      scopes,
+      public,
+      active,
+      updated_at: DateTime::<Utc>::from_timestamp(row.updated_at, 0).unwrap_or_default(),
+      created_at: DateTime::<Utc>::from_timestamp(row.created_at, 0).unwrap_or_default(),
+    }
+  }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateOAuth2Client {
+  pub name: String,
+  pub redirect_uris: Vec<String>,
+  /// Allowed `post_logout_redirect_uri`s of the end session endpoint
+  pub post_logout_redirect_uris: Option<Vec<String>>,
+  /// Receives OpenID Connect Back-Channel Logout tokens when the user signs out
+  pub backchannel_logout_uri: Option<String>,
+  /// Scopes the client may request, the client may not request any scope when omitted
+  pub scopes: Option<Vec<String>>,
+  pub client_id: Option<uuid::Uuid>,
+  pub client_secret: Option<uuid::Uuid>,
+  /// Public clients, like single page and mobile apps, have no client secret and must use PKCE
+  pub public: Option<bool>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateOAuth2Client {
+  pub name: Option<String>,
+  pub redirect_uris: Option<Vec<String>>,
+  pub post_logout_redirect_uris: Option<Vec<String>>,
+  /// An empty uri removes the back-channel logout uri
+  pub backchannel_logout_uri: Option<String>,
+  pub scopes: Option<Vec<String>>,
+  pub client_secret: Option<uuid::Uuid>,
+  pub active: Option<bool>,
+}
@@ -0,0 +1,64 @@
+use rand::Rng;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// How many recovery codes are generated at a time
+pub const RECOVERY_CODE_COUNT: usize = 10;
+/// Lowercase letters and digits without the easily confused `0`, `1`, `i`, `l` and `o`
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+const RECOVERY_CODE_LENGTH: usize = 10;
+/// Leading characters of a code stored in plain text next to its hash, a user's codes have
+/// distinct lookups so checking a code costs a single hash
+const RECOVERY_CODE_LOOKUP_LENGTH: usize = 2;
+/// Counts wrong recovery codes like the other MFA types
+pub const RECOVERY_CODE_MFA_TYPE: &str = "recovery-code";
+
+#[derive(Serialize, ToSchema)]
+pub struct RecoveryCodes {
+  /// Shown only once, each code can be used once in place of the user's MFA type
+  #[schema(example = json!(["k7mqa-3hx9d"]))]
+  pub codes: Vec<String>,
+}
+
+/// Generates a code like `k7mqa-3hx9d`
+pub fn generate_recovery_code() -> String {
+  let mut rng = rand::rng();
+  let code = (0..RECOVERY_CODE_LENGTH)
+    .map(|_| RECOVERY_CODE_ALPHABET[rng.random_range(0..RECOVERY_CODE_ALPHABET.len())] as char)
+    .collect::<String>();
+  format!(
+    "{}-{}",
+    &code[..RECOVERY_CODE_LENGTH / 2],
+    &code[RECOVERY_CODE_LENGTH / 2..]
+  )
+}
+
+/// Generates [`RECOVERY_CODE_COUNT`] codes with distinct lookups
+pub fn generate_recovery_codes() -> Vec<String> {
+  let mut codes = Vec::<String>::with_capacity(RECOVERY_CODE_COUNT);
+  while codes.len() < RECOVERY_CODE_COUNT {
+    let code = generate_recovery_code();
+    let lookup = recovery_code_lookup(&normalize_recovery_code(&code));
+    if !codes
+      .iter()
+      .any(|other| recovery_code_lookup(&normalize_recovery_code(other)) == lookup)
+    {
+      codes.push(code);
+    }
+  }
+  codes
+}
+
+/// The lookup of a normalized code
+pub fn recovery_code_lookup(code: &str) -> String {
+  code.chars().take(RECOVERY_CODE_LOOKUP_LENGTH).collect()
+}
+
+/// Lowercases the code and strips the separator and whitespace, so codes can be typed loosely
+pub fn normalize_recovery_code(code: &str) -> String {
+  code
+    .chars()
+    .filter(|c| *c != '-' && !c.is_whitespace())
+    .flat_map(char::to_lowercase)
+    .collect()
+}
@@ -13,6 +13,7 @@ pub struct ServiceAccount {
   pub name: String,
   pub active: bool,
   pub admin: bool,
+  pub scopes: Vec<String>,
   pub updated_at: DateTime<Utc>,
   pub created_at: DateTime<Utc>,
 }
@@ -21,6 +22,7 @@ impl From<ServiceAccountRow> for ServiceAccount {
   fn from(row: ServiceAccountRow) -> Self {
     let active = row.is_active();
     let admin = row.is_admin();
+    let scopes = row.scopes().map(ToOwned::to_owned).collect();
     Self {
       id: row.id,
       client_id: uuid::Uuid::parse_str(&row.client_id).unwrap_or_default(),
@@ -28,6 +30,7 @@ impl From<ServiceAccountRow> for ServiceAccount {
       client_secret: None,
       active,
       admin,
+      scopes,
       updated_at: DateTime::<Utc>::from_timestamp(row.updated_at, 0).unwrap_or_default(),
       created_at: DateTime::<Utc>::from_timestamp(row.created_at, 0).unwrap_or_default(),
     }
@@ -46,6 +49,10 @@ pub struct CreateServiceAccount {
   pub client_id: Option<uuid::Uuid>,
   pub client_secret: Option<uuid::Uuid>,
   pub admin: Option<bool>,
+  /// Service account scopes and the OpenID scopes of the users it may impersonate, defaults to
+  /// the scopes of the token creating the service account
+  #[schema(example = json!(["users:read"]))]
+  pub scopes: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -55,4 +62,5 @@ pub struct UpdateServiceAccount {
   pub client_secret: Option<uuid::Uuid>,
   pub admin: Option<bool>,
   pub active: Option<bool>,
+  pub scopes: Option<Vec<String>>,
 }
@@ -1,11 +1,15 @@
 use std::{convert::Infallible, fmt, str::FromStr};
 
-use base64::{prelude::BASE64_STANDARD, Engine};
 use chrono::{DateTime, Utc};
+use jsonwebtoken::errors::ErrorKind;
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
 
-use crate::{core::encryption::random_bytes, repository::tenant::TenantRow};
+use crate::{
+  core::encryption::{generate_key_pair, key_pair_from_private_key, public_key_pem},
+  middleware::claims::is_hmac_algorithm,
+  repository::tenant::TenantRow,
+};
 
 use super::tenant_oauth2_provider::TenantOAuth2Provider;
 
@@ -16,6 +20,8 @@ pub struct Tenant {
   pub issuer: String,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub audience: Option<String>,
+  /// Audiences other than the default a token request can pick with the `resource` parameter
+  pub allowed_audiences: Vec<String>,
   pub algorithm: Algorithm,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub public_key: Option<String>,
@@ -23,6 +29,12 @@ pub struct Tenant {
   pub private_key: Option<String>,
   pub expires_in_seconds: i64,
   pub refresh_expires_in_seconds: i64,
+  pub key_version: i64,
+  /// PEM encoded RSA public key the issued ID tokens are encrypted to
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub encryption_public_key: Option<String>,
+  /// Access and refresh tokens are encrypted with a secret only this server holds
+  pub encrypt_access_tokens: bool,
   pub oauth2_providers: Vec<TenantOAuth2Provider>,
   pub updated_at: DateTime<Utc>,
   pub created_at: DateTime<Utc>,
@@ -30,16 +42,27 @@ pub struct Tenant {
 
 impl From<TenantRow> for Tenant {
   fn from(row: TenantRow) -> Self {
+    let encrypt_access_tokens = row.encrypts_access_tokens();
     Self {
       id: row.id,
       client_id: uuid::Uuid::from_str(&row.client_id).unwrap_or_default(),
       issuer: row.issuer,
       audience: row.audience,
+      allowed_audiences: row
+        .allowed_audiences
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(ToOwned::to_owned)
+        .collect(),
       algorithm: Algorithm::from_str(&row.algorithm).unwrap_or_default(),
       public_key: row.public_key,
       private_key: None,
       expires_in_seconds: row.expires_in_seconds,
       refresh_expires_in_seconds: row.refresh_expires_in_seconds,
+      key_version: row.key_version,
+      encryption_public_key: row.encryption_public_key,
+      encrypt_access_tokens,
       oauth2_providers: Vec::new(),
       updated_at: DateTime::<Utc>::from_timestamp(row.updated_at, 0).unwrap_or_default(),
       created_at: DateTime::<Utc>::from_timestamp(row.created_at, 0).unwrap_or_default(),
@@ -54,7 +77,7 @@ pub struct TenantPagination {
 }
 
 /// jsonwebtoken::Algorithm
-#[derive(Serialize, Deserialize, ToSchema, Default)]
+#[derive(Serialize, Deserialize, ToSchema, Default, Clone, Copy, PartialEq, Eq)]
 pub enum Algorithm {
   /// HMAC using SHA-256
   #[default]
@@ -88,28 +111,64 @@ pub enum Algorithm {
 }
 
 impl Algorithm {
+  /// Returns the public and private keys for the algorithm, a new key pair is generated when no
+  /// private key is given, otherwise the public key is derived from the given private key and
+  /// must match the given public key if any.
   pub fn keys(
     &self,
     public_key: Option<String>,
     private_key: Option<String>,
-  ) -> (Option<String>, String) {
-    match self {
-      Algorithm::HS256 => (
-        public_key,
-        private_key.unwrap_or_else(|| BASE64_STANDARD.encode(random_bytes(256))),
-      ),
-      Algorithm::HS384 => (
-        public_key,
-        private_key.unwrap_or_else(|| BASE64_STANDARD.encode(random_bytes(384))),
-      ),
-      Algorithm::HS512 => (
-        public_key,
-        private_key.unwrap_or_else(|| BASE64_STANDARD.encode(random_bytes(512))),
-      ),
-      _ => (
-        public_key,
-        private_key.unwrap_or_else(|| BASE64_STANDARD.encode(random_bytes(256))),
-      ),
+  ) -> Result<(Option<String>, String), jsonwebtoken::errors::Error> {
+    let algorithm = jsonwebtoken::Algorithm::from(*self);
+    match (public_key, private_key) {
+      (None, Some(private_key)) if is_hmac_algorithm(algorithm) => Ok((None, private_key)),
+      (public_key, Some(private_key)) if !is_hmac_algorithm(algorithm) => {
+        let (derived_public_key, private_key) = key_pair_from_private_key(algorithm, &private_key)?;
+        if let Some(public_key) = public_key {
+          if public_key_pem(algorithm, &public_key)? != derived_public_key {
+            return Err(ErrorKind::InvalidKeyFormat.into());
+          }
+        }
+        Ok((Some(derived_public_key), private_key))
+      }
+      (None, None) => generate_key_pair(algorithm),
+      _ => Err(ErrorKind::InvalidKeyFormat.into()),
+    }
+  }
+}
+
+/// Allowed audiences are stored space separated, like scopes.
+pub fn is_valid_audience(audience: &str) -> bool {
+  !audience.is_empty() && !audience.contains(char::is_whitespace)
+}
+
+/// Returns the client's encryption public key re-encoded as SPKI, an empty key is passed through
+/// to turn ID token encryption off.
+pub fn encryption_public_key(
+  encryption_public_key: Option<String>,
+) -> Result<Option<String>, jsonwebtoken::errors::Error> {
+  match encryption_public_key {
+    Some(public_key) if public_key.is_empty() => Ok(Some(public_key)),
+    Some(public_key) => public_key_pem(jsonwebtoken::Algorithm::RS256, &public_key).map(Some),
+    None => Ok(None),
+  }
+}
+
+impl From<Algorithm> for jsonwebtoken::Algorithm {
+  fn from(algorithm: Algorithm) -> Self {
+    match algorithm {
+      Algorithm::HS256 => jsonwebtoken::Algorithm::HS256,
+      Algorithm::HS384 => jsonwebtoken::Algorithm::HS384,
+      Algorithm::HS512 => jsonwebtoken::Algorithm::HS512,
+      Algorithm::ES256 => jsonwebtoken::Algorithm::ES256,
+      Algorithm::ES384 => jsonwebtoken::Algorithm::ES384,
+      Algorithm::RS256 => jsonwebtoken::Algorithm::RS256,
+      Algorithm::RS384 => jsonwebtoken::Algorithm::RS384,
+      Algorithm::RS512 => jsonwebtoken::Algorithm::RS512,
+      Algorithm::PS256 => jsonwebtoken::Algorithm::PS256,
+      Algorithm::PS384 => jsonwebtoken::Algorithm::PS384,
+      Algorithm::PS512 => jsonwebtoken::Algorithm::PS512,
+      Algorithm::EdDSA => jsonwebtoken::Algorithm::EdDSA,
     }
   }
 }
@@ -158,18 +217,32 @@ impl fmt::Display for Algorithm {
 #[derive(Deserialize, ToSchema)]
 pub struct CreateTenant {
   pub client_id: Option<uuid::Uuid>,
+  /// Defaults to `{server.url}/tenants/{client_id}`, where the tenant's OpenID Connect discovery
+  /// document is served
   #[schema(example = "Example")]
-  pub issuer: String,
+  pub issuer: Option<String>,
   #[schema(example = "https://example.com")]
   pub audience: String,
+  /// Audiences other than the default a token request can pick with the `resource` parameter
+  #[schema(example = json!(["https://api.example.com"]))]
+  pub allowed_audiences: Option<Vec<String>>,
   #[schema(example = "HS256")]
   pub algorithm: Option<Algorithm>,
+  /// PEM encoded public key for asymmetric algorithms, must match the private key
   pub public_key: Option<String>,
+  /// PEM encoded private key for asymmetric algorithms, the public key is derived from it,
+  /// a new key pair is generated when omitted
   pub private_key: Option<String>,
   #[schema(example = "86400")]
   pub expires_in_seconds: Option<i64>,
   #[schema(example = "604800")]
   pub refresh_expires_in_seconds: Option<i64>,
+  /// PEM encoded RSA public key registered by the client, issued ID tokens are encrypted to it
+  /// as RSA-OAEP-256 and A256GCM JWEs
+  pub encryption_public_key: Option<String>,
+  /// Encrypts access and refresh tokens with a secret only this server holds, resource servers
+  /// then have to introspect them instead of verifying them against the JWKS
+  pub encrypt_access_tokens: Option<bool>,
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -179,14 +252,27 @@ pub struct UpdateTenant {
   pub issuer: Option<String>,
   #[schema(example = "example.com")]
   pub audience: Option<String>,
+  /// Replaces the audiences other than the default a token request can pick
+  #[schema(example = json!(["https://api.example.com"]))]
+  pub allowed_audiences: Option<Vec<String>>,
   #[schema(example = "HS256")]
   pub algorithm: Option<Algorithm>,
+  /// PEM encoded public key for asymmetric algorithms, must match the private key
   pub public_key: Option<String>,
+  /// PEM encoded private key for asymmetric algorithms, the public key is derived from it,
+  /// a new key pair is generated when omitted
   pub private_key: Option<String>,
   #[schema(example = "86400")]
   pub expires_in_seconds: Option<i64>,
   #[schema(example = "604800")]
   pub refresh_expires_in_seconds: Option<i64>,
+  /// PEM encoded RSA public key registered by the client, an empty key stops encrypting ID tokens
+  pub encryption_public_key: Option<String>,
+  /// Encrypts access and refresh tokens with a secret only this server holds
+  pub encrypt_access_tokens: Option<bool>,
+  /// Replaces the secret access and refresh tokens are encrypted with, tokens encrypted with the
+  /// replaced secret stay readable until the next rotation
+  pub rotate_encryption_secret: Option<bool>,
 }
 
 #[derive(Deserialize, IntoParams)]
@@ -0,0 +1,70 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::repository::tenant_claim_mapping::TenantClaimMappingRow;
+
+#[derive(Serialize, ToSchema)]
+pub struct TenantClaimMapping {
+  pub id: i64,
+  pub tenant_id: i64,
+  pub claim: String,
+  #[schema(example = "user")]
+  pub source: String,
+  /// The claim's value for static mappings, otherwise the name of the mapped field
+  #[schema(value_type = Object, example = "username")]
+  pub value: serde_json::Value,
+  pub access_token: bool,
+  pub id_token: bool,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<TenantClaimMappingRow> for TenantClaimMapping {
+  fn from(row: TenantClaimMappingRow) -> Self {
+    let value = if row.is_static() {
+      row.static_value()
+    } else {
+      row.value.clone().into()
+    };
+    let access_token = row.in_access_token();
+    let id_token = row.in_id_token();
+    Self {
+      id: row.id,
+      tenant_id: row.tenant_id,
+      claim: row.claim,
+      source: row.source,
+      value,
+      access_token,
+      id_token,
+      updated_at: DateTime::<Utc>::from_timestamp(row.updated_at, 0).unwrap_or_default(),
+      created_at: DateTime::<Utc>::from_timestamp(row.created_at, 0).unwrap_or_default(),
+    }
+  }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateTenantClaimMapping {
+  #[schema(example = "username")]
+  pub claim: String,
+  /// One of `static`, `user`, `user_info` or `application`
+  #[schema(example = "user")]
+  pub source: String,
+  /// Any JSON value for static mappings, otherwise the name of the mapped field
+  #[schema(value_type = Object, example = "username")]
+  pub value: serde_json::Value,
+  /// Defaults to true
+  pub access_token: Option<bool>,
+  /// Defaults to true
+  pub id_token: Option<bool>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateTenantClaimMapping {
+  pub claim: Option<String>,
+  pub source: Option<String>,
+  #[schema(value_type = Option<Object>)]
+  pub value: Option<serde_json::Value>,
+  pub access_token: Option<bool>,
+  pub id_token: Option<bool>,
+}
@@ -0,0 +1,54 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::repository::tenant_key::TenantKeyRow;
+
+use super::tenant::Algorithm;
+
+#[derive(Serialize, ToSchema)]
+pub struct TenantKey {
+  pub id: i64,
+  pub tenant_id: i64,
+  pub version: i64,
+  /// One of `next`, `active`, `retiring` or `revoked`
+  #[schema(example = "active")]
+  pub state: String,
+  pub algorithm: Algorithm,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub public_key: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub expires_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<TenantKeyRow> for TenantKey {
+  fn from(row: TenantKeyRow) -> Self {
+    Self {
+      id: row.id,
+      tenant_id: row.tenant_id,
+      version: row.version,
+      state: row.state,
+      algorithm: Algorithm::from_str(&row.algorithm).unwrap_or_default(),
+      public_key: row.public_key,
+      expires_at: row
+        .expires_at
+        .and_then(|expires_at| DateTime::<Utc>::from_timestamp(expires_at, 0)),
+      updated_at: DateTime::<Utc>::from_timestamp(row.updated_at, 0).unwrap_or_default(),
+      created_at: DateTime::<Utc>::from_timestamp(row.created_at, 0).unwrap_or_default(),
+    }
+  }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateTenantKey {
+  /// Defaults to the tenant's current algorithm
+  #[schema(example = "RS256")]
+  pub algorithm: Option<Algorithm>,
+  /// PEM encoded private key for asymmetric algorithms, the public key is derived from it,
+  /// a new key pair is generated when omitted
+  pub private_key: Option<String>,
+}
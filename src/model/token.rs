@@ -2,12 +2,41 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+use super::webauthn::WebAuthnAssertion;
+
 pub const TOKEN_ISSUED_TYPE_PASSWORD: &str = "password";
 pub const TOKEN_ISSUED_TYPE_REFRESH_TOKEN: &str = "refresh-token";
 pub const TOKEN_ISSUED_TYPE_AUTHORIZATION_CODE: &str = "authorization-code";
 pub const TOKEN_ISSUED_TYPE_SERVICE_ACCOUNT: &str = "service-account";
+pub const TOKEN_ISSUED_TYPE_DEVICE_CODE: &str = "device-code";
 pub const TOKEN_ISSUED_TYPE_REGISTER: &str = "register";
 pub const TOKEN_ISSUED_TYPE_MFA: &str = "mfa";
+pub const TOKEN_ISSUED_TYPE_WEBAUTHN: &str = "webauthn";
+
+pub const GRANT_TYPE_PASSWORD: &str = "password";
+pub const GRANT_TYPE_REFRESH_TOKEN: &str = "refresh_token";
+pub const GRANT_TYPE_CLIENT_CREDENTIALS: &str = "client_credentials";
+pub const GRANT_TYPE_AUTHORIZATION_CODE: &str = "authorization_code";
+pub const GRANT_TYPE_DEVICE_CODE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+pub const GRANT_TYPE_TOKEN_EXCHANGE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+
+pub const TOKEN_TYPE_URN_ACCESS_TOKEN: &str = "urn:ietf:params:oauth:token-type:access_token";
+pub const TOKEN_TYPE_URN_JWT: &str = "urn:ietf:params:oauth:token-type:jwt";
+
+pub const OAUTH2_ERROR_INVALID_REQUEST: &str = "invalid_request";
+pub const OAUTH2_ERROR_INVALID_CLIENT: &str = "invalid_client";
+pub const OAUTH2_ERROR_INVALID_GRANT: &str = "invalid_grant";
+pub const OAUTH2_ERROR_UNAUTHORIZED_CLIENT: &str = "unauthorized_client";
+pub const OAUTH2_ERROR_INVALID_SCOPE: &str = "invalid_scope";
+/// RFC 8693 section 2.2.2, the requested audience is not accepted
+pub const OAUTH2_ERROR_INVALID_TARGET: &str = "invalid_target";
+pub const OAUTH2_ERROR_UNSUPPORTED_GRANT_TYPE: &str = "unsupported_grant_type";
+pub const OAUTH2_ERROR_SERVER_ERROR: &str = "server_error";
+/// RFC 8628 section 3.5 device code errors
+pub const OAUTH2_ERROR_AUTHORIZATION_PENDING: &str = "authorization_pending";
+pub const OAUTH2_ERROR_SLOW_DOWN: &str = "slow_down";
+pub const OAUTH2_ERROR_ACCESS_DENIED: &str = "access_denied";
+pub const OAUTH2_ERROR_EXPIRED_TOKEN: &str = "expired_token";
 
 #[derive(Serialize, ToSchema)]
 pub struct Token {
@@ -39,15 +68,30 @@ pub enum TokenRequest {
     password: String,
     #[schema(example = "openid")]
     scope: Option<String>,
+    /// RFC 8707 resource indicator, one of the tenant's audiences, defaults to its audience
+    resource: Option<String>,
   },
   #[serde(rename = "refresh-token")]
   #[schema(title = "TokenRequestRefreshToken")]
-  RefreshToken { refresh_token: String },
+  RefreshToken {
+    refresh_token: String,
+    /// RFC 8707 resource indicator, defaults to the audience of the refreshed token
+    resource: Option<String>,
+    /// Required for refresh tokens issued to an OAuth2 client
+    client_id: Option<String>,
+    /// Required for refresh tokens issued to confidential clients
+    client_secret: Option<String>,
+  },
   #[serde(rename = "service-account")]
   #[schema(title = "TokenRequestServiceAccount")]
   ServiceAccount {
     client_id: uuid::Uuid,
     client_secret: uuid::Uuid,
+    /// A subset of the service account's scopes, defaults to all of them
+    #[schema(example = "users:read")]
+    scope: Option<String>,
+    /// RFC 8707 resource indicator, one of the tenant's audiences, defaults to its audience
+    resource: Option<String>,
   },
   #[serde(rename = "authorization-code")]
   #[schema(title = "TokenRequestAuthorizationCode")]
@@ -55,5 +99,156 @@ pub enum TokenRequest {
     code: String,
     #[schema(example = "openid")]
     scope: Option<String>,
+    /// Required when the code was issued by /authorize with a redirect_uri
+    redirect_uri: Option<String>,
+    /// Required when the code was issued by /authorize with a code_challenge
+    code_verifier: Option<String>,
+    /// Required for codes issued by /authorize
+    client_id: Option<String>,
+    /// Required for codes issued by /authorize to confidential clients
+    client_secret: Option<String>,
+    /// RFC 8707 resource indicator, one of the tenant's audiences, defaults to its audience
+    resource: Option<String>,
   },
+  #[serde(rename = "urn:ietf:params:oauth:grant-type:device_code")]
+  #[schema(title = "TokenRequestDeviceCode")]
+  DeviceCode {
+    device_code: String,
+    client_id: Option<String>,
+    /// Required for device codes issued to confidential clients
+    client_secret: Option<String>,
+  },
+  #[serde(rename = "urn:ietf:params:oauth:grant-type:token-exchange")]
+  #[schema(title = "TokenRequestTokenExchange")]
+  TokenExchange(TokenExchangeRequest),
+  #[serde(rename = "webauthn")]
+  #[schema(title = "TokenRequestWebAuthn")]
+  WebAuthn(WebAuthnTokenRequest),
+}
+
+/// A passkey login without a password, signing a challenge of `/token/webauthn-options`
+#[derive(Deserialize, ToSchema)]
+pub struct WebAuthnTokenRequest {
+  #[serde(flatten)]
+  pub assertion: WebAuthnAssertion,
+  #[schema(example = "openid")]
+  pub scope: Option<String>,
+  /// RFC 8707 resource indicator, one of the tenant's audiences, defaults to its audience
+  pub resource: Option<String>,
+}
+
+/// RFC 8693 token exchange request. A user token can be narrowed to fewer scopes or another
+/// audience, optionally delegated to a service account `actor_token`. A service account token
+/// can be exchanged for a token of the `requested_subject` user, acting as that user.
+#[derive(Deserialize, ToSchema)]
+pub struct TokenExchangeRequest {
+  pub subject_token: String,
+  #[schema(example = "urn:ietf:params:oauth:token-type:access_token")]
+  pub subject_token_type: String,
+  pub actor_token: Option<String>,
+  #[schema(example = "urn:ietf:params:oauth:token-type:access_token")]
+  pub actor_token_type: Option<String>,
+  #[schema(example = "urn:ietf:params:oauth:token-type:access_token")]
+  pub requested_token_type: Option<String>,
+  /// The user id a service account subject token is exchanged for
+  pub requested_subject: Option<i64>,
+  #[schema(example = "openid")]
+  pub scope: Option<String>,
+  /// The tenant's audience or the client id of one of the tenant's OAuth2 clients
+  pub audience: Option<String>,
+}
+
+/// RFC 6749 token request, sent form encoded by standard OAuth2 clients. The client credentials
+/// are sent with HTTP Basic authentication or as `client_id` and `client_secret`.
+#[derive(Deserialize, ToSchema)]
+pub struct OAuth2TokenRequest {
+  #[schema(example = "client_credentials")]
+  pub grant_type: String,
+  pub client_id: Option<String>,
+  pub client_secret: Option<String>,
+  #[schema(example = "openid")]
+  pub scope: Option<String>,
+  /// Required for the password grant
+  pub username: Option<String>,
+  /// Required for the password grant
+  pub password: Option<String>,
+  /// Required for the refresh_token grant
+  pub refresh_token: Option<String>,
+  /// Required for the authorization_code grant
+  pub code: Option<String>,
+  pub redirect_uri: Option<String>,
+  pub code_verifier: Option<String>,
+  /// Required for the device code grant
+  pub device_code: Option<String>,
+  /// Required for the token exchange grant
+  pub subject_token: Option<String>,
+  /// Required for the token exchange grant
+  pub subject_token_type: Option<String>,
+  pub actor_token: Option<String>,
+  pub actor_token_type: Option<String>,
+  pub requested_token_type: Option<String>,
+  pub requested_subject: Option<i64>,
+  /// The token exchange audience, or the resource indicator of the other grants
+  pub audience: Option<String>,
+  /// RFC 8707 resource indicator, one of the tenant's audiences
+  pub resource: Option<String>,
+}
+
+/// RFC 6749 error response of form encoded token requests
+#[derive(Serialize, ToSchema)]
+pub struct OAuth2Error {
+  #[schema(example = "invalid_grant")]
+  pub error: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error_description: Option<String>,
+}
+
+/// RFC 7009 token revocation request
+#[derive(Deserialize, ToSchema)]
+pub struct RevokeTokenRequest {
+  /// The access or refresh token to revoke
+  pub token: String,
+  #[schema(example = "refresh_token")]
+  pub token_type_hint: Option<String>,
+  /// The client the token was issued to, unless sent by HTTP Basic authentication
+  pub client_id: Option<String>,
+  pub client_secret: Option<String>,
+}
+
+/// RFC 7662 token introspection request
+#[derive(Deserialize, ToSchema)]
+pub struct IntrospectTokenRequest {
+  /// The access or refresh token to introspect
+  pub token: String,
+  #[schema(example = "access_token")]
+  pub token_type_hint: Option<String>,
+}
+
+/// RFC 7662 token introspection response, only `active` is returned for inactive tokens
+#[derive(Serialize, ToSchema, Default)]
+pub struct TokenIntrospection {
+  pub active: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub scope: Option<String>,
+  /// The tenant's client id the token was issued by
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub client_id: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub sub: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub sub_type: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub token_type: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub exp: Option<i64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub iat: Option<i64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub nbf: Option<i64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub iss: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub aud: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub jti: Option<String>,
 }
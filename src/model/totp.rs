@@ -6,22 +6,30 @@ use crate::repository::user_totp::UserTOTPRow;
 
 #[derive(Serialize, ToSchema)]
 pub struct UserTOTP {
+  /// `false` until confirmed with a first valid code
+  pub active: bool,
   pub algorithm: String,
   pub digits: i64,
   pub step: i64,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub secret: Option<String>,
+  /// The `otpauth://` URI of a pending TOTP, also available as a QR code
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub uri: Option<String>,
   pub updated_at: DateTime<Utc>,
   pub created_at: DateTime<Utc>,
 }
 
 impl From<UserTOTPRow> for UserTOTP {
   fn from(row: UserTOTPRow) -> Self {
+    let active = row.is_active();
     Self {
+      active,
       algorithm: row.algorithm,
       digits: row.digits,
       step: row.step,
-      secret: Some(row.secret),
+      secret: (!active).then_some(row.secret),
+      uri: None,
       updated_at: DateTime::<Utc>::from_timestamp(row.updated_at, 0).unwrap_or_default(),
       created_at: DateTime::<Utc>::from_timestamp(row.created_at, 0).unwrap_or_default(),
     }
@@ -37,3 +45,9 @@ pub struct CreateTOTPRequest {
   #[schema(example = "30")]
   pub step: Option<i64>,
 }
+
+#[derive(Deserialize, ToSchema)]
+pub struct ConfirmTOTPRequest {
+  #[schema(example = "123456")]
+  pub code: String,
+}
@@ -8,7 +8,7 @@ use validator::Validate;
 use crate::repository::{
   user::UserRow, user_config::UserConfigRow, user_email::UserEmailRow, user_info::UserInfoRow,
   user_mfa::UserMFATypeRow, user_oauth2_provider::UserOAuth2ProviderRow,
-  user_phone_number::UserPhoneNumberRow,
+  user_phone_number::UserPhoneNumberRow, user_session::UserSessionRow,
 };
 
 #[derive(Serialize, ToSchema, Default)]
@@ -26,6 +26,9 @@ pub struct User {
   #[serde(skip_serializing_if = "Option::is_none")]
   pub config: Option<UserConfig>,
   pub mfa_types: Vec<UserMFAType>,
+  /// Unused recovery codes, only shown to the user themselves
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub recovery_codes_remaining: Option<i64>,
   pub info: UserInfo,
   pub updated_at: DateTime<Utc>,
   pub created_at: DateTime<Utc>,
@@ -182,16 +185,51 @@ impl From<UserOAuth2ProviderRow> for UserOAuth2Provider {
   }
 }
 
+#[derive(Serialize, ToSchema)]
+pub struct UserSession {
+  pub id: i64,
+  pub tenant_id: i64,
+  /// The `sid` claim of the session's tokens
+  pub sid: String,
+  #[schema(example = "password")]
+  pub grant_type: Option<String>,
+  pub ip_address: Option<String>,
+  pub user_agent: Option<String>,
+  pub expires_at: DateTime<Utc>,
+  pub last_seen_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<UserSessionRow> for UserSession {
+  fn from(row: UserSessionRow) -> Self {
+    Self {
+      id: row.id,
+      tenant_id: row.tenant_id,
+      sid: row.sid,
+      grant_type: row.grant_type,
+      ip_address: row.ip_address,
+      user_agent: row.user_agent,
+      expires_at: DateTime::<Utc>::from_timestamp(row.expires_at, 0).unwrap_or_default(),
+      last_seen_at: DateTime::<Utc>::from_timestamp(row.last_seen_at, 0).unwrap_or_default(),
+      created_at: DateTime::<Utc>::from_timestamp(row.created_at, 0).unwrap_or_default(),
+    }
+  }
+}
+
 #[derive(Deserialize, Serialize, ToSchema)]
 pub enum UserMFAType {
   #[serde(rename = "none")]
   None,
   #[serde(rename = "totp")]
   TOTP,
+  #[serde(rename = "hotp")]
+  HOTP,
   #[serde(rename = "email")]
   Email,
   #[serde(rename = "text")]
   Text,
+  #[serde(rename = "webauthn")]
+  WebAuthn,
 }
 
 impl fmt::Display for UserMFAType {
@@ -199,8 +237,10 @@ impl fmt::Display for UserMFAType {
     match self {
       Self::None => write!(f, "none"),
       Self::TOTP => write!(f, "totp"),
+      Self::HOTP => write!(f, "hotp"),
       Self::Email => write!(f, "email"),
       Self::Text => write!(f, "text"),
+      Self::WebAuthn => write!(f, "webauthn"),
     }
   }
 }
@@ -209,8 +249,10 @@ impl From<UserMFATypeRow> for UserMFAType {
   fn from(row: UserMFATypeRow) -> Self {
     match row.r#type.as_str() {
       "totp" => Self::TOTP,
+      "hotp" => Self::HOTP,
       "email" => Self::Email,
       "text" => Self::Text,
+      "webauthn" => Self::WebAuthn,
       _ => panic!("Unknown MFA type: {}", row.r#type),
     }
   }
@@ -0,0 +1,82 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::middleware::openid_claims::OpenIdProfile;
+
+/// OpenID Connect userinfo response with the standard claim names of OpenID Connect Core 1.0
+/// section 5.1, only the claims allowed by the token's scopes are set.
+#[derive(Serialize, ToSchema)]
+pub struct UserInfo {
+  pub sub: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub given_name: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub family_name: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub middle_name: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub nickname: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub preferred_username: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub picture: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub website: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub email: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub email_verified: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub gender: Option<String>,
+  /// Formatted as YYYY-MM-DD
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[schema(example = "1990-05-17")]
+  pub birthdate: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub zoneinfo: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub locale: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub phone_number: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub phone_number_verified: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub address: Option<UserInfoAddress>,
+}
+
+/// OpenID Connect Core 1.0 section 5.1.1 address claim, users only store a formatted address
+#[derive(Serialize, ToSchema)]
+pub struct UserInfoAddress {
+  pub formatted: String,
+}
+
+impl UserInfo {
+  pub fn new(sub: i64, profile: OpenIdProfile) -> Self {
+    Self {
+      sub: sub.to_string(),
+      name: profile.name,
+      given_name: profile.given_name,
+      family_name: profile.family_name,
+      middle_name: profile.middle_name,
+      nickname: profile.nickname,
+      preferred_username: profile.preferred_username,
+      picture: profile.profile_picture,
+      website: profile.website,
+      email: profile.email,
+      email_verified: profile.email_verified,
+      gender: profile.gender,
+      birthdate: profile
+        .birthdate
+        .map(|birthdate| birthdate.format("%Y-%m-%d").to_string()),
+      zoneinfo: profile.zone_info,
+      locale: profile.locale,
+      phone_number: profile.phone_number,
+      phone_number_verified: profile.phone_number_verified,
+      address: profile
+        .address
+        .map(|formatted| UserInfoAddress { formatted }),
+    }
+  }
+}
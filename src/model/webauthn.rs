@@ -0,0 +1,141 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::repository::user_webauthn_credential::UserWebAuthnCredentialRow;
+
+pub const PUBLIC_KEY_CREDENTIAL_TYPE: &str = "public-key";
+
+#[derive(Serialize, ToSchema)]
+pub struct UserWebAuthnCredential {
+  pub id: i64,
+  pub name: String,
+  /// Base64url encoded credential id
+  pub credential_id: String,
+  pub last_used_at: Option<DateTime<Utc>>,
+  pub updated_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<UserWebAuthnCredentialRow> for UserWebAuthnCredential {
+  fn from(row: UserWebAuthnCredentialRow) -> Self {
+    Self {
+      id: row.id,
+      name: row.name,
+      credential_id: row.credential_id,
+      last_used_at: row
+        .last_used_at
+        .and_then(|last_used_at| DateTime::<Utc>::from_timestamp(last_used_at, 0)),
+      updated_at: DateTime::<Utc>::from_timestamp(row.updated_at, 0).unwrap_or_default(),
+      created_at: DateTime::<Utc>::from_timestamp(row.created_at, 0).unwrap_or_default(),
+    }
+  }
+}
+
+/// The response of `navigator.credentials.create`, all binary values base64url encoded
+#[derive(Validate, Deserialize, ToSchema)]
+pub struct CreateWebAuthnCredentialRequest {
+  #[validate(length(min = 1, max = 255))]
+  #[schema(example = "Laptop")]
+  pub name: String,
+  pub client_data_json: String,
+  pub attestation_object: String,
+}
+
+/// The response of `navigator.credentials.get`, all binary values base64url encoded
+#[derive(Deserialize, ToSchema)]
+pub struct WebAuthnAssertion {
+  pub credential_id: String,
+  pub client_data_json: String,
+  pub authenticator_data: String,
+  pub signature: String,
+}
+
+/// `PublicKeyCredentialCreationOptions` for `navigator.credentials.create`, binary values base64url
+/// encoded
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WebAuthnCreationOptions {
+  pub challenge: String,
+  pub rp: WebAuthnRelyingParty,
+  pub user: WebAuthnUser,
+  pub pub_key_cred_params: Vec<WebAuthnCredentialParameters>,
+  /// Milliseconds
+  pub timeout: i64,
+  pub exclude_credentials: Vec<WebAuthnCredentialDescriptor>,
+  pub authenticator_selection: WebAuthnAuthenticatorSelection,
+  pub attestation: String,
+}
+
+/// `PublicKeyCredentialRequestOptions` for `navigator.credentials.get`, binary values base64url
+/// encoded
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WebAuthnRequestOptions {
+  pub challenge: String,
+  pub rp_id: String,
+  /// Milliseconds
+  pub timeout: i64,
+  /// Empty for passkey logins, letting the authenticator pick a discoverable credential
+  pub allow_credentials: Vec<WebAuthnCredentialDescriptor>,
+  pub user_verification: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WebAuthnRelyingParty {
+  pub id: String,
+  pub name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WebAuthnUser {
+  /// Base64url encoded user handle
+  pub id: String,
+  pub name: String,
+  pub display_name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WebAuthnCredentialParameters {
+  pub r#type: String,
+  pub alg: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WebAuthnCredentialDescriptor {
+  pub r#type: String,
+  /// Base64url encoded credential id
+  pub id: String,
+}
+
+impl From<UserWebAuthnCredentialRow> for WebAuthnCredentialDescriptor {
+  fn from(row: UserWebAuthnCredentialRow) -> Self {
+    Self {
+      r#type: PUBLIC_KEY_CREDENTIAL_TYPE.to_owned(),
+      id: row.credential_id,
+    }
+  }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WebAuthnAuthenticatorSelection {
+  pub resident_key: String,
+  pub user_verification: String,
+}
+
+/// A challenge handed out with WebAuthn options, stored until a response signs it
+#[derive(Serialize, Deserialize)]
+pub struct WebAuthnChallenge {
+  pub application_id: i64,
+  /// Set for registrations and MFA, passkey logins find the user by the credential
+  pub user_id: Option<i64>,
+}
+
+impl WebAuthnChallenge {
+  pub fn kv_key(challenge: &str) -> String {
+    format!("webauthn-challenge:{challenge}")
+  }
+}
@@ -0,0 +1,132 @@
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+  errors::{Error, ErrorKind},
+  Algorithm,
+};
+use rsa::{pkcs8::DecodePublicKey, traits::PublicKeyParts};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Deserialize, IntoParams)]
+pub struct WellKnownQuery {
+  /// Tenant client id, used when the Tenant-ID header is not sent
+  pub client_id: Option<uuid::Uuid>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OpenIdConfiguration {
+  pub issuer: String,
+  pub authorization_endpoint: String,
+  pub device_authorization_endpoint: String,
+  pub token_endpoint: String,
+  pub userinfo_endpoint: String,
+  pub end_session_endpoint: String,
+  pub jwks_uri: String,
+  pub scopes_supported: Vec<String>,
+  pub response_types_supported: Vec<String>,
+  pub grant_types_supported: Vec<String>,
+  pub subject_types_supported: Vec<String>,
+  pub id_token_signing_alg_values_supported: Vec<String>,
+  /// Only set when the tenant encrypts its tokens
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub id_token_encryption_alg_values_supported: Vec<String>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub id_token_encryption_enc_values_supported: Vec<String>,
+  pub token_endpoint_auth_methods_supported: Vec<String>,
+  pub code_challenge_methods_supported: Vec<String>,
+  pub acr_values_supported: Vec<String>,
+  pub claims_supported: Vec<String>,
+  pub backchannel_logout_supported: bool,
+  pub backchannel_logout_session_supported: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct JsonWebKeySet {
+  pub keys: Vec<JsonWebKey>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct JsonWebKey {
+  #[schema(example = "RSA")]
+  pub kty: String,
+  #[serde(rename = "use")]
+  #[schema(example = "sig")]
+  pub r#use: String,
+  #[schema(example = "RS256")]
+  pub alg: String,
+  #[schema(example = "1-1")]
+  pub kid: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub n: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub e: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub crv: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub x: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub y: Option<String>,
+}
+
+impl JsonWebKey {
+  /// Builds the JWK for a PEM encoded public key, HMAC algorithms have no public key so they are
+  /// never published.
+  pub fn from_public_key(
+    kid: String,
+    algorithm: Algorithm,
+    public_key: &str,
+  ) -> Result<Option<Self>, Error> {
+    let mut jwk = Self {
+      kty: String::new(),
+      r#use: "sig".to_owned(),
+      alg: format!("{:?}", algorithm),
+      kid,
+      n: None,
+      e: None,
+      crv: None,
+      x: None,
+      y: None,
+    };
+    match algorithm {
+      Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => return Ok(None),
+      Algorithm::RS256
+      | Algorithm::RS384
+      | Algorithm::RS512
+      | Algorithm::PS256
+      | Algorithm::PS384
+      | Algorithm::PS512 => {
+        let public_key = rsa::RsaPublicKey::from_public_key_pem(public_key)
+          .map_err(|e| Error::from(ErrorKind::InvalidRsaKey(e.to_string())))?;
+        jwk.kty = "RSA".to_owned();
+        jwk.n = Some(BASE64_URL_SAFE_NO_PAD.encode(public_key.n().to_bytes_be()));
+        jwk.e = Some(BASE64_URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be()));
+      }
+      Algorithm::ES256 => {
+        let public_key = p256::PublicKey::from_public_key_pem(public_key)
+          .map_err(|_| Error::from(ErrorKind::InvalidEcdsaKey))?;
+        let point = p256::EncodedPoint::from(public_key);
+        jwk.kty = "EC".to_owned();
+        jwk.crv = Some("P-256".to_owned());
+        jwk.x = point.x().map(|x| BASE64_URL_SAFE_NO_PAD.encode(x));
+        jwk.y = point.y().map(|y| BASE64_URL_SAFE_NO_PAD.encode(y));
+      }
+      Algorithm::ES384 => {
+        let public_key = p384::PublicKey::from_public_key_pem(public_key)
+          .map_err(|_| Error::from(ErrorKind::InvalidEcdsaKey))?;
+        let point = p384::EncodedPoint::from(public_key);
+        jwk.kty = "EC".to_owned();
+        jwk.crv = Some("P-384".to_owned());
+        jwk.x = point.x().map(|x| BASE64_URL_SAFE_NO_PAD.encode(x));
+        jwk.y = point.y().map(|y| BASE64_URL_SAFE_NO_PAD.encode(y));
+      }
+      Algorithm::EdDSA => {
+        let public_key = ed25519_dalek::VerifyingKey::from_public_key_pem(public_key)
+          .map_err(|_| Error::from(ErrorKind::InvalidKeyFormat))?;
+        jwk.kty = "OKP".to_owned();
+        jwk.crv = Some("Ed25519".to_owned());
+        jwk.x = Some(BASE64_URL_SAFE_NO_PAD.encode(public_key.as_bytes()));
+      }
+    }
+    Ok(Some(jwk))
+  }
+}
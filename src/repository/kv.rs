@@ -1,21 +1,23 @@
 use chrono::Duration;
-use serde::{Serialize, de::DeserializeOwned};
+use serde::{de::DeserializeOwned, Serialize};
 
 #[derive(Clone, sqlx::FromRow)]
 pub struct KVRow {
   pub key: String,
   pub value: String,
-  pub expries_at: Option<i64>,
+  pub expires_at: Option<i64>,
   pub updated_at: i64,
   pub created_at: i64,
 }
 
 pub async fn kv_get(pool: &sqlx::AnyPool, key: String) -> sqlx::Result<Option<KVRow>> {
-  sqlx::query_as(r#"SELECT * FROM key_values WHERE "key" = $1 AND ("updated_at" IS NULL OR "updated_at" <= $2);"#)
-    .bind(key)
-    .bind(chrono::Utc::now().timestamp())
-    .fetch_optional(pool)
-    .await
+  sqlx::query_as(
+    r#"SELECT * FROM key_values WHERE "key" = $1 AND ("expires_at" IS NULL OR "expires_at" > $2);"#,
+  )
+  .bind(key)
+  .bind(chrono::Utc::now().timestamp())
+  .fetch_optional(pool)
+  .await
 }
 
 pub async fn kv_upsert(
@@ -25,10 +27,10 @@ pub async fn kv_upsert(
   expires_at: Option<i64>,
 ) -> sqlx::Result<KVRow> {
   sqlx::query_as(
-    r#"INSERT INTO key_values ("key", "value", "expries_at")
+    r#"INSERT INTO key_values ("key", "value", "expires_at")
         VALUES ($1, $2, $3)
         ON CONFLICT ("key")
-        DO UPDATE SET "value" = $2, "expries_at" = $3, "updated_at" = $5
+        DO UPDATE SET "value" = $2, "expires_at" = $3, "updated_at" = $4
         RETURNING *;"#,
   )
   .bind(key)
@@ -39,6 +41,36 @@ pub async fn kv_upsert(
   .await
 }
 
+/// Atomically adds one to the integer stored at `key`, starting again from one with a new
+/// `expires_at` when it is missing or expired.
+pub async fn kv_increment(
+  pool: &sqlx::AnyPool,
+  key: String,
+  expires_at: Option<i64>,
+) -> sqlx::Result<KVRow> {
+  sqlx::query_as(
+    r#"INSERT INTO key_values ("key", "value", "expires_at")
+        VALUES ($1, '1', $2)
+        ON CONFLICT ("key")
+        DO UPDATE SET
+          "value" = CASE
+            WHEN key_values."expires_at" IS NOT NULL AND key_values."expires_at" <= $3 THEN '1'
+            ELSE CAST(CAST(key_values."value" AS INTEGER) + 1 AS TEXT)
+          END,
+          "expires_at" = CASE
+            WHEN key_values."expires_at" IS NOT NULL AND key_values."expires_at" <= $3 THEN $2
+            ELSE key_values."expires_at"
+          END,
+          "updated_at" = $3
+        RETURNING *;"#,
+  )
+  .bind(key)
+  .bind(expires_at)
+  .bind(chrono::Utc::now().timestamp())
+  .fetch_one(pool)
+  .await
+}
+
 pub async fn kv_delete(pool: &sqlx::AnyPool, key: String) -> sqlx::Result<Option<KVRow>> {
   sqlx::query_as(r#"DELETE FROM key_values WHERE "key" = $1 RETURNING *;"#)
     .bind(key)
@@ -46,7 +78,7 @@ pub async fn kv_delete(pool: &sqlx::AnyPool, key: String) -> sqlx::Result<Option
     .await
     .map(|row_optional: Option<KVRow>| {
       row_optional.and_then(|row| {
-        if let Some(expires_at) = row.expries_at {
+        if let Some(expires_at) = row.expires_at {
           if expires_at < chrono::Utc::now().timestamp() {
             return None;
           }
@@ -62,10 +94,7 @@ where
   T: DeserializeOwned,
 {
   match kv_get(pool, key.into()).await {
-    Ok(Some(row)) => match serde_json::from_str(&row.value) {
-      Ok(value) => Some(value),
-      _ => None,
-    },
+    Ok(Some(row)) => serde_json::from_str(&row.value).ok(),
     _ => None,
   }
 }
@@ -76,35 +105,49 @@ where
   T: Serialize,
 {
   match serde_json::to_string(value) {
-    Ok(value) => {
-      match kv_upsert(
-        pool,
-        key.into(),
-        value,
-        expires
-          .and_then(|e| chrono::Utc::now().checked_add_signed(e))
-          .map(|d| d.timestamp()),
-      )
-      .await
-      {
-        Ok(_) => true,
-        _ => false,
-      }
-    }
+    Ok(value) => kv_upsert(
+      pool,
+      key.into(),
+      value,
+      expires
+        .and_then(|e| chrono::Utc::now().checked_add_signed(e))
+        .map(|d| d.timestamp()),
+    )
+    .await
+    .is_ok(),
     _ => false,
   }
 }
 
+/// Returns the count after adding one to it, `None` when it could not be stored.
+pub async fn increment<S>(pool: &sqlx::AnyPool, key: S, expires: Option<Duration>) -> Option<i64>
+where
+  S: Into<String>,
+{
+  match kv_increment(
+    pool,
+    key.into(),
+    expires
+      .and_then(|e| chrono::Utc::now().checked_add_signed(e))
+      .map(|d| d.timestamp()),
+  )
+  .await
+  {
+    Ok(row) => row.value.parse().ok(),
+    Err(e) => {
+      log::error!("error incrementing key value: {}", e);
+      None
+    }
+  }
+}
+
 pub async fn delete<S, T>(pool: &sqlx::AnyPool, key: S) -> Option<T>
 where
   S: Into<String>,
   T: DeserializeOwned,
 {
   match kv_delete(pool, key.into()).await {
-    Ok(Some(row)) => match serde_json::from_str(&row.value) {
-      Ok(value) => Some(value),
-      _ => None,
-    },
+    Ok(Some(row)) => serde_json::from_str(&row.value).ok(),
     _ => None,
   }
 }
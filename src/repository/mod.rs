@@ -1,14 +1,23 @@
 pub mod application;
 pub mod kv;
+pub mod oauth2_client;
+pub mod refresh_token;
 pub mod service_account;
 pub mod tenant;
+pub mod tenant_claim_mapping;
+pub mod tenant_key;
 pub mod tenant_oauth2_provider;
+pub mod token_revocation;
 pub mod user;
 pub mod user_config;
 pub mod user_email;
+pub mod user_hotp;
 pub mod user_info;
 pub mod user_mfa;
 pub mod user_oauth2_provider;
 pub mod user_password;
 pub mod user_phone_number;
+pub mod user_recovery_code;
+pub mod user_session;
 pub mod user_totp;
+pub mod user_webauthn_credential;
@@ -0,0 +1,204 @@
+use crate::core::encryption::verify_password;
+
+#[derive(Clone, sqlx::FromRow)]
+pub struct OAuth2ClientRow {
+  pub id: i64,
+  pub tenant_id: i64,
+  pub client_id: String,
+  pub encrypted_client_secret: Option<String>,
+  pub name: String,
+  pub redirect_uris: String,
+  pub post_logout_redirect_uris: Option<String>,
+  pub backchannel_logout_uri: Option<String>,
+  /// Space separated scopes the client may request
+  pub scopes: String,
+  pub active: i64,
+  pub updated_at: i64,
+  pub created_at: i64,
+}
+
+impl OAuth2ClientRow {
+  pub fn is_active(&self) -> bool {
+    self.active != 0
+  }
+  /// Public clients have no client secret and must use PKCE
+  pub fn is_public(&self) -> bool {
+    self.encrypted_client_secret.is_none()
+  }
+  pub fn redirect_uris(&self) -> impl Iterator<Item = &str> {
+    self.redirect_uris.split_whitespace()
+  }
+  pub fn has_redirect_uri(&self, redirect_uri: &str) -> bool {
+    self.redirect_uris().any(|uri| uri == redirect_uri)
+  }
+  pub fn post_logout_redirect_uris(&self) -> impl Iterator<Item = &str> {
+    self
+      .post_logout_redirect_uris
+      .as_deref()
+      .unwrap_or_default()
+      .split_whitespace()
+  }
+  pub fn has_post_logout_redirect_uri(&self, post_logout_redirect_uri: &str) -> bool {
+    self
+      .post_logout_redirect_uris()
+      .any(|uri| uri == post_logout_redirect_uri)
+  }
+  pub fn scopes(&self) -> impl Iterator<Item = &str> {
+    self.scopes.split_whitespace()
+  }
+  pub fn allows_scope(&self, scope: &str) -> bool {
+    self.scopes().any(|allowed| allowed == scope)
+  }
+  /// OpenID Connect Back-Channel Logout uri, an empty uri was cleared
+  pub fn backchannel_logout_uri(&self) -> Option<&str> {
+    self
+      .backchannel_logout_uri
+      .as_deref()
+      .filter(|uri| !uri.is_empty())
+  }
+  pub fn verify(&self, secret: &str) -> Result<bool, argon2::Error> {
+    match self.encrypted_client_secret.as_ref() {
+      Some(encrypted_client_secret) => verify_password(secret, encrypted_client_secret),
+      None => Ok(false),
+    }
+  }
+}
+
+pub async fn get_oauth2_clients(
+  pool: &sqlx::AnyPool,
+  tenant_id: i64,
+) -> sqlx::Result<Vec<OAuth2ClientRow>> {
+  sqlx::query_as(
+    r#"SELECT oc.*
+    FROM oauth2_clients oc
+    WHERE oc.tenant_id = $1
+    ORDER BY oc.updated_at DESC;"#,
+  )
+  .bind(tenant_id)
+  .fetch_all(pool)
+  .await
+}
+
+pub async fn get_oauth2_client_by_id(
+  pool: &sqlx::AnyPool,
+  tenant_id: i64,
+  oauth2_client_id: i64,
+) -> sqlx::Result<Option<OAuth2ClientRow>> {
+  sqlx::query_as(
+    r#"SELECT oc.*
+    FROM oauth2_clients oc
+    WHERE oc.tenant_id = $1 AND oc.id = $2
+    LIMIT 1;"#,
+  )
+  .bind(tenant_id)
+  .bind(oauth2_client_id)
+  .fetch_optional(pool)
+  .await
+}
+
+pub async fn get_oauth2_client_by_client_id(
+  pool: &sqlx::AnyPool,
+  client_id: &str,
+) -> sqlx::Result<Option<OAuth2ClientRow>> {
+  sqlx::query_as(
+    r#"SELECT oc.*
+    FROM oauth2_clients oc
+    WHERE oc.client_id = $1
+    LIMIT 1;"#,
+  )
+  .bind(client_id)
+  .fetch_optional(pool)
+  .await
+}
+
+pub struct CreateOAuth2Client {
+  pub client_id: String,
+  pub encrypted_client_secret: Option<String>,
+  pub name: String,
+  pub redirect_uris: String,
+  pub post_logout_redirect_uris: Option<String>,
+  pub backchannel_logout_uri: Option<String>,
+  pub scopes: String,
+}
+
+pub async fn create_oauth2_client(
+  pool: &sqlx::AnyPool,
+  tenant_id: i64,
+  params: CreateOAuth2Client,
+) -> sqlx::Result<OAuth2ClientRow> {
+  sqlx::query_as(
+    r#"INSERT INTO oauth2_clients (tenant_id, client_id, encrypted_client_secret, name, redirect_uris, post_logout_redirect_uris, backchannel_logout_uri, scopes)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+    RETURNING *;"#,
+  )
+  .bind(tenant_id)
+  .bind(params.client_id)
+  .bind(params.encrypted_client_secret)
+  .bind(params.name)
+  .bind(params.redirect_uris)
+  .bind(params.post_logout_redirect_uris)
+  .bind(params.backchannel_logout_uri)
+  .bind(params.scopes)
+  .fetch_one(pool)
+  .await
+}
+
+#[derive(Default)]
+pub struct UpdateOAuth2Client {
+  pub encrypted_client_secret: Option<String>,
+  pub name: Option<String>,
+  pub redirect_uris: Option<String>,
+  pub post_logout_redirect_uris: Option<String>,
+  pub backchannel_logout_uri: Option<String>,
+  pub scopes: Option<String>,
+  pub active: Option<i64>,
+}
+
+pub async fn update_oauth2_client(
+  pool: &sqlx::AnyPool,
+  tenant_id: i64,
+  oauth2_client_id: i64,
+  params: UpdateOAuth2Client,
+) -> sqlx::Result<Option<OAuth2ClientRow>> {
+  sqlx::query_as(
+    r#"UPDATE oauth2_clients
+    SET encrypted_client_secret = COALESCE($3, encrypted_client_secret),
+        name = COALESCE($4, name),
+        redirect_uris = COALESCE($5, redirect_uris),
+        post_logout_redirect_uris = COALESCE($6, post_logout_redirect_uris),
+        backchannel_logout_uri = COALESCE($7, backchannel_logout_uri),
+        scopes = COALESCE($8, scopes),
+        active = COALESCE($9, active),
+        updated_at = $10
+    WHERE tenant_id = $1 AND id = $2
+    RETURNING *;"#,
+  )
+  .bind(tenant_id)
+  .bind(oauth2_client_id)
+  .bind(params.encrypted_client_secret)
+  .bind(params.name)
+  .bind(params.redirect_uris)
+  .bind(params.post_logout_redirect_uris)
+  .bind(params.backchannel_logout_uri)
+  .bind(params.scopes)
+  .bind(params.active)
+  .bind(chrono::Utc::now().timestamp())
+  .fetch_optional(pool)
+  .await
+}
+
+pub async fn delete_oauth2_client(
+  pool: &sqlx::AnyPool,
+  tenant_id: i64,
+  oauth2_client_id: i64,
+) -> sqlx::Result<Option<OAuth2ClientRow>> {
+  sqlx::query_as(
+    r#"DELETE FROM oauth2_clients
+    WHERE tenant_id = $1 AND id = $2
+    RETURNING *;"#,
+  )
+  .bind(tenant_id)
+  .bind(oauth2_client_id)
+  .fetch_optional(pool)
+  .await
+}
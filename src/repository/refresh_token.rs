@@ -0,0 +1,192 @@
+use crate::core::database::run_transaction;
+
+#[derive(sqlx::FromRow)]
+pub struct RefreshTokenFamilyRow {
+  pub id: i64,
+  pub tenant_id: i64,
+  pub sub_type: String,
+  pub sub: i64,
+  pub revoked_at: Option<i64>,
+  pub expires_at: i64,
+  pub mfa_validated: i64,
+  pub updated_at: i64,
+  pub created_at: i64,
+}
+
+impl RefreshTokenFamilyRow {
+  pub fn is_revoked(&self) -> bool {
+    self.revoked_at.is_some()
+  }
+
+  pub fn is_expired(&self, now: i64) -> bool {
+    self.expires_at <= now
+  }
+
+  pub fn is_mfa_validated(&self) -> bool {
+    self.mfa_validated != 0
+  }
+}
+
+#[derive(sqlx::FromRow)]
+pub struct RefreshTokenRow {
+  pub id: i64,
+  pub refresh_token_family_id: i64,
+  pub jti: String,
+  pub used_at: Option<i64>,
+  pub expires_at: i64,
+  pub created_at: i64,
+}
+
+impl RefreshTokenRow {
+  pub fn is_used(&self) -> bool {
+    self.used_at.is_some()
+  }
+}
+
+pub struct CreateRefreshTokenFamily {
+  pub tenant_id: i64,
+  pub sub_type: String,
+  pub sub: i64,
+  /// The absolute expiry of the family, rotated tokens never outlive it
+  pub expires_at: i64,
+  /// Whether the login that started the family passed MFA
+  pub mfa_validated: bool,
+}
+
+/// Starts a new refresh token family, returning its first refresh token.
+pub async fn create_refresh_token_family(
+  pool: &sqlx::AnyPool,
+  params: CreateRefreshTokenFamily,
+) -> sqlx::Result<RefreshTokenRow> {
+  run_transaction(pool, |transaction| {
+    Box::pin(async move {
+      let family: RefreshTokenFamilyRow = sqlx::query_as(
+        r#"INSERT INTO refresh_token_families (tenant_id, sub_type, sub, expires_at, mfa_validated)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING *;"#,
+      )
+      .bind(params.tenant_id)
+      .bind(params.sub_type)
+      .bind(params.sub)
+      .bind(params.expires_at)
+      .bind(i64::from(params.mfa_validated))
+      .fetch_one(&mut **transaction)
+      .await?;
+
+      create_refresh_token_internal(transaction, family.id, params.expires_at).await
+    })
+  })
+  .await
+}
+
+pub enum RefreshTokenRotation {
+  /// The presented token was unused, it is now retired and replaced by this token.
+  Rotated {
+    family: RefreshTokenFamilyRow,
+    refresh_token: RefreshTokenRow,
+  },
+  /// The presented token was already used, so its family has been revoked.
+  Reused,
+  /// The token does not exist or its family was revoked or expired.
+  Invalid,
+  /// The token was issued to another OAuth2 client, it is left unused.
+  ClientMismatch,
+}
+
+/// Retires the refresh token with `jti` and issues the next token in its family, expiring with
+/// the family. Presenting a token that was already retired revokes the whole family. The token
+/// must be presented by the OAuth2 client its session was started for, if any.
+pub async fn rotate_refresh_token(
+  pool: &sqlx::AnyPool,
+  jti: &str,
+  oauth2_client_id: Option<i64>,
+) -> sqlx::Result<RefreshTokenRotation> {
+  let jti = jti.to_owned();
+  run_transaction(pool, |transaction| {
+    Box::pin(async move {
+      let refresh_token: RefreshTokenRow = match sqlx::query_as(
+        r#"SELECT rt.*
+        FROM refresh_tokens rt
+        WHERE rt.jti = $1
+        LIMIT 1;"#,
+      )
+      .bind(&jti)
+      .fetch_optional(&mut **transaction)
+      .await?
+      {
+        Some(refresh_token) => refresh_token,
+        None => return Ok(RefreshTokenRotation::Invalid),
+      };
+      let family: RefreshTokenFamilyRow = sqlx::query_as(
+        r#"SELECT rtf.*
+        FROM refresh_token_families rtf
+        WHERE rtf.id = $1
+        LIMIT 1;"#,
+      )
+      .bind(refresh_token.refresh_token_family_id)
+      .fetch_one(&mut **transaction)
+      .await?;
+      let now = chrono::Utc::now().timestamp();
+      if family.is_revoked() || family.is_expired(now) {
+        return Ok(RefreshTokenRotation::Invalid);
+      }
+      let session_oauth2_client_id: Option<Option<i64>> = sqlx::query_scalar(
+        r#"SELECT us.oauth2_client_id
+        FROM user_sessions us
+        WHERE us.refresh_token_family_id = $1
+        LIMIT 1;"#,
+      )
+      .bind(family.id)
+      .fetch_optional(&mut **transaction)
+      .await?;
+      if session_oauth2_client_id.flatten() != oauth2_client_id {
+        return Ok(RefreshTokenRotation::ClientMismatch);
+      }
+
+      let result = sqlx::query(
+        r#"UPDATE refresh_tokens SET used_at = $2
+        WHERE id = $1 AND used_at IS NULL;"#,
+      )
+      .bind(refresh_token.id)
+      .bind(now)
+      .execute(&mut **transaction)
+      .await?;
+      if result.rows_affected() == 0 {
+        sqlx::query(
+          r#"UPDATE refresh_token_families SET revoked_at = $2, updated_at = $2
+          WHERE id = $1;"#,
+        )
+        .bind(family.id)
+        .bind(now)
+        .execute(&mut **transaction)
+        .await?;
+        return Ok(RefreshTokenRotation::Reused);
+      }
+
+      let refresh_token =
+        create_refresh_token_internal(transaction, family.id, family.expires_at).await?;
+      Ok(RefreshTokenRotation::Rotated {
+        family,
+        refresh_token,
+      })
+    })
+  })
+  .await
+}
+
+async fn create_refresh_token_internal(
+  transaction: &mut sqlx::Transaction<'_, sqlx::Any>,
+  refresh_token_family_id: i64,
+  expires_at: i64,
+) -> sqlx::Result<RefreshTokenRow> {
+  sqlx::query_as(
+    r#"INSERT INTO refresh_tokens (refresh_token_family_id, jti, expires_at)
+    VALUES ($1, $2, $3)
+    RETURNING *;"#,
+  )
+  .bind(refresh_token_family_id)
+  .bind(uuid::Uuid::new_v4().to_string())
+  .bind(expires_at)
+  .fetch_one(&mut **transaction)
+  .await
+}
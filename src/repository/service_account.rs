@@ -9,6 +9,8 @@ pub struct ServiceAccountRow {
   pub name: String,
   pub active: i64,
   pub admin: i64,
+  /// Space separated scopes, service accounts without scopes are allowed none
+  pub scopes: Option<String>,
   pub updated_at: i64,
   pub created_at: i64,
 }
@@ -20,6 +22,16 @@ impl ServiceAccountRow {
   pub fn is_admin(&self) -> bool {
     self.admin != 0
   }
+  pub fn scopes(&self) -> impl Iterator<Item = &str> {
+    self
+      .scopes
+      .as_deref()
+      .unwrap_or_default()
+      .split_whitespace()
+  }
+  pub fn allows_scope(&self, scope: &str) -> bool {
+    self.scopes().any(|allowed| allowed == scope)
+  }
   pub fn verify(&self, secret: &str) -> Result<bool, argon2::Error> {
     verify_password(secret, &self.encrypted_client_secret)
   }
@@ -81,6 +93,7 @@ pub struct CreateServiceAccount {
   pub encrypted_client_secret: String,
   pub name: String,
   pub admin: bool,
+  pub scopes: Option<String>,
 }
 
 pub async fn create_service_account(
@@ -89,8 +102,8 @@ pub async fn create_service_account(
   service_account: CreateServiceAccount,
 ) -> sqlx::Result<ServiceAccountRow> {
   sqlx::query_as(
-    r#"INSERT INTO service_accounts (application_id, client_id, encrypted_client_secret, name, admin)
-    VALUES ($1, $2, $3, $4, $5)
+    r#"INSERT INTO service_accounts (application_id, client_id, encrypted_client_secret, name, admin, scopes)
+    VALUES ($1, $2, $3, $4, $5, $6)
     RETURNING *;"#,
   )
   .bind(application_id)
@@ -98,6 +111,7 @@ pub async fn create_service_account(
   .bind(service_account.encrypted_client_secret)
   .bind(service_account.name)
   .bind(service_account.admin)
+  .bind(service_account.scopes)
   .fetch_one(pool)
   .await
 }
@@ -109,6 +123,7 @@ pub struct UpdateServiceAccount {
   pub name: Option<String>,
   pub admin: Option<bool>,
   pub active: Option<i64>,
+  pub scopes: Option<String>,
 }
 
 pub async fn update_service_account(
@@ -124,7 +139,8 @@ pub async fn update_service_account(
         name = COALESCE($5, name),
         admin = COALESCE($6, admin),
         active = COALESCE($7, active),
-        updated_at = $8
+        scopes = COALESCE($8, scopes),
+        updated_at = $9
     WHERE application_id = $1 AND id = $2
     RETURNING *;"#,
   )
@@ -135,6 +151,7 @@ pub async fn update_service_account(
   .bind(service_account.name)
   .bind(service_account.admin)
   .bind(service_account.active)
+  .bind(service_account.scopes)
   .bind(chrono::Utc::now().timestamp())
   .fetch_optional(pool)
   .await
@@ -1,4 +1,8 @@
-#[derive(sqlx::FromRow)]
+use crate::core::database::run_transaction;
+
+use super::tenant_key::{create_tenant_key_internal, CreateTenantKey, TENANT_KEY_STATE_ACTIVE};
+
+#[derive(Clone, sqlx::FromRow)]
 pub struct TenantRow {
   pub id: i64,
   pub application_id: i64,
@@ -10,8 +14,50 @@ pub struct TenantRow {
   pub private_key: String,
   pub expires_in_seconds: i64,
   pub refresh_expires_in_seconds: i64,
+  pub key_version: i64,
   pub updated_at: i64,
   pub created_at: i64,
+  pub encryption_public_key: Option<String>,
+  pub encryption_secret: Option<String>,
+  pub allowed_audiences: Option<String>,
+  pub encrypt_access_tokens: i64,
+  pub previous_encryption_secret: Option<String>,
+}
+
+impl TenantRow {
+  /// A rotated out key keeps verifying until every token it could have signed has expired.
+  pub fn retiring_key_expires_at(&self) -> i64 {
+    chrono::Utc::now().timestamp() + self.expires_in_seconds.max(self.refresh_expires_in_seconds)
+  }
+
+  /// Every audience tokens can be issued for, the default audience first.
+  pub fn audiences(&self) -> Vec<String> {
+    let mut audiences: Vec<String> = self.audience.iter().cloned().collect();
+    for audience in self
+      .allowed_audiences
+      .as_deref()
+      .unwrap_or_default()
+      .split_whitespace()
+    {
+      if !audiences.iter().any(|allowed| allowed == audience) {
+        audiences.push(audience.to_owned());
+      }
+    }
+    audiences
+  }
+
+  pub fn allows_audience(&self, audience: &str) -> bool {
+    self.audiences().iter().any(|allowed| allowed == audience)
+  }
+
+  /// ID tokens are encrypted when the client registered a public key to encrypt them to.
+  pub fn encrypts_id_tokens(&self) -> bool {
+    self.encryption_public_key.is_some()
+  }
+
+  pub fn encrypts_access_tokens(&self) -> bool {
+    self.encrypt_access_tokens != 0
+  }
 }
 
 pub fn from_tenants_query<'a>(
@@ -75,6 +121,39 @@ pub async fn get_tenant_by_client_id(
   .await
 }
 
+/// The application's first tenant, used when a request does not identify a tenant
+pub async fn get_default_tenant(
+  pool: &sqlx::AnyPool,
+  application_id: i64,
+) -> sqlx::Result<Option<TenantRow>> {
+  sqlx::query_as(
+    r#"SELECT t.*
+    FROM tenants t
+    WHERE t.application_id = $1
+    ORDER BY t.id ASC
+    LIMIT 1;"#,
+  )
+  .bind(application_id)
+  .fetch_optional(pool)
+  .await
+}
+
+pub async fn get_tenant_by_oauth2_client_id(
+  pool: &sqlx::AnyPool,
+  oauth2_client_id: i64,
+) -> sqlx::Result<Option<TenantRow>> {
+  sqlx::query_as(
+    r#"SELECT t.*
+    FROM tenants t
+    JOIN oauth2_clients oc ON oc.tenant_id = t.id
+    WHERE oc.id = $1
+    LIMIT 1;"#,
+  )
+  .bind(oauth2_client_id)
+  .fetch_optional(pool)
+  .await
+}
+
 pub struct CreateTenant {
   pub client_id: String,
   pub issuer: String,
@@ -84,6 +163,10 @@ pub struct CreateTenant {
   pub private_key: String,
   pub expires_in_seconds: i64,
   pub refresh_expires_in_seconds: i64,
+  pub encryption_public_key: Option<String>,
+  pub encryption_secret: Option<String>,
+  pub encrypt_access_tokens: bool,
+  pub allowed_audiences: Vec<String>,
 }
 
 pub async fn create_tenant(
@@ -91,30 +174,57 @@ pub async fn create_tenant(
   application_id: i64,
   tenant: CreateTenant,
 ) -> sqlx::Result<TenantRow> {
-  sqlx::query_as(
-    r#"INSERT INTO tenants (
-      application_id,
-      client_id,
-      issuer,
-      audience,
-      algorithm,
-      public_key,
-      private_key,
-      expires_in_seconds,
-      refresh_expires_in_seconds
-    ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
-    RETURNING *;"#,
-  )
-  .bind(application_id)
-  .bind(tenant.client_id)
-  .bind(tenant.issuer)
-  .bind(tenant.audience)
-  .bind(tenant.algorithm)
-  .bind(tenant.public_key)
-  .bind(tenant.private_key)
-  .bind(tenant.expires_in_seconds)
-  .bind(tenant.refresh_expires_in_seconds)
-  .fetch_one(pool)
+  run_transaction(pool, |transaction| {
+    Box::pin(async move {
+      let tenant_row: TenantRow = sqlx::query_as(
+        r#"INSERT INTO tenants (
+          application_id,
+          client_id,
+          issuer,
+          audience,
+          algorithm,
+          public_key,
+          private_key,
+          expires_in_seconds,
+          refresh_expires_in_seconds,
+          encryption_public_key,
+          encryption_secret,
+          allowed_audiences,
+          encrypt_access_tokens
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+        RETURNING *;"#,
+      )
+      .bind(application_id)
+      .bind(tenant.client_id)
+      .bind(tenant.issuer)
+      .bind(tenant.audience)
+      .bind(&tenant.algorithm)
+      .bind(&tenant.public_key)
+      .bind(&tenant.private_key)
+      .bind(tenant.expires_in_seconds)
+      .bind(tenant.refresh_expires_in_seconds)
+      .bind(tenant.encryption_public_key)
+      .bind(tenant.encryption_secret)
+      .bind(Some(tenant.allowed_audiences.join(" ")).filter(|audiences| !audiences.is_empty()))
+      .bind(i64::from(tenant.encrypt_access_tokens))
+      .fetch_one(&mut **transaction)
+      .await?;
+
+      create_tenant_key_internal(
+        transaction,
+        tenant_row.id,
+        TENANT_KEY_STATE_ACTIVE,
+        CreateTenantKey {
+          algorithm: tenant.algorithm,
+          public_key: tenant.public_key,
+          private_key: tenant.private_key,
+        },
+      )
+      .await?;
+
+      Ok(tenant_row)
+    })
+  })
   .await
 }
 
@@ -122,13 +232,21 @@ pub struct UpdateTenant {
   pub client_id: Option<String>,
   pub issuer: Option<String>,
   pub audience: Option<String>,
-  pub algorithm: Option<String>,
-  pub public_key: Option<String>,
-  pub private_key: Option<String>,
   pub expires_in_seconds: Option<i64>,
   pub refresh_expires_in_seconds: Option<i64>,
+  /// An empty key turns token encryption off
+  pub encryption_public_key: Option<String>,
+  /// Only set when the tenant has no secret yet, unless the secret is rotated
+  pub encryption_secret: Option<String>,
+  pub encrypt_access_tokens: Option<bool>,
+  /// Replaces the secret with `encryption_secret`, the replaced secret keeps decrypting the
+  /// tokens encrypted with it until the next rotation
+  pub rotate_encryption_secret: bool,
+  pub allowed_audiences: Option<Vec<String>>,
 }
 
+/// Updates the tenant settings, signing keys are changed through
+/// [`super::tenant_key::rotate_tenant_key`].
 pub async fn update_tenant(
   pool: &sqlx::AnyPool,
   application_id: i64,
@@ -140,12 +258,24 @@ pub async fn update_tenant(
       client_id = COALESCE($3, client_id),
       issuer = COALESCE($4, issuer),
       audience = COALESCE($5, audience),
-      algorithm = COALESCE($6, algorithm),
-      public_key = COALESCE($7, public_key),
-      private_key = COALESCE($8, private_key),
-      expires_in_seconds = COALESCE($9, expires_in_seconds),
-      refresh_expires_in_seconds = COALESCE($10, refresh_expires_in_seconds),
-      updated_at = $11
+      expires_in_seconds = COALESCE($6, expires_in_seconds),
+      refresh_expires_in_seconds = COALESCE($7, refresh_expires_in_seconds),
+      encryption_public_key = CASE
+        WHEN $9 IS NULL THEN encryption_public_key
+        WHEN $9 = '' THEN NULL
+        ELSE $9
+      END,
+      encryption_secret = CASE
+        WHEN $13 = 1 THEN COALESCE($10, encryption_secret)
+        ELSE COALESCE(encryption_secret, $10)
+      END,
+      previous_encryption_secret = CASE
+        WHEN $13 = 1 AND $10 IS NOT NULL THEN encryption_secret
+        ELSE previous_encryption_secret
+      END,
+      encrypt_access_tokens = COALESCE($12, encrypt_access_tokens),
+      allowed_audiences = COALESCE($11, allowed_audiences),
+      updated_at = $8
     WHERE application_id = $1 AND id = $2
     RETURNING *;"#,
   )
@@ -154,12 +284,18 @@ pub async fn update_tenant(
   .bind(tenant.client_id)
   .bind(tenant.issuer)
   .bind(tenant.audience)
-  .bind(tenant.algorithm)
-  .bind(tenant.public_key)
-  .bind(tenant.private_key)
   .bind(tenant.expires_in_seconds)
   .bind(tenant.refresh_expires_in_seconds)
   .bind(chrono::Utc::now().timestamp())
+  .bind(tenant.encryption_public_key)
+  .bind(tenant.encryption_secret)
+  .bind(
+    tenant
+      .allowed_audiences
+      .map(|allowed_audiences| allowed_audiences.join(" ")),
+  )
+  .bind(tenant.encrypt_access_tokens.map(i64::from))
+  .bind(i64::from(tenant.rotate_encryption_secret))
   .fetch_optional(pool)
   .await
 }
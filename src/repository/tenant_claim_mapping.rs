@@ -0,0 +1,191 @@
+pub const CLAIM_MAPPING_SOURCE_STATIC: &str = "static";
+pub const CLAIM_MAPPING_SOURCE_USER: &str = "user";
+pub const CLAIM_MAPPING_SOURCE_USER_INFO: &str = "user_info";
+pub const CLAIM_MAPPING_SOURCE_APPLICATION: &str = "application";
+
+pub const CLAIM_MAPPING_SOURCES: [&str; 4] = [
+  CLAIM_MAPPING_SOURCE_STATIC,
+  CLAIM_MAPPING_SOURCE_USER,
+  CLAIM_MAPPING_SOURCE_USER_INFO,
+  CLAIM_MAPPING_SOURCE_APPLICATION,
+];
+/// The primary, or else first verified, email and phone number are used
+pub const CLAIM_MAPPING_USER_FIELDS: [&str; 8] = [
+  "id",
+  "username",
+  "email",
+  "email_verified",
+  "phone_number",
+  "phone_number_verified",
+  "updated_at",
+  "created_at",
+];
+pub const CLAIM_MAPPING_USER_INFO_FIELDS: [&str; 12] = [
+  "name",
+  "given_name",
+  "family_name",
+  "middle_name",
+  "nickname",
+  "profile_picture",
+  "website",
+  "gender",
+  "birthdate",
+  "zone_info",
+  "locale",
+  "address",
+];
+pub const CLAIM_MAPPING_APPLICATION_FIELDS: [&str; 2] = ["id", "name"];
+
+/// Whether `field` can be mapped from `source`, static mappings accept any value.
+pub fn is_valid_claim_mapping_field(source: &str, field: &str) -> bool {
+  match source {
+    CLAIM_MAPPING_SOURCE_STATIC => true,
+    CLAIM_MAPPING_SOURCE_USER => CLAIM_MAPPING_USER_FIELDS.contains(&field),
+    CLAIM_MAPPING_SOURCE_USER_INFO => CLAIM_MAPPING_USER_INFO_FIELDS.contains(&field),
+    CLAIM_MAPPING_SOURCE_APPLICATION => CLAIM_MAPPING_APPLICATION_FIELDS.contains(&field),
+    _ => false,
+  }
+}
+
+#[derive(Clone, sqlx::FromRow)]
+pub struct TenantClaimMappingRow {
+  pub id: i64,
+  pub tenant_id: i64,
+  pub claim: String,
+  pub source: String,
+  /// The JSON value of static mappings, otherwise the name of the mapped field
+  pub value: String,
+  pub access_token: i64,
+  pub id_token: i64,
+  pub updated_at: i64,
+  pub created_at: i64,
+}
+
+impl TenantClaimMappingRow {
+  pub fn is_static(&self) -> bool {
+    self.source == CLAIM_MAPPING_SOURCE_STATIC
+  }
+  pub fn in_access_token(&self) -> bool {
+    self.access_token != 0
+  }
+  pub fn in_id_token(&self) -> bool {
+    self.id_token != 0
+  }
+  pub fn static_value(&self) -> serde_json::Value {
+    serde_json::from_str(&self.value).unwrap_or_else(|_| self.value.clone().into())
+  }
+}
+
+pub async fn get_tenant_claim_mappings(
+  pool: &sqlx::AnyPool,
+  tenant_id: i64,
+) -> sqlx::Result<Vec<TenantClaimMappingRow>> {
+  sqlx::query_as(
+    r#"SELECT tcm.*
+    FROM tenant_claim_mappings tcm
+    WHERE tcm.tenant_id = $1
+    ORDER BY tcm.claim ASC;"#,
+  )
+  .bind(tenant_id)
+  .fetch_all(pool)
+  .await
+}
+
+pub async fn get_tenant_claim_mapping_by_id(
+  pool: &sqlx::AnyPool,
+  tenant_id: i64,
+  tenant_claim_mapping_id: i64,
+) -> sqlx::Result<Option<TenantClaimMappingRow>> {
+  sqlx::query_as(
+    r#"SELECT tcm.*
+    FROM tenant_claim_mappings tcm
+    WHERE tcm.tenant_id = $1 AND tcm.id = $2
+    LIMIT 1;"#,
+  )
+  .bind(tenant_id)
+  .bind(tenant_claim_mapping_id)
+  .fetch_optional(pool)
+  .await
+}
+
+pub struct CreateTenantClaimMapping {
+  pub claim: String,
+  pub source: String,
+  pub value: String,
+  pub access_token: i64,
+  pub id_token: i64,
+}
+
+pub async fn create_tenant_claim_mapping(
+  pool: &sqlx::AnyPool,
+  tenant_id: i64,
+  params: CreateTenantClaimMapping,
+) -> sqlx::Result<TenantClaimMappingRow> {
+  sqlx::query_as(
+    r#"INSERT INTO tenant_claim_mappings (tenant_id, claim, source, value, access_token, id_token)
+    VALUES ($1, $2, $3, $4, $5, $6)
+    RETURNING *;"#,
+  )
+  .bind(tenant_id)
+  .bind(params.claim)
+  .bind(params.source)
+  .bind(params.value)
+  .bind(params.access_token)
+  .bind(params.id_token)
+  .fetch_one(pool)
+  .await
+}
+
+#[derive(Default)]
+pub struct UpdateTenantClaimMapping {
+  pub claim: Option<String>,
+  pub source: Option<String>,
+  pub value: Option<String>,
+  pub access_token: Option<i64>,
+  pub id_token: Option<i64>,
+}
+
+pub async fn update_tenant_claim_mapping(
+  pool: &sqlx::AnyPool,
+  tenant_id: i64,
+  tenant_claim_mapping_id: i64,
+  params: UpdateTenantClaimMapping,
+) -> sqlx::Result<Option<TenantClaimMappingRow>> {
+  sqlx::query_as(
+    r#"UPDATE tenant_claim_mappings
+    SET claim = COALESCE($3, claim),
+        source = COALESCE($4, source),
+        value = COALESCE($5, value),
+        access_token = COALESCE($6, access_token),
+        id_token = COALESCE($7, id_token),
+        updated_at = $8
+    WHERE tenant_id = $1 AND id = $2
+    RETURNING *;"#,
+  )
+  .bind(tenant_id)
+  .bind(tenant_claim_mapping_id)
+  .bind(params.claim)
+  .bind(params.source)
+  .bind(params.value)
+  .bind(params.access_token)
+  .bind(params.id_token)
+  .bind(chrono::Utc::now().timestamp())
+  .fetch_optional(pool)
+  .await
+}
+
+pub async fn delete_tenant_claim_mapping(
+  pool: &sqlx::AnyPool,
+  tenant_id: i64,
+  tenant_claim_mapping_id: i64,
+) -> sqlx::Result<Option<TenantClaimMappingRow>> {
+  sqlx::query_as(
+    r#"DELETE FROM tenant_claim_mappings
+    WHERE tenant_id = $1 AND id = $2
+    RETURNING *;"#,
+  )
+  .bind(tenant_id)
+  .bind(tenant_claim_mapping_id)
+  .fetch_optional(pool)
+  .await
+}
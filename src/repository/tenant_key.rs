@@ -0,0 +1,250 @@
+use crate::core::database::run_transaction;
+
+use super::tenant::TenantRow;
+
+pub const TENANT_KEY_STATE_NEXT: &str = "next";
+pub const TENANT_KEY_STATE_ACTIVE: &str = "active";
+pub const TENANT_KEY_STATE_RETIRING: &str = "retiring";
+pub const TENANT_KEY_STATE_REVOKED: &str = "revoked";
+
+#[derive(sqlx::FromRow)]
+pub struct TenantKeyRow {
+  pub id: i64,
+  pub tenant_id: i64,
+  pub version: i64,
+  pub state: String,
+  pub algorithm: String,
+  pub public_key: Option<String>,
+  pub private_key: String,
+  pub expires_at: Option<i64>,
+  pub updated_at: i64,
+  pub created_at: i64,
+}
+
+impl TenantKeyRow {
+  /// Keys that have not been revoked or expired can still verify tokens they signed.
+  pub fn is_verifiable(&self) -> bool {
+    match self.state.as_str() {
+      TENANT_KEY_STATE_ACTIVE => true,
+      TENANT_KEY_STATE_RETIRING => !self.is_expired(),
+      _ => false,
+    }
+  }
+
+  /// Keys that are published in the tenant's JSON Web Key Set.
+  pub fn is_published(&self) -> bool {
+    self.state == TENANT_KEY_STATE_NEXT || self.is_verifiable()
+  }
+
+  pub fn is_expired(&self) -> bool {
+    self
+      .expires_at
+      .map(|expires_at| expires_at <= chrono::Utc::now().timestamp())
+      .unwrap_or(false)
+  }
+}
+
+pub async fn get_tenant_keys(
+  pool: &sqlx::AnyPool,
+  tenant_id: i64,
+) -> sqlx::Result<Vec<TenantKeyRow>> {
+  sqlx::query_as(
+    r#"SELECT tk.*
+    FROM tenant_keys tk
+    WHERE tk.tenant_id = $1
+    ORDER BY tk.version DESC;"#,
+  )
+  .bind(tenant_id)
+  .fetch_all(pool)
+  .await
+}
+
+pub async fn get_tenant_key_by_version(
+  pool: &sqlx::AnyPool,
+  tenant_id: i64,
+  version: i64,
+) -> sqlx::Result<Option<TenantKeyRow>> {
+  sqlx::query_as(
+    r#"SELECT tk.*
+    FROM tenant_keys tk
+    WHERE tk.tenant_id = $1 AND tk.version = $2
+    LIMIT 1;"#,
+  )
+  .bind(tenant_id)
+  .bind(version)
+  .fetch_optional(pool)
+  .await
+}
+
+pub async fn get_next_tenant_key(
+  pool: &sqlx::AnyPool,
+  tenant_id: i64,
+) -> sqlx::Result<Option<TenantKeyRow>> {
+  sqlx::query_as(
+    r#"SELECT tk.*
+    FROM tenant_keys tk
+    WHERE tk.tenant_id = $1 AND tk.state = $2
+    ORDER BY tk.version DESC
+    LIMIT 1;"#,
+  )
+  .bind(tenant_id)
+  .bind(TENANT_KEY_STATE_NEXT)
+  .fetch_optional(pool)
+  .await
+}
+
+pub struct CreateTenantKey {
+  pub algorithm: String,
+  pub public_key: Option<String>,
+  pub private_key: String,
+}
+
+pub async fn create_tenant_key(
+  pool: &sqlx::AnyPool,
+  tenant_id: i64,
+  params: CreateTenantKey,
+) -> sqlx::Result<TenantKeyRow> {
+  run_transaction(pool, |transaction| {
+    Box::pin(async move {
+      create_tenant_key_internal(transaction, tenant_id, TENANT_KEY_STATE_NEXT, params).await
+    })
+  })
+  .await
+}
+
+pub(crate) async fn create_tenant_key_internal(
+  transaction: &mut sqlx::Transaction<'_, sqlx::Any>,
+  tenant_id: i64,
+  state: &str,
+  params: CreateTenantKey,
+) -> sqlx::Result<TenantKeyRow> {
+  let (version,): (i64,) = sqlx::query_as(
+    r#"SELECT COALESCE(MAX(tk.version), 0) + 1 FROM tenant_keys tk WHERE tk.tenant_id = $1;"#,
+  )
+  .bind(tenant_id)
+  .fetch_one(&mut **transaction)
+  .await?;
+
+  sqlx::query_as(
+    r#"INSERT INTO tenant_keys
+      (tenant_id, version, state, algorithm, public_key, private_key)
+      VALUES ($1, $2, $3, $4, $5, $6)
+      RETURNING *;"#,
+  )
+  .bind(tenant_id)
+  .bind(version)
+  .bind(state)
+  .bind(params.algorithm)
+  .bind(params.public_key)
+  .bind(params.private_key)
+  .fetch_one(&mut **transaction)
+  .await
+}
+
+/// Promotes the newest `next` key to `active`, the current active key is moved to `retiring`
+/// and stays valid for verification until `retiring_expires_at`. When `new_key` is given it is
+/// created first and becomes the active key.
+pub async fn rotate_tenant_key(
+  pool: &sqlx::AnyPool,
+  tenant_id: i64,
+  retiring_expires_at: i64,
+  new_key: Option<CreateTenantKey>,
+) -> sqlx::Result<Option<TenantRow>> {
+  run_transaction(pool, |transaction| {
+    Box::pin(async move {
+      let next_key = match new_key {
+        Some(new_key) => Some(
+          create_tenant_key_internal(transaction, tenant_id, TENANT_KEY_STATE_NEXT, new_key)
+            .await?,
+        ),
+        None => {
+          sqlx::query_as::<_, TenantKeyRow>(
+            r#"SELECT tk.*
+            FROM tenant_keys tk
+            WHERE tk.tenant_id = $1 AND tk.state = $2
+            ORDER BY tk.version DESC
+            LIMIT 1;"#,
+          )
+          .bind(tenant_id)
+          .bind(TENANT_KEY_STATE_NEXT)
+          .fetch_optional(&mut **transaction)
+          .await?
+        }
+      };
+      let next_key = match next_key {
+        Some(next_key) => next_key,
+        None => return Ok(None),
+      };
+      let now = chrono::Utc::now().timestamp();
+
+      sqlx::query(
+        r#"UPDATE tenant_keys SET
+          state = $2,
+          expires_at = $3,
+          updated_at = $4
+        WHERE tenant_id = $1 AND state = $5;"#,
+      )
+      .bind(tenant_id)
+      .bind(TENANT_KEY_STATE_RETIRING)
+      .bind(retiring_expires_at)
+      .bind(now)
+      .bind(TENANT_KEY_STATE_ACTIVE)
+      .execute(&mut **transaction)
+      .await?;
+
+      sqlx::query(
+        r#"UPDATE tenant_keys SET
+          state = $2,
+          updated_at = $3
+        WHERE id = $1;"#,
+      )
+      .bind(next_key.id)
+      .bind(TENANT_KEY_STATE_ACTIVE)
+      .bind(now)
+      .execute(&mut **transaction)
+      .await?;
+
+      sqlx::query_as(
+        r#"UPDATE tenants SET
+          algorithm = $2,
+          public_key = $3,
+          private_key = $4,
+          key_version = $5,
+          updated_at = $6
+        WHERE id = $1
+        RETURNING *;"#,
+      )
+      .bind(tenant_id)
+      .bind(next_key.algorithm)
+      .bind(next_key.public_key)
+      .bind(next_key.private_key)
+      .bind(next_key.version)
+      .bind(now)
+      .fetch_optional(&mut **transaction)
+      .await
+    })
+  })
+  .await
+}
+
+/// Revokes a key that is not the active signing key, tokens signed with it are rejected.
+pub async fn revoke_tenant_key(
+  pool: &sqlx::AnyPool,
+  tenant_id: i64,
+  version: i64,
+) -> sqlx::Result<Option<TenantKeyRow>> {
+  sqlx::query_as(
+    r#"UPDATE tenant_keys SET
+      state = $3,
+      updated_at = $4
+    WHERE tenant_id = $1 AND version = $2 AND state != $5
+    RETURNING *;"#,
+  )
+  .bind(tenant_id)
+  .bind(version)
+  .bind(TENANT_KEY_STATE_REVOKED)
+  .bind(chrono::Utc::now().timestamp())
+  .bind(TENANT_KEY_STATE_ACTIVE)
+  .fetch_optional(pool)
+  .await
+}
@@ -0,0 +1,131 @@
+use crate::core::database::run_transaction;
+
+#[derive(sqlx::FromRow)]
+pub struct TokenRevocationRow {
+  pub id: i64,
+  pub application_id: i64,
+  pub jti: Option<String>,
+  pub sub_type: Option<String>,
+  pub sub: Option<i64>,
+  pub revoked_at: i64,
+  pub created_at: i64,
+}
+
+/// Returns true when the token with `jti` was revoked, or when every token of its subject issued
+/// before a cutoff later than `iat` was revoked.
+pub async fn is_token_revoked(
+  pool: &sqlx::AnyPool,
+  application_id: i64,
+  jti: Option<&str>,
+  sub_type: &str,
+  sub: i64,
+  iat: i64,
+) -> sqlx::Result<bool> {
+  let revocation: Option<(i64,)> = sqlx::query_as(
+    r#"SELECT tr.id
+    FROM token_revocations tr
+    WHERE tr.application_id = $1 AND (
+      (tr.jti IS NOT NULL AND tr.jti = $2) OR
+      (tr.jti IS NULL AND tr.sub_type = $3 AND tr.sub = $4 AND tr.revoked_at > $5)
+    )
+    LIMIT 1;"#,
+  )
+  .bind(application_id)
+  .bind(jti)
+  .bind(sub_type)
+  .bind(sub)
+  .bind(iat)
+  .fetch_optional(pool)
+  .await?;
+  Ok(revocation.is_some())
+}
+
+pub struct RevokeToken {
+  pub jti: String,
+  pub sub_type: String,
+  pub sub: i64,
+}
+
+/// Revokes a single token, when it is a refresh token its refresh token family is revoked too.
+pub async fn revoke_token(
+  pool: &sqlx::AnyPool,
+  application_id: i64,
+  params: RevokeToken,
+) -> sqlx::Result<TokenRevocationRow> {
+  run_transaction(pool, |transaction| {
+    Box::pin(async move {
+      let now = chrono::Utc::now().timestamp();
+      sqlx::query(
+        r#"UPDATE refresh_token_families SET revoked_at = $2, updated_at = $2
+        WHERE revoked_at IS NULL AND id IN (
+          SELECT rt.refresh_token_family_id FROM refresh_tokens rt WHERE rt.jti = $1
+        );"#,
+      )
+      .bind(&params.jti)
+      .bind(now)
+      .execute(&mut **transaction)
+      .await?;
+
+      sqlx::query_as(
+        r#"INSERT INTO token_revocations (application_id, jti, sub_type, sub, revoked_at)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING *;"#,
+      )
+      .bind(application_id)
+      .bind(params.jti)
+      .bind(params.sub_type)
+      .bind(params.sub)
+      .bind(now)
+      .fetch_one(&mut **transaction)
+      .await
+    })
+  })
+  .await
+}
+
+/// Revokes every access and refresh token issued to the subject so far. Tokens only carry whole
+/// seconds, so the cutoff is the start of the next second and this returns once it has passed,
+/// tokens issued afterwards can never fall before it.
+pub async fn revoke_subject_tokens(
+  pool: &sqlx::AnyPool,
+  application_id: i64,
+  sub_type: &str,
+  sub: i64,
+) -> sqlx::Result<TokenRevocationRow> {
+  let sub_type = sub_type.to_owned();
+  let revocation: TokenRevocationRow = run_transaction(pool, |transaction| {
+    Box::pin(async move {
+      let now = chrono::Utc::now().timestamp();
+      sqlx::query(
+        r#"UPDATE refresh_token_families SET revoked_at = $4, updated_at = $4
+        WHERE revoked_at IS NULL AND sub_type = $2 AND sub = $3 AND tenant_id IN (
+          SELECT t.id FROM tenants t WHERE t.application_id = $1
+        );"#,
+      )
+      .bind(application_id)
+      .bind(&sub_type)
+      .bind(sub)
+      .bind(now)
+      .execute(&mut **transaction)
+      .await?;
+
+      sqlx::query_as(
+        r#"INSERT INTO token_revocations (application_id, sub_type, sub, revoked_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *;"#,
+      )
+      .bind(application_id)
+      .bind(sub_type)
+      .bind(sub)
+      .bind(now + 1)
+      .fetch_one(&mut **transaction)
+      .await
+    })
+  })
+  .await?;
+  let until_cutoff = revocation.revoked_at * 1000 - chrono::Utc::now().timestamp_millis();
+  if until_cutoff > 0 {
+    tokio::time::sleep(std::time::Duration::from_millis(until_cutoff as u64)).await;
+  }
+  Ok(revocation)
+}
@@ -258,7 +258,7 @@ pub async fn create_user_with_oauth2(
         r#"INSERT INTO user_oauth2_providers ("user_id", "tenant_oauth2_provider_id", "email") VALUES ($1, $2, $3);"#,
       )
       .bind(user.id)
-      .bind(&params.tenant_oauth2_provider_id)
+      .bind(params.tenant_oauth2_provider_id)
       .bind(&params.email)
       .execute(&mut **transaction)
       .await?;
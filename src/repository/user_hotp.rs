@@ -0,0 +1,162 @@
+use std::io;
+
+use totp_rs::Secret;
+
+use crate::core::database::run_transaction;
+
+#[derive(sqlx::FromRow)]
+pub struct UserHOTPRow {
+  pub user_id: i64,
+  pub algorithm: String,
+  pub digits: i64,
+  /// The next counter the token is expected to produce a code for
+  pub counter: i64,
+  pub secret: String,
+  pub updated_at: i64,
+  pub created_at: i64,
+}
+
+impl UserHOTPRow {
+  pub fn algorithm(&self) -> totp_rs::Algorithm {
+    match self.algorithm.as_str() {
+      "SHA1" => totp_rs::Algorithm::SHA1,
+      "SHA256" => totp_rs::Algorithm::SHA256,
+      "SHA512" => totp_rs::Algorithm::SHA512,
+      _ => totp_rs::Algorithm::SHA1,
+    }
+  }
+
+  /// HOTP is TOTP with a one second step where the counter takes the place of the time
+  fn hotp(&self) -> Result<totp_rs::TOTP, totp_rs::TotpUrlError> {
+    totp_rs::TOTP::new(
+      self.algorithm(),
+      self.digits as usize,
+      0,
+      1,
+      Secret::Encoded(self.secret.to_owned())
+        .to_bytes()
+        .unwrap_or_default(),
+    )
+  }
+
+  /// Returns the counter of the code within `look_ahead` counters of the stored one
+  pub fn verify(&self, code: &str, look_ahead: i64) -> Result<Option<i64>, io::Error> {
+    let hotp = self.hotp().map_err(|e| io::Error::other(e.to_string()))?;
+    Ok(
+      (self.counter..=(self.counter + look_ahead))
+        .find(|counter| hotp.check(code, *counter as u64)),
+    )
+  }
+
+  /// Returns the counter of `next_code` when it directly follows `code` within `look_ahead`
+  /// counters of the stored one
+  pub fn resync(
+    &self,
+    code: &str,
+    next_code: &str,
+    look_ahead: i64,
+  ) -> Result<Option<i64>, io::Error> {
+    let hotp = self.hotp().map_err(|e| io::Error::other(e.to_string()))?;
+    Ok(
+      (self.counter..=(self.counter + look_ahead))
+        .find(|counter| {
+          hotp.check(code, *counter as u64) && hotp.check(next_code, (*counter + 1) as u64)
+        })
+        .map(|counter| counter + 1),
+    )
+  }
+}
+
+pub async fn get_user_hotp_by_user_id(
+  pool: &sqlx::AnyPool,
+  user_id: i64,
+) -> sqlx::Result<Option<UserHOTPRow>> {
+  sqlx::query_as(
+    r#"SELECT uh.*
+    FROM user_hotps uh
+    WHERE uh.user_id = $1
+    LIMIT 1;"#,
+  )
+  .bind(user_id)
+  .fetch_optional(pool)
+  .await
+}
+
+pub struct CreateUserHOTP {
+  pub algorithm: String,
+  pub digits: i64,
+  pub counter: i64,
+  pub secret: String,
+}
+
+pub async fn create_user_hotp(
+  pool: &sqlx::AnyPool,
+  user_id: i64,
+  params: CreateUserHOTP,
+) -> sqlx::Result<UserHOTPRow> {
+  sqlx::query_as(
+    r#"INSERT INTO user_hotps (user_id, algorithm, digits, counter, secret)
+    VALUES ($1, $2, $3, $4, $5)
+    RETURNING *;"#,
+  )
+  .bind(user_id)
+  .bind(params.algorithm)
+  .bind(params.digits)
+  .bind(params.counter)
+  .bind(params.secret)
+  .fetch_one(pool)
+  .await
+}
+
+/// Moves the counter past the last accepted code, returns `None` when a concurrent request
+/// already moved it from `current_counter`
+pub async fn update_user_hotp_counter(
+  pool: &sqlx::AnyPool,
+  user_id: i64,
+  current_counter: i64,
+  counter: i64,
+) -> sqlx::Result<Option<UserHOTPRow>> {
+  sqlx::query_as(
+    r#"UPDATE user_hotps SET
+      counter = $3,
+      updated_at = $4
+    WHERE user_id = $1 AND counter = $2
+    RETURNING *;"#,
+  )
+  .bind(user_id)
+  .bind(current_counter)
+  .bind(counter)
+  .bind(chrono::Utc::now().timestamp())
+  .fetch_optional(pool)
+  .await
+}
+
+pub async fn delete_user_hotp(
+  pool: &sqlx::AnyPool,
+  user_id: i64,
+) -> sqlx::Result<Option<UserHOTPRow>> {
+  run_transaction(pool, |transaction| {
+    Box::pin(async move {
+      sqlx::query(
+        r#"UPDATE user_configs SET
+        mfa_type = NULL,
+        updated_at = $2
+        WHERE user_id = $1 AND mfa_type = 'hotp';"#,
+      )
+      .bind(user_id)
+      .bind(chrono::Utc::now().timestamp())
+      .execute(&mut **transaction)
+      .await?;
+
+      sqlx::query_as(
+        r#"DELETE FROM user_hotps
+        WHERE user_id = $1
+        RETURNING *;"#,
+      )
+      .bind(user_id)
+      .fetch_optional(&mut **transaction)
+      .await
+    })
+  })
+  .await
+}
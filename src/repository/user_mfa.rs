@@ -17,6 +17,11 @@ pub(crate) async fn get_user_mfa_types_by_user_id_internal(
     r#"SELECT ut.user_id, 'totp' as type 
       FROM user_totps ut 
       JOIN users u ON u.id = ut.user_id 
+      WHERE u.application_id = $1 AND u.id = $2 AND ut.active = 1
+      UNION
+      SELECT uh.user_id, 'hotp' as type 
+      FROM user_hotps uh 
+      JOIN users u ON u.id = uh.user_id 
       WHERE u.application_id = $1 AND u.id = $2
       UNION
       SELECT ue.user_id, 'email' as type 
@@ -27,7 +32,12 @@ pub(crate) async fn get_user_mfa_types_by_user_id_internal(
       SELECT upn.user_id, 'text' as type 
       FROM user_phone_numbers upn 
       JOIN users u ON u.id = upn.user_id 
-      WHERE u.application_id = $1 AND u.id = $2 AND upn."verified" = 1;"#,
+      WHERE u.application_id = $1 AND u.id = $2 AND upn."verified" = 1
+      UNION
+      SELECT uwc.user_id, 'webauthn' as type 
+      FROM user_webauthn_credentials uwc 
+      JOIN users u ON u.id = uwc.user_id 
+      WHERE u.application_id = $1 AND u.id = $2;"#,
   )
   .bind(application_id)
   .bind(user_id)
@@ -58,7 +68,16 @@ pub async fn get_users_mfa_types(
     r#"SELECT ut.user_id, 'totp' as type 
     FROM user_totps ut 
     JOIN users u ON u.id = ut.user_id 
-    WHERE ut.user_id IN (SELECT u.id"#,
+    WHERE ut.active = 1 AND ut.user_id IN (SELECT u.id"#,
+  );
+  from_users_query(&mut qb, application_id, limit, offset);
+  qb.push(")");
+  qb.push(" UNION ");
+  qb.push(
+    r#"SELECT uh.user_id, 'hotp' as type 
+    FROM user_hotps uh 
+    JOIN users u ON u.id = uh.user_id 
+    WHERE uh.user_id IN (SELECT u.id"#,
   );
   from_users_query(&mut qb, application_id, limit, offset);
   qb.push(")");
@@ -80,5 +99,14 @@ pub async fn get_users_mfa_types(
   );
   from_users_query(&mut qb, application_id, limit, offset);
   qb.push(")");
+  qb.push(" UNION ");
+  qb.push(
+    r#"SELECT uwc.user_id, 'webauthn' as type 
+    FROM user_webauthn_credentials uwc
+    JOIN users u ON u.id = uwc.user_id 
+    WHERE uwc.user_id IN (SELECT u.id"#,
+  );
+  from_users_query(&mut qb, application_id, limit, offset);
+  qb.push(")");
   qb.build_query_as().fetch_all(pool).await
 }
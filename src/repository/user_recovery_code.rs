@@ -0,0 +1,113 @@
+use crate::core::database::run_transaction;
+
+#[derive(sqlx::FromRow)]
+pub struct UserRecoveryCodeRow {
+  pub id: i64,
+  pub user_id: i64,
+  /// Argon2 hash of the code, the code itself is only shown when it is generated
+  pub code: String,
+  /// Leading characters of the code, `None` for codes created before lookups were stored
+  pub lookup: Option<String>,
+  pub used_at: Option<i64>,
+  pub created_at: i64,
+}
+
+/// The unused codes a code with the lookup could be, codes without a lookup are always included
+pub async fn get_unused_user_recovery_codes_by_lookup(
+  pool: &sqlx::AnyPool,
+  user_id: i64,
+  lookup: &str,
+) -> sqlx::Result<Vec<UserRecoveryCodeRow>> {
+  sqlx::query_as(
+    r#"SELECT urc.*
+    FROM user_recovery_codes urc
+    WHERE urc.user_id = $1 AND urc.used_at IS NULL AND (urc.lookup = $2 OR urc.lookup IS NULL)
+    ORDER BY urc.id;"#,
+  )
+  .bind(user_id)
+  .bind(lookup)
+  .fetch_all(pool)
+  .await
+}
+
+pub async fn count_unused_user_recovery_codes_by_user_id(
+  pool: &sqlx::AnyPool,
+  user_id: i64,
+) -> sqlx::Result<i64> {
+  sqlx::query_scalar(
+    r#"SELECT COUNT(*)
+    FROM user_recovery_codes urc
+    WHERE urc.user_id = $1 AND urc.used_at IS NULL;"#,
+  )
+  .bind(user_id)
+  .fetch_one(pool)
+  .await
+}
+
+pub struct CreateUserRecoveryCode {
+  pub lookup: String,
+  /// Argon2 hash of the code
+  pub code: String,
+}
+
+/// Replaces all of the user's recovery codes, used or not, with the new codes
+pub async fn replace_user_recovery_codes(
+  pool: &sqlx::AnyPool,
+  user_id: i64,
+  codes: Vec<CreateUserRecoveryCode>,
+) -> sqlx::Result<()> {
+  run_transaction(pool, |transaction| {
+    Box::pin(async move {
+      sqlx::query(
+        r#"DELETE FROM user_recovery_codes
+        WHERE user_id = $1;"#,
+      )
+      .bind(user_id)
+      .execute(&mut **transaction)
+      .await?;
+
+      for code in codes {
+        sqlx::query(
+          r#"INSERT INTO user_recovery_codes ("user_id", "code", "lookup")
+          VALUES ($1, $2, $3);"#,
+        )
+        .bind(user_id)
+        .bind(code.code)
+        .bind(code.lookup)
+        .execute(&mut **transaction)
+        .await?;
+      }
+
+      Ok(())
+    })
+  })
+  .await
+}
+
+/// Marks the code as used, returns `None` when it was already used
+pub async fn use_user_recovery_code(
+  pool: &sqlx::AnyPool,
+  id: i64,
+) -> sqlx::Result<Option<UserRecoveryCodeRow>> {
+  sqlx::query_as(
+    r#"UPDATE user_recovery_codes SET
+      "used_at" = $2
+    WHERE id = $1 AND used_at IS NULL
+    RETURNING *;"#,
+  )
+  .bind(id)
+  .bind(chrono::Utc::now().timestamp())
+  .fetch_optional(pool)
+  .await
+}
+
+pub async fn delete_user_recovery_codes(pool: &sqlx::AnyPool, user_id: i64) -> sqlx::Result<u64> {
+  sqlx::query(
+    r#"DELETE FROM user_recovery_codes
+    WHERE user_id = $1;"#,
+  )
+  .bind(user_id)
+  .execute(pool)
+  .await
+  .map(|result| result.rows_affected())
+}
@@ -0,0 +1,246 @@
+use crate::core::database::run_transaction;
+
+#[derive(sqlx::FromRow)]
+pub struct UserSessionRow {
+  pub id: i64,
+  pub tenant_id: i64,
+  pub user_id: i64,
+  pub refresh_token_family_id: i64,
+  pub oauth2_client_id: Option<i64>,
+  pub sid: String,
+  pub grant_type: Option<String>,
+  pub ip_address: Option<String>,
+  pub user_agent: Option<String>,
+  pub expires_at: i64,
+  pub revoked_at: Option<i64>,
+  pub last_seen_at: i64,
+  pub updated_at: i64,
+  pub created_at: i64,
+  pub amr: Option<String>,
+  pub auth_time: Option<i64>,
+}
+
+impl UserSessionRow {
+  /// How the user logged in to start the session.
+  pub fn amr(&self) -> Vec<String> {
+    self
+      .amr
+      .as_deref()
+      .unwrap_or_default()
+      .split_whitespace()
+      .map(ToOwned::to_owned)
+      .collect()
+  }
+
+  /// When the user logged in, sessions started before it was recorded use their creation time.
+  pub fn auth_time(&self) -> i64 {
+    self.auth_time.unwrap_or(self.created_at)
+  }
+}
+
+/// The user's sessions that are neither revoked nor expired, most recently used first.
+pub async fn get_user_sessions(
+  pool: &sqlx::AnyPool,
+  user_id: i64,
+) -> sqlx::Result<Vec<UserSessionRow>> {
+  sqlx::query_as(
+    r#"SELECT us.*
+    FROM user_sessions us
+    JOIN refresh_token_families rtf ON rtf.id = us.refresh_token_family_id
+    WHERE us.user_id = $1 AND us.revoked_at IS NULL AND rtf.revoked_at IS NULL AND us.expires_at > $2
+    ORDER BY us.last_seen_at DESC;"#,
+  )
+  .bind(user_id)
+  .bind(chrono::Utc::now().timestamp())
+  .fetch_all(pool)
+  .await
+}
+
+pub async fn get_user_session_by_sid(
+  pool: &sqlx::AnyPool,
+  sid: &str,
+) -> sqlx::Result<Option<UserSessionRow>> {
+  sqlx::query_as(
+    r#"SELECT us.*
+    FROM user_sessions us
+    WHERE us.sid = $1
+    LIMIT 1;"#,
+  )
+  .bind(sid)
+  .fetch_optional(pool)
+  .await
+}
+
+/// Returns true when the session, or the refresh token family behind it, was revoked.
+pub async fn is_user_session_revoked(pool: &sqlx::AnyPool, sid: &str) -> sqlx::Result<bool> {
+  let revoked: Option<(i64,)> = sqlx::query_as(
+    r#"SELECT us.id
+    FROM user_sessions us
+    JOIN refresh_token_families rtf ON rtf.id = us.refresh_token_family_id
+    WHERE us.sid = $1 AND (us.revoked_at IS NOT NULL OR rtf.revoked_at IS NOT NULL)
+    LIMIT 1;"#,
+  )
+  .bind(sid)
+  .fetch_optional(pool)
+  .await?;
+  Ok(revoked.is_some())
+}
+
+pub struct CreateUserSession {
+  pub tenant_id: i64,
+  pub user_id: i64,
+  pub refresh_token_family_id: i64,
+  pub oauth2_client_id: Option<i64>,
+  pub grant_type: Option<String>,
+  pub ip_address: Option<String>,
+  pub user_agent: Option<String>,
+  pub expires_at: i64,
+  pub amr: Vec<String>,
+  pub auth_time: i64,
+}
+
+pub async fn create_user_session(
+  pool: &sqlx::AnyPool,
+  params: CreateUserSession,
+) -> sqlx::Result<UserSessionRow> {
+  sqlx::query_as(
+    r#"INSERT INTO user_sessions (tenant_id, user_id, refresh_token_family_id, oauth2_client_id, sid, grant_type, ip_address, user_agent, expires_at, amr, auth_time)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+    RETURNING *;"#,
+  )
+  .bind(params.tenant_id)
+  .bind(params.user_id)
+  .bind(params.refresh_token_family_id)
+  .bind(params.oauth2_client_id)
+  .bind(uuid::Uuid::new_v4().to_string())
+  .bind(params.grant_type)
+  .bind(params.ip_address)
+  .bind(params.user_agent)
+  .bind(params.expires_at)
+  .bind(Some(params.amr.join(" ")).filter(|amr| !amr.is_empty()))
+  .bind(params.auth_time)
+  .fetch_one(pool)
+  .await
+}
+
+/// Records the use of the session behind a refresh token family and extends it until the new
+/// refresh token expires.
+pub async fn touch_user_session_by_refresh_token_family_id(
+  pool: &sqlx::AnyPool,
+  refresh_token_family_id: i64,
+  expires_at: i64,
+) -> sqlx::Result<Option<UserSessionRow>> {
+  sqlx::query_as(
+    r#"UPDATE user_sessions
+    SET expires_at = $2, last_seen_at = $3, updated_at = $3
+    WHERE refresh_token_family_id = $1 AND revoked_at IS NULL
+    RETURNING *;"#,
+  )
+  .bind(refresh_token_family_id)
+  .bind(expires_at)
+  .bind(chrono::Utc::now().timestamp())
+  .fetch_optional(pool)
+  .await
+}
+
+/// Revokes one of the user's sessions and its refresh token family.
+pub async fn revoke_user_session(
+  pool: &sqlx::AnyPool,
+  user_id: i64,
+  user_session_id: i64,
+) -> sqlx::Result<Option<UserSessionRow>> {
+  run_transaction(pool, |transaction| {
+    Box::pin(async move {
+      let now = chrono::Utc::now().timestamp();
+      let row: Option<UserSessionRow> = sqlx::query_as(
+        r#"UPDATE user_sessions SET revoked_at = $3, updated_at = $3
+        WHERE user_id = $1 AND id = $2 AND revoked_at IS NULL
+        RETURNING *;"#,
+      )
+      .bind(user_id)
+      .bind(user_session_id)
+      .bind(now)
+      .fetch_optional(&mut **transaction)
+      .await?;
+      if let Some(row) = row.as_ref() {
+        sqlx::query(
+          r#"UPDATE refresh_token_families SET revoked_at = $2, updated_at = $2
+          WHERE id = $1 AND revoked_at IS NULL;"#,
+        )
+        .bind(row.refresh_token_family_id)
+        .bind(now)
+        .execute(&mut **transaction)
+        .await?;
+      }
+      Ok(row)
+    })
+  })
+  .await
+}
+
+/// Revokes every live session the user has with the tenant and their refresh token families,
+/// returning the sessions that ended.
+pub async fn revoke_tenant_user_sessions(
+  pool: &sqlx::AnyPool,
+  tenant_id: i64,
+  user_id: i64,
+) -> sqlx::Result<Vec<UserSessionRow>> {
+  run_transaction(pool, |transaction| {
+    Box::pin(async move {
+      let now = chrono::Utc::now().timestamp();
+      let rows: Vec<UserSessionRow> = sqlx::query_as(
+        r#"UPDATE user_sessions SET revoked_at = $3, updated_at = $3
+        WHERE tenant_id = $1 AND user_id = $2 AND revoked_at IS NULL AND expires_at > $3
+        RETURNING *;"#,
+      )
+      .bind(tenant_id)
+      .bind(user_id)
+      .bind(now)
+      .fetch_all(&mut **transaction)
+      .await?;
+      for row in rows.iter() {
+        sqlx::query(
+          r#"UPDATE refresh_token_families SET revoked_at = $2, updated_at = $2
+          WHERE id = $1 AND revoked_at IS NULL;"#,
+        )
+        .bind(row.refresh_token_family_id)
+        .bind(now)
+        .execute(&mut **transaction)
+        .await?;
+      }
+      Ok(rows)
+    })
+  })
+  .await
+}
+
+/// Revokes every session of the user and their refresh token families.
+pub async fn revoke_user_sessions(pool: &sqlx::AnyPool, user_id: i64) -> sqlx::Result<()> {
+  run_transaction(pool, |transaction| {
+    Box::pin(async move {
+      let now = chrono::Utc::now().timestamp();
+      sqlx::query(
+        r#"UPDATE refresh_token_families SET revoked_at = $2, updated_at = $2
+        WHERE revoked_at IS NULL AND id IN (
+          SELECT us.refresh_token_family_id
+          FROM user_sessions us
+          WHERE us.user_id = $1 AND us.revoked_at IS NULL
+        );"#,
+      )
+      .bind(user_id)
+      .bind(now)
+      .execute(&mut **transaction)
+      .await?;
+      sqlx::query(
+        r#"UPDATE user_sessions SET revoked_at = $2, updated_at = $2
+        WHERE user_id = $1 AND revoked_at IS NULL;"#,
+      )
+      .bind(user_id)
+      .bind(now)
+      .execute(&mut **transaction)
+      .await?;
+      Ok(())
+    })
+  })
+  .await
+}
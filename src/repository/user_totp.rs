@@ -14,6 +14,8 @@ pub struct UserTOTPRow {
   pub secret: String,
   pub updated_at: i64,
   pub created_at: i64,
+  /// The time step of the last accepted code, older and equal steps are rejected as replays
+  pub last_used_step: Option<i64>,
 }
 
 impl UserTOTPRow {
@@ -34,7 +36,7 @@ impl UserTOTPRow {
     totp_rs::TOTP::new(
       self.algorithm(),
       self.digits as usize,
-      1,
+      0,
       self.step as u64,
       Secret::Encoded(self.secret.to_owned())
         .to_bytes()
@@ -42,17 +44,52 @@ impl UserTOTPRow {
     )
   }
 
-  pub fn verify(&self, code: &str) -> Result<bool, io::Error> {
-    match self.totp() {
-      Ok(totp) => match totp.check_current(code) {
-        Ok(valid) => Ok(valid),
-        Err(e) => Err(io::Error::new(io::ErrorKind::Other, e.to_string())),
-      },
-      Err(e) => Err(io::Error::new(io::ErrorKind::Other, e.to_string())),
-    }
+  /// Returns the time step of the code within `skew` steps of the current one, `None` when the
+  /// code is wrong or its step was already used.
+  pub fn verify(&self, code: &str, skew: u8) -> Result<Option<i64>, io::Error> {
+    let totp = self.totp().map_err(|e| io::Error::other(e.to_string()))?;
+    let current_step = chrono::Utc::now().timestamp() / self.step;
+    let skew = skew as i64;
+    Ok(
+      ((current_step - skew)..=(current_step + skew))
+        .filter(|step| {
+          self
+            .last_used_step
+            .is_none_or(|last_used_step| *step > last_used_step)
+        })
+        .find(|step| totp.check(code, (step * self.step) as u64)),
+    )
+  }
+
+  /// The `otpauth://` URI authenticator apps import, usually from a QR code
+  pub fn provisioning_uri(&self, issuer: &str, account_name: &str) -> String {
+    format!(
+      "otpauth://totp/{issuer}:{account_name}?secret={secret}&issuer={issuer}&algorithm={algorithm}&digits={digits}&period={step}",
+      issuer = urlencoding::encode(issuer),
+      account_name = urlencoding::encode(account_name),
+      secret = self.secret,
+      algorithm = self.algorithm,
+      digits = self.digits,
+      step = self.step,
+    )
   }
 }
 
+pub async fn get_pending_user_totp_by_user_id(
+  pool: &sqlx::AnyPool,
+  user_id: i64,
+) -> sqlx::Result<Option<UserTOTPRow>> {
+  sqlx::query_as(
+    r#"SELECT ut.*
+    FROM user_totps ut
+    WHERE ut.user_id = $1 AND ut.active = 0
+    LIMIT 1;"#,
+  )
+  .bind(user_id)
+  .fetch_optional(pool)
+  .await
+}
+
 pub async fn get_user_totp_by_user_id(
   pool: &sqlx::AnyPool,
   user_id: i64,
@@ -76,14 +113,25 @@ pub struct CreateUserTOTP {
   pub secret: String,
 }
 
+/// Creates a pending TOTP, or replaces one that was never confirmed. Returns `None` when the user
+/// already has an active TOTP.
 pub async fn create_user_totp(
   pool: &sqlx::AnyPool,
   user_id: i64,
   params: CreateUserTOTP,
-) -> sqlx::Result<UserTOTPRow> {
+) -> sqlx::Result<Option<UserTOTPRow>> {
   sqlx::query_as(
-    r#"INSERT INTO user_totps (user_id, algorithm, digits, step, secret)
-    VALUES ($1, $2, $3, $4, $5)
+    r#"INSERT INTO user_totps (user_id, active, algorithm, digits, step, secret)
+    VALUES ($1, 0, $2, $3, $4, $5)
+    ON CONFLICT (user_id) DO UPDATE SET
+      algorithm = EXCLUDED.algorithm,
+      digits = EXCLUDED.digits,
+      step = EXCLUDED.step,
+      secret = EXCLUDED.secret,
+      last_used_step = NULL,
+      updated_at = $6,
+      created_at = $6
+    WHERE user_totps.active = 0
     RETURNING *;"#,
   )
   .bind(user_id)
@@ -91,7 +139,48 @@ pub async fn create_user_totp(
   .bind(params.digits)
   .bind(params.step)
   .bind(params.secret)
-  .fetch_one(pool)
+  .bind(chrono::Utc::now().timestamp())
+  .fetch_optional(pool)
+  .await
+}
+
+/// Activates a pending TOTP after the first valid code, at time step `step`
+pub async fn confirm_user_totp(
+  pool: &sqlx::AnyPool,
+  user_id: i64,
+  step: i64,
+) -> sqlx::Result<Option<UserTOTPRow>> {
+  sqlx::query_as(
+    r#"UPDATE user_totps SET
+      active = 1,
+      last_used_step = $2,
+      updated_at = $3
+    WHERE user_id = $1 AND active = 0
+    RETURNING *;"#,
+  )
+  .bind(user_id)
+  .bind(step)
+  .bind(chrono::Utc::now().timestamp())
+  .fetch_optional(pool)
+  .await
+}
+
+/// Records the time step of an accepted code, returns `None` when a concurrent request already
+/// used it or a later step
+pub async fn use_user_totp(
+  pool: &sqlx::AnyPool,
+  user_id: i64,
+  step: i64,
+) -> sqlx::Result<Option<UserTOTPRow>> {
+  sqlx::query_as(
+    r#"UPDATE user_totps SET
+      last_used_step = $2
+    WHERE user_id = $1 AND active = 1 AND (last_used_step IS NULL OR last_used_step < $2)
+    RETURNING *;"#,
+  )
+  .bind(user_id)
+  .bind(step)
+  .fetch_optional(pool)
   .await
 }
 
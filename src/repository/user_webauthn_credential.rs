@@ -0,0 +1,135 @@
+use crate::core::database::run_transaction;
+
+#[derive(sqlx::FromRow)]
+pub struct UserWebAuthnCredentialRow {
+  pub id: i64,
+  pub user_id: i64,
+  /// Base64url encoded credential id
+  pub credential_id: String,
+  /// Base64url encoded COSE public key
+  pub public_key: String,
+  pub sign_count: i64,
+  pub name: String,
+  pub last_used_at: Option<i64>,
+  pub updated_at: i64,
+  pub created_at: i64,
+}
+
+pub async fn get_user_webauthn_credentials_by_user_id(
+  pool: &sqlx::AnyPool,
+  user_id: i64,
+) -> sqlx::Result<Vec<UserWebAuthnCredentialRow>> {
+  sqlx::query_as(
+    r#"SELECT uwc.*
+    FROM user_webauthn_credentials uwc
+    WHERE uwc.user_id = $1
+    ORDER BY uwc.id;"#,
+  )
+  .bind(user_id)
+  .fetch_all(pool)
+  .await
+}
+
+pub async fn get_webauthn_credential_by_credential_id(
+  pool: &sqlx::AnyPool,
+  application_id: i64,
+  credential_id: &str,
+) -> sqlx::Result<Option<UserWebAuthnCredentialRow>> {
+  sqlx::query_as(
+    r#"SELECT uwc.*
+    FROM user_webauthn_credentials uwc
+    JOIN users u ON u.id = uwc.user_id
+    WHERE u.application_id = $1 AND uwc.credential_id = $2
+    LIMIT 1;"#,
+  )
+  .bind(application_id)
+  .bind(credential_id)
+  .fetch_optional(pool)
+  .await
+}
+
+pub struct CreateUserWebAuthnCredential {
+  pub credential_id: String,
+  pub public_key: String,
+  pub sign_count: i64,
+  pub name: String,
+}
+
+pub async fn create_user_webauthn_credential(
+  pool: &sqlx::AnyPool,
+  user_id: i64,
+  params: CreateUserWebAuthnCredential,
+) -> sqlx::Result<UserWebAuthnCredentialRow> {
+  sqlx::query_as(
+    r#"INSERT INTO user_webauthn_credentials ("user_id", "credential_id", "public_key", "sign_count", "name")
+    VALUES ($1, $2, $3, $4, $5)
+    RETURNING *;"#,
+  )
+  .bind(user_id)
+  .bind(params.credential_id)
+  .bind(params.public_key)
+  .bind(params.sign_count)
+  .bind(params.name)
+  .fetch_one(pool)
+  .await
+}
+
+/// Records a successful use of the credential with the authenticator's new signature counter
+pub async fn use_user_webauthn_credential(
+  pool: &sqlx::AnyPool,
+  id: i64,
+  sign_count: i64,
+) -> sqlx::Result<Option<UserWebAuthnCredentialRow>> {
+  let now = chrono::Utc::now().timestamp();
+  sqlx::query_as(
+    r#"UPDATE user_webauthn_credentials SET
+      "sign_count" = $2,
+      "last_used_at" = $3,
+      "updated_at" = $3
+    WHERE id = $1
+    RETURNING *;"#,
+  )
+  .bind(id)
+  .bind(sign_count)
+  .bind(now)
+  .fetch_optional(pool)
+  .await
+}
+
+pub async fn delete_user_webauthn_credential(
+  pool: &sqlx::AnyPool,
+  user_id: i64,
+  id: i64,
+) -> sqlx::Result<Option<UserWebAuthnCredentialRow>> {
+  run_transaction(pool, |transaction| {
+    Box::pin(async move {
+      let credential: Option<UserWebAuthnCredentialRow> = sqlx::query_as(
+        r#"DELETE FROM user_webauthn_credentials
+        WHERE user_id = $1 AND id = $2
+        RETURNING *;"#,
+      )
+      .bind(user_id)
+      .bind(id)
+      .fetch_optional(&mut **transaction)
+      .await?;
+
+      sqlx::query(
+        r#"UPDATE user_configs SET
+        mfa_type = NULL,
+        updated_at = $2
+        WHERE user_id = $1 AND mfa_type = 'webauthn' AND NOT EXISTS(
+          SELECT uwc.id
+          FROM user_webauthn_credentials uwc
+          WHERE uwc.user_id = $1
+        );"#,
+      )
+      .bind(user_id)
+      .bind(chrono::Utc::now().timestamp())
+      .execute(&mut **transaction)
+      .await?;
+
+      Ok(credential)
+    })
+  })
+  .await
+}
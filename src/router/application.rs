@@ -1,6 +1,12 @@
 use crate::{
   core::error::{Errors, InternalError, INTERNAL_ERROR, NOT_ALLOWED_ERROR, NOT_FOUND_ERROR},
-  middleware::{json::Json, service_account_authorization::ServiceAccountAuthorization},
+  middleware::{
+    json::Json,
+    service_account_authorization::{
+      require_service_account_scope, ServiceAccountAuthorization, SCOPE_APPLICATIONS_READ,
+      SCOPE_APPLICATIONS_WRITE,
+    },
+  },
   model::{
     application::{Application, ApplicationPagination, CreateApplication, UpdateApplication},
     util::OffsetAndLimit,
@@ -29,6 +35,7 @@ pub const APPLICATION_TAG: &str = "application";
   responses(
     (status = 200, content_type = "application/json", body = ApplicationPagination),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -38,10 +45,16 @@ pub const APPLICATION_TAG: &str = "application";
 pub async fn all_applications(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Query(query): Query<OffsetAndLimit>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_APPLICATIONS_READ)
+  {
+    return e.into_response();
+  }
   let rows = if service_account.is_admin() {
     match repository::application::get_applications(&state.pool, query.limit, query.offset).await {
       Ok(rows) => rows,
@@ -95,6 +108,7 @@ pub async fn all_applications(
   responses(
     (status = 200, content_type = "application/json", body = Application),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 404, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
@@ -105,10 +119,16 @@ pub async fn all_applications(
 pub async fn get_application_by_id(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path(application_id): Path<i64>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_APPLICATIONS_READ)
+  {
+    return e.into_response();
+  }
   if !service_account.is_admin() && service_account.application_id != application_id {
     return InternalError::unauthorized()
       .with_error("view-application", NOT_ALLOWED_ERROR)
@@ -142,6 +162,7 @@ pub async fn get_application_by_id(
     (status = 201, content_type = "application/json", body = Application),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -151,10 +172,16 @@ pub async fn get_application_by_id(
 pub async fn create_application(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Json(payload): Json<CreateApplication>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_APPLICATIONS_WRITE)
+  {
+    return e.into_response();
+  }
   if !service_account.is_admin() {
     return InternalError::unauthorized()
       .with_error("create-application", NOT_ALLOWED_ERROR)
@@ -189,6 +216,7 @@ pub async fn create_application(
     (status = 200, content_type = "application/json", body = Application),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -198,11 +226,17 @@ pub async fn create_application(
 pub async fn update_application(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path(application_id): Path<i64>,
   Json(payload): Json<UpdateApplication>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_APPLICATIONS_WRITE)
+  {
+    return e.into_response();
+  }
   if !service_account.is_admin() {
     return InternalError::unauthorized()
       .with_error("update-application", NOT_ALLOWED_ERROR)
@@ -241,6 +275,7 @@ pub async fn update_application(
   responses(
     (status = 204),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 404, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
@@ -251,10 +286,16 @@ pub async fn update_application(
 pub async fn delete_application(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path(application_id): Path<i64>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_APPLICATIONS_WRITE)
+  {
+    return e.into_response();
+  }
   if !service_account.is_admin() {
     return InternalError::unauthorized()
       .with_error("delete-application", NOT_ALLOWED_ERROR)
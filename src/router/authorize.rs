@@ -0,0 +1,434 @@
+use crate::{
+  core::{
+    config::Config,
+    error::{
+      Errors, InternalError, INTERNAL_ERROR, INVALID_ERROR, NOT_ALLOWED_ERROR, REQUIRED_ERROR,
+    },
+  },
+  middleware::{
+    authorization::parse_authorization,
+    claims::{acr_from_amr, acr_satisfies, BasicClaims, TOKEN_SUB_TYPE_USER, TOKEN_TYPE_BEARER},
+    json_or_form::JsonOrForm,
+    openid_claims::parse_scopes,
+    user_authorization::UserAuthorization,
+  },
+  model::authorize::{
+    AuthorizationCodeGrant, AuthorizeQuery, AuthorizeSession, AuthorizeSessionRequest,
+    AUTHORIZE_SESSION_COOKIE, CODE_CHALLENGE_METHOD_S256, RESPONSE_TYPE_CODE,
+  },
+  repository::{
+    kv,
+    oauth2_client::get_oauth2_client_by_client_id,
+    tenant::{get_tenant_by_oauth2_client_id, TenantRow},
+    user::{get_user_by_id, UserRow},
+    user_session::{get_user_session_by_sid, is_user_session_revoked, UserSessionRow},
+  },
+};
+
+use axum::{
+  extract::{Query, RawQuery, State},
+  response::{IntoResponse, Response},
+};
+use chrono::Duration;
+use http::{
+  header::{COOKIE, LOCATION, ORIGIN, SET_COOKIE},
+  HeaderMap, HeaderValue, StatusCode,
+};
+use reqwest::Url;
+use sqlx::AnyPool;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use super::RouterState;
+
+pub const AUTHORIZE_TAG: &str = "authorize";
+
+#[utoipa::path(
+  get,
+  path = "/authorize",
+  tags = [AUTHORIZE_TAG],
+  params(AuthorizeQuery),
+  responses(
+    (status = 302, description = "Redirects to the client's redirect uri with a `code` and `state`, or an `error` and `state`, users without a browser session are redirected to the login page"),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    (),
+    ("Authorization" = [])
+  )
+)]
+pub async fn authorize(
+  State(state): State<RouterState>,
+  authorization: Result<UserAuthorization, InternalError>,
+  headers: HeaderMap,
+  RawQuery(raw_query): RawQuery,
+  Query(query): Query<AuthorizeQuery>,
+) -> impl IntoResponse {
+  let oauth2_client = match get_oauth2_client_by_client_id(&state.pool, &query.client_id).await {
+    Ok(Some(oauth2_client)) if oauth2_client.is_active() => oauth2_client,
+    Ok(_) => {
+      return InternalError::bad_request()
+        .with_error("client_id", INVALID_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error getting OAuth2 client: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  let redirect_uri = match query.redirect_uri.as_deref() {
+    Some(redirect_uri) if oauth2_client.has_redirect_uri(redirect_uri) => redirect_uri.to_owned(),
+    Some(_) => {
+      return InternalError::bad_request()
+        .with_error("redirect_uri", INVALID_ERROR)
+        .into_response();
+    }
+    None => {
+      let mut redirect_uris = oauth2_client.redirect_uris();
+      match (redirect_uris.next(), redirect_uris.next()) {
+        (Some(redirect_uri), None) => redirect_uri.to_owned(),
+        _ => {
+          return InternalError::bad_request()
+            .with_error("redirect_uri", REQUIRED_ERROR)
+            .into_response();
+        }
+      }
+    }
+  };
+  let redirect_url = match Url::parse(&redirect_uri) {
+    Ok(redirect_url) => redirect_url,
+    Err(e) => {
+      log::error!("error parsing OAuth2 client redirect uri: {}", e);
+      return InternalError::bad_request()
+        .with_error("redirect_uri", INVALID_ERROR)
+        .into_response();
+    }
+  };
+  // the redirect uri is trusted from here on, so errors are sent back to the client
+  let state_param = query.state.as_deref();
+
+  if query.response_type != RESPONSE_TYPE_CODE {
+    return redirect_with_error(redirect_url, "unsupported_response_type", state_param)
+      .into_response();
+  }
+  // the plain method would send the code verifier in the clear, so only S256 is supported
+  match (
+    query.code_challenge.as_deref(),
+    query.code_challenge_method.as_deref(),
+  ) {
+    (Some(_), Some(CODE_CHALLENGE_METHOD_S256)) => {}
+    (None, None) if !oauth2_client.is_public() => {}
+    _ => {
+      return redirect_with_error(redirect_url, "invalid_request", state_param).into_response();
+    }
+  }
+  if !parse_scopes(query.scope.as_deref())
+    .iter()
+    .all(|scope| oauth2_client.allows_scope(scope))
+  {
+    return redirect_with_error(redirect_url, "invalid_scope", state_param).into_response();
+  }
+
+  let tenant = match get_tenant_by_oauth2_client_id(&state.pool, oauth2_client.id).await {
+    Ok(Some(tenant)) => tenant,
+    Ok(None) => {
+      log::error!("tenant not found for OAuth2 client: {}", oauth2_client.id);
+      return redirect_with_error(redirect_url, "server_error", state_param).into_response();
+    }
+    Err(e) => {
+      log::error!("error getting tenant for OAuth2 client: {}", e);
+      return redirect_with_error(redirect_url, "server_error", state_param).into_response();
+    }
+  };
+  let (user, amr, auth_time) = match authorization {
+    Ok(UserAuthorization { user, claims, .. }) if user.application_id == tenant.application_id => {
+      (user, claims.amr, claims.auth_time)
+    }
+    _ => match authorize_session_user(&state.pool, &tenant, &headers).await {
+      Ok(Some((user, session))) => (user, session.amr(), Some(session.auth_time())),
+      Ok(None) => return login_redirect(&state.config, raw_query.as_deref()),
+      Err(e) => {
+        log::error!("error getting authorize session user: {}", e);
+        return redirect_with_error(redirect_url, "server_error", state_param).into_response();
+      }
+    },
+  };
+  // logins older than the client accepts log in again, a login can not gain a second factor
+  if let Some(max_age) = query.max_age {
+    if auth_time.is_none_or(|auth_time| chrono::Utc::now().timestamp() - auth_time > max_age) {
+      return login_redirect(&state.config, raw_query.as_deref());
+    }
+  }
+  if let Some(acr_values) = query.acr_values.as_deref() {
+    if !acr_satisfies(acr_from_amr(&amr).as_deref(), acr_values) {
+      return redirect_with_error(
+        redirect_url,
+        "unmet_authentication_requirements",
+        state_param,
+      )
+      .into_response();
+    }
+  }
+
+  let code = oauth2::CsrfToken::new_random_len(32).secret().to_owned();
+  let grant = AuthorizationCodeGrant {
+    application_id: tenant.application_id,
+    tenant_id: tenant.id,
+    oauth2_client_id: oauth2_client.id,
+    user_id: user.id,
+    redirect_uri: query.redirect_uri,
+    scope: query.scope,
+    nonce: query.nonce,
+    code_challenge: query.code_challenge,
+    amr,
+    auth_time,
+  };
+  if !kv::set(
+    &state.pool,
+    AuthorizationCodeGrant::kv_key(&code),
+    &grant,
+    Some(Duration::seconds(
+      state.config.oauth2.code_timeout_in_seconds as i64,
+    )),
+  )
+  .await
+  {
+    log::error!("error storing authorization code");
+    return redirect_with_error(redirect_url, "server_error", state_param).into_response();
+  }
+
+  let mut redirect_url = redirect_url;
+  redirect_url.query_pairs_mut().append_pair("code", &code);
+  if let Some(state_param) = state_param {
+    redirect_url
+      .query_pairs_mut()
+      .append_pair("state", state_param);
+  }
+  redirect(redirect_url).into_response()
+}
+
+#[utoipa::path(
+  post,
+  path = "/authorize/session",
+  tags = [AUTHORIZE_TAG],
+  request_body(
+    content(
+      (AuthorizeSessionRequest = "application/json"),
+      (AuthorizeSessionRequest = "application/x-www-form-urlencoded"),
+    )
+  ),
+  responses(
+    (status = 303, description = "Sets the browser session cookie and redirects to `return_to`"),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  )
+)]
+pub async fn authorize_session(
+  State(state): State<RouterState>,
+  headers: HeaderMap,
+  JsonOrForm(payload): JsonOrForm<AuthorizeSessionRequest>,
+) -> Response {
+  if !is_trusted_origin(&state.config, &headers) {
+    return InternalError::forbidden()
+      .with_error("origin", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  let server_url = state.config.server.url.trim_end_matches('/');
+  let authorize_url = format!("{server_url}/authorize");
+  if payload.return_to != authorize_url
+    && !payload.return_to.starts_with(&format!("{authorize_url}?"))
+  {
+    return InternalError::bad_request()
+      .with_error("return_to", INVALID_ERROR)
+      .into_response();
+  }
+  let (_, token_data) =
+    match parse_authorization::<BasicClaims>(&state.pool, &payload.access_token).await {
+      Ok(result) => result,
+      Err(e) => {
+        log::error!("invalid authorize session access token: {}", e);
+        return InternalError::unauthorized()
+          .with_error("access_token", INVALID_ERROR)
+          .into_response();
+      }
+    };
+  let claims = token_data.claims;
+  let session = match claims.sid.as_deref() {
+    Some(sid) if claims.r#type == TOKEN_TYPE_BEARER && claims.sub_type == TOKEN_SUB_TYPE_USER => {
+      match get_user_session_by_sid(&state.pool, sid).await {
+        Ok(session) => session,
+        Err(e) => {
+          log::error!("error getting user session: {}", e);
+          return InternalError::internal_error()
+            .with_application_error(INTERNAL_ERROR)
+            .into_response();
+        }
+      }
+    }
+    _ => None,
+  };
+  let Some(session) = session else {
+    return InternalError::unauthorized()
+      .with_error("access_token", INVALID_ERROR)
+      .into_response();
+  };
+  let cookie_path = Url::parse(server_url)
+    .map(|url| format!("{}/authorize", url.path().trim_end_matches('/')))
+    .unwrap_or_else(|_| "/authorize".to_owned());
+  let max_age = (session.expires_at - chrono::Utc::now().timestamp()).max(0);
+  // the sid is in every token of the session, so the cookie holds a secret of its own
+  let secret = oauth2::CsrfToken::new_random_len(32).secret().to_owned();
+  if !kv::set(
+    &state.pool,
+    AuthorizeSession::kv_key(&secret),
+    &AuthorizeSession { sid: session.sid },
+    Some(Duration::seconds(max_age)),
+  )
+  .await
+  {
+    log::error!("error storing authorize session");
+    return InternalError::internal_error()
+      .with_application_error(INTERNAL_ERROR)
+      .into_response();
+  }
+  let secure = if server_url.starts_with("https://") {
+    "; Secure"
+  } else {
+    ""
+  };
+  let cookie = format!(
+    "{AUTHORIZE_SESSION_COOKIE}={secret}; Path={cookie_path}; Max-Age={max_age}; HttpOnly; SameSite=Lax{secure}"
+  );
+  match (
+    HeaderValue::try_from(payload.return_to),
+    HeaderValue::try_from(cookie),
+  ) {
+    (Ok(location), Ok(cookie)) => (
+      StatusCode::SEE_OTHER,
+      [(LOCATION, location), (SET_COOKIE, cookie)],
+    )
+      .into_response(),
+    _ => InternalError::bad_request()
+      .with_error("return_to", INVALID_ERROR)
+      .into_response(),
+  }
+}
+
+pub fn create_router(state: RouterState) -> OpenApiRouter {
+  OpenApiRouter::new()
+    .routes(routes!(authorize))
+    .routes(routes!(authorize_session))
+    .with_state(state)
+}
+
+/// The user and session of the browser session cookie, when its session is live and belongs to
+/// the tenant.
+async fn authorize_session_user(
+  pool: &AnyPool,
+  tenant: &TenantRow,
+  headers: &HeaderMap,
+) -> sqlx::Result<Option<(UserRow, UserSessionRow)>> {
+  let Some(secret) = cookie(headers, AUTHORIZE_SESSION_COOKIE) else {
+    return Ok(None);
+  };
+  let Some(AuthorizeSession { sid }) = kv::get(pool, AuthorizeSession::kv_key(secret)).await else {
+    return Ok(None);
+  };
+  let session = match get_user_session_by_sid(pool, &sid).await? {
+    Some(session)
+      if session.tenant_id == tenant.id
+        && session.revoked_at.is_none()
+        && session.expires_at > chrono::Utc::now().timestamp() =>
+    {
+      session
+    }
+    _ => return Ok(None),
+  };
+  if is_user_session_revoked(pool, &sid).await? {
+    return Ok(None);
+  }
+  Ok(
+    get_user_by_id(pool, tenant.application_id, session.user_id)
+      .await?
+      .filter(|user| user.is_active())
+      .map(|user| (user, session)),
+  )
+}
+
+/// Whether a browser request comes from this server or its login page, so other sites can not
+/// log the user's browser in to an account of their choosing. Browsers always send the origin of
+/// cross-site posts, requests without one are not from a browser.
+fn is_trusted_origin(config: &Config, headers: &HeaderMap) -> bool {
+  let Some(origin) = headers.get(ORIGIN) else {
+    return true;
+  };
+  let Ok(origin) = origin.to_str() else {
+    return false;
+  };
+  [&config.server.url, &config.oauth2.login_uri]
+    .into_iter()
+    .filter_map(|url| Url::parse(url).ok())
+    .any(|url| url.origin().ascii_serialization() == origin)
+}
+
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+  headers
+    .get_all(COOKIE)
+    .iter()
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(';'))
+    .filter_map(|cookie| cookie.trim().split_once('='))
+    .find(|(cookie_name, _)| *cookie_name == name)
+    .map(|(_, value)| value)
+}
+
+/// Sends the user to the login page, which returns to this authorize request once logged in.
+fn login_redirect(config: &Config, raw_query: Option<&str>) -> Response {
+  let mut login_url = match Url::parse(&config.oauth2.login_uri) {
+    Ok(login_url) => login_url,
+    Err(e) => {
+      log::error!("error parsing login uri: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  let mut return_to = format!("{}/authorize", config.server.url.trim_end_matches('/'));
+  if let Some(raw_query) = raw_query {
+    return_to.push('?');
+    return_to.push_str(raw_query);
+  }
+  login_url
+    .query_pairs_mut()
+    .append_pair("return_to", &return_to);
+  redirect(login_url).into_response()
+}
+
+fn redirect_with_error(
+  mut redirect_url: Url,
+  error: &str,
+  state: Option<&str>,
+) -> impl IntoResponse {
+  redirect_url.query_pairs_mut().append_pair("error", error);
+  if let Some(state) = state {
+    redirect_url.query_pairs_mut().append_pair("state", state);
+  }
+  redirect(redirect_url)
+}
+
+pub(crate) fn redirect(redirect_url: Url) -> impl IntoResponse {
+  let url_header = match HeaderValue::try_from(redirect_url.as_str()) {
+    Ok(url_header) => url_header,
+    Err(e) => {
+      log::error!("error converting url to header value URL: {}", e);
+      return InternalError::internal_error()
+        .with_error("redirect_uri", INVALID_ERROR)
+        .into_response();
+    }
+  };
+  (StatusCode::FOUND, [(LOCATION, url_header)]).into_response()
+}
@@ -15,7 +15,7 @@ use crate::{
     openid_claims::{
       has_address_scope, has_email_scope, has_phone_scope, has_profile_scope, parse_scopes,
     },
-    user_authorization::UserAuthorization,
+    user_authorization::{UserAuthorization, require_step_up},
     validated_json::ValidatedJson,
   },
   model::{
@@ -33,6 +33,7 @@ use crate::{
     user_oauth2_provider::get_user_oauth2_providers_by_user_id,
     user_password::{create_user_password, get_user_active_password_by_user_id},
     user_phone_number::get_user_phone_numbers_by_user_id,
+    user_recovery_code::count_unused_user_recovery_codes_by_user_id,
   },
 };
 
@@ -64,12 +65,12 @@ pub const CURRENT_USER_TAG: &str = "current-user";
 )]
 pub async fn get_current_user(
   State(state): State<RouterState>,
-  UserAuthorization { user, scopes, .. }: UserAuthorization,
+  UserAuthorization { user, claims, .. }: UserAuthorization,
 ) -> impl IntoResponse {
   let application_id = user.application_id;
   let mut current_user = User::from(user);
 
-  let show_email = has_email_scope(&scopes);
+  let show_email = has_email_scope(&claims.scopes);
   if show_email {
     let emails =
       match get_user_emails_by_user_id(&state.pool, application_id, current_user.id).await {
@@ -89,7 +90,7 @@ pub async fn get_current_user(
       }
     }
   }
-  if has_phone_scope(&scopes) {
+  if has_phone_scope(&claims.scopes) {
     let phone_numbers =
       match get_user_phone_numbers_by_user_id(&state.pool, application_id, current_user.id).await {
         Ok(phone_numbers) => phone_numbers,
@@ -145,9 +146,19 @@ pub async fn get_current_user(
   for row in mfa_types {
     current_user.mfa_types.push(row.into());
   }
+  current_user.recovery_codes_remaining =
+    match count_unused_user_recovery_codes_by_user_id(&state.pool, current_user.id).await {
+      Ok(count) => Some(count),
+      Err(e) => {
+        log::error!("error counting user recovery codes: {}", e);
+        return InternalError::internal_error()
+          .with_application_error(INTERNAL_ERROR)
+          .into_response();
+      }
+    };
 
-  let show_profile = has_profile_scope(&scopes);
-  let show_address = has_address_scope(&scopes);
+  let show_profile = has_profile_scope(&claims.scopes);
+  let show_address = has_address_scope(&claims.scopes);
   if show_address || show_profile {
     let maybe_user_info =
       match get_user_info_by_user_id(&state.pool, application_id, current_user.id).await {
@@ -327,6 +338,12 @@ pub async fn reset_current_user_password(
       .with_error(AUTHORIZATION_HEADER, "invalid-token-type")
       .into_response();
   }
+  // reset password tokens are only issued by a password login
+  if claims.r#type == TOKEN_TYPE_BEARER {
+    if let Err(e) = require_step_up(&state.pool, &state.config, &claims).await {
+      return e.into_response();
+    }
+  }
   let user_id = claims.sub;
 
   match get_user_active_password_by_user_id(&state.pool, user_id).await {
@@ -363,24 +380,21 @@ pub async fn reset_current_user_password(
   {
     Ok(_) => {}
     Err(e) => {
-      match &e {
-        sqlx::Error::Configuration(e) => {
-          if e.to_string().contains("password_already_used") {
-            return InternalError::bad_request()
-              .with_error(
-                "password",
-                (
-                  ALREADY_USED_ERROR,
-                  HashMap::from([(
-                    "password.history".to_owned(),
-                    json!(state.config.password.history),
-                  )]),
-                ),
-              )
-              .into_response();
-          }
+      if let sqlx::Error::Configuration(e) = &e {
+        if e.to_string().contains("password_already_used") {
+          return InternalError::bad_request()
+            .with_error(
+              "password",
+              (
+                ALREADY_USED_ERROR,
+                HashMap::from([(
+                  "password.history".to_owned(),
+                  json!(state.config.password.history),
+                )]),
+              ),
+            )
+            .into_response();
         }
-        _ => {}
       }
       log::error!("error creating user password: {}", e);
       return InternalError::internal_error()
@@ -508,8 +522,11 @@ pub async fn update_current_user_info(
 )]
 pub async fn deactivate_current_user(
   State(state): State<RouterState>,
-  UserAuthorization { user, .. }: UserAuthorization,
+  UserAuthorization { user, claims, .. }: UserAuthorization,
 ) -> impl IntoResponse {
+  if let Err(e) = require_step_up(&state.pool, &state.config, &claims).await {
+    return e.into_response();
+  }
   match repository::user::update_user(
     &state.pool,
     user.application_id,
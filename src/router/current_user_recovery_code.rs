@@ -0,0 +1,117 @@
+use axum::{extract::State, response::IntoResponse};
+use http::StatusCode;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+  core::{
+    encryption::encrypt_password,
+    error::{Errors, InternalError, INTERNAL_ERROR, NOT_FOUND_ERROR},
+  },
+  middleware::user_authorization::{require_step_up, UserAuthorization},
+  model::recovery_code::{
+    generate_recovery_codes, normalize_recovery_code, recovery_code_lookup, RecoveryCodes,
+  },
+  repository::user_recovery_code::{
+    delete_user_recovery_codes, replace_user_recovery_codes, CreateUserRecoveryCode,
+  },
+};
+
+use super::{current_user::CURRENT_USER_TAG, RouterState};
+
+#[utoipa::path(
+  post,
+  path = "/current-user/recovery-codes",
+  tags = [CURRENT_USER_TAG],
+  responses(
+    (status = 201, content_type = "application/json", body = RecoveryCodes),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn create_current_user_recovery_codes(
+  State(state): State<RouterState>,
+  UserAuthorization { user, claims, .. }: UserAuthorization,
+) -> impl IntoResponse {
+  if let Err(e) = require_step_up(&state.pool, &state.config, &claims).await {
+    return e.into_response();
+  }
+  let codes = generate_recovery_codes();
+  let hashed_codes = match codes
+    .iter()
+    .map(|code| {
+      let code = normalize_recovery_code(code);
+      encrypt_password(&state.config, &code).map(|hashed_code| CreateUserRecoveryCode {
+        lookup: recovery_code_lookup(&code),
+        code: hashed_code,
+      })
+    })
+    .collect::<Result<Vec<_>, _>>()
+  {
+    Ok(hashed_codes) => hashed_codes,
+    Err(e) => {
+      log::error!("error hashing recovery codes: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  if let Err(e) = replace_user_recovery_codes(&state.pool, user.id, hashed_codes).await {
+    log::error!("error creating user recovery codes: {}", e);
+    return InternalError::internal_error()
+      .with_application_error(INTERNAL_ERROR)
+      .into_response();
+  }
+
+  (StatusCode::CREATED, axum::Json(RecoveryCodes { codes })).into_response()
+}
+
+#[utoipa::path(
+  delete,
+  path = "/current-user/recovery-codes",
+  tags = [CURRENT_USER_TAG],
+  responses(
+    (status = 204),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn delete_current_user_recovery_codes(
+  State(state): State<RouterState>,
+  UserAuthorization { user, claims, .. }: UserAuthorization,
+) -> impl IntoResponse {
+  if let Err(e) = require_step_up(&state.pool, &state.config, &claims).await {
+    return e.into_response();
+  }
+  match delete_user_recovery_codes(&state.pool, user.id).await {
+    Ok(0) => {
+      return InternalError::not_found()
+        .with_error("recovery_code", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Ok(_) => {}
+    Err(e) => {
+      log::error!("error deleting user recovery codes: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  }
+
+  (StatusCode::NO_CONTENT, ()).into_response()
+}
+
+pub fn create_router(state: RouterState) -> OpenApiRouter {
+  OpenApiRouter::new()
+    .routes(routes!(
+      create_current_user_recovery_codes,
+      delete_current_user_recovery_codes
+    ))
+    .with_state(state)
+}
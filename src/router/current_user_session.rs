@@ -0,0 +1,117 @@
+use axum::{
+  extract::{Path, State},
+  response::IntoResponse,
+};
+use http::StatusCode;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+  core::error::{Errors, InternalError, INTERNAL_ERROR, NOT_FOUND_ERROR},
+  middleware::user_authorization::UserAuthorization,
+  model::user::UserSession,
+  repository::user_session::{get_user_sessions, revoke_user_session, revoke_user_sessions},
+};
+
+use super::{current_user::CURRENT_USER_TAG, RouterState};
+
+#[utoipa::path(
+  get,
+  path = "/current-user/sessions",
+  tags = [CURRENT_USER_TAG],
+  responses(
+    (status = 200, content_type = "application/json", body = Vec<UserSession>),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn current_user_sessions(
+  State(state): State<RouterState>,
+  UserAuthorization { user, .. }: UserAuthorization,
+) -> impl IntoResponse {
+  match get_user_sessions(&state.pool, user.id).await {
+    Ok(rows) => {
+      axum::Json(rows.into_iter().map(UserSession::from).collect::<Vec<_>>()).into_response()
+    }
+    Err(e) => {
+      log::error!("error getting user sessions: {e}");
+      InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response()
+    }
+  }
+}
+
+#[utoipa::path(
+  delete,
+  path = "/current-user/sessions",
+  tags = [CURRENT_USER_TAG],
+  responses(
+    (status = 204),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn delete_current_user_sessions(
+  State(state): State<RouterState>,
+  UserAuthorization { user, .. }: UserAuthorization,
+) -> impl IntoResponse {
+  if let Err(e) = revoke_user_sessions(&state.pool, user.id).await {
+    log::error!("error revoking user sessions: {e}");
+    return InternalError::internal_error()
+      .with_application_error(INTERNAL_ERROR)
+      .into_response();
+  }
+  (StatusCode::NO_CONTENT, ()).into_response()
+}
+
+#[utoipa::path(
+  delete,
+  path = "/current-user/sessions/{session_id}",
+  tags = [CURRENT_USER_TAG],
+  params(
+    ("session_id" = i64, Path, description = "Session ID to revoke"),
+  ),
+  responses(
+    (status = 204),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn delete_current_user_session(
+  State(state): State<RouterState>,
+  UserAuthorization { user, .. }: UserAuthorization,
+  Path(session_id): Path<i64>,
+) -> impl IntoResponse {
+  match revoke_user_session(&state.pool, user.id, session_id).await {
+    Ok(Some(_)) => {}
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("session", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error revoking user session={session_id}: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  (StatusCode::NO_CONTENT, ()).into_response()
+}
+
+pub fn create_router(state: RouterState) -> OpenApiRouter {
+  OpenApiRouter::new()
+    .routes(routes!(current_user_sessions, delete_current_user_sessions))
+    .routes(routes!(delete_current_user_session))
+    .with_state(state)
+}
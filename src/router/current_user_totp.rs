@@ -1,12 +1,17 @@
 use axum::{extract::State, response::IntoResponse};
-use http::StatusCode;
+use http::{header, StatusCode};
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
-  core::error::{Errors, InternalError, ALREADY_EXISTS_ERROR, INTERNAL_ERROR, NOT_FOUND_ERROR},
+  core::error::{
+    Errors, InternalError, ALREADY_EXISTS_ERROR, INTERNAL_ERROR, INVALID_ERROR, NOT_FOUND_ERROR,
+  },
   middleware::{json::Json, user_authorization::UserAuthorization},
-  model::totp::{CreateTOTPRequest, UserTOTP},
-  repository::user_totp::{create_user_totp, delete_user_totp, CreateUserTOTP},
+  model::totp::{ConfirmTOTPRequest, CreateTOTPRequest, UserTOTP},
+  repository::user_totp::{
+    confirm_user_totp, create_user_totp, delete_user_totp, get_pending_user_totp_by_user_id,
+    CreateUserTOTP,
+  },
 };
 
 use super::{current_user::CURRENT_USER_TAG, RouterState};
@@ -32,25 +37,43 @@ pub async fn create_current_user_totp(
   UserAuthorization { user, .. }: UserAuthorization,
   Json(payload): Json<CreateTOTPRequest>,
 ) -> impl IntoResponse {
+  let algorithm = payload.algorithm.unwrap_or("SHA1".to_owned());
+  let digits = payload.digits.unwrap_or(6);
+  let step = payload.step.unwrap_or(30);
+  if !matches!(algorithm.as_str(), "SHA1" | "SHA256" | "SHA512") {
+    return InternalError::bad_request()
+      .with_error("algorithm", INVALID_ERROR)
+      .into_response();
+  }
+  if !(6..=8).contains(&digits) {
+    return InternalError::bad_request()
+      .with_error("digits", INVALID_ERROR)
+      .into_response();
+  }
+  if step <= 0 {
+    return InternalError::bad_request()
+      .with_error("step", INVALID_ERROR)
+      .into_response();
+  }
   let totp = match create_user_totp(
     &state.pool,
     user.id,
     CreateUserTOTP {
       secret: totp_rs::Secret::generate_secret().to_encoded().to_string(),
-      algorithm: payload.algorithm.unwrap_or("SHA1".to_owned()),
-      digits: payload.digits.unwrap_or(6),
-      step: payload.step.unwrap_or(30),
+      algorithm,
+      digits,
+      step,
     },
   )
   .await
   {
-    Ok(totp) => totp,
+    Ok(Some(totp)) => totp,
+    Ok(None) => {
+      return InternalError::from(StatusCode::CONFLICT)
+        .with_error("totp", ALREADY_EXISTS_ERROR)
+        .into_response();
+    }
     Err(e) => {
-      if e.to_string().to_lowercase().contains("unique constraint") {
-        return InternalError::from(StatusCode::CONFLICT)
-          .with_error("totp", ALREADY_EXISTS_ERROR)
-          .into_response();
-      }
       log::error!("error creating user TOTP: {}", e);
       return InternalError::internal_error()
         .with_application_error(INTERNAL_ERROR)
@@ -58,7 +81,123 @@ pub async fn create_current_user_totp(
     }
   };
 
-  (StatusCode::CREATED, axum::Json(UserTOTP::from(totp))).into_response()
+  let uri = totp.provisioning_uri(&state.config.totp.issuer, &user.username);
+  let mut totp = UserTOTP::from(totp);
+  totp.uri = Some(uri);
+  (StatusCode::CREATED, axum::Json(totp)).into_response()
+}
+
+#[utoipa::path(
+  post,
+  path = "/current-user/totp/confirm",
+  tags = [CURRENT_USER_TAG],
+  request_body = ConfirmTOTPRequest,
+  responses(
+    (status = 200, content_type = "application/json", body = UserTOTP),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn confirm_current_user_totp(
+  State(state): State<RouterState>,
+  UserAuthorization { user, .. }: UserAuthorization,
+  Json(payload): Json<ConfirmTOTPRequest>,
+) -> impl IntoResponse {
+  let totp = match get_pending_user_totp_by_user_id(&state.pool, user.id).await {
+    Ok(Some(totp)) => totp,
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("totp", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error getting user TOTP: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  let step = match totp.verify(&payload.code, state.config.totp.skew) {
+    Ok(Some(step)) => step,
+    Ok(None) => {
+      return InternalError::bad_request()
+        .with_error("code", INVALID_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error verifying TOTP: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  match confirm_user_totp(&state.pool, user.id, step).await {
+    Ok(Some(totp)) => axum::Json(UserTOTP::from(totp)).into_response(),
+    Ok(None) => InternalError::not_found()
+      .with_error("totp", NOT_FOUND_ERROR)
+      .into_response(),
+    Err(e) => {
+      log::error!("error confirming user TOTP: {}", e);
+      InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response()
+    }
+  }
+}
+
+#[utoipa::path(
+  get,
+  path = "/current-user/totp/qr-code",
+  tags = [CURRENT_USER_TAG],
+  responses(
+    (status = 200, content_type = "image/svg+xml", body = String),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn current_user_totp_qr_code(
+  State(state): State<RouterState>,
+  UserAuthorization { user, .. }: UserAuthorization,
+) -> impl IntoResponse {
+  let totp = match get_pending_user_totp_by_user_id(&state.pool, user.id).await {
+    Ok(Some(totp)) => totp,
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("totp", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error getting user TOTP: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  let uri = totp.provisioning_uri(&state.config.totp.issuer, &user.username);
+  let qr_code = match qrcode::QrCode::new(uri.as_bytes()) {
+    Ok(qr_code) => qr_code,
+    Err(e) => {
+      log::error!("error creating TOTP QR code: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  let svg = qr_code
+    .render::<qrcode::render::svg::Color>()
+    .min_dimensions(200, 200)
+    .build();
+
+  ([(header::CONTENT_TYPE, "image/svg+xml")], svg).into_response()
 }
 
 #[utoipa::path(
@@ -100,5 +239,7 @@ pub async fn delete_current_user_totp(
 pub fn create_router(state: RouterState) -> OpenApiRouter {
   OpenApiRouter::new()
     .routes(routes!(create_current_user_totp, delete_current_user_totp))
+    .routes(routes!(confirm_current_user_totp))
+    .routes(routes!(current_user_totp_qr_code))
     .with_state(state)
 }
@@ -0,0 +1,295 @@
+use axum::{
+  extract::{Path, State},
+  response::IntoResponse,
+};
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use http::StatusCode;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+  core::{
+    error::{
+      Errors, InternalError, ALREADY_EXISTS_ERROR, INTERNAL_ERROR, INVALID_ERROR, NOT_FOUND_ERROR,
+    },
+    webauthn::{client_data_challenge, generate_challenge, verify_registration, COSE_ALGORITHMS},
+  },
+  middleware::{user_authorization::UserAuthorization, validated_json::ValidatedJson},
+  model::webauthn::{
+    CreateWebAuthnCredentialRequest, UserWebAuthnCredential, WebAuthnAuthenticatorSelection,
+    WebAuthnChallenge, WebAuthnCreationOptions, WebAuthnCredentialDescriptor,
+    WebAuthnCredentialParameters, WebAuthnRelyingParty, WebAuthnUser, PUBLIC_KEY_CREDENTIAL_TYPE,
+  },
+  repository::{
+    kv,
+    user_webauthn_credential::{
+      create_user_webauthn_credential, delete_user_webauthn_credential,
+      get_user_webauthn_credentials_by_user_id, CreateUserWebAuthnCredential,
+    },
+  },
+};
+
+use super::{current_user::CURRENT_USER_TAG, RouterState};
+
+#[utoipa::path(
+  get,
+  path = "/current-user/webauthn",
+  tags = [CURRENT_USER_TAG],
+  responses(
+    (status = 200, content_type = "application/json", body = Vec<UserWebAuthnCredential>),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn current_user_webauthn_credentials(
+  State(state): State<RouterState>,
+  UserAuthorization { user, .. }: UserAuthorization,
+) -> impl IntoResponse {
+  match get_user_webauthn_credentials_by_user_id(&state.pool, user.id).await {
+    Ok(rows) => axum::Json(
+      rows
+        .into_iter()
+        .map(UserWebAuthnCredential::from)
+        .collect::<Vec<_>>(),
+    )
+    .into_response(),
+    Err(e) => {
+      log::error!("error getting user webauthn credentials: {e}");
+      InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response()
+    }
+  }
+}
+
+#[utoipa::path(
+  post,
+  path = "/current-user/webauthn/options",
+  tags = [CURRENT_USER_TAG],
+  responses(
+    (status = 201, content_type = "application/json", body = WebAuthnCreationOptions),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn create_current_user_webauthn_options(
+  State(state): State<RouterState>,
+  UserAuthorization { user, .. }: UserAuthorization,
+) -> impl IntoResponse {
+  let credentials = match get_user_webauthn_credentials_by_user_id(&state.pool, user.id).await {
+    Ok(credentials) => credentials,
+    Err(e) => {
+      log::error!("error getting user webauthn credentials: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  let challenge = generate_challenge();
+  let timeout_in_seconds = state.config.webauthn.challenge_timeout_in_seconds;
+  if !kv::set(
+    &state.pool,
+    WebAuthnChallenge::kv_key(&challenge),
+    &WebAuthnChallenge {
+      application_id: user.application_id,
+      user_id: Some(user.id),
+    },
+    Some(chrono::Duration::seconds(timeout_in_seconds)),
+  )
+  .await
+  {
+    log::error!("error storing webauthn challenge");
+    return InternalError::internal_error()
+      .with_application_error(INTERNAL_ERROR)
+      .into_response();
+  }
+
+  (
+    StatusCode::CREATED,
+    axum::Json(WebAuthnCreationOptions {
+      challenge,
+      rp: WebAuthnRelyingParty {
+        id: state.config.webauthn.rp_id.clone(),
+        name: state.config.webauthn.rp_name.clone(),
+      },
+      user: WebAuthnUser {
+        id: BASE64_URL_SAFE_NO_PAD.encode(user.id.to_be_bytes()),
+        name: user.username.clone(),
+        display_name: user.username,
+      },
+      pub_key_cred_params: COSE_ALGORITHMS
+        .into_iter()
+        .map(|alg| WebAuthnCredentialParameters {
+          r#type: PUBLIC_KEY_CREDENTIAL_TYPE.to_owned(),
+          alg,
+        })
+        .collect(),
+      timeout: timeout_in_seconds * 1000,
+      exclude_credentials: credentials
+        .into_iter()
+        .map(WebAuthnCredentialDescriptor::from)
+        .collect(),
+      authenticator_selection: WebAuthnAuthenticatorSelection {
+        resident_key: "preferred".to_owned(),
+        user_verification: "preferred".to_owned(),
+      },
+      attestation: "none".to_owned(),
+    }),
+  )
+    .into_response()
+}
+
+#[utoipa::path(
+  post,
+  path = "/current-user/webauthn",
+  tags = [CURRENT_USER_TAG],
+  request_body = CreateWebAuthnCredentialRequest,
+  responses(
+    (status = 201, content_type = "application/json", body = UserWebAuthnCredential),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 409, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn create_current_user_webauthn_credential(
+  State(state): State<RouterState>,
+  UserAuthorization { user, .. }: UserAuthorization,
+  ValidatedJson(payload): ValidatedJson<CreateWebAuthnCredentialRequest>,
+) -> impl IntoResponse {
+  let (Ok(client_data_json), Ok(attestation_object)) = (
+    BASE64_URL_SAFE_NO_PAD.decode(&payload.client_data_json),
+    BASE64_URL_SAFE_NO_PAD.decode(&payload.attestation_object),
+  ) else {
+    return InternalError::bad_request()
+      .with_error("webauthn", INVALID_ERROR)
+      .into_response();
+  };
+  let challenge = match client_data_challenge(&client_data_json) {
+    Ok(challenge) => challenge,
+    Err(e) => {
+      log::error!("error parsing webauthn client data: {e}");
+      return InternalError::bad_request()
+        .with_error("webauthn", INVALID_ERROR)
+        .into_response();
+    }
+  };
+  match kv::delete::<_, WebAuthnChallenge>(&state.pool, WebAuthnChallenge::kv_key(&challenge)).await
+  {
+    Some(stored) if stored.user_id == Some(user.id) => {}
+    _ => {
+      return InternalError::bad_request()
+        .with_error("webauthn", INVALID_ERROR)
+        .into_response();
+    }
+  }
+  let authenticator_data = match verify_registration(
+    &state.config.webauthn,
+    &challenge,
+    &client_data_json,
+    &attestation_object,
+  ) {
+    Ok(authenticator_data) => authenticator_data,
+    Err(e) => {
+      log::error!("error verifying webauthn registration: {e}");
+      return InternalError::bad_request()
+        .with_error("webauthn", INVALID_ERROR)
+        .into_response();
+    }
+  };
+  let Some((credential_id, public_key)) = authenticator_data.credential else {
+    return InternalError::bad_request()
+      .with_error("webauthn", INVALID_ERROR)
+      .into_response();
+  };
+
+  let credential = match create_user_webauthn_credential(
+    &state.pool,
+    user.id,
+    CreateUserWebAuthnCredential {
+      credential_id: BASE64_URL_SAFE_NO_PAD.encode(credential_id),
+      public_key: BASE64_URL_SAFE_NO_PAD.encode(public_key),
+      sign_count: authenticator_data.sign_count as i64,
+      name: payload.name,
+    },
+  )
+  .await
+  {
+    Ok(credential) => credential,
+    Err(e) => {
+      if e.to_string().to_lowercase().contains("unique constraint") {
+        return InternalError::from(StatusCode::CONFLICT)
+          .with_error("webauthn", ALREADY_EXISTS_ERROR)
+          .into_response();
+      }
+      log::error!("error creating user webauthn credential: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+
+  (
+    StatusCode::CREATED,
+    axum::Json(UserWebAuthnCredential::from(credential)),
+  )
+    .into_response()
+}
+
+#[utoipa::path(
+  delete,
+  path = "/current-user/webauthn/{webauthn_credential_id}",
+  tags = [CURRENT_USER_TAG],
+  params(
+    ("webauthn_credential_id" = i64, Path, description = "WebAuthn credential ID to delete"),
+  ),
+  responses(
+    (status = 204),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn delete_current_user_webauthn_credential(
+  State(state): State<RouterState>,
+  UserAuthorization { user, .. }: UserAuthorization,
+  Path(webauthn_credential_id): Path<i64>,
+) -> impl IntoResponse {
+  match delete_user_webauthn_credential(&state.pool, user.id, webauthn_credential_id).await {
+    Ok(Some(_)) => {}
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("webauthn", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error deleting user webauthn credential={webauthn_credential_id}: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  }
+  (StatusCode::NO_CONTENT, ()).into_response()
+}
+
+pub fn create_router(state: RouterState) -> OpenApiRouter {
+  OpenApiRouter::new()
+    .routes(routes!(
+      current_user_webauthn_credentials,
+      create_current_user_webauthn_credential
+    ))
+    .routes(routes!(create_current_user_webauthn_options))
+    .routes(routes!(delete_current_user_webauthn_credential))
+    .with_state(state)
+}
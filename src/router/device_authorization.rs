@@ -0,0 +1,309 @@
+use crate::{
+  core::error::{Errors, InternalError, ALREADY_USED_ERROR, INTERNAL_ERROR, INVALID_ERROR},
+  middleware::{
+    json::Json, json_or_form::JsonOrForm, openid_claims::parse_scopes,
+    user_authorization::UserAuthorization,
+  },
+  model::device_authorization::{
+    ApproveDeviceAuthorization, DeviceAuthorization, DeviceAuthorizationRequest, DeviceCodeGrant,
+    PendingDeviceAuthorization, PendingDeviceAuthorizationQuery,
+  },
+  repository::{
+    kv,
+    oauth2_client::{get_oauth2_client_by_client_id, get_oauth2_client_by_id},
+    tenant::{get_tenant_by_oauth2_client_id, TenantRow},
+  },
+};
+
+use axum::{
+  extract::{Query, State},
+  response::IntoResponse,
+};
+use chrono::Duration;
+use http::{header::CACHE_CONTROL, StatusCode};
+use reqwest::Url;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use super::RouterState;
+
+pub const DEVICE_AUTHORIZATION_TAG: &str = "device-authorization";
+
+#[utoipa::path(
+  post,
+  path = "/device-authorization",
+  tags = [DEVICE_AUTHORIZATION_TAG],
+  request_body(
+    content(
+      (DeviceAuthorizationRequest = "application/json"),
+      (DeviceAuthorizationRequest = "application/x-www-form-urlencoded"),
+    )
+  ),
+  responses(
+    (status = 200, content_type = "application/json", body = DeviceAuthorization),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  )
+)]
+pub async fn device_authorization(
+  State(state): State<RouterState>,
+  JsonOrForm(payload): JsonOrForm<DeviceAuthorizationRequest>,
+) -> impl IntoResponse {
+  let oauth2_client = match get_oauth2_client_by_client_id(&state.pool, &payload.client_id).await {
+    Ok(Some(oauth2_client)) if oauth2_client.is_active() => oauth2_client,
+    Ok(_) => {
+      return InternalError::unauthorized()
+        .with_error("client_id", INVALID_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error getting OAuth2 client: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  if !oauth2_client.is_public() {
+    match payload
+      .client_secret
+      .map(|client_secret| oauth2_client.verify(&client_secret))
+    {
+      Some(Ok(true)) => {}
+      Some(Ok(false)) | None => {
+        return InternalError::unauthorized()
+          .with_error("client_secret", INVALID_ERROR)
+          .into_response();
+      }
+      Some(Err(e)) => {
+        log::error!("error verifying OAuth2 client secret: {}", e);
+        return InternalError::unauthorized()
+          .with_error("client_secret", INVALID_ERROR)
+          .into_response();
+      }
+    }
+  }
+  if !parse_scopes(payload.scope.as_deref())
+    .iter()
+    .all(|scope| oauth2_client.allows_scope(scope))
+  {
+    return InternalError::bad_request()
+      .with_error("scope", INVALID_ERROR)
+      .into_response();
+  }
+  let tenant = match get_tenant_by_oauth2_client_id(&state.pool, oauth2_client.id).await {
+    Ok(Some(tenant)) => tenant,
+    Ok(None) => {
+      log::error!("tenant not found for OAuth2 client: {}", oauth2_client.id);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error getting tenant for OAuth2 client: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+
+  let expires_in = state.config.oauth2.device_code_timeout_in_seconds as i64;
+  let device_code = oauth2::CsrfToken::new_random_len(32).secret().to_owned();
+  let user_code = DeviceCodeGrant::new_user_code();
+  let grant = DeviceCodeGrant {
+    application_id: tenant.application_id,
+    tenant_id: tenant.id,
+    oauth2_client_id: oauth2_client.id,
+    scope: payload.scope,
+    expires_at: chrono::Utc::now().timestamp() + expires_in,
+    user_id: None,
+    denied: false,
+    amr: Vec::new(),
+    auth_time: None,
+  };
+  let stored = kv::set(
+    &state.pool,
+    DeviceCodeGrant::kv_key(&device_code),
+    &grant,
+    Some(Duration::seconds(expires_in)),
+  )
+  .await
+    && kv::set(
+      &state.pool,
+      DeviceCodeGrant::user_code_kv_key(&user_code),
+      &device_code,
+      Some(Duration::seconds(expires_in)),
+    )
+    .await;
+  if !stored {
+    log::error!("error storing device code");
+    return InternalError::internal_error()
+      .with_application_error(INTERNAL_ERROR)
+      .into_response();
+  }
+
+  let verification_uri = state.config.oauth2.device_verification_uri.clone();
+  let verification_uri_complete = match Url::parse(&verification_uri) {
+    Ok(mut url) => {
+      url.query_pairs_mut().append_pair("user_code", &user_code);
+      url.to_string()
+    }
+    Err(e) => {
+      log::error!("error parsing device verification uri: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  (
+    [(CACHE_CONTROL, "no-store")],
+    axum::Json(DeviceAuthorization {
+      device_code,
+      user_code,
+      verification_uri,
+      verification_uri_complete,
+      expires_in,
+      interval: state.config.oauth2.device_code_interval_in_seconds as i64,
+    }),
+  )
+    .into_response()
+}
+
+#[utoipa::path(
+  get,
+  path = "/device-authorization/approve",
+  tags = [DEVICE_AUTHORIZATION_TAG],
+  params(PendingDeviceAuthorizationQuery),
+  responses(
+    (status = 200, content_type = "application/json", body = PendingDeviceAuthorization),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn pending_device_authorization(
+  State(state): State<RouterState>,
+  UserAuthorization { tenant, .. }: UserAuthorization,
+  Query(query): Query<PendingDeviceAuthorizationQuery>,
+) -> impl IntoResponse {
+  let grant = match pending_device_code_grant(&state.pool, &tenant, &query.user_code).await {
+    Ok((_, grant)) => grant,
+    Err(e) => return e.into_response(),
+  };
+  let oauth2_client =
+    match get_oauth2_client_by_id(&state.pool, grant.tenant_id, grant.oauth2_client_id).await {
+      Ok(Some(oauth2_client)) if oauth2_client.is_active() => oauth2_client,
+      Ok(_) => {
+        return InternalError::bad_request()
+          .with_error("user_code", INVALID_ERROR)
+          .into_response();
+      }
+      Err(e) => {
+        log::error!("error getting OAuth2 client: {}", e);
+        return InternalError::internal_error()
+          .with_application_error(INTERNAL_ERROR)
+          .into_response();
+      }
+    };
+  axum::Json(PendingDeviceAuthorization {
+    client_id: oauth2_client.client_id,
+    client_name: oauth2_client.name,
+    scopes: parse_scopes(grant.scope.as_deref()),
+    expires_in: grant.expires_at - chrono::Utc::now().timestamp(),
+  })
+  .into_response()
+}
+
+#[utoipa::path(
+  post,
+  path = "/device-authorization/approve",
+  tags = [DEVICE_AUTHORIZATION_TAG],
+  request_body = ApproveDeviceAuthorization,
+  responses(
+    (status = 204),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn approve_device_authorization(
+  State(state): State<RouterState>,
+  UserAuthorization {
+    user,
+    tenant,
+    claims,
+  }: UserAuthorization,
+  Json(payload): Json<ApproveDeviceAuthorization>,
+) -> impl IntoResponse {
+  let (device_code, mut grant) =
+    match pending_device_code_grant(&state.pool, &tenant, &payload.user_code).await {
+      Ok(pending) => pending,
+      Err(e) => return e.into_response(),
+    };
+  let device_code_kv_key = DeviceCodeGrant::kv_key(&device_code);
+  if payload.deny.unwrap_or(false) {
+    grant.denied = true;
+  } else {
+    grant.user_id = Some(user.id);
+    grant.amr = claims.amr;
+    grant.auth_time = claims.auth_time;
+  }
+  let expires_in = grant.expires_at - chrono::Utc::now().timestamp();
+  if !kv::set(
+    &state.pool,
+    device_code_kv_key,
+    &grant,
+    Some(Duration::seconds(expires_in)),
+  )
+  .await
+  {
+    log::error!("error storing device code approval");
+    return InternalError::internal_error()
+      .with_application_error(INTERNAL_ERROR)
+      .into_response();
+  }
+  kv::delete::<_, String>(
+    &state.pool,
+    DeviceCodeGrant::user_code_kv_key(&payload.user_code),
+  )
+  .await;
+
+  (StatusCode::NO_CONTENT, ()).into_response()
+}
+
+/// The device code and grant of a user code that is still waiting for a decision, only users of
+/// the tenant the device asked may see or decide it.
+async fn pending_device_code_grant(
+  pool: &sqlx::AnyPool,
+  tenant: &TenantRow,
+  user_code: &str,
+) -> Result<(String, DeviceCodeGrant), InternalError> {
+  let user_code_kv_key = DeviceCodeGrant::user_code_kv_key(user_code);
+  let Some(device_code) = kv::get::<_, String>(pool, user_code_kv_key).await else {
+    return Err(InternalError::bad_request().with_error("user_code", INVALID_ERROR));
+  };
+  let grant = match kv::get::<_, DeviceCodeGrant>(pool, DeviceCodeGrant::kv_key(&device_code)).await
+  {
+    Some(grant) if grant.tenant_id == tenant.id => grant,
+    _ => return Err(InternalError::bad_request().with_error("user_code", INVALID_ERROR)),
+  };
+  if grant.user_id.is_some() || grant.denied {
+    return Err(InternalError::bad_request().with_error("user_code", ALREADY_USED_ERROR));
+  }
+  Ok((device_code, grant))
+}
+
+pub fn create_router(state: RouterState) -> OpenApiRouter {
+  OpenApiRouter::new()
+    .routes(routes!(device_authorization))
+    .routes(routes!(
+      pending_device_authorization,
+      approve_device_authorization
+    ))
+    .with_state(state)
+}
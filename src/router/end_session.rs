@@ -0,0 +1,442 @@
+use std::{
+  collections::{hash_map::Entry, HashMap},
+  str::FromStr,
+  time::Duration,
+};
+
+use crate::{
+  core::{
+    config::Config,
+    encryption::{decode_jwe_header, is_jwe},
+    error::{Errors, InternalError, INTERNAL_ERROR, INVALID_ERROR, REQUIRED_ERROR},
+  },
+  middleware::{
+    authorization::{ApplicationIdTenantId, Authorization},
+    claims::{
+      parse_jwt_allow_expired, parse_jwt_no_validation, BasicClaims, TOKEN_SUB_TYPE_USER,
+      TOKEN_TYPE_BEARER, TOKEN_TYPE_ID,
+    },
+    json_or_form::JsonOrForm,
+  },
+  model::end_session::{EndSessionRequest, LogoutClaims},
+  repository::{
+    oauth2_client::{get_oauth2_client_by_client_id, get_oauth2_client_by_id, OAuth2ClientRow},
+    tenant::{get_tenant_by_id, TenantRow},
+    user_session::{
+      get_user_session_by_sid, is_user_session_revoked, revoke_tenant_user_sessions, UserSessionRow,
+    },
+  },
+};
+
+use axum::{
+  extract::{Query, State},
+  response::{IntoResponse, Response},
+};
+use http::StatusCode;
+use reqwest::Url;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use super::{authorize::redirect, RouterState};
+
+pub const END_SESSION_TAG: &str = "end-session";
+
+const BACKCHANNEL_LOGOUT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[utoipa::path(
+  get,
+  path = "/end-session",
+  tags = [END_SESSION_TAG],
+  params(EndSessionRequest),
+  responses(
+    (status = 204, description = "The user's session ended"),
+    (status = 302, description = "Redirects to the `post_logout_redirect_uri` with the `state`, or to the logout confirmation page"),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  )
+)]
+pub async fn get_end_session(
+  State(state): State<RouterState>,
+  authorization: Result<Authorization, InternalError>,
+  Query(query): Query<EndSessionRequest>,
+) -> impl IntoResponse {
+  end_session(&state, authorization.ok(), query).await
+}
+
+#[utoipa::path(
+  post,
+  path = "/end-session",
+  tags = [END_SESSION_TAG],
+  request_body(
+    content(
+      (EndSessionRequest = "application/json"),
+      (EndSessionRequest = "application/x-www-form-urlencoded"),
+    )
+  ),
+  responses(
+    (status = 204, description = "The user's session ended"),
+    (status = 302, description = "Redirects to the `post_logout_redirect_uri` with the `state`, or to the logout confirmation page"),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    (),
+    ("Authorization" = [])
+  )
+)]
+pub async fn post_end_session(
+  State(state): State<RouterState>,
+  authorization: Result<Authorization, InternalError>,
+  JsonOrForm(payload): JsonOrForm<EndSessionRequest>,
+) -> impl IntoResponse {
+  end_session(&state, authorization.ok(), payload).await
+}
+
+pub fn create_router(state: RouterState) -> OpenApiRouter {
+  OpenApiRouter::new()
+    .routes(routes!(get_end_session, post_end_session))
+    .with_state(state)
+}
+
+/// Logs the user out of the tenant once the `sid` of the `id_token_hint` names one of their live
+/// sessions, every session they have with the tenant ends, including their login and browser
+/// session, and each session's client is notified through back-channel logout. When the hint
+/// names no live session the user is sent to confirm the logout with their access token.
+async fn end_session(
+  state: &RouterState,
+  authorization: Option<Authorization>,
+  request: EndSessionRequest,
+) -> Response {
+  let (tenant, claims) = match parse_id_token_hint(&state.pool, &request.id_token_hint).await {
+    Ok(tenant_claims) => tenant_claims,
+    Err(e) => return e.into_response(),
+  };
+  let now = chrono::Utc::now().timestamp();
+  if claims.iat < now - state.config.oauth2.id_token_hint_max_age_in_seconds {
+    log::error!("id_token_hint is too old");
+    return InternalError::bad_request()
+      .with_error("id_token_hint", INVALID_ERROR)
+      .into_response();
+  }
+  let session = match id_token_hint_session(&state.pool, &tenant, &claims).await {
+    Ok(Some(session)) => session,
+    Ok(None) => {
+      match authorization.filter(|authorization| {
+        authorization.tenant.id == tenant.id
+          && authorization.claims.r#type == TOKEN_TYPE_BEARER
+          && authorization.claims.sub_type == TOKEN_SUB_TYPE_USER
+          && authorization.claims.sub == claims.sub
+      }) {
+        Some(authorization) => {
+          match confirmed_session(&state.pool, &tenant, &authorization.claims).await {
+            Ok(Some(session)) => session,
+            Ok(None) => {
+              return InternalError::bad_request()
+                .with_error("id_token_hint", INVALID_ERROR)
+                .into_response();
+            }
+            Err(e) => return e.into_response(),
+          }
+        }
+        None => return logout_confirmation_redirect(&state.config, &request),
+      }
+    }
+    Err(e) => return e.into_response(),
+  };
+  let post_logout_redirect_url = match request.post_logout_redirect_uri.as_deref() {
+    Some(post_logout_redirect_uri) => {
+      let Some(client_id) = request.client_id.as_deref() else {
+        return InternalError::bad_request()
+          .with_error("client_id", REQUIRED_ERROR)
+          .into_response();
+      };
+      let oauth2_client = match get_oauth2_client_by_client_id(&state.pool, client_id).await {
+        Ok(Some(oauth2_client)) if oauth2_client.tenant_id == tenant.id => oauth2_client,
+        Ok(_) => {
+          return InternalError::bad_request()
+            .with_error("client_id", INVALID_ERROR)
+            .into_response();
+        }
+        Err(e) => {
+          log::error!("error getting OAuth2 client: {}", e);
+          return InternalError::internal_error()
+            .with_application_error(INTERNAL_ERROR)
+            .into_response();
+        }
+      };
+      if !oauth2_client.has_post_logout_redirect_uri(post_logout_redirect_uri) {
+        return InternalError::bad_request()
+          .with_error("post_logout_redirect_uri", INVALID_ERROR)
+          .into_response();
+      }
+      match Url::parse(post_logout_redirect_uri) {
+        Ok(post_logout_redirect_url) => Some(post_logout_redirect_url),
+        Err(e) => {
+          log::error!("error parsing post logout redirect uri: {}", e);
+          return InternalError::bad_request()
+            .with_error("post_logout_redirect_uri", INVALID_ERROR)
+            .into_response();
+        }
+      }
+    }
+    None => None,
+  };
+
+  let sessions = match revoke_tenant_user_sessions(&state.pool, tenant.id, session.user_id).await {
+    Ok(sessions) if sessions.iter().any(|ended| ended.id == session.id) => sessions,
+    Ok(_) => {
+      return InternalError::bad_request()
+        .with_error("id_token_hint", INVALID_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error revoking user sessions: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  // clients are notified after responding, a slow back-channel must not hold up the user
+  let pool = state.pool.clone();
+  tokio::spawn(async move {
+    send_backchannel_logouts(&pool, &tenant, claims.sub, sessions).await;
+  });
+
+  match post_logout_redirect_url {
+    Some(mut post_logout_redirect_url) => {
+      if let Some(state_param) = request.state.as_deref() {
+        post_logout_redirect_url
+          .query_pairs_mut()
+          .append_pair("state", state_param);
+      }
+      redirect(post_logout_redirect_url).into_response()
+    }
+    None => (StatusCode::NO_CONTENT, ()).into_response(),
+  }
+}
+
+/// The live session named by the hint's `sid`. Hints of a session that was already ended are
+/// rejected, hints without a session or of an expired one need the user's confirmation.
+async fn id_token_hint_session(
+  pool: &sqlx::AnyPool,
+  tenant: &TenantRow,
+  claims: &BasicClaims,
+) -> Result<Option<UserSessionRow>, InternalError> {
+  let invalid = || InternalError::bad_request().with_error("id_token_hint", INVALID_ERROR);
+  let Some(sid) = claims.sid.as_deref() else {
+    return Ok(None);
+  };
+  let session = match get_user_session_by_sid(pool, sid).await {
+    Ok(Some(session)) if session.tenant_id == tenant.id && session.user_id == claims.sub => session,
+    Ok(Some(_)) => {
+      log::error!("id_token_hint session belongs to another user");
+      return Err(invalid());
+    }
+    Ok(None) => return Ok(None),
+    Err(e) => {
+      log::error!("error getting user session: {}", e);
+      return Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR));
+    }
+  };
+  match is_user_session_revoked(pool, sid).await {
+    Ok(true) => {
+      log::error!("id_token_hint session was already ended");
+      Err(invalid())
+    }
+    Ok(false) if session.expires_at <= chrono::Utc::now().timestamp() => Ok(None),
+    Ok(false) => Ok(Some(session)),
+    Err(e) => {
+      log::error!("error checking user session: {}", e);
+      Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR))
+    }
+  }
+}
+
+/// The session of the access token the user confirmed the logout with.
+async fn confirmed_session(
+  pool: &sqlx::AnyPool,
+  tenant: &TenantRow,
+  claims: &BasicClaims,
+) -> Result<Option<UserSessionRow>, InternalError> {
+  let Some(sid) = claims.sid.as_deref() else {
+    return Ok(None);
+  };
+  match get_user_session_by_sid(pool, sid).await {
+    Ok(session) => {
+      Ok(session.filter(|session| session.tenant_id == tenant.id && session.user_id == claims.sub))
+    }
+    Err(e) => {
+      log::error!("error getting user session: {}", e);
+      Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR))
+    }
+  }
+}
+
+/// Sends the user to the logout confirmation page with the end session parameters.
+fn logout_confirmation_redirect(config: &Config, request: &EndSessionRequest) -> Response {
+  let mut logout_confirmation_url = match Url::parse(&config.oauth2.logout_confirmation_uri) {
+    Ok(logout_confirmation_url) => logout_confirmation_url,
+    Err(e) => {
+      log::error!("error parsing logout confirmation uri: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  {
+    let mut query_pairs = logout_confirmation_url.query_pairs_mut();
+    query_pairs.append_pair("id_token_hint", &request.id_token_hint);
+    for (name, value) in [
+      (
+        "post_logout_redirect_uri",
+        &request.post_logout_redirect_uri,
+      ),
+      ("client_id", &request.client_id),
+      ("state", &request.state),
+    ] {
+      if let Some(value) = value {
+        query_pairs.append_pair(name, value);
+      }
+    }
+  }
+  redirect(logout_confirmation_url).into_response()
+}
+
+/// Finds the tenant that signed the id token by its `kid`, expired id tokens are accepted.
+async fn parse_id_token_hint(
+  pool: &sqlx::AnyPool,
+  id_token_hint: &str,
+) -> Result<(TenantRow, BasicClaims), InternalError> {
+  let invalid = || InternalError::bad_request().with_error("id_token_hint", INVALID_ERROR);
+  // ID tokens encrypted to the client are decrypted by it before being sent back, a JWE hint
+  // must be encrypted with the tenant's secret and names the tenant in its header
+  let kid = if is_jwe(id_token_hint) {
+    decode_jwe_header(id_token_hint).map(|header| header.kid)
+  } else {
+    parse_jwt_no_validation::<serde_json::Value>(id_token_hint).map(|token| token.header.kid)
+  };
+  let kid = match kid {
+    Ok(kid) => kid,
+    Err(e) => {
+      log::error!("invalid id_token_hint: {}", e);
+      return Err(invalid());
+    }
+  };
+  let Some(Ok(application_id_tenant_id)) = kid.as_deref().map(ApplicationIdTenantId::from_str)
+  else {
+    log::error!("invalid id_token_hint kid");
+    return Err(invalid());
+  };
+  let tenant = match get_tenant_by_id(
+    pool,
+    application_id_tenant_id.application_id(),
+    application_id_tenant_id.tenant_id(),
+  )
+  .await
+  {
+    Ok(Some(tenant)) => tenant,
+    Ok(None) => {
+      log::error!("invalid id_token_hint tenant not found");
+      return Err(invalid());
+    }
+    Err(e) => {
+      log::error!("error getting tenant: {}", e);
+      return Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR));
+    }
+  };
+  let claims = match parse_jwt_allow_expired::<BasicClaims>(pool, id_token_hint, &tenant).await {
+    Ok(token_data) => token_data.claims,
+    Err(e) => {
+      log::error!("invalid id_token_hint: {}", e);
+      return Err(invalid());
+    }
+  };
+  if claims.r#type != TOKEN_TYPE_ID || claims.sub_type != TOKEN_SUB_TYPE_USER {
+    log::error!("id_token_hint is not a user's id token");
+    return Err(invalid());
+  }
+  Ok((tenant, claims))
+}
+
+/// Posts a logout token for each ended session to its client's back-channel logout uri. Failed
+/// deliveries are logged, the sessions have already ended.
+async fn send_backchannel_logouts(
+  pool: &sqlx::AnyPool,
+  tenant: &TenantRow,
+  user_id: i64,
+  sessions: Vec<UserSessionRow>,
+) {
+  let mut oauth2_clients: HashMap<i64, Option<OAuth2ClientRow>> = HashMap::new();
+  let mut logouts = Vec::new();
+  for session in sessions {
+    let Some(oauth2_client_id) = session.oauth2_client_id else {
+      continue;
+    };
+    let oauth2_client = match oauth2_clients.entry(oauth2_client_id) {
+      Entry::Occupied(entry) => entry.into_mut(),
+      Entry::Vacant(entry) => {
+        match get_oauth2_client_by_id(pool, tenant.id, oauth2_client_id).await {
+          Ok(oauth2_client) => entry.insert(oauth2_client),
+          Err(e) => {
+            log::error!("error getting OAuth2 client: {}", e);
+            entry.insert(None)
+          }
+        }
+      }
+    };
+    let Some(oauth2_client) = oauth2_client.as_ref() else {
+      continue;
+    };
+    let Some(backchannel_logout_uri) = oauth2_client.backchannel_logout_uri() else {
+      continue;
+    };
+    let logout_token = match LogoutClaims::new(
+      tenant,
+      user_id,
+      oauth2_client.client_id.clone(),
+      session.sid,
+    )
+    .encode(tenant)
+    {
+      Ok(logout_token) => logout_token,
+      Err(e) => {
+        log::error!("error encoding logout token: {}", e);
+        continue;
+      }
+    };
+    logouts.push((backchannel_logout_uri.to_owned(), logout_token));
+  }
+  if logouts.is_empty() {
+    return;
+  }
+  let http_client = match reqwest::ClientBuilder::new()
+    .redirect(reqwest::redirect::Policy::none())
+    .timeout(BACKCHANNEL_LOGOUT_TIMEOUT)
+    .build()
+  {
+    Ok(http_client) => http_client,
+    Err(e) => {
+      log::error!("error building back-channel logout client: {}", e);
+      return;
+    }
+  };
+  for (backchannel_logout_uri, logout_token) in logouts {
+    match http_client
+      .post(&backchannel_logout_uri)
+      .form(&[("logout_token", logout_token)])
+      .send()
+      .await
+    {
+      Ok(response) if response.status().is_success() => {}
+      Ok(response) => log::error!(
+        "back-channel logout to {} failed with status {}",
+        backchannel_logout_uri,
+        response.status()
+      ),
+      Err(e) => log::error!(
+        "error sending back-channel logout to {}: {}",
+        backchannel_logout_uri,
+        e
+      ),
+    }
+  }
+}
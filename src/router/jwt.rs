@@ -14,9 +14,11 @@ use crate::{
   },
   middleware::{
     authorization::{parse_authorization, ApplicationIdTenantId},
-    claims::{tenant_encoding_key, TOKEN_TYPE_BEARER},
+    claims::{encrypt_tenant_jwt, tenant_encoding_key, TOKEN_TYPE_BEARER},
     json::Json,
-    service_account_authorization::ServiceAccountAuthorization,
+    service_account_authorization::{
+      require_service_account_scope, ServiceAccountAuthorization, SCOPE_TOKENS_SIGN,
+    },
   },
   model::token::Token,
 };
@@ -34,6 +36,7 @@ pub const JWT_TAG: &str = "jwt";
     (status = 201, content_type = "application/json", body = Token),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -41,9 +44,16 @@ pub const JWT_TAG: &str = "jwt";
   )
 )]
 pub async fn create_jwt(
-  ServiceAccountAuthorization { tenant, .. }: ServiceAccountAuthorization,
+  ServiceAccountAuthorization {
+    service_account,
+    tenant,
+    scopes,
+  }: ServiceAccountAuthorization,
   Json(claims): Json<Map<String, Value>>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TOKENS_SIGN) {
+    return e.into_response();
+  }
   let algorithm = match jsonwebtoken::Algorithm::from_str(&tenant.algorithm) {
     Ok(algorithm) => algorithm,
     Err(_) => {
@@ -57,6 +67,7 @@ pub async fn create_jwt(
   header.kid = Some(ApplicationIdTenantId::new_kid(
     tenant.application_id,
     tenant.id,
+    tenant.key_version,
   ));
 
   let key = match tenant_encoding_key(&tenant, algorithm) {
@@ -67,7 +78,9 @@ pub async fn create_jwt(
         .into_response()
     }
   };
-  let token = match jsonwebtoken::encode(&header, &claims, &key) {
+  let token = match jsonwebtoken::encode(&header, &claims, &key)
+    .and_then(|token| encrypt_tenant_jwt(&tenant, token, TOKEN_TYPE_BEARER))
+  {
     Ok(token) => token,
     Err(_) => {
       return InternalError::internal_error()
@@ -1,30 +1,67 @@
+use std::fmt;
+
 use axum::{extract::State, response::IntoResponse};
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use http::StatusCode;
+use rand::Rng;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
   core::{
-    error::{Errors, InternalError, INTERNAL_ERROR, INVALID_ERROR, NOT_FOUND_ERROR},
+    config::WebAuthnConfig,
+    encryption::verify_password,
+    error::{
+      Errors, InternalError, INTERNAL_ERROR, INVALID_ERROR, NOT_ALLOWED_ERROR, NOT_FOUND_ERROR,
+    },
     openapi::AUTHORIZATION_HEADER,
+    webauthn::{client_data_challenge, generate_challenge, verify_assertion, AuthenticatorData},
   },
   middleware::{
     authorization::{parse_authorization, Authorization},
     claims::{
-      BasicClaims, TOKEN_SUB_TYPE_SERVICE_ACCOUNT, TOKEN_TYPE_BEARER, TOKEN_TYPE_MFA_TOTP_PREFIX,
+      BasicClaims, AMR_HARDWARE_KEY, AMR_MFA, AMR_OTP, TOKEN_SUB_TYPE_SERVICE_ACCOUNT,
+      TOKEN_TYPE_BEARER, TOKEN_TYPE_MFA_TOTP_PREFIX,
     },
+    client_info::ClientInfo,
     json::Json,
+    service_account_authorization::{require_service_account_scope, SCOPE_USERS_WRITE},
   },
   model::{
-    mfa::MFARequest,
+    mfa::{
+      mfa_attempts_kv_key, MFARequest, OTPChallenge, OTP_EXPIRES_IN_SECONDS, OTP_MAX_ATTEMPTS,
+    },
+    recovery_code::{normalize_recovery_code, recovery_code_lookup, RECOVERY_CODE_MFA_TYPE},
     token::{Token, TOKEN_ISSUED_TYPE_MFA},
+    user::UserMFAType,
+    webauthn::{
+      WebAuthnAssertion, WebAuthnChallenge, WebAuthnCredentialDescriptor, WebAuthnRequestOptions,
+    },
   },
   repository::{
+    kv,
+    service_account::get_service_account_by_id,
     tenant::TenantRow,
     user::{get_user_by_id, UserRow},
-    user_totp::get_user_totp_by_user_id,
+    user_email::get_user_emails_by_user_id,
+    user_hotp::{get_user_hotp_by_user_id, update_user_hotp_counter},
+    user_phone_number::get_user_phone_numbers_by_user_id,
+    user_recovery_code::{get_unused_user_recovery_codes_by_lookup, use_user_recovery_code},
+    user_totp::{get_user_totp_by_user_id, use_user_totp},
+    user_webauthn_credential::{
+      get_user_webauthn_credentials_by_user_id, get_webauthn_credential_by_credential_id,
+      use_user_webauthn_credential, UserWebAuthnCredentialRow,
+    },
+  },
+  service::{
+    mail::{Mail, MailSender},
+    sms::{Sms, SmsSender},
   },
 };
 
-use super::{token::create_user_token, RouterState};
+use super::{
+  token::{create_user_token, UserTokenOptions},
+  RouterState,
+};
 
 pub const MFA_TAG: &str = "mfa";
 
@@ -36,6 +73,7 @@ pub const MFA_TAG: &str = "mfa";
   responses(
     (status = 201, content_type = "application/json", body = Token),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 404, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
@@ -46,6 +84,7 @@ pub const MFA_TAG: &str = "mfa";
 pub async fn mfa(
   State(state): State<RouterState>,
   Authorization { claims, tenant, .. }: Authorization,
+  client_info: ClientInfo,
   Json(payload): Json<MFARequest>,
 ) -> impl IntoResponse {
   if !claims.r#type.starts_with(TOKEN_TYPE_MFA_TOTP_PREFIX) {
@@ -70,11 +109,68 @@ pub async fn mfa(
     }
   };
   match payload {
-    MFARequest::TOTP { code } => totp_request(&state.pool, user, claims, tenant, code)
-      .await
-      .into_response(),
+    MFARequest::TOTP { code } => totp_request(
+      &state.pool,
+      state.config.totp.skew,
+      user,
+      claims,
+      tenant,
+      code,
+      client_info,
+    )
+    .await
+    .into_response(),
+    MFARequest::HOTP { code } => hotp_request(
+      &state.pool,
+      state.config.hotp.look_ahead,
+      user,
+      claims,
+      tenant,
+      code,
+      client_info,
+    )
+    .await
+    .into_response(),
+    MFARequest::Email { code } => otp_request(
+      &state.pool,
+      user,
+      claims,
+      tenant,
+      code,
+      client_info,
+      UserMFAType::Email,
+    )
+    .await
+    .into_response(),
+    MFARequest::Text { code } => otp_request(
+      &state.pool,
+      user,
+      claims,
+      tenant,
+      code,
+      client_info,
+      UserMFAType::Text,
+    )
+    .await
+    .into_response(),
+    MFARequest::WebAuthn(assertion) => webauthn_request(
+      &state.pool,
+      &state.config.webauthn,
+      user,
+      claims,
+      tenant,
+      assertion,
+      client_info,
+    )
+    .await
+    .into_response(),
+    MFARequest::RecoveryCode { code } => {
+      recovery_code_request(&state.pool, user, claims, tenant, code, client_info)
+        .await
+        .into_response()
+    }
     MFARequest::ServiceAccount { code } => {
-      service_account_request(&state.pool, user, claims, tenant, code)
+      service_account_request(&state.pool, user, claims, tenant, code, client_info)
         .await
         .into_response()
     }
@@ -83,10 +179,12 @@ pub async fn mfa(
 
 async fn totp_request(
   pool: &sqlx::AnyPool,
+  skew: u8,
   user: UserRow,
   claims: BasicClaims,
   tenant: TenantRow,
   code: String,
+  client_info: ClientInfo,
 ) -> impl IntoResponse {
   let totp = match get_user_totp_by_user_id(pool, user.id).await {
     Ok(Some(totp)) => totp,
@@ -103,9 +201,14 @@ async fn totp_request(
     }
   };
 
-  match totp.verify(&code) {
-    Ok(true) => (),
-    Ok(false) => {
+  if !count_mfa_attempt(pool, UserMFAType::TOTP, user.id).await {
+    return InternalError::unauthorized()
+      .with_error("totp", INVALID_ERROR)
+      .into_response();
+  }
+  let step = match totp.verify(&code, skew) {
+    Ok(Some(step)) => step,
+    Ok(None) => {
       return InternalError::unauthorized()
         .with_error("totp", INVALID_ERROR)
         .into_response();
@@ -116,8 +219,427 @@ async fn totp_request(
         .with_application_error(INTERNAL_ERROR)
         .into_response();
     }
+  };
+  match use_user_totp(pool, user.id, step).await {
+    Ok(Some(_)) => reset_mfa_attempts(pool, UserMFAType::TOTP, user.id).await,
+    Ok(None) => {
+      return InternalError::unauthorized()
+        .with_error("totp", INVALID_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error updating user TOTP: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  }
+
+  let mut amr = claims.amr;
+  amr.extend([AMR_OTP.to_owned(), AMR_MFA.to_owned()]);
+  create_user_token(
+    pool,
+    tenant,
+    user,
+    Some(claims.scopes.join(" ")),
+    Some(TOKEN_ISSUED_TYPE_MFA.to_owned()),
+    true,
+    UserTokenOptions {
+      audience: claims.aud,
+      client_info,
+      amr,
+      ..Default::default()
+    },
+  )
+  .await
+  .into_response()
+}
+
+async fn hotp_request(
+  pool: &sqlx::AnyPool,
+  look_ahead: i64,
+  user: UserRow,
+  claims: BasicClaims,
+  tenant: TenantRow,
+  code: String,
+  client_info: ClientInfo,
+) -> impl IntoResponse {
+  let hotp = match get_user_hotp_by_user_id(pool, user.id).await {
+    Ok(Some(hotp)) => hotp,
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("hotp", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error getting user HOTP: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+
+  if !count_mfa_attempt(pool, UserMFAType::HOTP, user.id).await {
+    return InternalError::unauthorized()
+      .with_error("hotp", INVALID_ERROR)
+      .into_response();
+  }
+  let counter = match hotp.verify(&code, look_ahead) {
+    Ok(Some(counter)) => counter,
+    Ok(None) => {
+      return InternalError::unauthorized()
+        .with_error("hotp", INVALID_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error verifying HOTP: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  // codes up to the accepted one can not be used again, which also resynchronizes the counter
+  match update_user_hotp_counter(pool, user.id, hotp.counter, counter + 1).await {
+    Ok(Some(_)) => reset_mfa_attempts(pool, UserMFAType::HOTP, user.id).await,
+    Ok(None) => {
+      return InternalError::unauthorized()
+        .with_error("hotp", INVALID_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error updating user HOTP: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  }
+
+  let mut amr = claims.amr;
+  amr.extend([AMR_OTP.to_owned(), AMR_MFA.to_owned()]);
+  create_user_token(
+    pool,
+    tenant,
+    user,
+    Some(claims.scopes.join(" ")),
+    Some(TOKEN_ISSUED_TYPE_MFA.to_owned()),
+    true,
+    UserTokenOptions {
+      audience: claims.aud,
+      client_info,
+      amr,
+      ..Default::default()
+    },
+  )
+  .await
+  .into_response()
+}
+
+async fn otp_request(
+  pool: &sqlx::AnyPool,
+  user: UserRow,
+  claims: BasicClaims,
+  tenant: TenantRow,
+  code: String,
+  client_info: ClientInfo,
+  mfa_type: UserMFAType,
+) -> impl IntoResponse {
+  let mfa_type = mfa_type.to_string();
+  let Some(key) = claims
+    .jti
+    .as_deref()
+    .map(|jti| OTPChallenge::kv_key(&mfa_type, jti))
+  else {
+    return InternalError::unauthorized()
+      .with_error(mfa_type, INVALID_ERROR)
+      .into_response();
+  };
+  // taken out so concurrent guesses can not reuse the same attempt
+  let mut challenge = match kv::delete::<_, OTPChallenge>(pool, &key).await {
+    Some(challenge) if challenge.user_id == user.id => challenge,
+    _ => {
+      return InternalError::unauthorized()
+        .with_error(mfa_type, INVALID_ERROR)
+        .into_response();
+    }
+  };
+  if challenge.code != code {
+    challenge.attempts += 1;
+    let expires_in = challenge.expires_at - chrono::Utc::now().timestamp();
+    if challenge.attempts < OTP_MAX_ATTEMPTS && expires_in > 0 {
+      kv::set(
+        pool,
+        key,
+        &challenge,
+        Some(chrono::Duration::seconds(expires_in)),
+      )
+      .await;
+    }
+    return InternalError::unauthorized()
+      .with_error(mfa_type, INVALID_ERROR)
+      .into_response();
+  }
+
+  let mut amr = claims.amr;
+  amr.extend([AMR_OTP.to_owned(), AMR_MFA.to_owned()]);
+  create_user_token(
+    pool,
+    tenant,
+    user,
+    Some(claims.scopes.join(" ")),
+    Some(TOKEN_ISSUED_TYPE_MFA.to_owned()),
+    true,
+    UserTokenOptions {
+      audience: claims.aud,
+      client_info,
+      amr,
+      ..Default::default()
+    },
+  )
+  .await
+  .into_response()
+}
+
+/// Counts an attempt at the user's code, false once more than [`OTP_MAX_ATTEMPTS`] were made in
+/// the window so codes can not be guessed without limit (RFC 4226 section 7.3). Attempts are
+/// counted before the code is checked so concurrent guesses can not share one.
+pub(crate) async fn count_mfa_attempt(
+  pool: &sqlx::AnyPool,
+  mfa_type: impl fmt::Display,
+  user_id: i64,
+) -> bool {
+  kv::increment(
+    pool,
+    mfa_attempts_kv_key(&mfa_type.to_string(), user_id),
+    Some(chrono::Duration::seconds(OTP_EXPIRES_IN_SECONDS)),
+  )
+  .await
+  .is_some_and(|attempts| attempts <= OTP_MAX_ATTEMPTS as i64)
+}
+
+pub(crate) async fn reset_mfa_attempts(
+  pool: &sqlx::AnyPool,
+  mfa_type: impl fmt::Display,
+  user_id: i64,
+) {
+  kv::delete::<_, i64>(pool, mfa_attempts_kv_key(&mfa_type.to_string(), user_id)).await;
+}
+
+/// Mails a one-time code to the user's primary verified email, answered with the `email` MFA
+/// request using the `mfa-email` token with the given `jti`.
+pub(crate) async fn send_email_otp(
+  pool: &sqlx::AnyPool,
+  mail_sender: &dyn MailSender,
+  user: &UserRow,
+  jti: &str,
+) -> Result<(), InternalError> {
+  let emails = match get_user_emails_by_user_id(pool, user.application_id, user.id).await {
+    Ok(emails) => emails,
+    Err(e) => {
+      log::error!("error getting user emails: {}", e);
+      return Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR));
+    }
+  };
+  let Some(email) = emails
+    .into_iter()
+    .find(|email| email.is_primary() && email.is_verified())
+  else {
+    return Err(InternalError::not_found().with_error("email", NOT_FOUND_ERROR));
+  };
+  let code = create_otp_challenge(pool, user, UserMFAType::Email, jti).await?;
+  mail_sender
+    .send(Mail {
+      to: email.email,
+      subject: "Your one-time code".to_owned(),
+      body: format!(
+        "Your one-time code is {code}, it expires in {} minutes.",
+        OTP_EXPIRES_IN_SECONDS / 60
+      ),
+    })
+    .await
+}
+
+/// Texts a one-time code to the user's primary verified phone number, answered with the `text`
+/// MFA request using the `mfa-text` token with the given `jti`.
+pub(crate) async fn send_text_otp(
+  pool: &sqlx::AnyPool,
+  sms_sender: &dyn SmsSender,
+  user: &UserRow,
+  jti: &str,
+) -> Result<(), InternalError> {
+  let phone_numbers =
+    match get_user_phone_numbers_by_user_id(pool, user.application_id, user.id).await {
+      Ok(phone_numbers) => phone_numbers,
+      Err(e) => {
+        log::error!("error getting user phone numbers: {}", e);
+        return Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR));
+      }
+    };
+  let Some(phone_number) = phone_numbers
+    .into_iter()
+    .find(|phone_number| phone_number.is_primary() && phone_number.is_verified())
+  else {
+    return Err(InternalError::not_found().with_error("phone_number", NOT_FOUND_ERROR));
+  };
+  let code = create_otp_challenge(pool, user, UserMFAType::Text, jti).await?;
+  sms_sender
+    .send(Sms {
+      to: phone_number.phone_number,
+      body: format!(
+        "Your one-time code is {code}, it expires in {} minutes.",
+        OTP_EXPIRES_IN_SECONDS / 60
+      ),
+    })
+    .await
+}
+
+async fn create_otp_challenge(
+  pool: &sqlx::AnyPool,
+  user: &UserRow,
+  mfa_type: UserMFAType,
+  jti: &str,
+) -> Result<String, InternalError> {
+  let code = format!("{:06}", rand::rng().random_range(0..1_000_000));
+  let challenge = OTPChallenge {
+    user_id: user.id,
+    code: code.clone(),
+    attempts: 0,
+    expires_at: chrono::Utc::now().timestamp() + OTP_EXPIRES_IN_SECONDS,
+  };
+  if !kv::set(
+    pool,
+    OTPChallenge::kv_key(&mfa_type.to_string(), jti),
+    &challenge,
+    Some(chrono::Duration::seconds(OTP_EXPIRES_IN_SECONDS)),
+  )
+  .await
+  {
+    log::error!("error storing {} one-time code", mfa_type);
+    return Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR));
+  }
+  Ok(code)
+}
+
+async fn recovery_code_request(
+  pool: &sqlx::AnyPool,
+  user: UserRow,
+  claims: BasicClaims,
+  tenant: TenantRow,
+  code: String,
+  client_info: ClientInfo,
+) -> impl IntoResponse {
+  if !count_mfa_attempt(pool, RECOVERY_CODE_MFA_TYPE, user.id).await {
+    return InternalError::unauthorized()
+      .with_error("recovery_code", INVALID_ERROR)
+      .into_response();
+  }
+  let code = normalize_recovery_code(&code);
+  let recovery_codes =
+    match get_unused_user_recovery_codes_by_lookup(pool, user.id, &recovery_code_lookup(&code))
+      .await
+    {
+      Ok(recovery_codes) => recovery_codes,
+      Err(e) => {
+        log::error!("error getting user recovery codes: {}", e);
+        return InternalError::internal_error()
+          .with_application_error(INTERNAL_ERROR)
+          .into_response();
+      }
+    };
+  let Some(recovery_code) = recovery_codes
+    .into_iter()
+    .find(|recovery_code| verify_password(&code, &recovery_code.code).unwrap_or(false))
+  else {
+    return InternalError::unauthorized()
+      .with_error("recovery_code", INVALID_ERROR)
+      .into_response();
+  };
+  match use_user_recovery_code(pool, recovery_code.id).await {
+    Ok(Some(_)) => reset_mfa_attempts(pool, RECOVERY_CODE_MFA_TYPE, user.id).await,
+    Ok(None) => {
+      return InternalError::unauthorized()
+        .with_error("recovery_code", INVALID_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error using user recovery code: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  }
+
+  let mut amr = claims.amr;
+  amr.extend([AMR_OTP.to_owned(), AMR_MFA.to_owned()]);
+  create_user_token(
+    pool,
+    tenant,
+    user,
+    Some(claims.scopes.join(" ")),
+    Some(TOKEN_ISSUED_TYPE_MFA.to_owned()),
+    true,
+    UserTokenOptions {
+      audience: claims.aud,
+      client_info,
+      amr,
+      ..Default::default()
+    },
+  )
+  .await
+  .into_response()
+}
+
+#[utoipa::path(
+  post,
+  path = "/mfa/webauthn-options",
+  tags = [MFA_TAG],
+  responses(
+    (status = 201, content_type = "application/json", body = WebAuthnRequestOptions),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn mfa_webauthn_options(
+  State(state): State<RouterState>,
+  Authorization { claims, .. }: Authorization,
+) -> impl IntoResponse {
+  if !claims.r#type.starts_with(TOKEN_TYPE_MFA_TOTP_PREFIX) {
+    return InternalError::unauthorized()
+      .with_error(AUTHORIZATION_HEADER, "invalid-token-type")
+      .into_response();
+  }
+  match create_webauthn_request_options(
+    &state.pool,
+    &state.config.webauthn,
+    claims.app,
+    Some(claims.sub),
+  )
+  .await
+  {
+    Ok(options) => (StatusCode::CREATED, axum::Json(options)).into_response(),
+    Err(e) => e.into_response(),
   }
+}
 
+async fn webauthn_request(
+  pool: &sqlx::AnyPool,
+  config: &WebAuthnConfig,
+  user: UserRow,
+  claims: BasicClaims,
+  tenant: TenantRow,
+  assertion: WebAuthnAssertion,
+  client_info: ClientInfo,
+) -> impl IntoResponse {
+  if let Err(e) =
+    verify_webauthn_assertion(pool, config, user.application_id, Some(user.id), &assertion).await
+  {
+    return e.into_response();
+  }
+
+  let mut amr = claims.amr;
+  amr.extend([AMR_HARDWARE_KEY.to_owned(), AMR_MFA.to_owned()]);
   create_user_token(
     pool,
     tenant,
@@ -125,17 +647,159 @@ async fn totp_request(
     Some(claims.scopes.join(" ")),
     Some(TOKEN_ISSUED_TYPE_MFA.to_owned()),
     true,
+    UserTokenOptions {
+      audience: claims.aud,
+      client_info,
+      amr,
+      ..Default::default()
+    },
   )
   .await
   .into_response()
 }
 
+/// Stores a new challenge and returns the options for `navigator.credentials.get`, allowing the
+/// user's credentials or, without a user, any discoverable credential for a passkey login.
+pub(crate) async fn create_webauthn_request_options(
+  pool: &sqlx::AnyPool,
+  config: &WebAuthnConfig,
+  application_id: i64,
+  user_id: Option<i64>,
+) -> Result<WebAuthnRequestOptions, InternalError> {
+  let allow_credentials = match user_id {
+    Some(user_id) => match get_user_webauthn_credentials_by_user_id(pool, user_id).await {
+      Ok(credentials) => credentials
+        .into_iter()
+        .map(WebAuthnCredentialDescriptor::from)
+        .collect(),
+      Err(e) => {
+        log::error!("error getting user webauthn credentials: {}", e);
+        return Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR));
+      }
+    },
+    None => Vec::new(),
+  };
+  let challenge = generate_challenge();
+  if !kv::set(
+    pool,
+    WebAuthnChallenge::kv_key(&challenge),
+    &WebAuthnChallenge {
+      application_id,
+      user_id,
+    },
+    Some(chrono::Duration::seconds(
+      config.challenge_timeout_in_seconds,
+    )),
+  )
+  .await
+  {
+    log::error!("error storing webauthn challenge");
+    return Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR));
+  }
+  Ok(WebAuthnRequestOptions {
+    challenge,
+    rp_id: config.rp_id.clone(),
+    timeout: config.challenge_timeout_in_seconds * 1000,
+    user_verification: if user_id.is_some() {
+      "discouraged"
+    } else {
+      "required"
+    }
+    .to_owned(),
+    allow_credentials,
+  })
+}
+
+/// Verifies the assertion signs a challenge handed out by [`create_webauthn_request_options`]
+/// with a credential of the application's users, or only of `user_id` when given, and records
+/// its use.
+pub(crate) async fn verify_webauthn_assertion(
+  pool: &sqlx::AnyPool,
+  config: &WebAuthnConfig,
+  application_id: i64,
+  user_id: Option<i64>,
+  assertion: &WebAuthnAssertion,
+) -> Result<(UserWebAuthnCredentialRow, AuthenticatorData), InternalError> {
+  let invalid = || InternalError::unauthorized().with_error("webauthn", INVALID_ERROR);
+  let (Ok(client_data_json), Ok(authenticator_data), Ok(signature)) = (
+    BASE64_URL_SAFE_NO_PAD.decode(&assertion.client_data_json),
+    BASE64_URL_SAFE_NO_PAD.decode(&assertion.authenticator_data),
+    BASE64_URL_SAFE_NO_PAD.decode(&assertion.signature),
+  ) else {
+    return Err(invalid());
+  };
+  let challenge = client_data_challenge(&client_data_json).map_err(|e| {
+    log::error!("error parsing webauthn client data: {}", e);
+    invalid()
+  })?;
+  let Some(stored) =
+    kv::delete::<_, WebAuthnChallenge>(pool, WebAuthnChallenge::kv_key(&challenge)).await
+  else {
+    return Err(invalid());
+  };
+  let credential =
+    match get_webauthn_credential_by_credential_id(pool, application_id, &assertion.credential_id)
+      .await
+    {
+      Ok(Some(credential)) => credential,
+      Ok(None) => return Err(invalid()),
+      Err(e) => {
+        log::error!("error getting webauthn credential: {}", e);
+        return Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR));
+      }
+    };
+  if stored.application_id != application_id
+    || stored
+      .user_id
+      .is_some_and(|stored_user_id| stored_user_id != credential.user_id)
+    || user_id.is_some_and(|user_id| user_id != credential.user_id)
+  {
+    return Err(invalid());
+  }
+  let public_key = BASE64_URL_SAFE_NO_PAD
+    .decode(&credential.public_key)
+    .map_err(|e| {
+      log::error!("error decoding webauthn public key: {}", e);
+      InternalError::internal_error().with_application_error(INTERNAL_ERROR)
+    })?;
+  let verified = verify_assertion(
+    config,
+    &challenge,
+    &public_key,
+    &client_data_json,
+    &authenticator_data,
+    &signature,
+  )
+  .map_err(|e| {
+    log::error!("error verifying webauthn assertion: {}", e);
+    invalid()
+  })?;
+  let sign_count = verified.sign_count as i64;
+  // authenticators without a counter always sign 0, a counter that did not grow means a clone
+  if (sign_count != 0 || credential.sign_count != 0) && sign_count <= credential.sign_count {
+    log::error!(
+      "webauthn credential {} sign count did not increase",
+      credential.id
+    );
+    return Err(invalid());
+  }
+  match use_user_webauthn_credential(pool, credential.id, sign_count).await {
+    Ok(Some(credential)) => Ok((credential, verified)),
+    Ok(None) => Err(invalid()),
+    Err(e) => {
+      log::error!("error updating webauthn credential: {}", e);
+      Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR))
+    }
+  }
+}
+
 async fn service_account_request(
   pool: &sqlx::AnyPool,
   user: UserRow,
   claims: BasicClaims,
   tenant: TenantRow,
   service_account_token: String,
+  client_info: ClientInfo,
 ) -> impl IntoResponse {
   let service_account_claims =
     match parse_authorization::<BasicClaims>(pool, &service_account_token).await {
@@ -151,6 +815,40 @@ async fn service_account_request(
       .with_error("token", "invalid-token-sub-type")
       .into_response();
   }
+  let service_account = match get_service_account_by_id(
+    pool,
+    service_account_claims.claims.app,
+    service_account_claims.claims.sub,
+  )
+  .await
+  {
+    Ok(Some(service_account)) if service_account.is_active() => service_account,
+    Ok(_) => {
+      return InternalError::unauthorized()
+        .with_error("token", INVALID_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error getting service account: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  if let Err(e) = require_service_account_scope(
+    &service_account,
+    &service_account_claims.claims.scopes,
+    SCOPE_USERS_WRITE,
+  ) {
+    return e.into_response();
+  }
+  if !service_account.is_admin() && service_account.application_id != claims.app {
+    return InternalError::from(StatusCode::FORBIDDEN)
+      .with_error("token", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  let mut amr = claims.amr;
+  amr.push(AMR_MFA.to_owned());
   create_user_token(
     pool,
     tenant,
@@ -158,11 +856,20 @@ async fn service_account_request(
     Some(claims.scopes.join(" ")),
     Some(TOKEN_ISSUED_TYPE_MFA.to_owned()),
     true,
+    UserTokenOptions {
+      audience: claims.aud,
+      client_info,
+      amr,
+      ..Default::default()
+    },
   )
   .await
   .into_response()
 }
 
 pub fn create_router(state: RouterState) -> OpenApiRouter {
-  OpenApiRouter::new().routes(routes!(mfa)).with_state(state)
+  OpenApiRouter::new()
+    .routes(routes!(mfa))
+    .routes(routes!(mfa_webauthn_options))
+    .with_state(state)
 }
@@ -1,53 +1,79 @@
 pub mod application;
+pub mod authorize;
 pub mod current_user;
 pub mod current_user_config;
 pub mod current_user_email;
 pub mod current_user_phone_number;
+pub mod current_user_recovery_code;
+pub mod current_user_session;
 pub mod current_user_totp;
+pub mod current_user_webauthn;
+pub mod device_authorization;
+pub mod end_session;
 pub mod jwt;
 pub mod mfa;
 pub mod oauth2;
+pub mod oauth2_client;
 pub mod openapi;
 pub mod register;
 pub mod service_account;
 pub mod tenant;
+pub mod tenant_claim_mapping;
+pub mod tenant_key;
 pub mod tenant_oauth2_provider;
 pub mod token;
 pub mod user;
 pub mod user_email;
+pub mod user_hotp;
 pub mod user_phone_number;
+pub mod user_session;
+pub mod userinfo;
 pub mod util;
+pub mod well_known;
 
 use std::sync::Arc;
 
 use application::APPLICATION_TAG;
+use authorize::AUTHORIZE_TAG;
 use axum::Router;
 use current_user::CURRENT_USER_TAG;
+use device_authorization::DEVICE_AUTHORIZATION_TAG;
+use end_session::END_SESSION_TAG;
 use jwt::JWT_TAG;
 use mfa::MFA_TAG;
 use oauth2::OAUTH2_TAG;
+use oauth2_client::OAUTH2_CLIENT_TAG;
 use openapi::OPENAPI_TAG;
 use register::REGISTER_TAG;
 use service_account::SERVICE_ACCOUNT_TAG;
 use sqlx::AnyPool;
 use tenant::TENANT_TAG;
+use tenant_key::TENANT_KEY_TAG;
+use tenant_claim_mapping::TENANT_CLAIM_MAPPING_TAG;
 use tenant_oauth2_provider::TENANT_OAUTH2_PROVIDER_TAG;
 use token::TOKEN_TAG;
 use tower_http::{compression::CompressionLayer, cors::CorsLayer, trace::TraceLayer};
 use user::USER_TAG;
+use userinfo::USERINFO_TAG;
 use util::UTIL_TAG;
 use utoipa::{Modify, OpenApi};
 use utoipa_axum::router::OpenApiRouter;
+use well_known::WELL_KNOWN_TAG;
 
-use crate::core::{
-  config::Config,
-  openapi::{SecurityAddon, ServersAddon},
+use crate::{
+  core::{
+    config::Config,
+    openapi::{SecurityAddon, ServersAddon},
+  },
+  service::{mail::MailSender, sms::SmsSender},
 };
 
 #[derive(Clone)]
 pub struct RouterState {
   pub pool: AnyPool,
   pub config: Arc<Config>,
+  pub mail_sender: Arc<dyn MailSender>,
+  pub sms_sender: Arc<dyn SmsSender>,
 }
 
 unsafe impl Send for RouterState {}
@@ -58,18 +84,26 @@ unsafe impl Sync for RouterState {}
   info(license(name = "MIT OR Apache-2.0", identifier = "https://spdx.org/licenses/MIT.html")),
   tags(
     (name = APPLICATION_TAG, description = "Application endpoints"),
+    (name = AUTHORIZE_TAG, description = "OAuth2 authorization server endpoints"),
     (name = CURRENT_USER_TAG, description = "Current user endpoints"),
+    (name = DEVICE_AUTHORIZATION_TAG, description = "OAuth2 device authorization endpoints"),
+    (name = END_SESSION_TAG, description = "OpenID Connect logout endpoints"),
     (name = JWT_TAG, description = "JSON Web Token endpoints"),
     (name = MFA_TAG, description = "Multi-factor authentication endpoints"),
     (name = UTIL_TAG, description = "Utility endpoints"),
     (name = OAUTH2_TAG, description = "OAuth2 endpoints"),
+    (name = OAUTH2_CLIENT_TAG, description = "Registered OAuth2 client endpoints"),
     (name = OPENAPI_TAG, description = "OpenApi endpoints"),
     (name = REGISTER_TAG, description = "Register endpoints"),
     (name = SERVICE_ACCOUNT_TAG, description = "Service Account endpoints"),
+    (name = TENANT_KEY_TAG, description = "Tenant signing key endpoints"),
+    (name = TENANT_CLAIM_MAPPING_TAG, description = "Tenant claim mapping endpoints"),
     (name = TENANT_OAUTH2_PROVIDER_TAG, description = "Tenant OAuth2 Provider endpoints"),
     (name = TENANT_TAG, description = "Tenant endpoints"),
     (name = TOKEN_TAG, description = "Token endpoints"),
     (name = USER_TAG, description = "User endpoints"),
+    (name = USERINFO_TAG, description = "OpenID Connect userinfo endpoints"),
+    (name = WELL_KNOWN_TAG, description = "Well-known discovery endpoints"),
   ),
   modifiers(&SecurityAddon)
 )]
@@ -82,23 +116,36 @@ pub fn create_router(state: RouterState) -> Router {
 
   let open_api_router = OpenApiRouter::with_openapi(openapi)
     .merge(application::create_router(state.clone()))
+    .merge(authorize::create_router(state.clone()))
     .merge(current_user::create_router(state.clone()))
     .merge(current_user_config::create_router(state.clone()))
     .merge(current_user_email::create_router(state.clone()))
     .merge(current_user_phone_number::create_router(state.clone()))
+    .merge(current_user_recovery_code::create_router(state.clone()))
+    .merge(current_user_session::create_router(state.clone()))
     .merge(current_user_totp::create_router(state.clone()))
+    .merge(current_user_webauthn::create_router(state.clone()))
+    .merge(device_authorization::create_router(state.clone()))
+    .merge(end_session::create_router(state.clone()))
     .merge(jwt::create_router(state.clone()))
     .merge(mfa::create_router(state.clone()))
     .merge(oauth2::create_router(state.clone()))
+    .merge(oauth2_client::create_router(state.clone()))
     .merge(register::create_router(state.clone()))
     .merge(service_account::create_router(state.clone()))
+    .merge(tenant_key::create_router(state.clone()))
+    .merge(tenant_claim_mapping::create_router(state.clone()))
     .merge(tenant_oauth2_provider::create_router(state.clone()))
     .merge(tenant::create_router(state.clone()))
     .merge(token::create_router(state.clone()))
     .merge(user::create_router(state.clone()))
     .merge(user_email::create_router(state.clone()))
+    .merge(user_hotp::create_router(state.clone()))
     .merge(user_phone_number::create_router(state.clone()))
-    .merge(util::create_router(state.clone()));
+    .merge(user_session::create_router(state.clone()))
+    .merge(userinfo::create_router(state.clone()))
+    .merge(util::create_router(state.clone()))
+    .merge(well_known::create_router(state.clone()));
 
   let openapi = open_api_router.get_openapi().clone();
   open_api_router
@@ -5,8 +5,8 @@ use crate::{
   },
   middleware::{
     claims::{
-      BasicClaims, Claims, TOKEN_SUB_TYPE_USER, TOKEN_TYPE_AUTHORIZATION_CODE, parse_jwt,
-      parse_jwt_no_validation,
+      AMR_FEDERATED, BasicClaims, Claims, TOKEN_SUB_TYPE_USER, TOKEN_TYPE_AUTHORIZATION_CODE,
+      parse_jwt, parse_jwt_no_validation,
     },
     openid_claims::{SCOPE_ADDRESS, SCOPE_EMAIL, SCOPE_PHONE, SCOPE_PROFILE, parse_scopes},
     tenant_id::TenantId,
@@ -228,7 +228,7 @@ pub async fn oauth2_callback(
   };
 
   let oauth2_state_token: jsonwebtoken::TokenData<OAuth2State> =
-    match parse_jwt::<OAuth2State>(&oauth2_state_token_string, &tenant) {
+    match parse_jwt::<OAuth2State>(&state.pool, &oauth2_state_token_string, &tenant).await {
       Ok(token) => token,
       Err(e) => {
         log::error!("error parsing OAuth2 state: {}", e);
@@ -528,6 +528,13 @@ pub async fn oauth2_callback(
     iss: tenant.issuer.clone(),
     aud: tenant.audience.clone(),
     scopes,
+    jti: Some(uuid::Uuid::new_v4().to_string()),
+    sid: None,
+    auth_time: Some(now.timestamp()),
+    amr: vec![AMR_FEDERATED.to_owned()],
+    acr: None,
+    act: None,
+    custom: serde_json::Map::new(),
   };
 
   let authorization_code = match claims.encode(&tenant) {
@@ -0,0 +1,515 @@
+use crate::{
+  core::{
+    encryption,
+    error::{
+      Errors, InternalError, ALREADY_EXISTS_ERROR, INTERNAL_ERROR, INVALID_ERROR,
+      NOT_ALLOWED_ERROR, NOT_FOUND_ERROR, REQUIRED_ERROR,
+    },
+  },
+  middleware::{
+    json::Json,
+    service_account_authorization::{
+      require_service_account_scope, ServiceAccountAuthorization, SCOPE_TENANTS_ADMIN,
+      SCOPE_TENANTS_READ,
+    },
+  },
+  model::{
+    oauth2_client::{CreateOAuth2Client, OAuth2Client, UpdateOAuth2Client},
+    util::ApplicationId,
+  },
+  repository,
+};
+
+use axum::{
+  extract::{Path, Query, State},
+  response::IntoResponse,
+};
+use http::StatusCode;
+use reqwest::Url;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use super::RouterState;
+
+pub const OAUTH2_CLIENT_TAG: &str = "oauth2-client";
+
+#[utoipa::path(
+  get,
+  path = "/tenants/{tenant_id}/oauth2-clients",
+  tags = [OAUTH2_CLIENT_TAG],
+  params(
+    ("tenant_id" = i64, Path, description = "Tenant ID"),
+    ApplicationId
+  ),
+  responses(
+    (status = 200, content_type = "application/json", body = Vec<OAuth2Client>),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn oauth2_clients(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  Path(tenant_id): Path<i64>,
+  Query(application_id): Query<ApplicationId>,
+) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_READ) {
+    return e.into_response();
+  }
+  let application_id = application_id
+    .application_id
+    .unwrap_or(service_account.application_id);
+  if !service_account.is_admin() && service_account.application_id != application_id {
+    return InternalError::unauthorized()
+      .with_error("get-oauth2-clients", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  match repository::tenant::get_tenant_by_id(&state.pool, application_id, tenant_id).await {
+    Ok(Some(..)) => {}
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("tenant", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error getting tenant: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  let rows = match repository::oauth2_client::get_oauth2_clients(&state.pool, tenant_id).await {
+    Ok(rows) => rows,
+    Err(e) => {
+      log::error!("error getting OAuth2 clients: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  axum::Json(rows.into_iter().map(OAuth2Client::from).collect::<Vec<_>>()).into_response()
+}
+
+#[utoipa::path(
+  post,
+  path = "/tenants/{tenant_id}/oauth2-clients",
+  tags = [OAUTH2_CLIENT_TAG],
+  request_body = CreateOAuth2Client,
+  params(
+    ("tenant_id" = i64, Path, description = "Tenant ID"),
+    ApplicationId
+  ),
+  responses(
+    (status = 201, content_type = "application/json", body = OAuth2Client),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 409, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn create_oauth2_client(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  Path(tenant_id): Path<i64>,
+  Query(application_id): Query<ApplicationId>,
+  Json(payload): Json<CreateOAuth2Client>,
+) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_ADMIN) {
+    return e.into_response();
+  }
+  let application_id = application_id
+    .application_id
+    .unwrap_or(service_account.application_id);
+  if !service_account.is_admin() && service_account.application_id != application_id {
+    return InternalError::unauthorized()
+      .with_error("create-oauth2-clients", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  match repository::tenant::get_tenant_by_id(&state.pool, application_id, tenant_id).await {
+    Ok(Some(..)) => {}
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("tenant", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error getting tenant: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  let redirect_uris = match join_redirect_uris(&payload.redirect_uris) {
+    Ok(redirect_uris) => redirect_uris,
+    Err(e) => return e.into_response(),
+  };
+  let post_logout_redirect_uris = match payload
+    .post_logout_redirect_uris
+    .as_deref()
+    .map(|uris| join_uris("post_logout_redirect_uris", uris))
+    .transpose()
+  {
+    Ok(post_logout_redirect_uris) => post_logout_redirect_uris,
+    Err(e) => return e.into_response(),
+  };
+  let backchannel_logout_uri = match payload
+    .backchannel_logout_uri
+    .map(validate_backchannel_logout_uri)
+    .transpose()
+  {
+    Ok(backchannel_logout_uri) => backchannel_logout_uri,
+    Err(e) => return e.into_response(),
+  };
+  let scopes = match join_scopes(payload.scopes.as_deref().unwrap_or_default()) {
+    Ok(scopes) => scopes,
+    Err(e) => return e.into_response(),
+  };
+  let client_id = payload.client_id.unwrap_or_else(uuid::Uuid::new_v4);
+  let client_secret = if payload.public.unwrap_or(false) {
+    None
+  } else {
+    Some(payload.client_secret.unwrap_or_else(uuid::Uuid::new_v4))
+  };
+  let encrypted_client_secret = match client_secret
+    .map(|client_secret| {
+      encryption::encrypt_password(state.config.as_ref(), &client_secret.to_string())
+    })
+    .transpose()
+  {
+    Ok(encrypted_client_secret) => encrypted_client_secret,
+    Err(e) => {
+      log::error!("error encrypting client_secret: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  let row = match repository::oauth2_client::create_oauth2_client(
+    &state.pool,
+    tenant_id,
+    repository::oauth2_client::CreateOAuth2Client {
+      client_id: client_id.to_string(),
+      encrypted_client_secret,
+      name: payload.name,
+      redirect_uris,
+      post_logout_redirect_uris,
+      backchannel_logout_uri,
+      scopes,
+    },
+  )
+  .await
+  {
+    Ok(row) => row,
+    Err(e) => {
+      if e.to_string().to_lowercase().contains("unique constraint") {
+        return InternalError::from(StatusCode::CONFLICT)
+          .with_error("client_id", ALREADY_EXISTS_ERROR)
+          .into_response();
+      }
+      log::error!("error creating OAuth2 client: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  let mut oauth2_client = OAuth2Client::from(row);
+  oauth2_client.client_secret = client_secret;
+  (StatusCode::CREATED, axum::Json(oauth2_client)).into_response()
+}
+
+#[utoipa::path(
+  put,
+  path = "/tenants/{tenant_id}/oauth2-clients/{oauth2_client_id}",
+  tags = [OAUTH2_CLIENT_TAG],
+  request_body = UpdateOAuth2Client,
+  params(
+    ("tenant_id" = i64, Path, description = "Tenant ID"),
+    ("oauth2_client_id" = i64, Path, description = "OAuth2 Client ID"),
+    ApplicationId,
+  ),
+  responses(
+    (status = 200, content_type = "application/json", body = OAuth2Client),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn update_oauth2_client(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  Path((tenant_id, oauth2_client_id)): Path<(i64, i64)>,
+  Query(application_id): Query<ApplicationId>,
+  Json(payload): Json<UpdateOAuth2Client>,
+) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_ADMIN) {
+    return e.into_response();
+  }
+  let application_id = application_id
+    .application_id
+    .unwrap_or(service_account.application_id);
+  if !service_account.is_admin() && service_account.application_id != application_id {
+    return InternalError::unauthorized()
+      .with_error("update-oauth2-clients", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  let existing_row =
+    match repository::tenant::get_tenant_by_id(&state.pool, application_id, tenant_id).await {
+      Ok(Some(..)) => {
+        match repository::oauth2_client::get_oauth2_client_by_id(
+          &state.pool,
+          tenant_id,
+          oauth2_client_id,
+        )
+        .await
+        {
+          Ok(Some(row)) => row,
+          Ok(None) => {
+            return InternalError::not_found()
+              .with_error("oauth2-client", NOT_FOUND_ERROR)
+              .into_response();
+          }
+          Err(e) => {
+            log::error!("error getting OAuth2 client: {e}");
+            return InternalError::internal_error()
+              .with_application_error(INTERNAL_ERROR)
+              .into_response();
+          }
+        }
+      }
+      Ok(None) => {
+        return InternalError::not_found()
+          .with_error("tenant", NOT_FOUND_ERROR)
+          .into_response();
+      }
+      Err(e) => {
+        log::error!("error getting tenant: {e}");
+        return InternalError::internal_error()
+          .with_application_error(INTERNAL_ERROR)
+          .into_response();
+      }
+    };
+  let redirect_uris = match payload.redirect_uris.as_deref().map(join_redirect_uris) {
+    Some(Ok(redirect_uris)) => Some(redirect_uris),
+    Some(Err(e)) => return e.into_response(),
+    None => None,
+  };
+  let post_logout_redirect_uris = match payload
+    .post_logout_redirect_uris
+    .as_deref()
+    .map(|uris| join_uris("post_logout_redirect_uris", uris))
+    .transpose()
+  {
+    Ok(post_logout_redirect_uris) => post_logout_redirect_uris,
+    Err(e) => return e.into_response(),
+  };
+  let backchannel_logout_uri = match payload
+    .backchannel_logout_uri
+    .map(validate_backchannel_logout_uri)
+    .transpose()
+  {
+    Ok(backchannel_logout_uri) => backchannel_logout_uri,
+    Err(e) => return e.into_response(),
+  };
+  let scopes = match payload.scopes.as_deref().map(join_scopes).transpose() {
+    Ok(scopes) => scopes,
+    Err(e) => return e.into_response(),
+  };
+  if payload.client_secret.is_some() && existing_row.is_public() {
+    return InternalError::bad_request()
+      .with_error("client_secret", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  let encrypted_client_secret = match payload
+    .client_secret
+    .map(|client_secret| {
+      encryption::encrypt_password(state.config.as_ref(), &client_secret.to_string())
+    })
+    .transpose()
+  {
+    Ok(encrypted_client_secret) => encrypted_client_secret,
+    Err(e) => {
+      log::error!("error encrypting client_secret: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  let row = match repository::oauth2_client::update_oauth2_client(
+    &state.pool,
+    tenant_id,
+    oauth2_client_id,
+    repository::oauth2_client::UpdateOAuth2Client {
+      encrypted_client_secret,
+      name: payload.name,
+      redirect_uris,
+      post_logout_redirect_uris,
+      backchannel_logout_uri,
+      scopes,
+      active: payload.active.map(Into::into),
+    },
+  )
+  .await
+  {
+    Ok(Some(row)) => row,
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("oauth2-client", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error updating OAuth2 client: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  axum::Json(OAuth2Client::from(row)).into_response()
+}
+
+#[utoipa::path(
+  delete,
+  path = "/tenants/{tenant_id}/oauth2-clients/{oauth2_client_id}",
+  tags = [OAUTH2_CLIENT_TAG],
+  params(
+    ("tenant_id" = i64, Path, description = "Tenant ID"),
+    ("oauth2_client_id" = i64, Path, description = "OAuth2 Client ID"),
+    ApplicationId,
+  ),
+  responses(
+    (status = 204),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn delete_oauth2_client(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  Path((tenant_id, oauth2_client_id)): Path<(i64, i64)>,
+  Query(application_id): Query<ApplicationId>,
+) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_ADMIN) {
+    return e.into_response();
+  }
+  let application_id = application_id
+    .application_id
+    .unwrap_or(service_account.application_id);
+  if !service_account.is_admin() && service_account.application_id != application_id {
+    return InternalError::unauthorized()
+      .with_error("delete-oauth2-clients", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  match repository::tenant::get_tenant_by_id(&state.pool, application_id, tenant_id).await {
+    Ok(Some(..)) => {}
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("tenant", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error getting tenant: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  match repository::oauth2_client::delete_oauth2_client(&state.pool, tenant_id, oauth2_client_id)
+    .await
+  {
+    Ok(Some(_)) => {}
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("oauth2-client", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error deleting OAuth2 client: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  }
+  (StatusCode::NO_CONTENT, ()).into_response()
+}
+
+pub fn create_router(state: RouterState) -> OpenApiRouter {
+  OpenApiRouter::new()
+    .routes(routes!(oauth2_clients, create_oauth2_client))
+    .routes(routes!(update_oauth2_client, delete_oauth2_client))
+    .with_state(state)
+}
+
+fn join_redirect_uris(redirect_uris: &[String]) -> Result<String, InternalError> {
+  if redirect_uris.is_empty() {
+    return Err(InternalError::bad_request().with_error("redirect_uris", REQUIRED_ERROR));
+  }
+  join_uris("redirect_uris", redirect_uris)
+}
+
+/// Redirect URIs must be absolute URLs without a fragment, they are stored space separated.
+fn join_uris(field: &str, uris: &[String]) -> Result<String, InternalError> {
+  for uri in uris {
+    if !is_valid_uri(uri) {
+      return Err(InternalError::bad_request().with_error(field, INVALID_ERROR));
+    }
+  }
+  Ok(uris.join(" "))
+}
+
+/// Scopes are stored space separated, so they can not be empty or contain whitespace.
+fn join_scopes(scopes: &[String]) -> Result<String, InternalError> {
+  if scopes
+    .iter()
+    .any(|scope| scope.is_empty() || scope.contains(char::is_whitespace))
+  {
+    return Err(InternalError::bad_request().with_error("scopes", INVALID_ERROR));
+  }
+  Ok(scopes.join(" "))
+}
+
+/// An empty back-channel logout uri is kept so updates can remove it.
+fn validate_backchannel_logout_uri(uri: String) -> Result<String, InternalError> {
+  if uri.is_empty() || is_valid_uri(&uri) {
+    Ok(uri)
+  } else {
+    Err(InternalError::bad_request().with_error("backchannel_logout_uri", INVALID_ERROR))
+  }
+}
+
+fn is_valid_uri(uri: &str) -> bool {
+  !uri.contains(char::is_whitespace) && Url::parse(uri).is_ok_and(|url| url.fragment().is_none())
+}
@@ -1,6 +1,9 @@
 use crate::{
   core::error::{Errors, InternalError, ALREADY_EXISTS_ERROR, INTERNAL_ERROR, NOT_ALLOWED_ERROR},
-  middleware::{openid_claims::SCOPE_OPENID, tenant_id::TenantId, validated_json::ValidatedJson},
+  middleware::{
+    claims::AMR_PASSWORD, client_info::ClientInfo, openid_claims::SCOPE_OPENID,
+    tenant_id::TenantId, validated_json::ValidatedJson,
+  },
   model::{
     register::RegisterUser,
     token::{Token, TOKEN_ISSUED_TYPE_REGISTER},
@@ -15,7 +18,10 @@ use axum::{extract::State, response::IntoResponse};
 use http::StatusCode;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
-use super::{token::create_user_token, RouterState};
+use super::{
+  token::{create_user_token, UserTokenOptions},
+  RouterState,
+};
 
 pub const REGISTER_TAG: &str = "register";
 
@@ -37,6 +43,7 @@ pub const REGISTER_TAG: &str = "register";
 pub async fn register_user(
   State(state): State<RouterState>,
   TenantId(tenant): TenantId,
+  client_info: ClientInfo,
   ValidatedJson(payload): ValidatedJson<RegisterUser>,
 ) -> impl IntoResponse {
   if !state.config.user.register_enabled {
@@ -90,6 +97,11 @@ pub async fn register_user(
     Some(SCOPE_OPENID.to_owned()),
     Some(TOKEN_ISSUED_TYPE_REGISTER.to_owned()),
     true,
+    UserTokenOptions {
+      client_info,
+      amr: vec![AMR_PASSWORD.to_owned()],
+      ..Default::default()
+    },
   )
   .await
   .into_response()
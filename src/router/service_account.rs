@@ -1,9 +1,18 @@
 use crate::{
   core::{
     encryption,
-    error::{Errors, InternalError, INTERNAL_ERROR, NOT_ALLOWED_ERROR, NOT_FOUND_ERROR},
+    error::{
+      Errors, InternalError, INTERNAL_ERROR, INVALID_ERROR, NOT_ALLOWED_ERROR, NOT_FOUND_ERROR,
+    },
+  },
+  middleware::{
+    claims::TOKEN_SUB_TYPE_SERVICE_ACCOUNT,
+    json::Json,
+    service_account_authorization::{
+      require_service_account_scope, ServiceAccountAuthorization, IMPERSONATION_SCOPES,
+      SCOPE_SERVICE_ACCOUNTS_READ, SCOPE_SERVICE_ACCOUNTS_WRITE, SERVICE_ACCOUNT_SCOPES,
+    },
   },
-  middleware::{json::Json, service_account_authorization::ServiceAccountAuthorization},
   model::{
     service_account::{
       CreateServiceAccount, ServiceAccount, ServiceAccountPagination, UpdateServiceAccount,
@@ -35,6 +44,7 @@ pub const SERVICE_ACCOUNT_TAG: &str = "service-account";
   responses(
     (status = 200, content_type = "application/json", body = ServiceAccountPagination),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -44,11 +54,18 @@ pub const SERVICE_ACCOUNT_TAG: &str = "service-account";
 pub async fn all_service_accounts(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Query(query): Query<OffsetAndLimit>,
   Query(application_id): Query<ApplicationId>,
 ) -> impl IntoResponse {
+  if let Err(e) =
+    require_service_account_scope(&service_account, &scopes, SCOPE_SERVICE_ACCOUNTS_READ)
+  {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -99,6 +116,7 @@ pub async fn all_service_accounts(
   responses(
     (status = 200, content_type = "application/json", body = ServiceAccount),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 404, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
@@ -109,11 +127,18 @@ pub async fn all_service_accounts(
 pub async fn get_service_account_by_id(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path(service_account_id): Path<i64>,
   Query(application_id): Query<ApplicationId>,
 ) -> impl IntoResponse {
+  if let Err(e) =
+    require_service_account_scope(&service_account, &scopes, SCOPE_SERVICE_ACCOUNTS_READ)
+  {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -158,6 +183,7 @@ pub async fn get_service_account_by_id(
     (status = 201, content_type = "application/json", body = ServiceAccount),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -167,11 +193,18 @@ pub async fn get_service_account_by_id(
 pub async fn create_service_account(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Query(application_id): Query<ApplicationId>,
   Json(payload): Json<CreateServiceAccount>,
 ) -> impl IntoResponse {
+  if let Err(e) =
+    require_service_account_scope(&service_account, &scopes, SCOPE_SERVICE_ACCOUNTS_WRITE)
+  {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -186,6 +219,12 @@ pub async fn create_service_account(
       .with_error("create-admin-service-account", NOT_ALLOWED_ERROR)
       .into_response();
   }
+  let requested_scopes = payload.scopes.unwrap_or_else(|| scopes.clone());
+  let service_account_scopes =
+    match grantable_scopes(&service_account, &scopes, Some(requested_scopes)) {
+      Ok(service_account_scopes) => service_account_scopes,
+      Err(e) => return e.into_response(),
+    };
   let client_id = payload.client_id.unwrap_or_else(uuid::Uuid::new_v4);
   let client_secret = payload.client_secret.unwrap_or_else(uuid::Uuid::new_v4);
   let encrypted_client_secret =
@@ -206,6 +245,7 @@ pub async fn create_service_account(
       client_id: client_id.to_string(),
       encrypted_client_secret,
       admin: is_admin,
+      scopes: service_account_scopes,
     },
   )
   .await
@@ -236,6 +276,7 @@ pub async fn create_service_account(
     (status = 200, content_type = "application/json", body = ServiceAccount),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -245,12 +286,19 @@ pub async fn create_service_account(
 pub async fn update_service_account(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path(service_account_id): Path<i64>,
   Query(application_id): Query<ApplicationId>,
   Json(payload): Json<UpdateServiceAccount>,
 ) -> impl IntoResponse {
+  if let Err(e) =
+    require_service_account_scope(&service_account, &scopes, SCOPE_SERVICE_ACCOUNTS_WRITE)
+  {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -259,13 +307,54 @@ pub async fn update_service_account(
       .with_error("view-service-accounts", NOT_ALLOWED_ERROR)
       .into_response();
   }
-  let mut params = repository::service_account::UpdateServiceAccount::default();
-  params.admin = payload.admin;
+  let mut params = repository::service_account::UpdateServiceAccount {
+    admin: payload.admin,
+    ..Default::default()
+  };
   if params.admin == Some(true) && !service_account.is_admin() {
     return InternalError::unauthorized()
       .with_error("update-admin-service-account", NOT_ALLOWED_ERROR)
       .into_response();
   }
+  match grantable_scopes(&service_account, &scopes, payload.scopes) {
+    Ok(service_account_scopes) => params.scopes = service_account_scopes,
+    Err(e) => return e.into_response(),
+  }
+  // changing a service account, its credentials included, hands out whatever it is allowed
+  match repository::service_account::get_service_account_by_id(
+    &state.pool,
+    application_id,
+    service_account_id,
+  )
+  .await
+  {
+    Ok(Some(updated_service_account)) => {
+      if updated_service_account.is_admin() && !service_account.is_admin() {
+        return InternalError::unauthorized()
+          .with_error("update-admin-service-account", NOT_ALLOWED_ERROR)
+          .into_response();
+      }
+      if !updated_service_account
+        .scopes()
+        .all(|scope| holds_scope(&service_account, &scopes, scope))
+      {
+        return InternalError::from(StatusCode::FORBIDDEN)
+          .with_error("scopes", NOT_ALLOWED_ERROR)
+          .into_response();
+      }
+    }
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error(SERVICE_ACCOUNT_TAG, NOT_FOUND_ERROR)
+        .into_response()
+    }
+    Err(e) => {
+      log::error!("error getting service account: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  }
   if let Some(client_id) = payload.client_id {
     params.client_id = Some(client_id.to_string().to_owned());
   }
@@ -321,6 +410,7 @@ pub async fn update_service_account(
   responses(
     (status = 204),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 404, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
@@ -331,11 +421,18 @@ pub async fn update_service_account(
 pub async fn delete_service_account(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path(service_account_id): Path<i64>,
   Query(application_id): Query<ApplicationId>,
 ) -> impl IntoResponse {
+  if let Err(e) =
+    require_service_account_scope(&service_account, &scopes, SCOPE_SERVICE_ACCOUNTS_WRITE)
+  {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -367,6 +464,121 @@ pub async fn delete_service_account(
   (StatusCode::NO_CONTENT, ()).into_response()
 }
 
+#[utoipa::path(
+  post,
+  path = "/service-accounts/{service_account_id}/revoke-tokens",
+  tags = [SERVICE_ACCOUNT_TAG],
+  params(
+    ("service_account_id" = i64, Path, description = "ServiceAccount ID"),
+    ApplicationId,
+  ),
+  responses(
+    (status = 204),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn revoke_service_account_tokens(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  Path(service_account_id): Path<i64>,
+  Query(application_id): Query<ApplicationId>,
+) -> impl IntoResponse {
+  if let Err(e) =
+    require_service_account_scope(&service_account, &scopes, SCOPE_SERVICE_ACCOUNTS_WRITE)
+  {
+    return e.into_response();
+  }
+  let application_id = application_id
+    .application_id
+    .unwrap_or(service_account.application_id);
+  if !service_account.is_admin() && service_account.application_id != application_id {
+    return InternalError::unauthorized()
+      .with_error("revoke-service-account-tokens", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  match repository::service_account::get_service_account_by_id(
+    &state.pool,
+    application_id,
+    service_account_id,
+  )
+  .await
+  {
+    Ok(Some(..)) => {}
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("service-account", NOT_FOUND_ERROR)
+        .into_response()
+    }
+    Err(e) => {
+      log::error!("error getting service account: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  }
+  match repository::token_revocation::revoke_subject_tokens(
+    &state.pool,
+    application_id,
+    TOKEN_SUB_TYPE_SERVICE_ACCOUNT,
+    service_account_id,
+  )
+  .await
+  {
+    Ok(..) => {}
+    Err(e) => {
+      log::error!("error revoking service account tokens: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  }
+  (StatusCode::NO_CONTENT, ()).into_response()
+}
+
+/// Validates the scopes given to a service account, service accounts can only hand out scopes
+/// they hold themselves.
+fn grantable_scopes(
+  service_account: &repository::service_account::ServiceAccountRow,
+  granted_scopes: &[String],
+  scopes: Option<Vec<String>>,
+) -> Result<Option<String>, InternalError> {
+  let Some(scopes) = scopes else {
+    return Ok(None);
+  };
+  for scope in &scopes {
+    if !SERVICE_ACCOUNT_SCOPES.contains(&scope.as_str())
+      && !IMPERSONATION_SCOPES.contains(&scope.as_str())
+    {
+      return Err(InternalError::bad_request().with_error("scopes", INVALID_ERROR));
+    }
+    if !holds_scope(service_account, granted_scopes, scope) {
+      return Err(
+        InternalError::from(StatusCode::FORBIDDEN).with_error("scopes", NOT_ALLOWED_ERROR),
+      );
+    }
+  }
+  Ok(Some(scopes.join(" ")))
+}
+
+/// True when the scope is in the caller's token and still allowed to its service account
+fn holds_scope(
+  service_account: &repository::service_account::ServiceAccountRow,
+  granted_scopes: &[String],
+  scope: &str,
+) -> bool {
+  service_account.allows_scope(scope) && granted_scopes.iter().any(|granted| granted == scope)
+}
+
 pub fn create_router(state: RouterState) -> OpenApiRouter {
   OpenApiRouter::new()
     .routes(routes!(all_service_accounts))
@@ -374,5 +586,6 @@ pub fn create_router(state: RouterState) -> OpenApiRouter {
     .routes(routes!(create_service_account))
     .routes(routes!(update_service_account))
     .routes(routes!(delete_service_account))
+    .routes(routes!(revoke_service_account_tokens))
     .with_state(state)
 }
@@ -1,10 +1,24 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, str::FromStr};
 
 use crate::{
-  core::error::{Errors, InternalError, INTERNAL_ERROR, NOT_ALLOWED_ERROR, NOT_FOUND_ERROR},
-  middleware::{json::Json, service_account_authorization::ServiceAccountAuthorization},
+  core::{
+    encryption::generate_jwe_secret,
+    error::{
+      Errors, InternalError, INTERNAL_ERROR, INVALID_ERROR, NOT_ALLOWED_ERROR, NOT_FOUND_ERROR,
+    },
+  },
+  middleware::{
+    json::Json,
+    service_account_authorization::{
+      require_service_account_scope, ServiceAccountAuthorization, SCOPE_TENANTS_ADMIN,
+      SCOPE_TENANTS_READ,
+    },
+  },
   model::{
-    tenant::{CreateTenant, Tenant, TenantPagination, TenantQuery, UpdateTenant},
+    tenant::{
+      encryption_public_key, is_valid_audience, Algorithm, CreateTenant, Tenant, TenantPagination,
+      TenantQuery, UpdateTenant,
+    },
     tenant_oauth2_provider::TenantOAuth2Provider,
     util::{ApplicationId, OffsetAndLimit},
   },
@@ -24,7 +38,7 @@ use axum::{
 use http::StatusCode;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
-use super::RouterState;
+use super::{well_known::tenant_issuer_url, RouterState};
 
 pub const TENANT_TAG: &str = "tenant";
 
@@ -40,6 +54,7 @@ pub const TENANT_TAG: &str = "tenant";
   responses(
     (status = 200, content_type = "application/json", body = TenantPagination),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -49,12 +64,17 @@ pub const TENANT_TAG: &str = "tenant";
 pub async fn all_tenants(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Query(offset_and_limit): Query<OffsetAndLimit>,
   Query(query): Query<TenantQuery>,
   Query(application_id): Query<ApplicationId>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_READ) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -136,6 +156,7 @@ pub async fn all_tenants(
   responses(
     (status = 200, content_type = "application/json", body = Tenant),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 404, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
@@ -146,12 +167,17 @@ pub async fn all_tenants(
 pub async fn get_tenant_by_id(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path(tenant_id): Path<i64>,
   Query(application_id): Query<ApplicationId>,
   Query(query): Query<TenantQuery>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_READ) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -205,6 +231,7 @@ pub async fn get_tenant_by_id(
     (status = 201, content_type = "application/json", body = Tenant),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -214,11 +241,16 @@ pub async fn get_tenant_by_id(
 pub async fn create_tenant(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Query(application_id): Query<ApplicationId>,
   Json(payload): Json<CreateTenant>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_ADMIN) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -227,23 +259,53 @@ pub async fn create_tenant(
       .with_error("create-service-accounts", NOT_ALLOWED_ERROR)
       .into_response();
   }
+  if let Err(e) = validate_allowed_audiences(payload.allowed_audiences.as_deref()) {
+    return e.into_response();
+  }
   let algorithm = payload.algorithm.unwrap_or_default();
-  let (public_key, private_key) = algorithm.keys(payload.public_key, payload.private_key);
+  let (public_key, private_key) = match algorithm.keys(payload.public_key, payload.private_key) {
+    Ok(keys) => keys,
+    Err(e) => {
+      log::error!("error creating tenant keys: {}", e);
+      return InternalError::bad_request()
+        .with_error("private_key", INVALID_ERROR)
+        .into_response();
+    }
+  };
+  let encryption_public_key =
+    match encryption_public_key(payload.encryption_public_key.filter(|key| !key.is_empty())) {
+      Ok(encryption_public_key) => encryption_public_key,
+      Err(e) => {
+        log::error!("error parsing tenant encryption key: {}", e);
+        return InternalError::bad_request()
+          .with_error("encryption_public_key", INVALID_ERROR)
+          .into_response();
+      }
+    };
+  let encrypt_access_tokens = payload.encrypt_access_tokens.unwrap_or(false);
+  let client_id = payload
+    .client_id
+    .unwrap_or_else(uuid::Uuid::new_v4)
+    .to_string();
+  let issuer = payload
+    .issuer
+    .unwrap_or_else(|| tenant_issuer_url(&state.config.server.url, &client_id));
   let tenant_row = match repository::tenant::create_tenant(
     &state.pool,
     application_id,
     repository::tenant::CreateTenant {
-      client_id: payload
-        .client_id
-        .unwrap_or_else(uuid::Uuid::new_v4)
-        .to_string(),
-      issuer: payload.issuer,
+      client_id,
+      issuer,
       audience: payload.audience,
       algorithm: algorithm.to_string(),
-      public_key: public_key,
-      private_key: private_key,
+      public_key,
+      private_key,
       expires_in_seconds: payload.expires_in_seconds.unwrap_or(86400),
       refresh_expires_in_seconds: payload.refresh_expires_in_seconds.unwrap_or(604800),
+      encryption_public_key,
+      encryption_secret: encrypt_access_tokens.then(generate_jwe_secret),
+      encrypt_access_tokens,
+      allowed_audiences: payload.allowed_audiences.unwrap_or_default(),
     },
   )
   .await
@@ -275,6 +337,7 @@ pub async fn create_tenant(
     (status = 200, content_type = "application/json", body = Tenant),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -284,12 +347,17 @@ pub async fn create_tenant(
 pub async fn update_tenant(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path(tenant_id): Path<i64>,
   Query(application_id): Query<ApplicationId>,
   Json(payload): Json<UpdateTenant>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_ADMIN) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -298,7 +366,55 @@ pub async fn update_tenant(
       .with_error("update-service-accounts", NOT_ALLOWED_ERROR)
       .into_response();
   }
-  let tenant = match repository::tenant::update_tenant(
+  if let Err(e) = validate_allowed_audiences(payload.allowed_audiences.as_deref()) {
+    return e.into_response();
+  }
+  let rotate_key =
+    payload.algorithm.is_some() || payload.public_key.is_some() || payload.private_key.is_some();
+  let new_key = if rotate_key {
+    let current_algorithm =
+      match repository::tenant::get_tenant_by_id(&state.pool, application_id, tenant_id).await {
+        Ok(Some(tenant)) => Algorithm::from_str(&tenant.algorithm).unwrap_or_default(),
+        Ok(None) => {
+          return InternalError::not_found()
+            .with_error(TENANT_TAG, NOT_FOUND_ERROR)
+            .into_response();
+        }
+        Err(e) => {
+          log::error!("error getting tenant: {}", e);
+          return InternalError::internal_error()
+            .with_application_error(INTERNAL_ERROR)
+            .into_response();
+        }
+      };
+    let algorithm = payload.algorithm.unwrap_or(current_algorithm);
+    match algorithm.keys(payload.public_key, payload.private_key) {
+      Ok((public_key, private_key)) => Some(repository::tenant_key::CreateTenantKey {
+        algorithm: algorithm.to_string(),
+        public_key,
+        private_key,
+      }),
+      Err(e) => {
+        log::error!("error creating tenant keys: {}", e);
+        return InternalError::bad_request()
+          .with_error("private_key", INVALID_ERROR)
+          .into_response();
+      }
+    }
+  } else {
+    None
+  };
+  let encryption_public_key = match encryption_public_key(payload.encryption_public_key) {
+    Ok(encryption_public_key) => encryption_public_key,
+    Err(e) => {
+      log::error!("error parsing tenant encryption key: {}", e);
+      return InternalError::bad_request()
+        .with_error("encryption_public_key", INVALID_ERROR)
+        .into_response();
+    }
+  };
+  let rotate_encryption_secret = payload.rotate_encryption_secret.unwrap_or(false);
+  let mut tenant = match repository::tenant::update_tenant(
     &state.pool,
     application_id,
     tenant_id,
@@ -306,11 +422,14 @@ pub async fn update_tenant(
       client_id: payload.client_id.as_ref().map(ToString::to_string),
       issuer: payload.issuer,
       audience: payload.audience,
-      algorithm: payload.algorithm.as_ref().map(ToString::to_string),
-      public_key: payload.public_key,
-      private_key: payload.private_key,
       expires_in_seconds: payload.expires_in_seconds,
       refresh_expires_in_seconds: payload.refresh_expires_in_seconds,
+      encryption_public_key,
+      encryption_secret: (payload.encrypt_access_tokens == Some(true) || rotate_encryption_secret)
+        .then(generate_jwe_secret),
+      encrypt_access_tokens: payload.encrypt_access_tokens,
+      rotate_encryption_secret,
+      allowed_audiences: payload.allowed_audiences,
     },
   )
   .await
@@ -328,6 +447,29 @@ pub async fn update_tenant(
         .into_response();
     }
   };
+  if let Some(new_key) = new_key {
+    tenant = match repository::tenant_key::rotate_tenant_key(
+      &state.pool,
+      tenant.id,
+      tenant.retiring_key_expires_at(),
+      Some(new_key),
+    )
+    .await
+    {
+      Ok(Some(tenant)) => tenant,
+      Ok(None) => {
+        return InternalError::not_found()
+          .with_error(TENANT_TAG, NOT_FOUND_ERROR)
+          .into_response()
+      }
+      Err(e) => {
+        log::error!("error rotating tenant key: {}", e);
+        return InternalError::internal_error()
+          .with_application_error(INTERNAL_ERROR)
+          .into_response();
+      }
+    };
+  }
   axum::Json(Tenant::from(tenant)).into_response()
 }
 
@@ -342,6 +484,7 @@ pub async fn update_tenant(
   responses(
     (status = 204),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 404, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
@@ -352,11 +495,16 @@ pub async fn update_tenant(
 pub async fn delete_tenant(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path(tenant_id): Path<i64>,
   Query(application_id): Query<ApplicationId>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_ADMIN) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -382,6 +530,17 @@ pub async fn delete_tenant(
   (StatusCode::NO_CONTENT, ()).into_response()
 }
 
+fn validate_allowed_audiences(allowed_audiences: Option<&[String]>) -> Result<(), InternalError> {
+  if allowed_audiences.is_some_and(|allowed_audiences| {
+    !allowed_audiences
+      .iter()
+      .all(|audience| is_valid_audience(audience))
+  }) {
+    return Err(InternalError::bad_request().with_error("allowed_audiences", INVALID_ERROR));
+  }
+  Ok(())
+}
+
 pub fn create_router(state: RouterState) -> OpenApiRouter {
   OpenApiRouter::new()
     .routes(routes!(all_tenants))
@@ -0,0 +1,439 @@
+use crate::{
+  core::error::{
+    Errors, InternalError, ALREADY_EXISTS_ERROR, INTERNAL_ERROR, INVALID_ERROR, NOT_ALLOWED_ERROR,
+    NOT_FOUND_ERROR,
+  },
+  middleware::{
+    claims::RESERVED_CLAIMS,
+    json::Json,
+    service_account_authorization::{
+      require_service_account_scope, ServiceAccountAuthorization, SCOPE_TENANTS_ADMIN,
+      SCOPE_TENANTS_READ,
+    },
+  },
+  model::{
+    tenant_claim_mapping::{
+      CreateTenantClaimMapping, TenantClaimMapping, UpdateTenantClaimMapping,
+    },
+    util::ApplicationId,
+  },
+  repository::{
+    self,
+    tenant_claim_mapping::{
+      is_valid_claim_mapping_field, CLAIM_MAPPING_SOURCES, CLAIM_MAPPING_SOURCE_STATIC,
+    },
+  },
+};
+
+use axum::{
+  extract::{Path, Query, State},
+  response::IntoResponse,
+};
+use http::StatusCode;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use super::RouterState;
+
+pub const TENANT_CLAIM_MAPPING_TAG: &str = "tenant-claim-mapping";
+
+#[utoipa::path(
+  get,
+  path = "/tenants/{tenant_id}/claim-mappings",
+  tags = [TENANT_CLAIM_MAPPING_TAG],
+  params(
+    ("tenant_id" = i64, Path, description = "Tenant ID"),
+    ApplicationId
+  ),
+  responses(
+    (status = 200, content_type = "application/json", body = Vec<TenantClaimMapping>),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn tenant_claim_mappings(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  Path(tenant_id): Path<i64>,
+  Query(application_id): Query<ApplicationId>,
+) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_READ) {
+    return e.into_response();
+  }
+  let application_id = application_id
+    .application_id
+    .unwrap_or(service_account.application_id);
+  if !service_account.is_admin() && service_account.application_id != application_id {
+    return InternalError::unauthorized()
+      .with_error("get-tenant-claim-mappings", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  match repository::tenant::get_tenant_by_id(&state.pool, application_id, tenant_id).await {
+    Ok(Some(..)) => {}
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("tenant", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error getting tenant: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  let rows =
+    match repository::tenant_claim_mapping::get_tenant_claim_mappings(&state.pool, tenant_id).await
+    {
+      Ok(rows) => rows,
+      Err(e) => {
+        log::error!("error getting tenant claim mappings: {e}");
+        return InternalError::internal_error()
+          .with_application_error(INTERNAL_ERROR)
+          .into_response();
+      }
+    };
+  axum::Json(
+    rows
+      .into_iter()
+      .map(TenantClaimMapping::from)
+      .collect::<Vec<_>>(),
+  )
+  .into_response()
+}
+
+#[utoipa::path(
+  post,
+  path = "/tenants/{tenant_id}/claim-mappings",
+  tags = [TENANT_CLAIM_MAPPING_TAG],
+  request_body = CreateTenantClaimMapping,
+  params(
+    ("tenant_id" = i64, Path, description = "Tenant ID"),
+    ApplicationId
+  ),
+  responses(
+    (status = 201, content_type = "application/json", body = TenantClaimMapping),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 409, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn create_tenant_claim_mapping(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  Path(tenant_id): Path<i64>,
+  Query(application_id): Query<ApplicationId>,
+  Json(payload): Json<CreateTenantClaimMapping>,
+) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_ADMIN) {
+    return e.into_response();
+  }
+  let application_id = application_id
+    .application_id
+    .unwrap_or(service_account.application_id);
+  if !service_account.is_admin() && service_account.application_id != application_id {
+    return InternalError::unauthorized()
+      .with_error("create-tenant-claim-mappings", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  match repository::tenant::get_tenant_by_id(&state.pool, application_id, tenant_id).await {
+    Ok(Some(..)) => {}
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("tenant", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error getting tenant: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  let value = match claim_mapping_value(&payload.claim, &payload.source, &payload.value) {
+    Ok(value) => value,
+    Err(e) => return e.into_response(),
+  };
+  let row = match repository::tenant_claim_mapping::create_tenant_claim_mapping(
+    &state.pool,
+    tenant_id,
+    repository::tenant_claim_mapping::CreateTenantClaimMapping {
+      claim: payload.claim,
+      source: payload.source,
+      value,
+      access_token: payload.access_token.unwrap_or(true).into(),
+      id_token: payload.id_token.unwrap_or(true).into(),
+    },
+  )
+  .await
+  {
+    Ok(row) => row,
+    Err(e) => {
+      if e.to_string().to_lowercase().contains("unique constraint") {
+        return InternalError::from(StatusCode::CONFLICT)
+          .with_error("claim", ALREADY_EXISTS_ERROR)
+          .into_response();
+      }
+      log::error!("error creating tenant claim mapping: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  (
+    StatusCode::CREATED,
+    axum::Json(TenantClaimMapping::from(row)),
+  )
+    .into_response()
+}
+
+#[utoipa::path(
+  put,
+  path = "/tenants/{tenant_id}/claim-mappings/{tenant_claim_mapping_id}",
+  tags = [TENANT_CLAIM_MAPPING_TAG],
+  request_body = UpdateTenantClaimMapping,
+  params(
+    ("tenant_id" = i64, Path, description = "Tenant ID"),
+    ("tenant_claim_mapping_id" = i64, Path, description = "Tenant Claim Mapping ID"),
+    ApplicationId,
+  ),
+  responses(
+    (status = 200, content_type = "application/json", body = TenantClaimMapping),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 409, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn update_tenant_claim_mapping(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  Path((tenant_id, tenant_claim_mapping_id)): Path<(i64, i64)>,
+  Query(application_id): Query<ApplicationId>,
+  Json(payload): Json<UpdateTenantClaimMapping>,
+) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_ADMIN) {
+    return e.into_response();
+  }
+  let application_id = application_id
+    .application_id
+    .unwrap_or(service_account.application_id);
+  if !service_account.is_admin() && service_account.application_id != application_id {
+    return InternalError::unauthorized()
+      .with_error("update-tenant-claim-mappings", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  let existing_row =
+    match repository::tenant::get_tenant_by_id(&state.pool, application_id, tenant_id).await {
+      Ok(Some(..)) => {
+        match repository::tenant_claim_mapping::get_tenant_claim_mapping_by_id(
+          &state.pool,
+          tenant_id,
+          tenant_claim_mapping_id,
+        )
+        .await
+        {
+          Ok(Some(row)) => row,
+          Ok(None) => {
+            return InternalError::not_found()
+              .with_error("tenant-claim-mapping", NOT_FOUND_ERROR)
+              .into_response();
+          }
+          Err(e) => {
+            log::error!("error getting tenant claim mapping: {e}");
+            return InternalError::internal_error()
+              .with_application_error(INTERNAL_ERROR)
+              .into_response();
+          }
+        }
+      }
+      Ok(None) => {
+        return InternalError::not_found()
+          .with_error("tenant", NOT_FOUND_ERROR)
+          .into_response();
+      }
+      Err(e) => {
+        log::error!("error getting tenant: {e}");
+        return InternalError::internal_error()
+          .with_application_error(INTERNAL_ERROR)
+          .into_response();
+      }
+    };
+  let existing = TenantClaimMapping::from(existing_row);
+  let claim = payload.claim.unwrap_or(existing.claim);
+  let source = payload.source.unwrap_or(existing.source);
+  let value = match claim_mapping_value(&claim, &source, &payload.value.unwrap_or(existing.value)) {
+    Ok(value) => value,
+    Err(e) => return e.into_response(),
+  };
+  let row = match repository::tenant_claim_mapping::update_tenant_claim_mapping(
+    &state.pool,
+    tenant_id,
+    tenant_claim_mapping_id,
+    repository::tenant_claim_mapping::UpdateTenantClaimMapping {
+      claim: Some(claim),
+      source: Some(source),
+      value: Some(value),
+      access_token: payload.access_token.map(Into::into),
+      id_token: payload.id_token.map(Into::into),
+    },
+  )
+  .await
+  {
+    Ok(Some(row)) => row,
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("tenant-claim-mapping", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      if e.to_string().to_lowercase().contains("unique constraint") {
+        return InternalError::from(StatusCode::CONFLICT)
+          .with_error("claim", ALREADY_EXISTS_ERROR)
+          .into_response();
+      }
+      log::error!("error updating tenant claim mapping: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  axum::Json(TenantClaimMapping::from(row)).into_response()
+}
+
+#[utoipa::path(
+  delete,
+  path = "/tenants/{tenant_id}/claim-mappings/{tenant_claim_mapping_id}",
+  tags = [TENANT_CLAIM_MAPPING_TAG],
+  params(
+    ("tenant_id" = i64, Path, description = "Tenant ID"),
+    ("tenant_claim_mapping_id" = i64, Path, description = "Tenant Claim Mapping ID"),
+    ApplicationId,
+  ),
+  responses(
+    (status = 204),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn delete_tenant_claim_mapping(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  Path((tenant_id, tenant_claim_mapping_id)): Path<(i64, i64)>,
+  Query(application_id): Query<ApplicationId>,
+) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_ADMIN) {
+    return e.into_response();
+  }
+  let application_id = application_id
+    .application_id
+    .unwrap_or(service_account.application_id);
+  if !service_account.is_admin() && service_account.application_id != application_id {
+    return InternalError::unauthorized()
+      .with_error("delete-tenant-claim-mappings", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  match repository::tenant::get_tenant_by_id(&state.pool, application_id, tenant_id).await {
+    Ok(Some(..)) => {}
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("tenant", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error getting tenant: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  match repository::tenant_claim_mapping::delete_tenant_claim_mapping(
+    &state.pool,
+    tenant_id,
+    tenant_claim_mapping_id,
+  )
+  .await
+  {
+    Ok(Some(_)) => {}
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("tenant-claim-mapping", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error deleting tenant claim mapping: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  }
+  (StatusCode::NO_CONTENT, ()).into_response()
+}
+
+pub fn create_router(state: RouterState) -> OpenApiRouter {
+  OpenApiRouter::new()
+    .routes(routes!(tenant_claim_mappings, create_tenant_claim_mapping))
+    .routes(routes!(
+      update_tenant_claim_mapping,
+      delete_tenant_claim_mapping
+    ))
+    .with_state(state)
+}
+
+/// Validates a claim mapping and returns the value to store, static values are stored as JSON and
+/// other sources store the name of the mapped field.
+fn claim_mapping_value(
+  claim: &str,
+  source: &str,
+  value: &serde_json::Value,
+) -> Result<String, InternalError> {
+  if claim.trim().is_empty() || RESERVED_CLAIMS.contains(&claim) {
+    return Err(InternalError::bad_request().with_error("claim", INVALID_ERROR));
+  }
+  if !CLAIM_MAPPING_SOURCES.contains(&source) {
+    return Err(InternalError::bad_request().with_error("source", INVALID_ERROR));
+  }
+  if source == CLAIM_MAPPING_SOURCE_STATIC {
+    return Ok(value.to_string());
+  }
+  match value.as_str() {
+    Some(field) if is_valid_claim_mapping_field(source, field) => Ok(field.to_owned()),
+    _ => Err(InternalError::bad_request().with_error("value", INVALID_ERROR)),
+  }
+}
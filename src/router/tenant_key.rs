@@ -0,0 +1,368 @@
+use std::str::FromStr;
+
+use crate::{
+  core::error::{
+    Errors, InternalError, INTERNAL_ERROR, INVALID_ERROR, NOT_ALLOWED_ERROR, NOT_FOUND_ERROR,
+  },
+  middleware::{
+    json::Json,
+    service_account_authorization::{
+      require_service_account_scope, ServiceAccountAuthorization, SCOPE_TENANTS_ADMIN,
+      SCOPE_TENANTS_READ,
+    },
+  },
+  model::{
+    tenant::{Algorithm, Tenant},
+    tenant_key::{CreateTenantKey, TenantKey},
+    util::ApplicationId,
+  },
+  repository::{self, tenant::TenantRow},
+};
+
+use axum::{
+  extract::{Path, Query, State},
+  response::IntoResponse,
+};
+use http::StatusCode;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use super::RouterState;
+
+pub const TENANT_KEY_TAG: &str = "tenant-key";
+
+#[utoipa::path(
+  get,
+  path = "/tenants/{tenant_id}/keys",
+  tags = [TENANT_KEY_TAG],
+  params(
+    ("tenant_id" = i64, Path, description = "Tenant ID"),
+    ApplicationId
+  ),
+  responses(
+    (status = 200, content_type = "application/json", body = Vec<TenantKey>),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn tenant_keys(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  Path(tenant_id): Path<i64>,
+  Query(application_id): Query<ApplicationId>,
+) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_READ) {
+    return e.into_response();
+  }
+  let application_id = application_id
+    .application_id
+    .unwrap_or(service_account.application_id);
+  if !service_account.is_admin() && service_account.application_id != application_id {
+    return InternalError::unauthorized()
+      .with_error("get-tenant-keys", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  match repository::tenant::get_tenant_by_id(&state.pool, application_id, tenant_id).await {
+    Ok(Some(..)) => {}
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("tenant", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error getting tenant: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  let keys = match repository::tenant_key::get_tenant_keys(&state.pool, tenant_id).await {
+    Ok(keys) => keys,
+    Err(e) => {
+      log::error!("error getting tenant keys: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  axum::Json(keys.into_iter().map(TenantKey::from).collect::<Vec<_>>()).into_response()
+}
+
+#[utoipa::path(
+  post,
+  path = "/tenants/{tenant_id}/keys",
+  tags = [TENANT_KEY_TAG],
+  request_body = CreateTenantKey,
+  params(
+    ("tenant_id" = i64, Path, description = "Tenant ID"),
+    ApplicationId
+  ),
+  responses(
+    (status = 201, content_type = "application/json", body = TenantKey),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn create_tenant_key(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  Path(tenant_id): Path<i64>,
+  Query(application_id): Query<ApplicationId>,
+  Json(payload): Json<CreateTenantKey>,
+) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_ADMIN) {
+    return e.into_response();
+  }
+  let application_id = application_id
+    .application_id
+    .unwrap_or(service_account.application_id);
+  if !service_account.is_admin() && service_account.application_id != application_id {
+    return InternalError::unauthorized()
+      .with_error("create-tenant-keys", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  let tenant =
+    match repository::tenant::get_tenant_by_id(&state.pool, application_id, tenant_id).await {
+      Ok(Some(tenant)) => tenant,
+      Ok(None) => {
+        return InternalError::not_found()
+          .with_error("tenant", NOT_FOUND_ERROR)
+          .into_response();
+      }
+      Err(e) => {
+        log::error!("error getting tenant: {e}");
+        return InternalError::internal_error()
+          .with_application_error(INTERNAL_ERROR)
+          .into_response();
+      }
+    };
+  let params = match new_tenant_key(&tenant, payload.algorithm, payload.private_key) {
+    Ok(params) => params,
+    Err(e) => {
+      log::error!("error creating tenant keys: {e}");
+      return InternalError::bad_request()
+        .with_error("private_key", INVALID_ERROR)
+        .into_response();
+    }
+  };
+  let key = match repository::tenant_key::create_tenant_key(&state.pool, tenant_id, params).await {
+    Ok(key) => key,
+    Err(e) => {
+      log::error!("error creating tenant key: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  (StatusCode::CREATED, axum::Json(TenantKey::from(key))).into_response()
+}
+
+#[utoipa::path(
+  post,
+  path = "/tenants/{tenant_id}/keys/rotate",
+  tags = [TENANT_KEY_TAG],
+  params(
+    ("tenant_id" = i64, Path, description = "Tenant ID"),
+    ApplicationId
+  ),
+  responses(
+    (status = 200, content_type = "application/json", body = Tenant),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn rotate_tenant_key(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  Path(tenant_id): Path<i64>,
+  Query(application_id): Query<ApplicationId>,
+) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_ADMIN) {
+    return e.into_response();
+  }
+  let application_id = application_id
+    .application_id
+    .unwrap_or(service_account.application_id);
+  if !service_account.is_admin() && service_account.application_id != application_id {
+    return InternalError::unauthorized()
+      .with_error("rotate-tenant-keys", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  let tenant =
+    match repository::tenant::get_tenant_by_id(&state.pool, application_id, tenant_id).await {
+      Ok(Some(tenant)) => tenant,
+      Ok(None) => {
+        return InternalError::not_found()
+          .with_error("tenant", NOT_FOUND_ERROR)
+          .into_response();
+      }
+      Err(e) => {
+        log::error!("error getting tenant: {e}");
+        return InternalError::internal_error()
+          .with_application_error(INTERNAL_ERROR)
+          .into_response();
+      }
+    };
+  let new_key = match repository::tenant_key::get_next_tenant_key(&state.pool, tenant_id).await {
+    Ok(Some(..)) => None,
+    Ok(None) => match new_tenant_key(&tenant, None, None) {
+      Ok(params) => Some(params),
+      Err(e) => {
+        log::error!("error creating tenant keys: {e}");
+        return InternalError::internal_error()
+          .with_application_error(INTERNAL_ERROR)
+          .into_response();
+      }
+    },
+    Err(e) => {
+      log::error!("error getting next tenant key: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  let tenant = match repository::tenant_key::rotate_tenant_key(
+    &state.pool,
+    tenant_id,
+    tenant.retiring_key_expires_at(),
+    new_key,
+  )
+  .await
+  {
+    Ok(Some(tenant)) => tenant,
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("tenant", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error rotating tenant key: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  axum::Json(Tenant::from(tenant)).into_response()
+}
+
+#[utoipa::path(
+  delete,
+  path = "/tenants/{tenant_id}/keys/{version}",
+  tags = [TENANT_KEY_TAG],
+  params(
+    ("tenant_id" = i64, Path, description = "Tenant ID"),
+    ("version" = i64, Path, description = "Key version"),
+    ApplicationId
+  ),
+  responses(
+    (status = 204),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn revoke_tenant_key(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  Path((tenant_id, version)): Path<(i64, i64)>,
+  Query(application_id): Query<ApplicationId>,
+) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_ADMIN) {
+    return e.into_response();
+  }
+  let application_id = application_id
+    .application_id
+    .unwrap_or(service_account.application_id);
+  if !service_account.is_admin() && service_account.application_id != application_id {
+    return InternalError::unauthorized()
+      .with_error("revoke-tenant-keys", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  match repository::tenant::get_tenant_by_id(&state.pool, application_id, tenant_id).await {
+    Ok(Some(..)) => {}
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("tenant", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error getting tenant: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  match repository::tenant_key::revoke_tenant_key(&state.pool, tenant_id, version).await {
+    Ok(Some(_)) => {}
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("tenant-key", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error revoking tenant key: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  }
+  (StatusCode::NO_CONTENT, ()).into_response()
+}
+
+pub fn create_router(state: RouterState) -> OpenApiRouter {
+  OpenApiRouter::new()
+    .routes(routes!(tenant_keys, create_tenant_key))
+    .routes(routes!(rotate_tenant_key))
+    .routes(routes!(revoke_tenant_key))
+    .with_state(state)
+}
+
+fn new_tenant_key(
+  tenant: &TenantRow,
+  algorithm: Option<Algorithm>,
+  private_key: Option<String>,
+) -> Result<repository::tenant_key::CreateTenantKey, jsonwebtoken::errors::Error> {
+  let algorithm =
+    algorithm.unwrap_or_else(|| Algorithm::from_str(&tenant.algorithm).unwrap_or_default());
+  let (public_key, private_key) = algorithm.keys(None, private_key)?;
+  Ok(repository::tenant_key::CreateTenantKey {
+    algorithm: algorithm.to_string(),
+    public_key,
+    private_key,
+  })
+}
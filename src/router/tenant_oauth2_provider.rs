@@ -2,7 +2,12 @@ use crate::{
   core::error::{
     Errors, InternalError, ALREADY_EXISTS_ERROR, INTERNAL_ERROR, NOT_ALLOWED_ERROR, NOT_FOUND_ERROR,
   },
-  middleware::{json::Json, service_account_authorization::ServiceAccountAuthorization},
+  middleware::{
+    json::Json,
+    service_account_authorization::{
+      require_service_account_scope, ServiceAccountAuthorization, SCOPE_TENANTS_ADMIN,
+    },
+  },
   model::{
     tenant_oauth2_provider::{
       CreateTenantOAuth2Provider, TenantOAuth2Provider, UpdateTenantOAuth2Provider,
@@ -36,6 +41,7 @@ pub const TENANT_OAUTH2_PROVIDER_TAG: &str = "tenant-oauth2-provider";
     (status = 201, content_type = "application/json", body = TenantOAuth2Provider),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 409, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
     (status = 501, content_type = "application/json", body = Errors),
@@ -47,12 +53,17 @@ pub const TENANT_OAUTH2_PROVIDER_TAG: &str = "tenant-oauth2-provider";
 pub async fn create_tenant_oauth2_provider(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path(tenant_id): Path<i64>,
   Query(application_id): Query<ApplicationId>,
   Json(payload): Json<CreateTenantOAuth2Provider>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_ADMIN) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -139,6 +150,7 @@ pub async fn create_tenant_oauth2_provider(
     (status = 200, content_type = "application/json", body = TenantOAuth2Provider),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 404, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
@@ -149,12 +161,17 @@ pub async fn create_tenant_oauth2_provider(
 pub async fn update_tenant_oauth2_provider(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path((tenant_id, tenant_oauht2_provider_id)): Path<(i64, i64)>,
   Query(application_id): Query<ApplicationId>,
   Json(payload): Json<UpdateTenantOAuth2Provider>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_ADMIN) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -224,6 +241,7 @@ pub async fn update_tenant_oauth2_provider(
     (status = 204),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 404, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
@@ -234,11 +252,16 @@ pub async fn update_tenant_oauth2_provider(
 pub async fn delete_tenant_oauth2_provider(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path((tenant_id, tenant_oauht2_provider_id)): Path<(i64, i64)>,
   Query(application_id): Query<ApplicationId>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TENANTS_ADMIN) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
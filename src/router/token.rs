@@ -1,43 +1,103 @@
 use crate::{
   core::{
     config::Config,
-    error::{Errors, InternalError, INTERNAL_ERROR, INVALID_ERROR, NOT_FOUND_ERROR},
+    error::{
+      Errors, InternalError, ALREADY_USED_ERROR, APPLICATION, INTERNAL_ERROR, INVALID_ERROR,
+      NOT_FOUND_ERROR, REQUIRED_ERROR,
+    },
+    openapi::TENENT_ID_HEADER,
   },
   middleware::{
+    authorization::parse_authorization,
     claims::{
-      parse_jwt, BasicClaims, Claims, TOKEN_SUB_TYPE_SERVICE_ACCOUNT, TOKEN_SUB_TYPE_USER,
+      acr_from_amr, parse_jwt, ActorClaims, BasicClaims, Claims, AMR_HARDWARE_KEY, AMR_MFA,
+      AMR_PASSWORD, TOKEN_SUB_TYPE_SERVICE_ACCOUNT, TOKEN_SUB_TYPE_USER,
       TOKEN_TYPE_AUTHORIZATION_CODE, TOKEN_TYPE_BEARER, TOKEN_TYPE_ID, TOKEN_TYPE_MFA_TOTP_PREFIX,
       TOKEN_TYPE_REFRESH, TOKEN_TYPE_RESET_PASSWORD,
     },
+    client_info::ClientInfo,
     json::Json,
+    json_or_form::{is_form_url_encoded, JsonOrForm},
     openid_claims::{
       has_address_scope, has_email_scope, has_phone_scope, has_profile_scope, parse_scopes,
-      OpenIdClaims,
+      OpenIdClaims, OpenIdProfile, SCOPE_ADDRESS, SCOPE_EMAIL, SCOPE_PHONE, SCOPE_PROFILE,
+    },
+    service_account_authorization::{
+      require_service_account_scope, ServiceAccountAuthorization, IMPERSONATION_SCOPES,
+      SCOPE_TOKENS_INTROSPECT, SCOPE_USERS_IMPERSONATE, SERVICE_ACCOUNT_SCOPES,
     },
     tenant_id::TenantId,
   },
-  model::token::{
-    Token, TokenRequest, TOKEN_ISSUED_TYPE_AUTHORIZATION_CODE, TOKEN_ISSUED_TYPE_PASSWORD,
-    TOKEN_ISSUED_TYPE_REFRESH_TOKEN, TOKEN_ISSUED_TYPE_SERVICE_ACCOUNT,
+  model::{
+    authorize::AuthorizationCodeGrant,
+    device_authorization::DeviceCodeGrant,
+    token::{
+      IntrospectTokenRequest, OAuth2Error, OAuth2TokenRequest, RevokeTokenRequest, Token,
+      TokenExchangeRequest, TokenIntrospection, TokenRequest, WebAuthnTokenRequest,
+      GRANT_TYPE_AUTHORIZATION_CODE, GRANT_TYPE_CLIENT_CREDENTIALS, GRANT_TYPE_DEVICE_CODE,
+      GRANT_TYPE_PASSWORD, GRANT_TYPE_REFRESH_TOKEN, GRANT_TYPE_TOKEN_EXCHANGE,
+      OAUTH2_ERROR_ACCESS_DENIED, OAUTH2_ERROR_AUTHORIZATION_PENDING, OAUTH2_ERROR_EXPIRED_TOKEN,
+      OAUTH2_ERROR_INVALID_CLIENT, OAUTH2_ERROR_INVALID_GRANT, OAUTH2_ERROR_INVALID_REQUEST,
+      OAUTH2_ERROR_INVALID_SCOPE, OAUTH2_ERROR_INVALID_TARGET, OAUTH2_ERROR_SERVER_ERROR,
+      OAUTH2_ERROR_SLOW_DOWN, OAUTH2_ERROR_UNAUTHORIZED_CLIENT,
+      OAUTH2_ERROR_UNSUPPORTED_GRANT_TYPE, TOKEN_ISSUED_TYPE_AUTHORIZATION_CODE,
+      TOKEN_ISSUED_TYPE_DEVICE_CODE, TOKEN_ISSUED_TYPE_PASSWORD, TOKEN_ISSUED_TYPE_REFRESH_TOKEN,
+      TOKEN_ISSUED_TYPE_SERVICE_ACCOUNT, TOKEN_ISSUED_TYPE_WEBAUTHN, TOKEN_TYPE_URN_ACCESS_TOKEN,
+      TOKEN_TYPE_URN_JWT,
+    },
+    user::UserMFAType,
+    webauthn::{WebAuthnAssertion, WebAuthnRequestOptions},
   },
   repository::{
-    service_account::{get_service_account_by_client_id, ServiceAccountRow},
-    tenant::TenantRow,
+    self, kv,
+    oauth2_client::{get_oauth2_client_by_client_id, OAuth2ClientRow},
+    refresh_token::{
+      create_refresh_token_family, rotate_refresh_token, CreateRefreshTokenFamily,
+      RefreshTokenRotation, RefreshTokenRow,
+    },
+    service_account::{
+      get_service_account_by_client_id, get_service_account_by_id, ServiceAccountRow,
+    },
+    tenant::{get_tenant_by_client_id, get_tenant_by_oauth2_client_id, TenantRow},
+    tenant_claim_mapping::{
+      get_tenant_claim_mappings, CLAIM_MAPPING_SOURCE_APPLICATION, CLAIM_MAPPING_SOURCE_STATIC,
+      CLAIM_MAPPING_SOURCE_USER, CLAIM_MAPPING_SOURCE_USER_INFO,
+    },
+    token_revocation::RevokeToken,
     user::{get_user_by_id, get_user_by_username_or_primary_email, UserRow},
     user_config::get_user_config_by_user_id,
     user_email::get_user_emails_by_user_id,
     user_info::get_user_info_by_user_id,
     user_password::get_user_active_password_by_user_id,
     user_phone_number::get_user_phone_numbers_by_user_id,
+    user_session::{
+      create_user_session, get_user_session_by_sid, touch_user_session_by_refresh_token_family_id,
+      CreateUserSession, UserSessionRow,
+    },
   },
+  service::{mail::MailSender, sms::SmsSender},
 };
 
-use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use axum::{
+  extract::{FromRequest, Request, State},
+  http::{
+    header::{AUTHORIZATION, CACHE_CONTROL, WWW_AUTHENTICATE},
+    HeaderMap, HeaderValue, StatusCode,
+  },
+  response::{IntoResponse, Response},
+};
+use base64::{prelude::BASE64_STANDARD, Engine};
 use chrono::{DateTime, Utc};
 use sqlx::AnyPool;
+use std::sync::Arc;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
-use super::RouterState;
+use super::{
+  mfa::{
+    create_webauthn_request_options, send_email_otp, send_text_otp, verify_webauthn_assertion,
+  },
+  RouterState,
+};
 
 pub const TOKEN_TAG: &str = "token";
 
@@ -45,8 +105,14 @@ pub const TOKEN_TAG: &str = "token";
   post,
   path = "/token",
   tags = [TOKEN_TAG],
-  request_body = TokenRequest,
+  request_body(
+    content(
+      (TokenRequest = "application/json"),
+      (OAuth2TokenRequest = "application/x-www-form-urlencoded"),
+    )
+  ),
   responses(
+    (status = 200, description = "Form encoded requests", content_type = "application/json", body = Token),
     (status = 201, content_type = "application/json", body = Token),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
@@ -54,61 +120,798 @@ pub const TOKEN_TAG: &str = "token";
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
-    ("TenantUUID" = [])
+    ("TenantUUID" = []),
+    ()
   )
 )]
 pub async fn token(
   State(state): State<RouterState>,
-  TenantId(tenant): TenantId,
-  Json(payload): Json<TokenRequest>,
+  tenant_id: Result<TenantId, InternalError>,
+  client_info: ClientInfo,
+  request: Request,
 ) -> impl IntoResponse {
+  if is_form_url_encoded(request.headers()) {
+    let tenant = match tenant_id {
+      Ok(TenantId(tenant)) => Some(tenant),
+      Err(_) if request.headers().contains_key(TENENT_ID_HEADER) => {
+        return oauth2_error(OAUTH2_ERROR_INVALID_REQUEST, None);
+      }
+      Err(_) => None,
+    };
+    return oauth2_token_request(&state, tenant, request, client_info).await;
+  }
+  let TenantId(tenant) = match tenant_id {
+    Ok(tenant_id) => tenant_id,
+    Err(e) => return e.into_response(),
+  };
+  let Json(payload) = match Json::<TokenRequest>::from_request(request, &state).await {
+    Ok(payload) => payload,
+    Err(e) => return e.into_response(),
+  };
+  token_request(&state, tenant, payload, client_info).await
+}
+
+async fn token_request(
+  state: &RouterState,
+  tenant: TenantRow,
+  payload: TokenRequest,
+  client_info: ClientInfo,
+) -> Response {
   match payload {
     TokenRequest::Password {
       username,
       password,
       scope,
-    } => password_request(
+      resource,
+    } => {
+      let audience = match resource_audience(&tenant, resource) {
+        Ok(audience) => audience,
+        Err(e) => return e.into_response(),
+      };
+      password_request(
+        &state.pool,
+        &state.config,
+        &state.mail_sender,
+        &state.sms_sender,
+        tenant,
+        username,
+        password,
+        scope,
+        audience,
+        client_info,
+      )
+      .await
+      .into_response()
+    }
+    TokenRequest::RefreshToken {
+      refresh_token,
+      resource,
+      client_id,
+      client_secret,
+    } => {
+      let audience = match resource_audience(&tenant, resource) {
+        Ok(audience) => audience,
+        Err(e) => return e.into_response(),
+      };
+      refresh_token_request(
+        state,
+        tenant,
+        refresh_token,
+        audience,
+        client_id.as_deref(),
+        client_secret.as_deref(),
+      )
+      .await
+      .into_response()
+    }
+    TokenRequest::ServiceAccount {
+      client_id,
+      client_secret,
+      scope,
+      resource,
+    } => {
+      let audience = match resource_audience(&tenant, resource) {
+        Ok(audience) => audience,
+        Err(e) => return e.into_response(),
+      };
+      service_account_request(
+        &state.pool,
+        tenant,
+        client_id,
+        client_secret,
+        scope,
+        audience,
+      )
+      .await
+      .into_response()
+    }
+    TokenRequest::AuthorizationCode {
+      code,
+      scope,
+      redirect_uri,
+      code_verifier,
+      client_id,
+      client_secret,
+      resource,
+    } => {
+      let audience = match resource_audience(&tenant, resource) {
+        Ok(audience) => audience,
+        Err(e) => return e.into_response(),
+      };
+      match kv::get(&state.pool, AuthorizationCodeGrant::kv_key(&code)).await {
+        Some(grant) => oauth2_client_authorization_code_request(
+          &state.pool,
+          tenant,
+          &code,
+          grant,
+          redirect_uri,
+          code_verifier,
+          client_id,
+          client_secret,
+          audience,
+          client_info,
+        )
+        .await
+        .into_response(),
+        None => authorization_code_request(&state.pool, tenant, code, scope, audience, client_info)
+          .await
+          .into_response(),
+      }
+    }
+    TokenRequest::DeviceCode {
+      device_code,
+      client_id,
+      client_secret,
+    } => match device_code_grant(
       &state.pool,
       &state.config,
-      tenant,
-      username,
-      password,
-      scope,
+      &tenant,
+      &device_code,
+      client_id.as_deref(),
+      client_secret.as_deref(),
     )
     .await
-    .into_response(),
-    TokenRequest::RefreshToken { refresh_token } => {
-      refresh_token_request(&state.pool, tenant, refresh_token)
+    {
+      Ok(grant) => device_code_request(&state.pool, tenant, grant, client_info)
+        .await
+        .into_response(),
+      Err(OAUTH2_ERROR_INVALID_CLIENT) => InternalError::unauthorized()
+        .with_error("client_id", INVALID_ERROR)
+        .into_response(),
+      Err(OAUTH2_ERROR_SERVER_ERROR) => InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response(),
+      Err(error) => InternalError::bad_request()
+        .with_error("device_code", error)
+        .into_response(),
+    },
+    TokenRequest::TokenExchange(token_exchange) => {
+      token_exchange_request(&state.pool, tenant, token_exchange)
         .await
         .into_response()
     }
-    TokenRequest::ServiceAccount {
-      client_id,
-      client_secret,
-    } => service_account_request(&state.pool, tenant, client_id, client_secret)
-      .await
-      .into_response(),
-    TokenRequest::AuthorizationCode { code, scope } => {
-      authorization_code_request(&state.pool, tenant, code, scope)
+    TokenRequest::WebAuthn(WebAuthnTokenRequest {
+      assertion,
+      scope,
+      resource,
+    }) => {
+      let audience = match resource_audience(&tenant, resource) {
+        Ok(audience) => audience,
+        Err(e) => return e.into_response(),
+      };
+      webauthn_request(state, tenant, assertion, scope, audience, client_info)
         .await
         .into_response()
     }
   }
 }
 
+/// The `aud` of a token requested for an RFC 8707 resource, which must be one of the tenant's
+/// audiences.
+fn resource_audience(
+  tenant: &TenantRow,
+  resource: Option<String>,
+) -> Result<Option<String>, InternalError> {
+  match resource {
+    Some(resource) if tenant.allows_audience(&resource) => Ok(Some(resource)),
+    Some(_) => Err(InternalError::bad_request().with_error("resource", INVALID_ERROR)),
+    None => Ok(None),
+  }
+}
+
+/// Translates an RFC 6749 form encoded token request into a [`TokenRequest`], the client is
+/// identified and authenticated by HTTP Basic authentication or the `client_id` and
+/// `client_secret` parameters.
+async fn oauth2_token_request(
+  state: &RouterState,
+  tenant: Option<TenantRow>,
+  request: Request,
+  client_info: ClientInfo,
+) -> Response {
+  let basic_credentials = match basic_client_credentials(request.headers()) {
+    Ok(basic_credentials) => basic_credentials,
+    Err(_) => return oauth2_error(OAUTH2_ERROR_INVALID_CLIENT, None),
+  };
+  let axum::Form(payload) =
+    match axum::Form::<OAuth2TokenRequest>::from_request(request, state).await {
+      Ok(payload) => payload,
+      Err(rejection) => {
+        return oauth2_error(OAUTH2_ERROR_INVALID_REQUEST, Some(rejection.body_text()));
+      }
+    };
+  let (client_id, client_secret) = match basic_credentials {
+    Some(_) if payload.client_secret.is_some() => {
+      return oauth2_error(
+        OAUTH2_ERROR_INVALID_REQUEST,
+        Some("only one client authentication method is allowed".to_owned()),
+      );
+    }
+    Some((client_id, client_secret)) => (Some(client_id), Some(client_secret)),
+    None => (payload.client_id, payload.client_secret),
+  };
+
+  let oauth2_client = match client_id.as_deref() {
+    Some(client_id) => match get_oauth2_client_by_client_id(&state.pool, client_id).await {
+      Ok(oauth2_client) => oauth2_client,
+      Err(e) => {
+        log::error!("error fetching OAuth2 client from database: {}", e);
+        return oauth2_error(OAUTH2_ERROR_SERVER_ERROR, None);
+      }
+    },
+    None => None,
+  };
+  if let Some(oauth2_client) = oauth2_client.as_ref() {
+    let authenticated = oauth2_client.is_active()
+      && (oauth2_client.is_public()
+        || client_secret
+          .as_deref()
+          .is_some_and(|client_secret| oauth2_client.verify(client_secret).unwrap_or(false)));
+    if !authenticated {
+      return oauth2_error(OAUTH2_ERROR_INVALID_CLIENT, None);
+    }
+  }
+  let tenant = match tenant {
+    Some(tenant) => tenant,
+    None => {
+      match oauth2_request_tenant(&state.pool, client_id.as_deref(), oauth2_client.as_ref()).await {
+        Ok(Some(tenant)) => tenant,
+        Ok(None) if payload.grant_type == GRANT_TYPE_CLIENT_CREDENTIALS => {
+          // service accounts belong to an application, not a tenant, so it must be named
+          return oauth2_error(
+            OAUTH2_ERROR_INVALID_REQUEST,
+            Some(format!(
+              "the {TENENT_ID_HEADER} header is required for service accounts"
+            )),
+          );
+        }
+        Ok(None) => return oauth2_error(OAUTH2_ERROR_INVALID_CLIENT, None),
+        Err(e) => {
+          log::error!("error fetching tenant from database: {}", e);
+          return oauth2_error(OAUTH2_ERROR_SERVER_ERROR, None);
+        }
+      }
+    }
+  };
+  if oauth2_client
+    .as_ref()
+    .is_some_and(|oauth2_client| oauth2_client.tenant_id != tenant.id)
+  {
+    return oauth2_error(OAUTH2_ERROR_INVALID_CLIENT, None);
+  }
+
+  let mut unauthorized_error = OAUTH2_ERROR_INVALID_GRANT;
+  let resource = payload.resource.or(payload.audience);
+  let grant_request = match payload.grant_type.as_str() {
+    GRANT_TYPE_PASSWORD => match (payload.username, payload.password) {
+      (Some(username), Some(password)) => TokenRequest::Password {
+        username,
+        password,
+        scope: payload.scope,
+        resource,
+      },
+      _ => return oauth2_error(OAUTH2_ERROR_INVALID_REQUEST, None),
+    },
+    GRANT_TYPE_REFRESH_TOKEN => match payload.refresh_token {
+      Some(refresh_token) => TokenRequest::RefreshToken {
+        refresh_token,
+        resource,
+        client_id,
+        client_secret,
+      },
+      None => return oauth2_error(OAUTH2_ERROR_INVALID_REQUEST, None),
+    },
+    GRANT_TYPE_CLIENT_CREDENTIALS => {
+      unauthorized_error = OAUTH2_ERROR_INVALID_CLIENT;
+      match (
+        client_id.as_deref().map(uuid::Uuid::parse_str),
+        client_secret.as_deref().map(uuid::Uuid::parse_str),
+      ) {
+        (Some(Ok(client_id)), Some(Ok(client_secret))) => TokenRequest::ServiceAccount {
+          client_id,
+          client_secret,
+          scope: payload.scope,
+          resource,
+        },
+        _ => return oauth2_error(OAUTH2_ERROR_INVALID_CLIENT, None),
+      }
+    }
+    GRANT_TYPE_AUTHORIZATION_CODE => match payload.code {
+      Some(code) => TokenRequest::AuthorizationCode {
+        code,
+        scope: payload.scope,
+        redirect_uri: payload.redirect_uri,
+        code_verifier: payload.code_verifier,
+        client_id,
+        client_secret,
+        resource,
+      },
+      None => return oauth2_error(OAUTH2_ERROR_INVALID_REQUEST, None),
+    },
+    GRANT_TYPE_DEVICE_CODE => {
+      let Some(device_code) = payload.device_code else {
+        return oauth2_error(OAUTH2_ERROR_INVALID_REQUEST, None);
+      };
+      // the device code errors tell the device whether to keep polling, so they are not mapped
+      // from the response status like the other grants
+      return match device_code_grant(
+        &state.pool,
+        &state.config,
+        &tenant,
+        &device_code,
+        client_id.as_deref(),
+        client_secret.as_deref(),
+      )
+      .await
+      {
+        Ok(grant) => {
+          oauth2_token_response(
+            device_code_request(&state.pool, tenant, grant, client_info)
+              .await
+              .into_response(),
+            unauthorized_error,
+          )
+          .await
+        }
+        Err(error) => oauth2_error(error, None),
+      };
+    }
+    GRANT_TYPE_TOKEN_EXCHANGE => match (payload.subject_token, payload.subject_token_type) {
+      (Some(subject_token), Some(subject_token_type)) => {
+        TokenRequest::TokenExchange(TokenExchangeRequest {
+          subject_token,
+          subject_token_type,
+          actor_token: payload.actor_token,
+          actor_token_type: payload.actor_token_type,
+          requested_token_type: payload.requested_token_type,
+          requested_subject: payload.requested_subject,
+          scope: payload.scope,
+          audience: resource,
+        })
+      }
+      _ => return oauth2_error(OAUTH2_ERROR_INVALID_REQUEST, None),
+    },
+    _ => return oauth2_error(OAUTH2_ERROR_UNSUPPORTED_GRANT_TYPE, None),
+  };
+
+  oauth2_token_response(
+    token_request(state, tenant, grant_request, client_info).await,
+    unauthorized_error,
+  )
+  .await
+}
+
+/// Maps a [`token_request`] response to an RFC 6749 section 5 token or error response, the error
+/// code and description are taken from the first error of the response.
+async fn oauth2_token_response(mut response: Response, unauthorized_error: &str) -> Response {
+  let status = response.status();
+  if status.is_success() {
+    *response.status_mut() = StatusCode::OK;
+    response
+      .headers_mut()
+      .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    return response;
+  }
+  if status.is_server_error() {
+    return oauth2_error(OAUTH2_ERROR_SERVER_ERROR, None);
+  }
+  let errors = match axum::body::to_bytes(response.into_body(), usize::MAX).await {
+    Ok(body) => serde_json::from_slice::<Errors>(&body).unwrap_or_default(),
+    Err(e) => {
+      log::error!("error reading token response: {}", e);
+      return oauth2_error(OAUTH2_ERROR_SERVER_ERROR, None);
+    }
+  };
+  let mut fields = errors
+    .iter()
+    .filter(|(field, _)| field.as_str() != APPLICATION)
+    .filter_map(|(field, messages)| Some((field.as_str(), messages.first()?.code())))
+    .collect::<Vec<_>>();
+  fields.sort();
+  let Some((field, code)) = fields.first().copied() else {
+    return oauth2_error(unauthorized_error, None);
+  };
+  let error = match field {
+    "scope" => OAUTH2_ERROR_INVALID_SCOPE,
+    "audience" | "resource" => OAUTH2_ERROR_INVALID_TARGET,
+    "client_id" | "client_secret" => OAUTH2_ERROR_INVALID_CLIENT,
+    _ if code == REQUIRED_ERROR => OAUTH2_ERROR_INVALID_REQUEST,
+    "requested_token_type" | "subject_token_type" | "actor_token_type" => {
+      OAUTH2_ERROR_INVALID_REQUEST
+    }
+    _ if status == StatusCode::FORBIDDEN => OAUTH2_ERROR_UNAUTHORIZED_CLIENT,
+    _ => unauthorized_error,
+  };
+  oauth2_error(error, Some(format!("{field} is {code}")))
+}
+
+/// The tenant of a form encoded token request without a Tenant-ID header, from the OAuth2
+/// client or the tenant's own client id.
+async fn oauth2_request_tenant(
+  pool: &AnyPool,
+  client_id: Option<&str>,
+  oauth2_client: Option<&OAuth2ClientRow>,
+) -> sqlx::Result<Option<TenantRow>> {
+  if let Some(oauth2_client) = oauth2_client {
+    return get_tenant_by_oauth2_client_id(pool, oauth2_client.id).await;
+  }
+  let Some(client_id) = client_id else {
+    return Ok(None);
+  };
+  get_tenant_by_client_id(pool, client_id).await
+}
+
+/// The client of a revocation request, RFC 7009 section 2.1
+enum RevocationClient {
+  OAuth2Client(OAuth2ClientRow),
+  ServiceAccount(ServiceAccountRow),
+  /// The tenant's own public client, used by the password grant
+  Tenant(String),
+}
+
+/// Authenticates the client of a revocation request by its `client_id`, confidential clients
+/// must send their secret, returns `None` when the client is unknown or fails to authenticate.
+async fn authenticate_revocation_client(
+  pool: &AnyPool,
+  client_id: &str,
+  client_secret: Option<&str>,
+) -> sqlx::Result<Option<RevocationClient>> {
+  if let Some(oauth2_client) = get_oauth2_client_by_client_id(pool, client_id).await? {
+    let authenticated = oauth2_client.is_active()
+      && (oauth2_client.is_public()
+        || client_secret
+          .is_some_and(|client_secret| oauth2_client.verify(client_secret).unwrap_or(false)));
+    return Ok(authenticated.then_some(RevocationClient::OAuth2Client(oauth2_client)));
+  }
+  if let Some(service_account) = get_service_account_by_client_id(pool, client_id).await? {
+    let authenticated = service_account.is_active()
+      && client_secret
+        .is_some_and(|client_secret| service_account.verify(client_secret).unwrap_or(false));
+    return Ok(authenticated.then_some(RevocationClient::ServiceAccount(service_account)));
+  }
+  Ok(
+    get_tenant_by_client_id(pool, client_id)
+      .await?
+      .map(|tenant| RevocationClient::Tenant(tenant.client_id)),
+  )
+}
+
+/// Returns true when the token was issued to the client, user tokens belong to the OAuth2 client
+/// of their session, or to the tenant's own client when the session has no OAuth2 client.
+async fn is_token_issued_to_client(
+  pool: &AnyPool,
+  client: &RevocationClient,
+  tenant: &TenantRow,
+  claims: &BasicClaims,
+) -> sqlx::Result<bool> {
+  if let RevocationClient::ServiceAccount(service_account) = client {
+    return Ok(
+      claims.sub_type == TOKEN_SUB_TYPE_SERVICE_ACCOUNT
+        && claims.sub == service_account.id
+        && tenant.application_id == service_account.application_id,
+    );
+  }
+  if claims.sub_type != TOKEN_SUB_TYPE_USER {
+    return Ok(false);
+  }
+  let session_oauth2_client_id = match claims.sid.as_deref() {
+    Some(sid) => get_user_session_by_sid(pool, sid)
+      .await?
+      .and_then(|session| session.oauth2_client_id),
+    None => None,
+  };
+  Ok(match client {
+    RevocationClient::OAuth2Client(oauth2_client) => {
+      oauth2_client.tenant_id == tenant.id && session_oauth2_client_id == Some(oauth2_client.id)
+    }
+    RevocationClient::Tenant(client_id) => {
+      &tenant.client_id == client_id && session_oauth2_client_id.is_none()
+    }
+    RevocationClient::ServiceAccount(_) => false,
+  })
+}
+
+/// RFC 6749 section 2.3.1 client credentials from an HTTP Basic authorization header
+fn basic_client_credentials(headers: &HeaderMap) -> Result<Option<(String, String)>, ()> {
+  let Some(authorization) = headers.get(AUTHORIZATION) else {
+    return Ok(None);
+  };
+  let Some(credentials) = authorization
+    .to_str()
+    .map_err(|_| ())?
+    .strip_prefix("Basic ")
+  else {
+    return Ok(None);
+  };
+  let credentials = BASE64_STANDARD
+    .decode(credentials.trim())
+    .map_err(|_| ())
+    .and_then(|credentials| String::from_utf8(credentials).map_err(|_| ()))?;
+  let (client_id, client_secret) = credentials.split_once(':').ok_or(())?;
+  let decode = |value: &str| {
+    urlencoding::decode(&value.replace('+', " "))
+      .map(|value| value.into_owned())
+      .map_err(|_| ())
+  };
+  Ok(Some((decode(client_id)?, decode(client_secret)?)))
+}
+
+fn oauth2_error(error: &str, error_description: Option<String>) -> Response {
+  let status = match error {
+    OAUTH2_ERROR_INVALID_CLIENT => StatusCode::UNAUTHORIZED,
+    OAUTH2_ERROR_SERVER_ERROR => StatusCode::INTERNAL_SERVER_ERROR,
+    _ => StatusCode::BAD_REQUEST,
+  };
+  let mut response = (
+    status,
+    axum::Json(OAuth2Error {
+      error: error.to_owned(),
+      error_description,
+    }),
+  )
+    .into_response();
+  let headers = response.headers_mut();
+  headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+  if status == StatusCode::UNAUTHORIZED {
+    headers.insert(WWW_AUTHENTICATE, HeaderValue::from_static("Basic"));
+  }
+  response
+}
+
+#[utoipa::path(
+  post,
+  path = "/token/revoke",
+  tags = [TOKEN_TAG],
+  request_body(
+    content(
+      (RevokeTokenRequest = "application/json"),
+      (RevokeTokenRequest = "application/x-www-form-urlencoded"),
+    )
+  ),
+  responses(
+    (status = 200),
+    (status = 400, content_type = "application/json", body = OAuth2Error),
+    (status = 401, content_type = "application/json", body = OAuth2Error),
+    (status = 500, content_type = "application/json", body = Errors),
+  )
+)]
+pub async fn revoke_token(
+  State(state): State<RouterState>,
+  headers: HeaderMap,
+  JsonOrForm(payload): JsonOrForm<RevokeTokenRequest>,
+) -> impl IntoResponse {
+  let (client_id, client_secret) = match basic_client_credentials(&headers) {
+    Ok(Some(_)) if payload.client_secret.is_some() => {
+      return oauth2_error(
+        OAUTH2_ERROR_INVALID_REQUEST,
+        Some("only one client authentication method is allowed".to_owned()),
+      );
+    }
+    Ok(Some((client_id, client_secret))) => (Some(client_id), Some(client_secret)),
+    Ok(None) => (payload.client_id, payload.client_secret),
+    Err(_) => return oauth2_error(OAUTH2_ERROR_INVALID_CLIENT, None),
+  };
+  let Some(client_id) = client_id else {
+    return oauth2_error(OAUTH2_ERROR_INVALID_CLIENT, None);
+  };
+  let client =
+    match authenticate_revocation_client(&state.pool, &client_id, client_secret.as_deref()).await {
+      Ok(Some(client)) => client,
+      Ok(None) => return oauth2_error(OAUTH2_ERROR_INVALID_CLIENT, None),
+      Err(e) => {
+        log::error!("error authenticating revocation client: {}", e);
+        return oauth2_error(OAUTH2_ERROR_SERVER_ERROR, None);
+      }
+    };
+  // invalid, expired and already revoked tokens are not an error, see RFC 7009 section 2.2
+  let (tenant, token_data) =
+    match parse_authorization::<BasicClaims>(&state.pool, &payload.token).await {
+      Ok(result) => result,
+      Err(e) => {
+        log::debug!("ignoring revocation of invalid token: {}", e);
+        return (StatusCode::OK, ()).into_response();
+      }
+    };
+  let claims = token_data.claims;
+  match is_token_issued_to_client(&state.pool, &client, &tenant, &claims).await {
+    Ok(true) => {}
+    Ok(false) => {
+      log::error!("token was not issued to the revoking client: {}", client_id);
+      return oauth2_error(OAUTH2_ERROR_UNAUTHORIZED_CLIENT, None);
+    }
+    Err(e) => {
+      log::error!("error checking the revoked token's client: {}", e);
+      return oauth2_error(OAUTH2_ERROR_SERVER_ERROR, None);
+    }
+  }
+  let jti = match claims.jti {
+    Some(jti) => jti,
+    None => {
+      log::debug!("ignoring revocation of token without jti");
+      return (StatusCode::OK, ()).into_response();
+    }
+  };
+  match repository::token_revocation::revoke_token(
+    &state.pool,
+    tenant.application_id,
+    RevokeToken {
+      jti,
+      sub_type: claims.sub_type,
+      sub: claims.sub,
+    },
+  )
+  .await
+  {
+    Ok(..) => (StatusCode::OK, ()).into_response(),
+    Err(e) => {
+      log::error!("error revoking token: {}", e);
+      InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response()
+    }
+  }
+}
+
+#[utoipa::path(
+  post,
+  path = "/token/introspect",
+  tags = [TOKEN_TAG],
+  request_body(
+    content(
+      (IntrospectTokenRequest = "application/json"),
+      (IntrospectTokenRequest = "application/x-www-form-urlencoded"),
+    )
+  ),
+  responses(
+    (status = 200, content_type = "application/json", body = TokenIntrospection),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn introspect_token(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  JsonOrForm(payload): JsonOrForm<IntrospectTokenRequest>,
+) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_TOKENS_INTROSPECT)
+  {
+    return e.into_response();
+  }
+  let inactive = || axum::Json(TokenIntrospection::default()).into_response();
+  let (tenant, token_data) =
+    match parse_authorization::<BasicClaims>(&state.pool, &payload.token).await {
+      Ok(result) => result,
+      Err(e) => {
+        log::debug!("introspected token is not active: {}", e);
+        return inactive();
+      }
+    };
+  if !service_account.is_admin() && service_account.application_id != tenant.application_id {
+    log::debug!("introspected token belongs to another application");
+    return inactive();
+  }
+  let claims = token_data.claims;
+  let active = match claims.sub_type.as_str() {
+    TOKEN_SUB_TYPE_USER => get_user_by_id(&state.pool, claims.app, claims.sub)
+      .await
+      .map(|user| user.map(|user| user.is_active()).unwrap_or(false)),
+    TOKEN_SUB_TYPE_SERVICE_ACCOUNT => {
+      get_service_account_by_id(&state.pool, claims.app, claims.sub)
+        .await
+        .map(|service_account| {
+          service_account
+            .map(|service_account| service_account.is_active())
+            .unwrap_or(false)
+        })
+    }
+    _ => Ok(false),
+  };
+  match active {
+    Ok(true) => {}
+    Ok(false) => return inactive(),
+    Err(e) => {
+      log::error!("error fetching token subject from database: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  }
+  let scope = claims.scopes.join(" ");
+  axum::Json(TokenIntrospection {
+    active: true,
+    scope: if scope.is_empty() { None } else { Some(scope) },
+    client_id: Some(tenant.client_id),
+    sub: Some(claims.sub.to_string()),
+    sub_type: Some(claims.sub_type),
+    token_type: Some(claims.r#type),
+    exp: Some(claims.exp),
+    iat: Some(claims.iat),
+    nbf: Some(claims.nbf),
+    iss: Some(claims.iss),
+    aud: claims.aud,
+    jti: claims.jti,
+  })
+  .into_response()
+}
+
 pub fn create_router(state: RouterState) -> OpenApiRouter {
   OpenApiRouter::new()
     .routes(routes!(token))
+    .routes(routes!(token_webauthn_options))
+    .routes(routes!(revoke_token))
+    .routes(routes!(introspect_token))
     .with_state(state)
 }
 
+#[utoipa::path(
+  post,
+  path = "/token/webauthn-options",
+  tags = [TOKEN_TAG],
+  responses(
+    (status = 201, content_type = "application/json", body = WebAuthnRequestOptions),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("TenantUUID" = [])
+  )
+)]
+pub async fn token_webauthn_options(
+  State(state): State<RouterState>,
+  TenantId(tenant): TenantId,
+) -> impl IntoResponse {
+  match create_webauthn_request_options(
+    &state.pool,
+    &state.config.webauthn,
+    tenant.application_id,
+    None,
+  )
+  .await
+  {
+    Ok(options) => (StatusCode::CREATED, axum::Json(options)).into_response(),
+    Err(e) => e.into_response(),
+  }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn password_request(
   pool: &AnyPool,
   config: &Config,
+  mail_sender: &Arc<dyn MailSender>,
+  sms_sender: &Arc<dyn SmsSender>,
   tenant: TenantRow,
   username: String,
   password: String,
   scope: Option<String>,
+  audience: Option<String>,
+  client_info: ClientInfo,
 ) -> impl IntoResponse {
   let user =
     match get_user_by_username_or_primary_email(pool, tenant.application_id, &username).await {
@@ -175,17 +978,117 @@ async fn password_request(
     scope,
     Some(TOKEN_ISSUED_TYPE_PASSWORD.to_owned()),
     false,
+    UserTokenOptions {
+      audience,
+      client_info,
+      amr: vec![AMR_PASSWORD.to_owned()],
+      mail_sender: Some(mail_sender.clone()),
+      sms_sender: Some(sms_sender.clone()),
+      ..Default::default()
+    },
+  )
+  .await
+  .into_response()
+}
+
+async fn webauthn_request(
+  state: &RouterState,
+  tenant: TenantRow,
+  assertion: WebAuthnAssertion,
+  scope: Option<String>,
+  audience: Option<String>,
+  client_info: ClientInfo,
+) -> impl IntoResponse {
+  let (credential, verified) = match verify_webauthn_assertion(
+    &state.pool,
+    &state.config.webauthn,
+    tenant.application_id,
+    None,
+    &assertion,
+  )
+  .await
+  {
+    Ok(verified) => verified,
+    Err(e) => return e.into_response(),
+  };
+  let user = match get_user_by_id(&state.pool, tenant.application_id, credential.user_id).await {
+    Ok(Some(user)) if user.is_active() => user,
+    Ok(_) => {
+      return InternalError::unauthorized()
+        .with_error("webauthn", INVALID_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error fetching user from database: {}", e);
+      return InternalError::unauthorized()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  // a passkey that verified the user with a PIN or biometric is already a second factor
+  let user_verified = verified.is_user_verified();
+  let mut amr = vec![AMR_HARDWARE_KEY.to_owned()];
+  if user_verified {
+    amr.push(AMR_MFA.to_owned());
+  }
+  create_user_token(
+    &state.pool,
+    tenant,
+    user,
+    scope,
+    Some(TOKEN_ISSUED_TYPE_WEBAUTHN.to_owned()),
+    user_verified,
+    UserTokenOptions {
+      audience,
+      client_info,
+      amr,
+      mail_sender: Some(state.mail_sender.clone()),
+      sms_sender: Some(state.sms_sender.clone()),
+      ..Default::default()
+    },
   )
   .await
   .into_response()
 }
 
 async fn refresh_token_request(
-  pool: &AnyPool,
+  state: &RouterState,
   tenant: TenantRow,
   token_request: String,
+  audience: Option<String>,
+  client_id: Option<&str>,
+  client_secret: Option<&str>,
 ) -> impl IntoResponse {
-  let jwt = match parse_jwt::<BasicClaims>(&token_request, &tenant) {
+  let pool = &state.pool;
+  // RFC 6749 section 6, refresh tokens issued to a client are only refreshed by that client
+  let oauth2_client_id = match client_id {
+    Some(client_id) if client_id == tenant.client_id => None,
+    Some(client_id) => match get_oauth2_client_by_client_id(pool, client_id).await {
+      Ok(Some(oauth2_client))
+        if oauth2_client.tenant_id == tenant.id
+          && oauth2_client.is_active()
+          && (oauth2_client.is_public()
+            || client_secret.is_some_and(|client_secret| {
+              oauth2_client.verify(client_secret).unwrap_or(false)
+            })) =>
+      {
+        Some(oauth2_client.id)
+      }
+      Ok(_) => {
+        return InternalError::unauthorized()
+          .with_error("client_id", INVALID_ERROR)
+          .into_response();
+      }
+      Err(e) => {
+        log::error!("error fetching OAuth2 client from database: {}", e);
+        return InternalError::internal_error()
+          .with_application_error(INTERNAL_ERROR)
+          .into_response();
+      }
+    },
+    None => None,
+  };
+  let jwt = match parse_jwt::<BasicClaims>(pool, &token_request, &tenant).await {
     Ok(claims) => claims,
     Err(e) => {
       log::error!("error decoding jwt: {}", e);
@@ -200,64 +1103,660 @@ async fn refresh_token_request(
       .with_application_error(INTERNAL_ERROR)
       .into_response();
   }
+  let jti = match jwt.claims.jti.as_deref() {
+    Some(jti) => jti,
+    None => {
+      log::error!("refresh token is missing jti");
+      return InternalError::from(StatusCode::UNAUTHORIZED)
+        .with_error("refresh_token", INVALID_ERROR)
+        .into_response();
+    }
+  };
+  let (family, refresh_token) = match rotate_refresh_token(pool, jti, oauth2_client_id).await {
+    Ok(RefreshTokenRotation::Rotated {
+      family,
+      refresh_token,
+    }) => (family, refresh_token),
+    Ok(RefreshTokenRotation::Reused) => {
+      log::error!("refresh token reused, revoked its family: {}", jti);
+      return InternalError::from(StatusCode::UNAUTHORIZED)
+        .with_error("refresh_token", ALREADY_USED_ERROR)
+        .into_response();
+    }
+    Ok(RefreshTokenRotation::Invalid) => {
+      log::error!("refresh token not found or revoked: {}", jti);
+      return InternalError::from(StatusCode::UNAUTHORIZED)
+        .with_error("refresh_token", INVALID_ERROR)
+        .into_response();
+    }
+    Ok(RefreshTokenRotation::ClientMismatch) => {
+      log::error!("refresh token presented by another client: {}", jti);
+      return InternalError::from(StatusCode::UNAUTHORIZED)
+        .with_error("refresh_token", INVALID_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error rotating refresh token: {}", e);
+      return InternalError::from(StatusCode::UNAUTHORIZED)
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  if family.tenant_id != tenant.id
+    || family.sub_type != jwt.claims.sub_type
+    || family.sub != jwt.claims.sub
+  {
+    log::error!("refresh token family does not match token: {}", jti);
+    return InternalError::from(StatusCode::UNAUTHORIZED)
+      .with_error("refresh_token", INVALID_ERROR)
+      .into_response();
+  }
+  let audience = audience.or(jwt.claims.aud);
+  match family.sub_type.as_str() {
+    TOKEN_SUB_TYPE_USER => {
+      let user = match get_user_by_id(pool, jwt.claims.app, jwt.claims.sub).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+          return InternalError::from(StatusCode::UNAUTHORIZED)
+            .with_application_error(INTERNAL_ERROR)
+            .into_response();
+        }
+        Err(e) => {
+          log::error!("error fetching user from database: {}", e);
+          return InternalError::from(StatusCode::UNAUTHORIZED)
+            .with_application_error(INTERNAL_ERROR)
+            .into_response();
+        }
+      };
+      let scope = jwt.claims.scopes.join(" ");
+      create_user_token(
+        pool,
+        tenant,
+        user,
+        if scope.is_empty() { None } else { Some(scope) },
+        Some(TOKEN_ISSUED_TYPE_REFRESH_TOKEN.to_owned()),
+        family.is_mfa_validated(),
+        UserTokenOptions {
+          refresh_token: Some(refresh_token),
+          audience,
+          amr: jwt.claims.amr,
+          auth_time: jwt.claims.auth_time,
+          mail_sender: Some(state.mail_sender.clone()),
+          sms_sender: Some(state.sms_sender.clone()),
+          ..Default::default()
+        },
+      )
+      .await
+      .into_response()
+    }
+    TOKEN_SUB_TYPE_SERVICE_ACCOUNT => {
+      let service_account =
+        match get_service_account_by_id(pool, jwt.claims.app, jwt.claims.sub).await {
+          Ok(Some(service_account)) if service_account.is_active() => service_account,
+          Ok(_) => {
+            return InternalError::from(StatusCode::UNAUTHORIZED)
+              .with_application_error(INTERNAL_ERROR)
+              .into_response();
+          }
+          Err(e) => {
+            log::error!("error fetching service account from database: {}", e);
+            return InternalError::from(StatusCode::UNAUTHORIZED)
+              .with_application_error(INTERNAL_ERROR)
+              .into_response();
+          }
+        };
+      // scopes removed from the service account since the token was issued are dropped
+      let scopes = jwt
+        .claims
+        .scopes
+        .into_iter()
+        .filter(|scope| service_account.allows_scope(scope))
+        .collect();
+      create_service_token_token(
+        pool,
+        tenant,
+        service_account,
+        scopes,
+        audience,
+        Some(TOKEN_ISSUED_TYPE_REFRESH_TOKEN.to_owned()),
+        Some(refresh_token),
+      )
+      .await
+      .into_response()
+    }
+    sub_type => {
+      log::error!("invalid token sub_type: {}", sub_type);
+      InternalError::from(StatusCode::UNAUTHORIZED)
+        .with_application_error(INTERNAL_ERROR)
+        .into_response()
+    }
+  }
+}
+
+async fn authorization_code_request(
+  pool: &AnyPool,
+  tenant: TenantRow,
+  code: String,
+  scope: Option<String>,
+  audience: Option<String>,
+  client_info: ClientInfo,
+) -> impl IntoResponse {
+  let jwt = match parse_jwt::<BasicClaims>(pool, &code, &tenant).await {
+    Ok(claims) => claims,
+    Err(e) => {
+      log::error!("error decoding jwt: {}", e);
+      return InternalError::from(StatusCode::UNAUTHORIZED)
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  if jwt.claims.r#type != TOKEN_TYPE_AUTHORIZATION_CODE {
+    log::error!("invalid token type: {}", jwt.claims.r#type);
+    return InternalError::from(StatusCode::UNAUTHORIZED)
+      .with_application_error(INTERNAL_ERROR)
+      .into_response();
+  }
+  if jwt.claims.sub_type != TOKEN_SUB_TYPE_USER {
+    log::error!("invalid token sub_type: {}", jwt.claims.sub_type);
+    return InternalError::from(StatusCode::UNAUTHORIZED)
+      .with_application_error(INTERNAL_ERROR)
+      .into_response();
+  }
   let user = match get_user_by_id(pool, jwt.claims.app, jwt.claims.sub).await {
     Ok(Some(user)) => user,
     Ok(None) => {
+      log::error!("user not found: {}", jwt.claims.sub);
+      return InternalError::from(StatusCode::UNAUTHORIZED).into_response();
+    }
+    Err(e) => {
+      log::error!("error fetching user from database: {}", e);
+      return InternalError::from(StatusCode::UNAUTHORIZED)
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  let scope = scope.unwrap_or_else(|| jwt.claims.scopes.join(" "));
+  create_user_token(
+    pool,
+    tenant,
+    user,
+    if scope.is_empty() { None } else { Some(scope) },
+    Some(TOKEN_ISSUED_TYPE_AUTHORIZATION_CODE.to_owned()),
+    true,
+    UserTokenOptions {
+      audience,
+      client_info,
+      amr: jwt.claims.amr,
+      auth_time: jwt.claims.auth_time,
+      ..Default::default()
+    },
+  )
+  .await
+  .into_response()
+}
+
+/// Redeems a single-use authorization code issued to a registered OAuth2 client by /authorize.
+#[allow(clippy::too_many_arguments)]
+async fn oauth2_client_authorization_code_request(
+  pool: &AnyPool,
+  tenant: TenantRow,
+  code: &str,
+  grant: AuthorizationCodeGrant,
+  redirect_uri: Option<String>,
+  code_verifier: Option<String>,
+  client_id: Option<String>,
+  client_secret: Option<String>,
+  audience: Option<String>,
+  client_info: ClientInfo,
+) -> impl IntoResponse {
+  if grant.tenant_id != tenant.id {
+    log::error!("authorization code was issued by another tenant");
+    return InternalError::from(StatusCode::UNAUTHORIZED)
+      .with_error("code", INVALID_ERROR)
+      .into_response();
+  }
+  let oauth2_client = match client_id.as_deref() {
+    Some(client_id) => match get_oauth2_client_by_client_id(pool, client_id).await {
+      Ok(Some(oauth2_client))
+        if oauth2_client.id == grant.oauth2_client_id && oauth2_client.is_active() =>
+      {
+        oauth2_client
+      }
+      Ok(_) => {
+        return InternalError::from(StatusCode::UNAUTHORIZED)
+          .with_error("client_id", INVALID_ERROR)
+          .into_response();
+      }
+      Err(e) => {
+        log::error!("error fetching OAuth2 client from database: {}", e);
+        return InternalError::from(StatusCode::INTERNAL_SERVER_ERROR)
+          .with_application_error(INTERNAL_ERROR)
+          .into_response();
+      }
+    },
+    None => {
+      return InternalError::from(StatusCode::UNAUTHORIZED)
+        .with_error("client_id", REQUIRED_ERROR)
+        .into_response();
+    }
+  };
+  if !oauth2_client.is_public() {
+    match client_secret.map(|client_secret| oauth2_client.verify(&client_secret)) {
+      Some(Ok(true)) => {}
+      Some(Ok(false)) | None => {
+        return InternalError::from(StatusCode::UNAUTHORIZED)
+          .with_error("client_secret", INVALID_ERROR)
+          .into_response();
+      }
+      Some(Err(e)) => {
+        log::error!("error verifying OAuth2 client secret: {}", e);
+        return InternalError::from(StatusCode::UNAUTHORIZED)
+          .with_error("client_secret", INVALID_ERROR)
+          .into_response();
+      }
+    }
+  }
+  if grant.redirect_uri.is_some() && grant.redirect_uri != redirect_uri {
+    return InternalError::bad_request()
+      .with_error("redirect_uri", INVALID_ERROR)
+      .into_response();
+  }
+  if !grant.verify_code_verifier(code_verifier.as_deref()) {
+    return InternalError::bad_request()
+      .with_error("code_verifier", INVALID_ERROR)
+      .into_response();
+  }
+  // the code is only used up once every check has passed, whoever deletes it first redeems it
+  if kv::delete::<_, AuthorizationCodeGrant>(pool, AuthorizationCodeGrant::kv_key(code))
+    .await
+    .is_none()
+  {
+    return InternalError::from(StatusCode::UNAUTHORIZED)
+      .with_error("code", INVALID_ERROR)
+      .into_response();
+  }
+  let user = match get_user_by_id(pool, grant.application_id, grant.user_id).await {
+    Ok(Some(user)) if user.is_active() => user,
+    Ok(_) => {
+      log::error!("user not found or inactive: {}", grant.user_id);
+      return InternalError::from(StatusCode::UNAUTHORIZED).into_response();
+    }
+    Err(e) => {
+      log::error!("error fetching user from database: {}", e);
       return InternalError::from(StatusCode::UNAUTHORIZED)
         .with_application_error(INTERNAL_ERROR)
         .into_response();
     }
+  };
+  create_user_token(
+    pool,
+    tenant,
+    user,
+    grant.scope,
+    Some(TOKEN_ISSUED_TYPE_AUTHORIZATION_CODE.to_owned()),
+    true,
+    UserTokenOptions {
+      nonce: grant.nonce,
+      audience,
+      client_info,
+      oauth2_client_id: Some(oauth2_client.id),
+      amr: grant.amr,
+      auth_time: grant.auth_time,
+      ..Default::default()
+    },
+  )
+  .await
+  .into_response()
+}
+
+/// RFC 8693 token exchange. A user subject token is narrowed to the requested scopes and
+/// audience, delegated to the service account of the `actor_token` if one is given. A service
+/// account subject token impersonates the `requested_subject` user with the service account as
+/// the actor, limited to the impersonation scopes the service account was granted. Either way
+/// the service account needs the `users:impersonate` scope. Exchanged tokens are never
+/// refreshable.
+async fn token_exchange_request(
+  pool: &AnyPool,
+  tenant: TenantRow,
+  request: TokenExchangeRequest,
+) -> impl IntoResponse {
+  if !is_exchangeable_token_type(&request.subject_token_type) {
+    return InternalError::bad_request()
+      .with_error("subject_token_type", INVALID_ERROR)
+      .into_response();
+  }
+  if request
+    .requested_token_type
+    .as_deref()
+    .is_some_and(|requested_token_type| requested_token_type != TOKEN_TYPE_URN_ACCESS_TOKEN)
+  {
+    return InternalError::bad_request()
+      .with_error("requested_token_type", INVALID_ERROR)
+      .into_response();
+  }
+  if let Some(audience) = request.audience.as_deref() {
+    match is_exchange_audience(pool, &tenant, audience).await {
+      Ok(true) => {}
+      Ok(false) => {
+        return InternalError::bad_request()
+          .with_error("audience", INVALID_ERROR)
+          .into_response();
+      }
+      Err(e) => {
+        log::error!("error fetching OAuth2 client from database: {}", e);
+        return InternalError::internal_error()
+          .with_application_error(INTERNAL_ERROR)
+          .into_response();
+      }
+    }
+  }
+  let subject = match parse_exchange_token(pool, &tenant, &request.subject_token).await {
+    Some(subject) => subject,
+    None => {
+      return InternalError::unauthorized()
+        .with_error("subject_token", INVALID_ERROR)
+        .into_response();
+    }
+  };
+  let actor = match request.actor_token.as_deref() {
+    Some(actor_token) => {
+      if !request
+        .actor_token_type
+        .as_deref()
+        .is_some_and(is_exchangeable_token_type)
+      {
+        return InternalError::bad_request()
+          .with_error("actor_token_type", INVALID_ERROR)
+          .into_response();
+      }
+      let actor = match parse_exchange_token(pool, &tenant, actor_token).await {
+        Some(actor) if actor.sub_type == TOKEN_SUB_TYPE_SERVICE_ACCOUNT => actor,
+        _ => {
+          return InternalError::unauthorized()
+            .with_error("actor_token", INVALID_ERROR)
+            .into_response();
+        }
+      };
+      if let Err(e) = impersonating_service_account(pool, &actor, "actor_token").await {
+        return e.into_response();
+      }
+      Some(actor)
+    }
+    None => None,
+  };
+
+  // narrowed tokens keep the subject's login, impersonated users never logged in
+  let (user_id, scope, actor, amr, auth_time) = match subject.sub_type.as_str() {
+    TOKEN_SUB_TYPE_USER => {
+      if request.requested_subject.is_some() {
+        return InternalError::bad_request()
+          .with_error("requested_subject", INVALID_ERROR)
+          .into_response();
+      }
+      let scopes = match request.scope.as_deref() {
+        Some(scope) => {
+          let scopes = parse_scopes(Some(scope));
+          if !scopes.iter().all(|scope| subject.scopes.contains(scope)) {
+            return InternalError::bad_request()
+              .with_error("scope", INVALID_ERROR)
+              .into_response();
+          }
+          scopes
+        }
+        None => subject.scopes,
+      };
+      // a new actor is recorded in front of the actor the subject token was already delegated to
+      let actor = match actor {
+        Some(actor) => Some(ActorClaims {
+          sub_type: actor.sub_type,
+          sub: actor.sub,
+          act: subject.act.map(Box::new),
+        }),
+        None => subject.act,
+      };
+      (
+        subject.sub,
+        scopes.join(" "),
+        actor,
+        subject.amr,
+        subject.auth_time,
+      )
+    }
+    TOKEN_SUB_TYPE_SERVICE_ACCOUNT => {
+      if actor.is_some() {
+        return InternalError::bad_request()
+          .with_error("actor_token", INVALID_ERROR)
+          .into_response();
+      }
+      let Some(requested_subject) = request.requested_subject else {
+        return InternalError::bad_request()
+          .with_error("requested_subject", REQUIRED_ERROR)
+          .into_response();
+      };
+      let service_account =
+        match impersonating_service_account(pool, &subject, "subject_token").await {
+          Ok(service_account) => service_account,
+          Err(e) => return e.into_response(),
+        };
+      let impersonation_scopes = IMPERSONATION_SCOPES
+        .into_iter()
+        .filter(|scope| service_account.allows_scope(scope));
+      let scopes = match request.scope.as_deref() {
+        Some(scope) => parse_scopes(Some(scope))
+          .into_iter()
+          .filter(|scope| impersonation_scopes.clone().any(|allowed| allowed == scope))
+          .collect::<Vec<_>>(),
+        None => impersonation_scopes.map(ToOwned::to_owned).collect(),
+      };
+      let actor = ActorClaims {
+        sub_type: subject.sub_type,
+        sub: subject.sub,
+        act: None,
+      };
+      (
+        requested_subject,
+        scopes.join(" "),
+        Some(actor),
+        Vec::new(),
+        None,
+      )
+    }
+    sub_type => {
+      log::error!("invalid token sub_type: {}", sub_type);
+      return InternalError::unauthorized()
+        .with_error("subject_token", INVALID_ERROR)
+        .into_response();
+    }
+  };
+
+  let user = match get_user_by_id(pool, tenant.application_id, user_id).await {
+    Ok(Some(user)) if user.is_active() => user,
+    Ok(_) => {
+      return InternalError::bad_request()
+        .with_error("requested_subject", NOT_FOUND_ERROR)
+        .into_response();
+    }
     Err(e) => {
       log::error!("error fetching user from database: {}", e);
-      return InternalError::from(StatusCode::UNAUTHORIZED)
+      return InternalError::internal_error()
         .with_application_error(INTERNAL_ERROR)
         .into_response();
     }
   };
-  let scope = jwt.claims.scopes.join(" ");
   create_user_token(
     pool,
     tenant,
     user,
     if scope.is_empty() { None } else { Some(scope) },
-    Some(TOKEN_ISSUED_TYPE_REFRESH_TOKEN.to_owned()),
-    false,
+    Some(TOKEN_TYPE_URN_ACCESS_TOKEN.to_owned()),
+    true,
+    UserTokenOptions {
+      audience: request.audience,
+      actor,
+      skip_refresh_token: true,
+      amr,
+      auth_time,
+      ..Default::default()
+    },
   )
   .await
   .into_response()
 }
 
-async fn authorization_code_request(
+/// The active service account of an impersonating subject token or delegated actor token, which
+/// must carry the `users:impersonate` scope the service account was granted.
+async fn impersonating_service_account(
   pool: &AnyPool,
-  tenant: TenantRow,
-  code: String,
-  scope: Option<String>,
-) -> impl IntoResponse {
-  let jwt = match parse_jwt::<BasicClaims>(&code, &tenant) {
-    Ok(claims) => claims,
+  claims: &BasicClaims,
+  token_field: &str,
+) -> Result<ServiceAccountRow, InternalError> {
+  let service_account = match get_service_account_by_id(pool, claims.app, claims.sub).await {
+    Ok(Some(service_account)) if service_account.is_active() => service_account,
+    Ok(_) => return Err(InternalError::unauthorized().with_error(token_field, INVALID_ERROR)),
     Err(e) => {
-      log::error!("error decoding jwt: {}", e);
-      return InternalError::from(StatusCode::UNAUTHORIZED)
-        .with_application_error(INTERNAL_ERROR)
-        .into_response();
+      log::error!("error fetching service account from database: {}", e);
+      return Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR));
     }
   };
-  if jwt.claims.r#type != TOKEN_TYPE_AUTHORIZATION_CODE {
-    log::error!("invalid token type: {}", jwt.claims.r#type);
-    return InternalError::from(StatusCode::UNAUTHORIZED)
-      .with_application_error(INTERNAL_ERROR)
-      .into_response();
+  require_service_account_scope(&service_account, &claims.scopes, SCOPE_USERS_IMPERSONATE)?;
+  Ok(service_account)
+}
+
+/// Exchanged tokens may only be issued for one of the tenant's audiences or for the client id of
+/// one of the tenant's active OAuth2 clients.
+async fn is_exchange_audience(
+  pool: &AnyPool,
+  tenant: &TenantRow,
+  audience: &str,
+) -> sqlx::Result<bool> {
+  if tenant.allows_audience(audience) {
+    return Ok(true);
   }
-  if jwt.claims.sub_type != TOKEN_SUB_TYPE_USER {
-    log::error!("invalid token sub_type: {}", jwt.claims.sub_type);
-    return InternalError::from(StatusCode::UNAUTHORIZED)
-      .with_application_error(INTERNAL_ERROR)
-      .into_response();
+  Ok(
+    get_oauth2_client_by_client_id(pool, audience)
+      .await?
+      .is_some_and(|oauth2_client| {
+        oauth2_client.tenant_id == tenant.id && oauth2_client.is_active()
+      }),
+  )
+}
+
+fn is_exchangeable_token_type(token_type: &str) -> bool {
+  token_type == TOKEN_TYPE_URN_ACCESS_TOKEN || token_type == TOKEN_TYPE_URN_JWT
+}
+
+/// The claims of a subject or actor token, which must be an unrevoked bearer token issued to the
+/// same application as the tenant handling the exchange.
+async fn parse_exchange_token(
+  pool: &AnyPool,
+  tenant: &TenantRow,
+  token: &str,
+) -> Option<BasicClaims> {
+  match parse_authorization::<BasicClaims>(pool, token).await {
+    Ok((_, token_data))
+      if token_data.claims.r#type == TOKEN_TYPE_BEARER
+        && token_data.claims.app == tenant.application_id =>
+    {
+      Some(token_data.claims)
+    }
+    Ok((_, token_data)) => {
+      log::error!(
+        "invalid exchange token type {} for application {}",
+        token_data.claims.r#type,
+        token_data.claims.app
+      );
+      None
+    }
+    Err(_) => None,
   }
-  let user = match get_user_by_id(pool, jwt.claims.app, jwt.claims.sub).await {
-    Ok(Some(user)) => user,
-    Ok(None) => {
-      log::error!("user not found: {}", jwt.claims.sub);
+}
+
+/// Loads the grant of a device code once its user code was approved, otherwise returns the
+/// RFC 8628 error telling the device to keep polling or to stop.
+async fn device_code_grant(
+  pool: &AnyPool,
+  config: &Config,
+  tenant: &TenantRow,
+  device_code: &str,
+  client_id: Option<&str>,
+  client_secret: Option<&str>,
+) -> Result<DeviceCodeGrant, &'static str> {
+  let device_code_kv_key = DeviceCodeGrant::kv_key(device_code);
+  let Some(grant) = kv::get::<_, DeviceCodeGrant>(pool, device_code_kv_key.clone()).await else {
+    return Err(OAUTH2_ERROR_EXPIRED_TOKEN);
+  };
+  if grant.tenant_id != tenant.id {
+    log::error!("device code was issued by another tenant");
+    return Err(OAUTH2_ERROR_INVALID_GRANT);
+  }
+  let oauth2_client = match client_id {
+    Some(client_id) => match get_oauth2_client_by_client_id(pool, client_id).await {
+      Ok(Some(oauth2_client))
+        if oauth2_client.id == grant.oauth2_client_id && oauth2_client.is_active() =>
+      {
+        oauth2_client
+      }
+      Ok(_) => return Err(OAUTH2_ERROR_INVALID_CLIENT),
+      Err(e) => {
+        log::error!("error fetching OAuth2 client from database: {}", e);
+        return Err(OAUTH2_ERROR_SERVER_ERROR);
+      }
+    },
+    None => return Err(OAUTH2_ERROR_INVALID_CLIENT),
+  };
+  if !oauth2_client.is_public() {
+    match client_secret.map(|client_secret| oauth2_client.verify(client_secret)) {
+      Some(Ok(true)) => {}
+      Some(Ok(false)) | None => return Err(OAUTH2_ERROR_INVALID_CLIENT),
+      Some(Err(e)) => {
+        log::error!("error verifying OAuth2 client secret: {}", e);
+        return Err(OAUTH2_ERROR_INVALID_CLIENT);
+      }
+    }
+  }
+  if grant.denied {
+    kv::delete::<_, DeviceCodeGrant>(pool, device_code_kv_key).await;
+    return Err(OAUTH2_ERROR_ACCESS_DENIED);
+  }
+  if grant.user_id.is_some() {
+    // device codes are single use, whoever deletes the grant first gets the token
+    return match kv::delete::<_, DeviceCodeGrant>(pool, device_code_kv_key).await {
+      Some(grant) => Ok(grant),
+      None => Err(OAUTH2_ERROR_EXPIRED_TOKEN),
+    };
+  }
+  let now = chrono::Utc::now().timestamp();
+  let interval = config.oauth2.device_code_interval_in_seconds as i64;
+  let polled_at_kv_key = DeviceCodeGrant::polled_at_kv_key(device_code);
+  let polled_at = kv::get::<_, i64>(pool, polled_at_kv_key.clone()).await;
+  kv::set(
+    pool,
+    polled_at_kv_key,
+    &now,
+    Some(chrono::Duration::seconds(grant.expires_at - now)),
+  )
+  .await;
+  if polled_at.is_some_and(|polled_at| now - polled_at < interval) {
+    return Err(OAUTH2_ERROR_SLOW_DOWN);
+  }
+  Err(OAUTH2_ERROR_AUTHORIZATION_PENDING)
+}
+
+async fn device_code_request(
+  pool: &AnyPool,
+  tenant: TenantRow,
+  grant: DeviceCodeGrant,
+  client_info: ClientInfo,
+) -> impl IntoResponse {
+  let Some(user_id) = grant.user_id else {
+    return InternalError::bad_request()
+      .with_error("device_code", OAUTH2_ERROR_AUTHORIZATION_PENDING)
+      .into_response();
+  };
+  let user = match get_user_by_id(pool, grant.application_id, user_id).await {
+    Ok(Some(user)) if user.is_active() => user,
+    Ok(_) => {
+      log::error!("user not found or inactive: {}", user_id);
       return InternalError::from(StatusCode::UNAUTHORIZED).into_response();
     }
     Err(e) => {
@@ -267,14 +1766,21 @@ async fn authorization_code_request(
         .into_response();
     }
   };
-  let scope = scope.unwrap_or_else(|| jwt.claims.scopes.join(" "));
+  // the user approved the device from an authenticated session
   create_user_token(
     pool,
     tenant,
     user,
-    if scope.is_empty() { None } else { Some(scope) },
-    Some(TOKEN_ISSUED_TYPE_AUTHORIZATION_CODE.to_owned()),
+    grant.scope,
+    Some(TOKEN_ISSUED_TYPE_DEVICE_CODE.to_owned()),
     true,
+    UserTokenOptions {
+      client_info,
+      oauth2_client_id: Some(grant.oauth2_client_id),
+      amr: grant.amr,
+      auth_time: grant.auth_time,
+      ..Default::default()
+    },
   )
   .await
   .into_response()
@@ -285,10 +1791,21 @@ async fn service_account_request(
   tenant: TenantRow,
   client_id: uuid::Uuid,
   client_secret: uuid::Uuid,
+  scope: Option<String>,
+  audience: Option<String>,
 ) -> impl IntoResponse {
   let service_account = match get_service_account_by_client_id(pool, &client_id.to_string()).await {
-    Ok(Some(service_account)) => service_account,
-    Ok(None) => return InternalError::from(StatusCode::UNAUTHORIZED).into_response(),
+    // service accounts may only get tokens from the tenants of their own application
+    Ok(Some(service_account))
+      if service_account.is_active() && service_account.application_id == tenant.application_id =>
+    {
+      service_account
+    }
+    Ok(_) => {
+      return InternalError::from(StatusCode::UNAUTHORIZED)
+        .with_error("client_id", INVALID_ERROR)
+        .into_response();
+    }
     Err(e) => {
       log::error!("error fetching service account from database: {}", e);
       return InternalError::from(StatusCode::UNAUTHORIZED)
@@ -297,29 +1814,55 @@ async fn service_account_request(
     }
   };
   match service_account.verify(&client_secret.to_string()) {
-    Ok(true) => create_service_token_token(
-      pool,
-      tenant,
-      service_account,
-      Some(TOKEN_ISSUED_TYPE_SERVICE_ACCOUNT.to_owned()),
-    )
-    .await
-    .into_response(),
-    Ok(false) => InternalError::from(StatusCode::UNAUTHORIZED).into_response(),
+    Ok(true) => {}
+    Ok(false) => return InternalError::from(StatusCode::UNAUTHORIZED).into_response(),
     Err(e) => {
       log::error!("error verifying user password: {}", e);
-      InternalError::from(StatusCode::UNAUTHORIZED)
+      return InternalError::from(StatusCode::UNAUTHORIZED)
         .with_error("client_secret", INVALID_ERROR)
-        .into_response()
+        .into_response();
     }
   }
+  let scopes = match scope.as_deref() {
+    Some(scope) => {
+      let scopes = parse_scopes(Some(scope));
+      let allowed = scopes.iter().all(|scope| {
+        SERVICE_ACCOUNT_SCOPES.contains(&scope.as_str()) && service_account.allows_scope(scope)
+      });
+      if !allowed {
+        return InternalError::bad_request()
+          .with_error("scope", INVALID_ERROR)
+          .into_response();
+      }
+      scopes
+    }
+    None => SERVICE_ACCOUNT_SCOPES
+      .into_iter()
+      .filter(|scope| service_account.allows_scope(scope))
+      .map(ToOwned::to_owned)
+      .collect(),
+  };
+  create_service_token_token(
+    pool,
+    tenant,
+    service_account,
+    scopes,
+    audience,
+    Some(TOKEN_ISSUED_TYPE_SERVICE_ACCOUNT.to_owned()),
+    None,
+  )
+  .await
+  .into_response()
 }
 
 async fn create_service_token_token(
-  _pool: &AnyPool,
+  pool: &AnyPool,
   tenant: TenantRow,
   service_account: ServiceAccountRow,
+  scopes: Vec<String>,
+  audience: Option<String>,
   issued_token_type: Option<String>,
+  refresh_token: Option<RefreshTokenRow>,
 ) -> impl IntoResponse {
   let now = chrono::Utc::now();
 
@@ -332,8 +1875,15 @@ async fn create_service_token_token(
     nbf: now.timestamp(),
     exp: now.timestamp() + tenant.expires_in_seconds,
     iss: tenant.issuer.clone(),
-    aud: tenant.audience.clone(),
-    scopes: Vec::with_capacity(0),
+    aud: audience.or_else(|| tenant.audience.clone()),
+    scopes,
+    jti: Some(uuid::Uuid::new_v4().to_string()),
+    sid: None,
+    auth_time: None,
+    amr: Vec::new(),
+    acr: None,
+    act: None,
+    custom: serde_json::Map::new(),
   };
 
   let access_token = match claims.encode(&tenant) {
@@ -346,17 +1896,9 @@ async fn create_service_token_token(
     }
   };
 
-  let mut refresh_claims = claims.clone();
-  refresh_claims.r#type = TOKEN_TYPE_REFRESH.to_owned();
-  refresh_claims.exp = refresh_claims.iat + tenant.refresh_expires_in_seconds;
-  let refresh_token = match claims.encode(&tenant) {
+  let refresh_token = match encode_refresh_token(pool, &tenant, &claims, refresh_token).await {
     Ok(token) => token,
-    Err(e) => {
-      log::error!("error encoding jwt: {}", e);
-      return InternalError::from(StatusCode::INTERNAL_SERVER_ERROR)
-        .with_application_error(INTERNAL_ERROR)
-        .into_response();
-    }
+    Err(e) => return e.into_response(),
   };
 
   (
@@ -367,7 +1909,7 @@ async fn create_service_token_token(
       issued_token_type,
       issued_at: DateTime::<Utc>::from_timestamp(claims.iat, 0).unwrap_or_default(),
       expires_in: tenant.expires_in_seconds,
-      scope: None,
+      scope: Some(claims.scopes.join(" ")).filter(|scope| !scope.is_empty()),
       refresh_token: Some(refresh_token),
       refresh_token_expires_in: Some(tenant.refresh_expires_in_seconds),
       id_token: None,
@@ -376,6 +1918,34 @@ async fn create_service_token_token(
     .into_response()
 }
 
+/// Optional inputs of [`create_user_token`]
+#[derive(Default)]
+pub(crate) struct UserTokenOptions {
+  /// The next refresh token of an existing refresh token family
+  pub refresh_token: Option<RefreshTokenRow>,
+  /// The OpenID Connect nonce of the authorization request, added to the id token
+  pub nonce: Option<String>,
+  /// Replaces the tenant's default audience, for tokens requested for a resource or narrowed by a
+  /// token exchange
+  pub audience: Option<String>,
+  /// The `act` claim of a delegated or impersonated token
+  pub actor: Option<ActorClaims>,
+  /// Exchanged tokens can not be refreshed, a new exchange is required instead
+  pub skip_refresh_token: bool,
+  /// Recorded on the session started by a login
+  pub client_info: ClientInfo,
+  /// The registered OAuth2 client the login was for, notified when the session ends
+  pub oauth2_client_id: Option<i64>,
+  /// How the user logged in, see the `AMR_` constants
+  pub amr: Vec<String>,
+  /// When the user logged in, logins starting a new session default to now
+  pub auth_time: Option<i64>,
+  /// Mails the one-time code of email MFA, logins that can require MFA must set it
+  pub mail_sender: Option<Arc<dyn MailSender>>,
+  /// Texts the one-time code of text MFA, logins that can require MFA must set it
+  pub sms_sender: Option<Arc<dyn SmsSender>>,
+}
+
 pub(crate) async fn create_user_token(
   pool: &AnyPool,
   tenant: TenantRow,
@@ -383,6 +1953,7 @@ pub(crate) async fn create_user_token(
   scope: Option<String>,
   issued_token_type: Option<String>,
   mfa_validated: bool,
+  options: UserTokenOptions,
 ) -> impl IntoResponse {
   if !mfa_validated {
     match get_user_config_by_user_id(pool, user.id).await {
@@ -396,6 +1967,7 @@ pub(crate) async fn create_user_token(
               scope,
               issued_token_type,
               format!("{TOKEN_TYPE_MFA_TOTP_PREFIX}{mfa_type}"),
+              options,
             )
             .await
             .into_response();
@@ -412,7 +1984,52 @@ pub(crate) async fn create_user_token(
     }
   }
   let now = chrono::Utc::now();
-  let scopes = parse_scopes(scope.as_ref().map(String::as_str));
+  let scopes = parse_scopes(scope.as_deref());
+  let (custom_claims, custom_id_claims) = match get_user_custom_claims(pool, &tenant, &user).await {
+    Ok(custom_claims) => custom_claims,
+    Err(e) => return e.into_response(),
+  };
+  let mut refresh_token = options.refresh_token;
+  let mut auth_time = options.auth_time;
+  let session = if let Some(refresh_token) = refresh_token.as_ref() {
+    match touch_user_session_by_refresh_token_family_id(
+      pool,
+      refresh_token.refresh_token_family_id,
+      refresh_token.expires_at,
+    )
+    .await
+    {
+      Ok(session) => session,
+      Err(e) => {
+        log::error!("error updating user session: {}", e);
+        return InternalError::internal_error()
+          .with_application_error(INTERNAL_ERROR)
+          .into_response();
+      }
+    }
+  } else if options.skip_refresh_token {
+    None
+  } else {
+    match create_user_session_refresh_token(
+      pool,
+      &tenant,
+      &user,
+      issued_token_type.clone(),
+      options.client_info,
+      options.oauth2_client_id,
+      mfa_validated,
+      options.amr.clone(),
+      *auth_time.get_or_insert(now.timestamp()),
+    )
+    .await
+    {
+      Ok((session, session_refresh_token)) => {
+        refresh_token = Some(session_refresh_token);
+        Some(session)
+      }
+      Err(e) => return e.into_response(),
+    }
+  };
 
   let claims = BasicClaims {
     r#type: TOKEN_TYPE_BEARER.to_owned(),
@@ -423,8 +2040,15 @@ pub(crate) async fn create_user_token(
     nbf: now.timestamp(),
     exp: now.timestamp() + tenant.expires_in_seconds,
     iss: tenant.issuer.clone(),
-    aud: tenant.audience.clone(),
+    aud: options.audience.or_else(|| tenant.audience.clone()),
     scopes: scopes.clone(),
+    jti: Some(uuid::Uuid::new_v4().to_string()),
+    sid: session.map(|session| session.sid),
+    auth_time,
+    acr: acr_from_amr(&options.amr),
+    amr: options.amr,
+    act: options.actor,
+    custom: custom_claims,
   };
 
   let access_token = match claims.encode(&tenant) {
@@ -437,116 +2061,39 @@ pub(crate) async fn create_user_token(
     }
   };
 
-  let mut refresh_claims = claims.clone();
-  refresh_claims.r#type = TOKEN_TYPE_REFRESH.to_owned();
-  refresh_claims.exp = refresh_claims.iat + tenant.refresh_expires_in_seconds;
-  let refresh_token = match refresh_claims.encode(&tenant) {
-    Ok(token) => token,
-    Err(e) => {
-      log::error!("error encoding jwt: {}", e);
-      return InternalError::from(StatusCode::INTERNAL_SERVER_ERROR)
-        .with_application_error(INTERNAL_ERROR)
-        .into_response();
+  let refresh_token = if options.skip_refresh_token {
+    None
+  } else {
+    match encode_refresh_token(pool, &tenant, &claims, refresh_token).await {
+      Ok(token) => Some(token),
+      Err(e) => return e.into_response(),
     }
   };
 
   let mut id_token = None;
-  let show_address = has_address_scope(&scopes);
-  let show_profile = has_profile_scope(&scopes);
-  let show_email = has_email_scope(&scopes);
-  let show_phone = has_phone_scope(&scopes);
-  if show_address || show_profile || show_email || show_phone {
+  if has_address_scope(&scopes)
+    || has_profile_scope(&scopes)
+    || has_email_scope(&scopes)
+    || has_phone_scope(&scopes)
+  {
+    let profile = match get_user_openid_profile(pool, &user, &scopes).await {
+      Ok(profile) => profile,
+      Err(e) => return e.into_response(),
+    };
     let mut id_claims = OpenIdClaims {
       claims: claims.clone(),
-      ..Default::default()
+      profile,
+      nonce: options.nonce,
     };
-    if show_address || show_profile {
-      let user_info = match get_user_info_by_user_id(pool, user.application_id, user.id).await {
-        Ok(Some(user_info)) => user_info,
-        Ok(None) => {
-          log::error!("user info not found for user: {}", user.id);
-          return InternalError::from(StatusCode::INTERNAL_SERVER_ERROR)
-            .with_application_error(INTERNAL_ERROR)
-            .into_response();
-        }
-        Err(e) => {
-          log::error!("error fetching user info from database: {}", e);
-          return InternalError::from(StatusCode::INTERNAL_SERVER_ERROR)
-            .with_application_error(INTERNAL_ERROR)
-            .into_response();
-        }
-      };
-      if show_address {
-        id_claims.profile.address = user_info.address;
-      }
-      if show_profile {
-        id_claims.profile.name = user_info.name;
-        id_claims.profile.given_name = user_info.given_name;
-        id_claims.profile.family_name = user_info.family_name;
-        id_claims.profile.middle_name = user_info.middle_name;
-        id_claims.profile.nickname = user_info.nickname;
-        id_claims.profile.preferred_username = Some(user.username.clone());
-        id_claims.profile.profile_picture = user_info.profile_picture;
-        id_claims.profile.website = user_info.website;
-        id_claims.profile.gender = user_info.gender;
-        id_claims.profile.birthdate = user_info
-          .birthdate
-          .map(|birthdate| DateTime::<Utc>::from_timestamp(birthdate, 0).unwrap_or_default());
-        id_claims.profile.zone_info = user_info.zone_info;
-        id_claims.profile.locale = user_info.locale;
-      }
-    }
-    if show_email {
-      match get_user_emails_by_user_id(pool, user.application_id, user.id).await {
-        Ok(emails) => {
-          if let Some(email) = emails.iter().find(|email| email.is_primary()) {
-            id_claims.profile.email_verified = Some(email.is_verified());
-            id_claims.profile.email = Some(email.email.clone());
-          } else if let Some(email) = emails.iter().find(|email| email.is_verified()) {
-            id_claims.profile.email_verified = Some(email.is_verified());
-            id_claims.profile.email = Some(email.email.clone());
-          } else if let Some(email) = emails.first() {
-            id_claims.profile.email_verified = Some(email.is_verified());
-            id_claims.profile.email = Some(email.email.clone());
-          }
-        }
-        Err(e) => {
-          log::error!("error getting user primary email: {}", e);
-          return InternalError::internal_error()
-            .with_application_error(INTERNAL_ERROR)
-            .into_response();
-        }
-      }
-    }
-    if show_phone {
-      match get_user_phone_numbers_by_user_id(pool, user.application_id, user.id).await {
-        Ok(phone_numbers) => {
-          if let Some(phone_number) = phone_numbers
-            .iter()
-            .find(|phone_number| phone_number.is_primary())
-          {
-            id_claims.profile.phone_number_verified = Some(phone_number.is_verified());
-            id_claims.profile.phone_number = Some(phone_number.phone_number.clone());
-          } else if let Some(phone_number) = phone_numbers
-            .iter()
-            .find(|phone_number| phone_number.is_verified())
-          {
-            id_claims.profile.phone_number_verified = Some(phone_number.is_verified());
-            id_claims.profile.phone_number = Some(phone_number.phone_number.clone());
-          } else if let Some(phone_number) = phone_numbers.first() {
-            id_claims.profile.phone_number_verified = Some(phone_number.is_verified());
-            id_claims.profile.phone_number = Some(phone_number.phone_number.clone());
-          }
-        }
-        Err(e) => {
-          log::error!("error getting user primary phone number: {}", e);
-          return InternalError::internal_error()
-            .with_application_error(INTERNAL_ERROR)
-            .into_response();
-        }
-      }
-    }
     id_claims.claims.r#type = TOKEN_TYPE_ID.to_owned();
+    id_claims.claims.custom = custom_id_claims;
+    if let Ok(serde_json::Value::Object(profile_claims)) = serde_json::to_value(&id_claims.profile)
+    {
+      id_claims
+        .claims
+        .custom
+        .retain(|claim, _| !profile_claims.contains_key(claim));
+    }
     id_token = match id_claims.encode(&tenant) {
       Ok(token) => Some(token),
       Err(e) => {
@@ -567,14 +2114,311 @@ pub(crate) async fn create_user_token(
       issued_at: DateTime::<Utc>::from_timestamp(claims.iat, 0).unwrap_or_default(),
       expires_in: tenant.expires_in_seconds,
       scope,
-      refresh_token: Some(refresh_token),
-      refresh_token_expires_in: Some(tenant.refresh_expires_in_seconds),
-      id_token: id_token,
+      refresh_token_expires_in: refresh_token
+        .as_ref()
+        .map(|_| tenant.refresh_expires_in_seconds),
+      refresh_token,
+      id_token,
     }),
   )
     .into_response()
 }
 
+/// Starts a new session for a login, its refresh token is the first of a new refresh token family.
+#[allow(clippy::too_many_arguments)]
+async fn create_user_session_refresh_token(
+  pool: &AnyPool,
+  tenant: &TenantRow,
+  user: &UserRow,
+  grant_type: Option<String>,
+  client_info: ClientInfo,
+  oauth2_client_id: Option<i64>,
+  mfa_validated: bool,
+  amr: Vec<String>,
+  auth_time: i64,
+) -> Result<(UserSessionRow, RefreshTokenRow), InternalError> {
+  let refresh_token = match create_refresh_token_family(
+    pool,
+    CreateRefreshTokenFamily {
+      tenant_id: tenant.id,
+      sub_type: TOKEN_SUB_TYPE_USER.to_owned(),
+      sub: user.id,
+      expires_at: chrono::Utc::now().timestamp() + tenant.refresh_expires_in_seconds,
+      mfa_validated,
+    },
+  )
+  .await
+  {
+    Ok(refresh_token) => refresh_token,
+    Err(e) => {
+      log::error!("error creating refresh token family: {}", e);
+      return Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR));
+    }
+  };
+  let session = match create_user_session(
+    pool,
+    CreateUserSession {
+      tenant_id: tenant.id,
+      user_id: user.id,
+      refresh_token_family_id: refresh_token.refresh_token_family_id,
+      oauth2_client_id,
+      grant_type,
+      ip_address: client_info.ip_address,
+      user_agent: client_info.user_agent,
+      expires_at: refresh_token.expires_at,
+      amr,
+      auth_time,
+    },
+  )
+  .await
+  {
+    Ok(session) => session,
+    Err(e) => {
+      log::error!("error creating user session: {}", e);
+      return Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR));
+    }
+  };
+  Ok((session, refresh_token))
+}
+
+/// The claims of the tenant's claim mappings for the `user`'s access token and id token.
+async fn get_user_custom_claims(
+  pool: &AnyPool,
+  tenant: &TenantRow,
+  user: &UserRow,
+) -> Result<
+  (
+    serde_json::Map<String, serde_json::Value>,
+    serde_json::Map<String, serde_json::Value>,
+  ),
+  InternalError,
+> {
+  let mut access_claims = serde_json::Map::new();
+  let mut id_claims = serde_json::Map::new();
+  let claim_mappings = match get_tenant_claim_mappings(pool, tenant.id).await {
+    Ok(claim_mappings) => claim_mappings,
+    Err(e) => {
+      log::error!("error getting tenant claim mappings: {}", e);
+      return Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR));
+    }
+  };
+  if claim_mappings.is_empty() {
+    return Ok((access_claims, id_claims));
+  }
+
+  let mut profile_scopes = Vec::<String>::new();
+  for claim_mapping in &claim_mappings {
+    let scope = match (claim_mapping.source.as_str(), claim_mapping.value.as_str()) {
+      (CLAIM_MAPPING_SOURCE_USER_INFO, "address") => SCOPE_ADDRESS,
+      (CLAIM_MAPPING_SOURCE_USER_INFO, _) => SCOPE_PROFILE,
+      (CLAIM_MAPPING_SOURCE_USER, "email" | "email_verified") => SCOPE_EMAIL,
+      (CLAIM_MAPPING_SOURCE_USER, "phone_number" | "phone_number_verified") => SCOPE_PHONE,
+      _ => continue,
+    };
+    if !profile_scopes
+      .iter()
+      .any(|profile_scope| profile_scope == scope)
+    {
+      profile_scopes.push(scope.to_owned());
+    }
+  }
+  let profile = if profile_scopes.is_empty() {
+    serde_json::Map::new()
+  } else {
+    match serde_json::to_value(get_user_openid_profile(pool, user, &profile_scopes).await?) {
+      Ok(serde_json::Value::Object(profile)) => profile,
+      Ok(_) => serde_json::Map::new(),
+      Err(e) => {
+        log::error!("error serializing user profile: {}", e);
+        return Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR));
+      }
+    }
+  };
+  let application = if claim_mappings
+    .iter()
+    .any(|claim_mapping| claim_mapping.source == CLAIM_MAPPING_SOURCE_APPLICATION)
+  {
+    match repository::application::get_application_by_id(pool, user.application_id).await {
+      Ok(application) => application,
+      Err(e) => {
+        log::error!("error getting application: {}", e);
+        return Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR));
+      }
+    }
+  } else {
+    None
+  };
+
+  for claim_mapping in &claim_mappings {
+    let value = match (claim_mapping.source.as_str(), claim_mapping.value.as_str()) {
+      (CLAIM_MAPPING_SOURCE_STATIC, _) => Some(claim_mapping.static_value()),
+      (CLAIM_MAPPING_SOURCE_USER, "id") => Some(user.id.into()),
+      (CLAIM_MAPPING_SOURCE_USER, "username") => Some(user.username.clone().into()),
+      (CLAIM_MAPPING_SOURCE_USER, "updated_at") => Some(user.updated_at.into()),
+      (CLAIM_MAPPING_SOURCE_USER, "created_at") => Some(user.created_at.into()),
+      (CLAIM_MAPPING_SOURCE_USER | CLAIM_MAPPING_SOURCE_USER_INFO, field) => {
+        profile.get(field).cloned()
+      }
+      (CLAIM_MAPPING_SOURCE_APPLICATION, "id") => Some(user.application_id.into()),
+      (CLAIM_MAPPING_SOURCE_APPLICATION, "name") => application
+        .as_ref()
+        .map(|application| application.name.clone().into()),
+      _ => None,
+    };
+    let Some(value) = value.filter(|value| !value.is_null()) else {
+      continue;
+    };
+    if claim_mapping.in_id_token() {
+      id_claims.insert(claim_mapping.claim.clone(), value.clone());
+    }
+    if claim_mapping.in_access_token() {
+      access_claims.insert(claim_mapping.claim.clone(), value);
+    }
+  }
+  Ok((access_claims, id_claims))
+}
+
+/// The OpenID Connect profile claims of the `user` allowed by the `scopes`, shared by the id
+/// token and the userinfo endpoint.
+pub(crate) async fn get_user_openid_profile(
+  pool: &AnyPool,
+  user: &UserRow,
+  scopes: &[String],
+) -> Result<OpenIdProfile, InternalError> {
+  let show_address = has_address_scope(scopes);
+  let show_profile = has_profile_scope(scopes);
+  let show_email = has_email_scope(scopes);
+  let show_phone = has_phone_scope(scopes);
+  let mut profile = OpenIdProfile::default();
+  if show_address || show_profile {
+    let user_info = match get_user_info_by_user_id(pool, user.application_id, user.id).await {
+      Ok(Some(user_info)) => user_info,
+      Ok(None) => {
+        log::error!("user info not found for user: {}", user.id);
+        return Err(
+          InternalError::from(StatusCode::INTERNAL_SERVER_ERROR)
+            .with_application_error(INTERNAL_ERROR),
+        );
+      }
+      Err(e) => {
+        log::error!("error fetching user info from database: {}", e);
+        return Err(
+          InternalError::from(StatusCode::INTERNAL_SERVER_ERROR)
+            .with_application_error(INTERNAL_ERROR),
+        );
+      }
+    };
+    if show_address {
+      profile.address = user_info.address;
+    }
+    if show_profile {
+      profile.name = user_info.name;
+      profile.given_name = user_info.given_name;
+      profile.family_name = user_info.family_name;
+      profile.middle_name = user_info.middle_name;
+      profile.nickname = user_info.nickname;
+      profile.preferred_username = Some(user.username.clone());
+      profile.profile_picture = user_info.profile_picture;
+      profile.website = user_info.website;
+      profile.gender = user_info.gender;
+      profile.birthdate = user_info
+        .birthdate
+        .map(|birthdate| DateTime::<Utc>::from_timestamp(birthdate, 0).unwrap_or_default());
+      profile.zone_info = user_info.zone_info;
+      profile.locale = user_info.locale;
+    }
+  }
+  if show_email {
+    match get_user_emails_by_user_id(pool, user.application_id, user.id).await {
+      Ok(emails) => {
+        if let Some(email) = emails.iter().find(|email| email.is_primary()) {
+          profile.email_verified = Some(email.is_verified());
+          profile.email = Some(email.email.clone());
+        } else if let Some(email) = emails.iter().find(|email| email.is_verified()) {
+          profile.email_verified = Some(email.is_verified());
+          profile.email = Some(email.email.clone());
+        } else if let Some(email) = emails.first() {
+          profile.email_verified = Some(email.is_verified());
+          profile.email = Some(email.email.clone());
+        }
+      }
+      Err(e) => {
+        log::error!("error getting user primary email: {}", e);
+        return Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR));
+      }
+    }
+  }
+  if show_phone {
+    match get_user_phone_numbers_by_user_id(pool, user.application_id, user.id).await {
+      Ok(phone_numbers) => {
+        if let Some(phone_number) = phone_numbers
+          .iter()
+          .find(|phone_number| phone_number.is_primary())
+        {
+          profile.phone_number_verified = Some(phone_number.is_verified());
+          profile.phone_number = Some(phone_number.phone_number.clone());
+        } else if let Some(phone_number) = phone_numbers
+          .iter()
+          .find(|phone_number| phone_number.is_verified())
+        {
+          profile.phone_number_verified = Some(phone_number.is_verified());
+          profile.phone_number = Some(phone_number.phone_number.clone());
+        } else if let Some(phone_number) = phone_numbers.first() {
+          profile.phone_number_verified = Some(phone_number.is_verified());
+          profile.phone_number = Some(phone_number.phone_number.clone());
+        }
+      }
+      Err(e) => {
+        log::error!("error getting user primary phone number: {}", e);
+        return Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR));
+      }
+    }
+  }
+  Ok(profile)
+}
+
+/// Encodes a refresh token for the access token `claims`, starting a new refresh token family
+/// unless the next `refresh_token` of an existing family is given.
+async fn encode_refresh_token(
+  pool: &AnyPool,
+  tenant: &TenantRow,
+  claims: &BasicClaims,
+  refresh_token: Option<RefreshTokenRow>,
+) -> Result<String, InternalError> {
+  let refresh_token = match refresh_token {
+    Some(refresh_token) => refresh_token,
+    None => match create_refresh_token_family(
+      pool,
+      CreateRefreshTokenFamily {
+        tenant_id: tenant.id,
+        sub_type: claims.sub_type.clone(),
+        sub: claims.sub,
+        expires_at: claims.iat + tenant.refresh_expires_in_seconds,
+        mfa_validated: false,
+      },
+    )
+    .await
+    {
+      Ok(refresh_token) => refresh_token,
+      Err(e) => {
+        log::error!("error creating refresh token family: {}", e);
+        return Err(
+          InternalError::from(StatusCode::INTERNAL_SERVER_ERROR)
+            .with_application_error(INTERNAL_ERROR),
+        );
+      }
+    },
+  };
+  let mut refresh_claims = claims.clone();
+  refresh_claims.r#type = TOKEN_TYPE_REFRESH.to_owned();
+  refresh_claims.exp = refresh_token.expires_at;
+  refresh_claims.jti = Some(refresh_token.jti);
+  refresh_claims.encode(tenant).map_err(|e| {
+    log::error!("error encoding jwt: {}", e);
+    InternalError::from(StatusCode::INTERNAL_SERVER_ERROR).with_application_error(INTERNAL_ERROR)
+  })
+}
+
 pub(crate) async fn create_reset_password_token(
   _pool: &AnyPool,
   tenant: TenantRow,
@@ -583,7 +2427,7 @@ pub(crate) async fn create_reset_password_token(
   issued_token_type: Option<String>,
 ) -> impl IntoResponse {
   let now = chrono::Utc::now();
-  let scopes = parse_scopes(scope.as_ref().map(String::as_str));
+  let scopes = parse_scopes(scope.as_deref());
 
   let claims = BasicClaims {
     r#type: TOKEN_TYPE_RESET_PASSWORD.to_owned(),
@@ -596,6 +2440,13 @@ pub(crate) async fn create_reset_password_token(
     iss: tenant.issuer.clone(),
     aud: tenant.audience.clone(),
     scopes: scopes.clone(),
+    jti: Some(uuid::Uuid::new_v4().to_string()),
+    sid: None,
+    auth_time: None,
+    amr: Vec::new(),
+    acr: None,
+    act: None,
+    custom: serde_json::Map::new(),
   };
 
   let access_token = match claims.encode(&tenant) {
@@ -626,15 +2477,16 @@ pub(crate) async fn create_reset_password_token(
 }
 
 async fn create_mfa_token(
-  _pool: &AnyPool,
+  pool: &AnyPool,
   tenant: TenantRow,
   user: UserRow,
   scope: Option<String>,
   issued_token_type: Option<String>,
   mfa_token_type: String,
+  options: UserTokenOptions,
 ) -> impl IntoResponse {
   let now = chrono::Utc::now();
-  let scopes = parse_scopes(scope.as_ref().map(String::as_str));
+  let scopes = parse_scopes(scope.as_deref());
 
   let claims = BasicClaims {
     r#type: mfa_token_type,
@@ -645,8 +2497,15 @@ async fn create_mfa_token(
     nbf: now.timestamp(),
     exp: now.timestamp() + tenant.expires_in_seconds,
     iss: tenant.issuer.clone(),
-    aud: tenant.audience.clone(),
+    aud: options.audience.or_else(|| tenant.audience.clone()),
     scopes: scopes.clone(),
+    jti: Some(uuid::Uuid::new_v4().to_string()),
+    sid: None,
+    auth_time: None,
+    amr: options.amr,
+    acr: None,
+    act: None,
+    custom: serde_json::Map::new(),
   };
 
   let access_token = match claims.encode(&tenant) {
@@ -659,6 +2518,25 @@ async fn create_mfa_token(
     }
   };
 
+  let jti = claims.jti.as_deref().unwrap_or_default();
+  let sent = if claims.r#type == format!("{TOKEN_TYPE_MFA_TOTP_PREFIX}{}", UserMFAType::Email) {
+    match options.mail_sender.as_deref() {
+      Some(mail_sender) => send_email_otp(pool, mail_sender, &user, jti).await,
+      None => Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR)),
+    }
+  } else if claims.r#type == format!("{TOKEN_TYPE_MFA_TOTP_PREFIX}{}", UserMFAType::Text) {
+    match options.sms_sender.as_deref() {
+      Some(sms_sender) => send_text_otp(pool, sms_sender, &user, jti).await,
+      None => Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR)),
+    }
+  } else {
+    Ok(())
+  };
+  if let Err(e) = sent {
+    log::error!("error sending {} one-time code", claims.r#type);
+    return e.into_response();
+  }
+
   (
     StatusCode::CREATED,
     axum::Json(Token {
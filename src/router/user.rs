@@ -6,7 +6,12 @@ use crate::{
     NOT_ALLOWED_ERROR, NOT_FOUND_ERROR,
   },
   middleware::{
-    json::Json, service_account_authorization::ServiceAccountAuthorization,
+    claims::TOKEN_SUB_TYPE_USER,
+    json::Json,
+    service_account_authorization::{
+      require_service_account_scope, ServiceAccountAuthorization, SCOPE_USERS_READ,
+      SCOPE_USERS_WRITE,
+    },
     validated_json::ValidatedJson,
   },
   model::{
@@ -73,6 +78,7 @@ pub struct ApiDoc;
   responses(
     (status = 200, content_type = "application/json", body = UserPagination),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -82,11 +88,16 @@ pub struct ApiDoc;
 pub async fn all_users(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Query(application_id): Query<ApplicationId>,
   Query(offset_and_limit): Query<OffsetAndLimit>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_USERS_READ) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -258,6 +269,7 @@ pub async fn all_users(
   responses(
     (status = 200, content_type = "application/json", body = User),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 404, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
@@ -268,11 +280,16 @@ pub async fn all_users(
 pub async fn get_user_by_id(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path(user_id): Path<i64>,
   Query(application_id): Query<ApplicationId>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_USERS_READ) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -353,6 +370,7 @@ pub async fn get_user_by_id(
     (status = 201, content_type = "application/json", body = User),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -362,11 +380,16 @@ pub async fn get_user_by_id(
 pub async fn create_user(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Query(application_id): Query<ApplicationId>,
   ValidatedJson(payload): ValidatedJson<CreateUser>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_USERS_WRITE) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -414,6 +437,7 @@ pub async fn create_user(
     (status = 200, content_type = "application/json", body = User),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -423,12 +447,17 @@ pub async fn create_user(
 pub async fn update_user(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path(user_id): Path<i64>,
   Query(application_id): Query<ApplicationId>,
   ValidatedJson(payload): ValidatedJson<UpdateUser>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_USERS_WRITE) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -529,6 +558,7 @@ pub async fn update_user(
     (status = 204),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -538,12 +568,17 @@ pub async fn update_user(
 pub async fn update_user_password(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path(user_id): Path<i64>,
   Query(application_id): Query<ApplicationId>,
   ValidatedJson(payload): ValidatedJson<UpdateUserPassword>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_USERS_WRITE) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -562,24 +597,21 @@ pub async fn update_user_password(
   {
     Ok(_) => {}
     Err(e) => {
-      match &e {
-        sqlx::Error::Configuration(e) => {
-          if e.to_string().contains("password_already_used") {
-            return InternalError::bad_request()
-              .with_error(
-                "password",
-                (
-                  ALREADY_USED_ERROR,
-                  HashMap::from([(
-                    "password.history".to_owned(),
-                    json!(state.config.password.history),
-                  )]),
-                ),
-              )
-              .into_response();
-          }
+      if let sqlx::Error::Configuration(e) = &e {
+        if e.to_string().contains("password_already_used") {
+          return InternalError::bad_request()
+            .with_error(
+              "password",
+              (
+                ALREADY_USED_ERROR,
+                HashMap::from([(
+                  "password.history".to_owned(),
+                  json!(state.config.password.history),
+                )]),
+              ),
+            )
+            .into_response();
         }
-        _ => {}
       }
       log::error!("error creating user password: {}", e);
       return InternalError::internal_error()
@@ -603,6 +635,7 @@ pub async fn update_user_password(
     (status = 200, content_type = "application/json", body = UserInfo),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -612,12 +645,17 @@ pub async fn update_user_password(
 pub async fn update_user_info(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path(user_id): Path<i64>,
   Query(application_id): Query<ApplicationId>,
   Json(payload): Json<UpdateUserInfoRequest>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_USERS_WRITE) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -685,6 +723,7 @@ pub async fn update_user_info(
     (status = 201, content_type = "application/json", body = Token),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -696,12 +735,15 @@ pub async fn create_user_reset_password_token(
   ServiceAccountAuthorization {
     service_account,
     tenant,
-    ..
+    scopes,
   }: ServiceAccountAuthorization,
   Path(user_id): Path<i64>,
   Query(application_id): Query<ApplicationId>,
   Json(payload): Json<UserResetPassword>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_USERS_WRITE) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -754,6 +796,7 @@ pub async fn create_user_reset_password_token(
     (status = 204),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -763,11 +806,16 @@ pub async fn create_user_reset_password_token(
 pub async fn delete_user(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path(user_id): Path<i64>,
   Query(application_id): Query<ApplicationId>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_USERS_WRITE) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -793,6 +841,79 @@ pub async fn delete_user(
   (StatusCode::NO_CONTENT, ()).into_response()
 }
 
+#[utoipa::path(
+  post,
+  path = "/users/{user_id}/revoke-tokens",
+  tags = [USER_TAG],
+  params(
+    ("user_id" = i64, Path, description = "User id"),
+    ApplicationId,
+  ),
+  responses(
+    (status = 204),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn revoke_user_tokens(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  Path(user_id): Path<i64>,
+  Query(application_id): Query<ApplicationId>,
+) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_USERS_WRITE) {
+    return e.into_response();
+  }
+  let application_id = application_id
+    .application_id
+    .unwrap_or(service_account.application_id);
+  if !service_account.is_admin() && service_account.application_id != application_id {
+    return InternalError::unauthorized()
+      .with_error("revoke-user-tokens", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  match repository::user::get_user_by_id(&state.pool, application_id, user_id).await {
+    Ok(Some(..)) => {}
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("user", NOT_FOUND_ERROR)
+        .into_response()
+    }
+    Err(e) => {
+      log::error!("error getting user: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  }
+  match repository::token_revocation::revoke_subject_tokens(
+    &state.pool,
+    application_id,
+    TOKEN_SUB_TYPE_USER,
+    user_id,
+  )
+  .await
+  {
+    Ok(..) => {}
+    Err(e) => {
+      log::error!("error revoking user tokens: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  }
+  (StatusCode::NO_CONTENT, ()).into_response()
+}
+
 pub fn create_router(state: RouterState) -> OpenApiRouter {
   OpenApiRouter::new()
     .routes(routes!(all_users))
@@ -803,5 +924,6 @@ pub fn create_router(state: RouterState) -> OpenApiRouter {
     .routes(routes!(update_user_password))
     .routes(routes!(update_user_info))
     .routes(routes!(delete_user))
+    .routes(routes!(revoke_user_tokens))
     .with_state(state)
 }
@@ -10,7 +10,10 @@ use crate::{
     Errors, InternalError, ALREADY_EXISTS_ERROR, INTERNAL_ERROR, NOT_ALLOWED_ERROR, NOT_FOUND_ERROR,
   },
   middleware::{
-    service_account_authorization::ServiceAccountAuthorization, validated_json::ValidatedJson,
+    service_account_authorization::{
+      require_service_account_scope, ServiceAccountAuthorization, SCOPE_USERS_WRITE,
+    },
+    validated_json::ValidatedJson,
   },
   model::{
     user::{ServiceAccountCreateUserEmail, ServiceAccountUpdateUserEmail, UserEmail},
@@ -34,6 +37,7 @@ use super::{user::USER_TAG, RouterState};
     (status = 201, content_type = "application/json", body = UserEmail),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 409, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
@@ -44,12 +48,17 @@ use super::{user::USER_TAG, RouterState};
 pub async fn create_user_email(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path(user_id): Path<i64>,
   Query(application_id): Query<ApplicationId>,
   ValidatedJson(payload): ValidatedJson<ServiceAccountCreateUserEmail>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_USERS_WRITE) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -113,6 +122,7 @@ pub async fn create_user_email(
     (status = 200, content_type = "application/json", body = UserEmail),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -122,12 +132,17 @@ pub async fn create_user_email(
 pub async fn update_user_email(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path((user_id, email_id)): Path<(i64, i64)>,
   Query(application_id): Query<ApplicationId>,
   ValidatedJson(payload): ValidatedJson<ServiceAccountUpdateUserEmail>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_USERS_WRITE) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -191,6 +206,7 @@ pub async fn update_user_email(
     (status = 204),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -200,11 +216,16 @@ pub async fn update_user_email(
 pub async fn delete_user_email(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path((user_id, email_id)): Path<(i64, i64)>,
   Query(application_id): Query<ApplicationId>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_USERS_WRITE) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -0,0 +1,328 @@
+use axum::{
+  extract::{Path, Query, State},
+  response::IntoResponse,
+};
+use http::StatusCode;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+  core::error::{
+    Errors, InternalError, ALREADY_EXISTS_ERROR, INTERNAL_ERROR, INVALID_ERROR, NOT_ALLOWED_ERROR,
+    NOT_FOUND_ERROR,
+  },
+  middleware::{
+    json::Json,
+    service_account_authorization::{
+      require_service_account_scope, ServiceAccountAuthorization, SCOPE_USERS_WRITE,
+    },
+  },
+  model::{
+    hotp::{ServiceAccountCreateUserHOTP, ServiceAccountResyncUserHOTP, UserHOTP},
+    user::UserMFAType,
+    util::ApplicationId,
+  },
+  repository,
+};
+
+use super::{
+  mfa::{count_mfa_attempt, reset_mfa_attempts},
+  user::USER_TAG,
+  RouterState,
+};
+
+#[utoipa::path(
+  post,
+  path = "/users/{user_id}/hotp",
+  tags = [USER_TAG],
+  request_body = ServiceAccountCreateUserHOTP,
+  params(
+    ("user_id" = i64, Path, description = "User id"),
+    ApplicationId
+  ),
+  responses(
+    (status = 201, content_type = "application/json", body = UserHOTP),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 409, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn create_user_hotp(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  Path(user_id): Path<i64>,
+  Query(application_id): Query<ApplicationId>,
+  Json(payload): Json<ServiceAccountCreateUserHOTP>,
+) -> impl IntoResponse {
+  if let Err(e) = require_user_write(
+    &state,
+    &service_account,
+    &scopes,
+    application_id,
+    user_id,
+    "create-user-hotp",
+  )
+  .await
+  {
+    return e.into_response();
+  }
+  let algorithm = payload.algorithm.unwrap_or("SHA1".to_owned());
+  let digits = payload.digits.unwrap_or(6);
+  let counter = payload.counter.unwrap_or(0);
+  if !matches!(algorithm.as_str(), "SHA1" | "SHA256" | "SHA512") {
+    return InternalError::bad_request()
+      .with_error("algorithm", INVALID_ERROR)
+      .into_response();
+  }
+  if !(6..=8).contains(&digits) {
+    return InternalError::bad_request()
+      .with_error("digits", INVALID_ERROR)
+      .into_response();
+  }
+  if counter < 0 {
+    return InternalError::bad_request()
+      .with_error("counter", INVALID_ERROR)
+      .into_response();
+  }
+  let secret = payload.secret.replace(' ', "").to_uppercase();
+  if !totp_rs::Secret::Encoded(secret.clone())
+    .to_bytes()
+    .is_ok_and(|seed| seed.len() >= 16)
+  {
+    return InternalError::bad_request()
+      .with_error("secret", INVALID_ERROR)
+      .into_response();
+  }
+  let hotp = match repository::user_hotp::create_user_hotp(
+    &state.pool,
+    user_id,
+    repository::user_hotp::CreateUserHOTP {
+      algorithm,
+      digits,
+      counter,
+      secret,
+    },
+  )
+  .await
+  {
+    Ok(hotp) => hotp,
+    Err(e) => {
+      if e.to_string().to_lowercase().contains("unique constraint") {
+        return InternalError::from(StatusCode::CONFLICT)
+          .with_error("hotp", ALREADY_EXISTS_ERROR)
+          .into_response();
+      }
+      log::error!("error creating user HOTP: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  (StatusCode::CREATED, axum::Json(UserHOTP::from(hotp))).into_response()
+}
+
+#[utoipa::path(
+  post,
+  path = "/users/{user_id}/hotp/resync",
+  tags = [USER_TAG],
+  request_body = ServiceAccountResyncUserHOTP,
+  params(
+    ("user_id" = i64, Path, description = "User id"),
+    ApplicationId
+  ),
+  responses(
+    (status = 200, content_type = "application/json", body = UserHOTP),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn resync_user_hotp(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  Path(user_id): Path<i64>,
+  Query(application_id): Query<ApplicationId>,
+  Json(payload): Json<ServiceAccountResyncUserHOTP>,
+) -> impl IntoResponse {
+  if let Err(e) = require_user_write(
+    &state,
+    &service_account,
+    &scopes,
+    application_id,
+    user_id,
+    "resync-user-hotp",
+  )
+  .await
+  {
+    return e.into_response();
+  }
+  let hotp = match repository::user_hotp::get_user_hotp_by_user_id(&state.pool, user_id).await {
+    Ok(Some(hotp)) => hotp,
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("hotp", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error getting user HOTP: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  // resynchronizing searches a wider window, so its guesses share the login attempt limit
+  if !count_mfa_attempt(&state.pool, UserMFAType::HOTP, user_id).await {
+    return InternalError::bad_request()
+      .with_error("code", INVALID_ERROR)
+      .into_response();
+  }
+  let counter = match hotp.resync(
+    &payload.code,
+    &payload.next_code,
+    state.config.hotp.resync_look_ahead,
+  ) {
+    Ok(Some(counter)) => counter,
+    Ok(None) => {
+      return InternalError::bad_request()
+        .with_error("code", INVALID_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error verifying HOTP: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  match repository::user_hotp::update_user_hotp_counter(
+    &state.pool,
+    user_id,
+    hotp.counter,
+    counter + 1,
+  )
+  .await
+  {
+    Ok(Some(hotp)) => {
+      reset_mfa_attempts(&state.pool, UserMFAType::HOTP, user_id).await;
+      axum::Json(UserHOTP::from(hotp)).into_response()
+    }
+    Ok(None) => InternalError::bad_request()
+      .with_error("code", INVALID_ERROR)
+      .into_response(),
+    Err(e) => {
+      log::error!("error updating user HOTP: {e}");
+      InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response()
+    }
+  }
+}
+
+#[utoipa::path(
+  delete,
+  path = "/users/{user_id}/hotp",
+  tags = [USER_TAG],
+  params(
+    ("user_id" = i64, Path, description = "User id"),
+    ApplicationId
+  ),
+  responses(
+    (status = 204),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn delete_user_hotp(
+  State(state): State<RouterState>,
+  ServiceAccountAuthorization {
+    service_account,
+    scopes,
+    ..
+  }: ServiceAccountAuthorization,
+  Path(user_id): Path<i64>,
+  Query(application_id): Query<ApplicationId>,
+) -> impl IntoResponse {
+  if let Err(e) = require_user_write(
+    &state,
+    &service_account,
+    &scopes,
+    application_id,
+    user_id,
+    "delete-user-hotp",
+  )
+  .await
+  {
+    return e.into_response();
+  }
+  match repository::user_hotp::delete_user_hotp(&state.pool, user_id).await {
+    Ok(Some(_)) => {}
+    Ok(None) => {
+      return InternalError::not_found()
+        .with_error("hotp", NOT_FOUND_ERROR)
+        .into_response();
+    }
+    Err(e) => {
+      log::error!("error deleting user HOTP: {e}");
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  }
+  (StatusCode::NO_CONTENT, ()).into_response()
+}
+
+/// The service account needs `users:write` for the user's application, which must have the user
+async fn require_user_write(
+  state: &RouterState,
+  service_account: &repository::service_account::ServiceAccountRow,
+  scopes: &[String],
+  application_id: ApplicationId,
+  user_id: i64,
+  action: &str,
+) -> Result<(), InternalError> {
+  require_service_account_scope(service_account, scopes, SCOPE_USERS_WRITE)?;
+  let application_id = application_id
+    .application_id
+    .unwrap_or(service_account.application_id);
+  if !service_account.is_admin() && service_account.application_id != application_id {
+    return Err(InternalError::unauthorized().with_error(action, NOT_ALLOWED_ERROR));
+  }
+  match repository::user::get_user_by_id(&state.pool, application_id, user_id).await {
+    Ok(Some(..)) => Ok(()),
+    Ok(None) => Err(InternalError::not_found().with_error("user", NOT_FOUND_ERROR)),
+    Err(e) => {
+      log::error!("error getting user: {e}");
+      Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR))
+    }
+  }
+}
+
+pub fn create_router(state: RouterState) -> OpenApiRouter {
+  OpenApiRouter::new()
+    .routes(routes!(create_user_hotp, delete_user_hotp))
+    .routes(routes!(resync_user_hotp))
+    .with_state(state)
+}
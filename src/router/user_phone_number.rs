@@ -10,7 +10,10 @@ use crate::{
     Errors, InternalError, ALREADY_EXISTS_ERROR, INTERNAL_ERROR, NOT_ALLOWED_ERROR, NOT_FOUND_ERROR,
   },
   middleware::{
-    service_account_authorization::ServiceAccountAuthorization, validated_json::ValidatedJson,
+    service_account_authorization::{
+      require_service_account_scope, ServiceAccountAuthorization, SCOPE_USERS_WRITE,
+    },
+    validated_json::ValidatedJson,
   },
   model::{
     user::{
@@ -36,6 +39,7 @@ use super::{user::USER_TAG, RouterState};
     (status = 201, content_type = "application/json", body = UserPhoneNumber),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 409, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
@@ -46,12 +50,17 @@ use super::{user::USER_TAG, RouterState};
 pub async fn create_user_phone_number(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path(user_id): Path<i64>,
   Query(application_id): Query<ApplicationId>,
   ValidatedJson(payload): ValidatedJson<ServiceAccountCreateUserPhoneNumber>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_USERS_WRITE) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -119,6 +128,7 @@ pub async fn create_user_phone_number(
     (status = 200, content_type = "application/json", body = UserPhoneNumber),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -128,12 +138,17 @@ pub async fn create_user_phone_number(
 pub async fn update_user_phone_number(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path((user_id, phone_number_id)): Path<(i64, i64)>,
   Query(application_id): Query<ApplicationId>,
   ValidatedJson(payload): ValidatedJson<ServiceAccountUpdateUserPhoneNumber>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_USERS_WRITE) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
@@ -197,6 +212,7 @@ pub async fn update_user_phone_number(
     (status = 204),
     (status = 400, content_type = "application/json", body = Errors),
     (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
     (status = 500, content_type = "application/json", body = Errors),
   ),
   security(
@@ -206,11 +222,16 @@ pub async fn update_user_phone_number(
 pub async fn delete_user_phone_number(
   State(state): State<RouterState>,
   ServiceAccountAuthorization {
-    service_account, ..
+    service_account,
+    scopes,
+    ..
   }: ServiceAccountAuthorization,
   Path((user_id, phone_number_id)): Path<(i64, i64)>,
   Query(application_id): Query<ApplicationId>,
 ) -> impl IntoResponse {
+  if let Err(e) = require_service_account_scope(&service_account, &scopes, SCOPE_USERS_WRITE) {
+    return e.into_response();
+  }
   let application_id = application_id
     .application_id
     .unwrap_or(service_account.application_id);
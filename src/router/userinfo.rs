@@ -0,0 +1,76 @@
+use crate::{
+  core::error::{Errors, InternalError, NOT_ALLOWED_ERROR},
+  middleware::{openid_claims::has_openid_scope, user_authorization::UserAuthorization},
+  model::userinfo::UserInfo,
+};
+
+use axum::{extract::State, response::IntoResponse};
+use http::StatusCode;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use super::{token::get_user_openid_profile, RouterState};
+
+pub const USERINFO_TAG: &str = "userinfo";
+
+#[utoipa::path(
+  get,
+  path = "/userinfo",
+  tags = [USERINFO_TAG],
+  responses(
+    (status = 200, content_type = "application/json", body = UserInfo),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn get_userinfo(
+  State(state): State<RouterState>,
+  authorization: UserAuthorization,
+) -> impl IntoResponse {
+  userinfo(&state, authorization).await
+}
+
+#[utoipa::path(
+  post,
+  path = "/userinfo",
+  tags = [USERINFO_TAG],
+  responses(
+    (status = 200, content_type = "application/json", body = UserInfo),
+    (status = 401, content_type = "application/json", body = Errors),
+    (status = 403, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    ("Authorization" = [])
+  )
+)]
+pub async fn post_userinfo(
+  State(state): State<RouterState>,
+  authorization: UserAuthorization,
+) -> impl IntoResponse {
+  userinfo(&state, authorization).await
+}
+
+pub fn create_router(state: RouterState) -> OpenApiRouter {
+  OpenApiRouter::new()
+    .routes(routes!(get_userinfo, post_userinfo))
+    .with_state(state)
+}
+
+async fn userinfo(
+  state: &RouterState,
+  UserAuthorization { user, claims, .. }: UserAuthorization,
+) -> axum::response::Response {
+  if !has_openid_scope(&claims.scopes) {
+    return InternalError::from(StatusCode::FORBIDDEN)
+      .with_error("scope", NOT_ALLOWED_ERROR)
+      .into_response();
+  }
+  match get_user_openid_profile(&state.pool, &user, &claims.scopes).await {
+    Ok(profile) => axum::Json(UserInfo::new(user.id, profile)).into_response(),
+    Err(e) => e.into_response(),
+  }
+}
@@ -0,0 +1,324 @@
+use std::str::FromStr;
+
+use crate::{
+  core::{
+    encryption::{JWE_ALGORITHM_RSA_OAEP_256, JWE_ENCRYPTION_A256GCM},
+    error::{Errors, InternalError, INTERNAL_ERROR, NOT_FOUND_ERROR},
+  },
+  middleware::{
+    authorization::ApplicationIdTenantId,
+    claims::{is_hmac_algorithm, ACR_MULTI_FACTOR, ACR_SINGLE_FACTOR},
+    openid_claims::{SCOPE_ADDRESS, SCOPE_EMAIL, SCOPE_OPENID, SCOPE_PHONE, SCOPE_PROFILE},
+    tenant_id::{TenantIdOrClientId, CLIENT_ID_QUERY_PARAM},
+  },
+  model::{
+    authorize::{CODE_CHALLENGE_METHOD_S256, RESPONSE_TYPE_CODE},
+    token::{
+      GRANT_TYPE_AUTHORIZATION_CODE, GRANT_TYPE_CLIENT_CREDENTIALS, GRANT_TYPE_DEVICE_CODE,
+      GRANT_TYPE_PASSWORD, GRANT_TYPE_REFRESH_TOKEN, GRANT_TYPE_TOKEN_EXCHANGE,
+    },
+    well_known::{JsonWebKey, JsonWebKeySet, OpenIdConfiguration, WellKnownQuery},
+  },
+  repository::{
+    tenant::{get_tenant_by_client_id, TenantRow},
+    tenant_key::{get_tenant_keys, TenantKeyRow},
+  },
+};
+
+use axum::{
+  extract::{Path, State},
+  response::{IntoResponse, Response},
+};
+use jsonwebtoken::errors::ErrorKind;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use super::RouterState;
+
+pub const WELL_KNOWN_TAG: &str = "well-known";
+
+/// The issuer of tenants created without one, their discovery document is served under it so
+/// OpenID Connect clients find it from the issuer alone.
+pub fn tenant_issuer_url(server_url: &str, client_id: &str) -> String {
+  format!("{server_url}/tenants/{client_id}")
+}
+
+#[utoipa::path(
+  get,
+  path = "/.well-known/openid-configuration",
+  tags = [WELL_KNOWN_TAG],
+  params(
+    WellKnownQuery,
+  ),
+  responses(
+    (status = 200, content_type = "application/json", body = OpenIdConfiguration),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    (),
+    ("TenantUUID" = [])
+  )
+)]
+pub async fn openid_configuration(
+  State(state): State<RouterState>,
+  TenantIdOrClientId(tenant): TenantIdOrClientId,
+) -> impl IntoResponse {
+  let jwks_uri = format!(
+    "{}/.well-known/jwks.json?{CLIENT_ID_QUERY_PARAM}={}",
+    state.config.server.url, tenant.client_id
+  );
+  tenant_openid_configuration(&state, tenant, jwks_uri).await
+}
+
+#[utoipa::path(
+  get,
+  path = "/tenants/{tenant_id}/.well-known/openid-configuration",
+  tags = [WELL_KNOWN_TAG],
+  params(
+    ("tenant_id" = uuid::Uuid, Path, description = "Tenant client id"),
+  ),
+  responses(
+    (status = 200, content_type = "application/json", body = OpenIdConfiguration),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  )
+)]
+pub async fn issuer_openid_configuration(
+  State(state): State<RouterState>,
+  Path(client_id): Path<uuid::Uuid>,
+) -> impl IntoResponse {
+  let tenant = match issuer_tenant(&state, client_id).await {
+    Ok(tenant) => tenant,
+    Err(e) => return e.into_response(),
+  };
+  let jwks_uri = format!(
+    "{}/.well-known/jwks.json",
+    tenant_issuer_url(&state.config.server.url, &tenant.client_id)
+  );
+  tenant_openid_configuration(&state, tenant, jwks_uri).await
+}
+
+async fn tenant_openid_configuration(
+  state: &RouterState,
+  tenant: TenantRow,
+  jwks_uri: String,
+) -> Response {
+  let tenant_keys = match get_tenant_keys(&state.pool, tenant.id).await {
+    Ok(tenant_keys) => tenant_keys,
+    Err(e) => {
+      log::error!("error getting tenant keys: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  // tokens signed by a retiring key of another algorithm are still valid
+  let mut id_token_signing_alg_values_supported = vec![tenant.algorithm.clone()];
+  for tenant_key in tenant_keys
+    .into_iter()
+    .filter(|tenant_key| tenant_key.is_published())
+  {
+    if !id_token_signing_alg_values_supported.contains(&tenant_key.algorithm) {
+      id_token_signing_alg_values_supported.push(tenant_key.algorithm);
+    }
+  }
+  let url = &state.config.server.url;
+  axum::Json(OpenIdConfiguration {
+    issuer: tenant.issuer.clone(),
+    authorization_endpoint: format!("{url}/authorize"),
+    device_authorization_endpoint: format!("{url}/device-authorization"),
+    token_endpoint: format!("{url}/token"),
+    userinfo_endpoint: format!("{url}/userinfo"),
+    end_session_endpoint: format!("{url}/end-session"),
+    jwks_uri,
+    scopes_supported: [
+      SCOPE_OPENID,
+      SCOPE_PROFILE,
+      SCOPE_EMAIL,
+      SCOPE_PHONE,
+      SCOPE_ADDRESS,
+    ]
+    .map(ToOwned::to_owned)
+    .to_vec(),
+    response_types_supported: vec![RESPONSE_TYPE_CODE.to_owned()],
+    grant_types_supported: [
+      GRANT_TYPE_PASSWORD,
+      GRANT_TYPE_REFRESH_TOKEN,
+      GRANT_TYPE_AUTHORIZATION_CODE,
+      GRANT_TYPE_CLIENT_CREDENTIALS,
+      GRANT_TYPE_DEVICE_CODE,
+      GRANT_TYPE_TOKEN_EXCHANGE,
+    ]
+    .map(ToOwned::to_owned)
+    .to_vec(),
+    subject_types_supported: vec!["public".to_owned()],
+    id_token_signing_alg_values_supported,
+    id_token_encryption_alg_values_supported: tenant
+      .encrypts_id_tokens()
+      .then(|| JWE_ALGORITHM_RSA_OAEP_256.to_owned())
+      .into_iter()
+      .collect(),
+    id_token_encryption_enc_values_supported: tenant
+      .encrypts_id_tokens()
+      .then(|| JWE_ENCRYPTION_A256GCM.to_owned())
+      .into_iter()
+      .collect(),
+    token_endpoint_auth_methods_supported: ["client_secret_basic", "client_secret_post", "none"]
+      .map(ToOwned::to_owned)
+      .to_vec(),
+    code_challenge_methods_supported: vec![CODE_CHALLENGE_METHOD_S256.to_owned()],
+    acr_values_supported: [ACR_SINGLE_FACTOR, ACR_MULTI_FACTOR]
+      .map(ToOwned::to_owned)
+      .to_vec(),
+    claims_supported: [
+      "iss",
+      "sub",
+      "aud",
+      "exp",
+      "iat",
+      "nbf",
+      "name",
+      "given_name",
+      "family_name",
+      "middle_name",
+      "nickname",
+      "preferred_username",
+      "picture",
+      "website",
+      "email",
+      "email_verified",
+      "gender",
+      "birthdate",
+      "zoneinfo",
+      "locale",
+      "phone_number",
+      "phone_number_verified",
+      "address",
+      "sid",
+      "auth_time",
+      "amr",
+      "acr",
+    ]
+    .map(ToOwned::to_owned)
+    .to_vec(),
+    backchannel_logout_supported: true,
+    backchannel_logout_session_supported: true,
+  })
+  .into_response()
+}
+
+#[utoipa::path(
+  get,
+  path = "/.well-known/jwks.json",
+  tags = [WELL_KNOWN_TAG],
+  params(
+    WellKnownQuery,
+  ),
+  responses(
+    (status = 200, content_type = "application/json", body = JsonWebKeySet),
+    (status = 400, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  ),
+  security(
+    (),
+    ("TenantUUID" = [])
+  )
+)]
+pub async fn jwks(
+  State(state): State<RouterState>,
+  TenantIdOrClientId(tenant): TenantIdOrClientId,
+) -> impl IntoResponse {
+  tenant_jwks(&state, &tenant).await
+}
+
+#[utoipa::path(
+  get,
+  path = "/tenants/{tenant_id}/.well-known/jwks.json",
+  tags = [WELL_KNOWN_TAG],
+  params(
+    ("tenant_id" = uuid::Uuid, Path, description = "Tenant client id"),
+  ),
+  responses(
+    (status = 200, content_type = "application/json", body = JsonWebKeySet),
+    (status = 404, content_type = "application/json", body = Errors),
+    (status = 500, content_type = "application/json", body = Errors),
+  )
+)]
+pub async fn issuer_jwks(
+  State(state): State<RouterState>,
+  Path(client_id): Path<uuid::Uuid>,
+) -> impl IntoResponse {
+  match issuer_tenant(&state, client_id).await {
+    Ok(tenant) => tenant_jwks(&state, &tenant).await,
+    Err(e) => e.into_response(),
+  }
+}
+
+async fn tenant_jwks(state: &RouterState, tenant: &TenantRow) -> Response {
+  let tenant_keys = match get_tenant_keys(&state.pool, tenant.id).await {
+    Ok(tenant_keys) => tenant_keys,
+    Err(e) => {
+      log::error!("error getting tenant keys: {}", e);
+      return InternalError::internal_error()
+        .with_application_error(INTERNAL_ERROR)
+        .into_response();
+    }
+  };
+  let mut keys = Vec::with_capacity(tenant_keys.len());
+  for tenant_key in tenant_keys
+    .iter()
+    .filter(|tenant_key| tenant_key.is_published())
+  {
+    match tenant_json_web_key(tenant, tenant_key) {
+      Ok(Some(key)) => keys.push(key),
+      Ok(None) => {}
+      Err(e) => {
+        log::error!("error creating json web key set: {}", e);
+        return InternalError::internal_error()
+          .with_application_error(INTERNAL_ERROR)
+          .into_response();
+      }
+    }
+  }
+  axum::Json(JsonWebKeySet { keys }).into_response()
+}
+
+pub fn create_router(state: RouterState) -> OpenApiRouter {
+  OpenApiRouter::new()
+    .routes(routes!(openid_configuration))
+    .routes(routes!(jwks))
+    .routes(routes!(issuer_openid_configuration))
+    .routes(routes!(issuer_jwks))
+    .with_state(state)
+}
+
+/// The tenant of an issuer path, named by its client id.
+async fn issuer_tenant(
+  state: &RouterState,
+  client_id: uuid::Uuid,
+) -> Result<TenantRow, InternalError> {
+  match get_tenant_by_client_id(&state.pool, &client_id.to_string()).await {
+    Ok(Some(tenant)) => Ok(tenant),
+    Ok(None) => Err(InternalError::not_found().with_error("tenant", NOT_FOUND_ERROR)),
+    Err(e) => {
+      log::error!("error getting tenant: {}", e);
+      Err(InternalError::internal_error().with_application_error(INTERNAL_ERROR))
+    }
+  }
+}
+
+fn tenant_json_web_key(
+  tenant: &TenantRow,
+  tenant_key: &TenantKeyRow,
+) -> Result<Option<JsonWebKey>, jsonwebtoken::errors::Error> {
+  let algorithm = jsonwebtoken::Algorithm::from_str(&tenant_key.algorithm)?;
+  if is_hmac_algorithm(algorithm) {
+    return Ok(None);
+  }
+  let public_key = tenant_key
+    .public_key
+    .as_deref()
+    .ok_or(ErrorKind::InvalidKeyFormat)?;
+  let kid = ApplicationIdTenantId::new_kid(tenant.application_id, tenant.id, tenant_key.version);
+  JsonWebKey::from_public_key(kid, algorithm, public_key)
+}
@@ -0,0 +1,115 @@
+use std::{
+  fmt,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+
+use futures_util::future::BoxFuture;
+use lettre::{message::Mailbox, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+
+use crate::core::{
+  config::MailConfig,
+  error::{InternalError, INTERNAL_ERROR},
+};
+
+pub const MAIL_TRANSPORT_SMTP: &str = "smtp";
+pub const MAIL_TRANSPORT_FILE: &str = "file";
+pub const MAIL_TRANSPORT_LOG: &str = "log";
+
+#[derive(Serialize)]
+pub struct Mail {
+  pub to: String,
+  pub subject: String,
+  pub body: String,
+}
+
+/// Delivers mails to users, like the one-time codes of email MFA challenges.
+pub trait MailSender: Send + Sync {
+  fn send(&self, mail: Mail) -> BoxFuture<'_, Result<(), InternalError>>;
+}
+
+pub fn create_mail_sender(config: &MailConfig) -> Result<Arc<dyn MailSender>, InternalError> {
+  match config.transport.as_str() {
+    MAIL_TRANSPORT_SMTP => Ok(Arc::new(SmtpMailSender::new(
+      &config.from,
+      &config.smtp_url,
+    )?)),
+    MAIL_TRANSPORT_FILE => Ok(Arc::new(FileMailSender {
+      path: Some(PathBuf::from(&config.file_path)),
+    })),
+    MAIL_TRANSPORT_LOG => Ok(Arc::new(FileMailSender { path: None })),
+    transport => Err(mail_error(format!("unknown mail transport {transport}"))),
+  }
+}
+
+pub struct SmtpMailSender {
+  from: Mailbox,
+  transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailSender {
+  pub fn new(from: &str, smtp_url: &str) -> Result<Self, InternalError> {
+    Ok(Self {
+      from: from.parse().map_err(mail_error)?,
+      transport: AsyncSmtpTransport::<Tokio1Executor>::from_url(smtp_url)
+        .map_err(mail_error)?
+        .build(),
+    })
+  }
+}
+
+impl MailSender for SmtpMailSender {
+  fn send(&self, mail: Mail) -> BoxFuture<'_, Result<(), InternalError>> {
+    Box::pin(async move {
+      let message = Message::builder()
+        .from(self.from.clone())
+        .to(mail.to.parse().map_err(mail_error)?)
+        .subject(mail.subject)
+        .body(mail.body)
+        .map_err(mail_error)?;
+      self.transport.send(message).await.map_err(mail_error)?;
+      Ok(())
+    })
+  }
+}
+
+/// Appends mails as JSON lines to a file, or only logs them without one, for local development
+/// and tests.
+pub struct FileMailSender {
+  path: Option<PathBuf>,
+}
+
+impl MailSender for FileMailSender {
+  fn send(&self, mail: Mail) -> BoxFuture<'_, Result<(), InternalError>> {
+    Box::pin(async move {
+      let Some(path) = self.path.as_ref() else {
+        log::info!("mail to {} {}: {}", mail.to, mail.subject, mail.body);
+        return Ok(());
+      };
+      append_json_line(path, &mail).await
+    })
+  }
+}
+
+pub(crate) async fn append_json_line<T: Serialize>(
+  path: &Path,
+  value: &T,
+) -> Result<(), InternalError> {
+  let mut line = serde_json::to_vec(value)?;
+  line.push(b'\n');
+  tokio::fs::OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(path)
+    .await?
+    .write_all(&line)
+    .await?;
+  Ok(())
+}
+
+fn mail_error(e: impl fmt::Display) -> InternalError {
+  log::error!("error sending mail: {}", e);
+  InternalError::internal_error().with_application_error(INTERNAL_ERROR)
+}
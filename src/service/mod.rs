@@ -1 +1,3 @@
+pub mod mail;
+pub mod sms;
 pub mod start_up;
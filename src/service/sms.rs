@@ -0,0 +1,98 @@
+use std::{fmt, path::PathBuf, sync::Arc};
+
+use futures_util::future::BoxFuture;
+use http::header::AUTHORIZATION;
+use serde::Serialize;
+
+use crate::core::{
+  config::SmsConfig,
+  error::{InternalError, INTERNAL_ERROR},
+};
+
+use super::mail::append_json_line;
+
+pub const SMS_TRANSPORT_WEBHOOK: &str = "webhook";
+pub const SMS_TRANSPORT_FILE: &str = "file";
+pub const SMS_TRANSPORT_LOG: &str = "log";
+
+#[derive(Serialize)]
+pub struct Sms {
+  pub to: String,
+  pub body: String,
+}
+
+/// Delivers text messages to users, like the one-time codes of text MFA challenges.
+pub trait SmsSender: Send + Sync {
+  fn send(&self, sms: Sms) -> BoxFuture<'_, Result<(), InternalError>>;
+}
+
+pub fn create_sms_sender(config: &SmsConfig) -> Result<Arc<dyn SmsSender>, InternalError> {
+  match config.transport.as_str() {
+    SMS_TRANSPORT_WEBHOOK => Ok(Arc::new(WebhookSmsSender::new(
+      &config.webhook_url,
+      &config.webhook_authorization,
+    )?)),
+    SMS_TRANSPORT_FILE => Ok(Arc::new(FileSmsSender {
+      path: Some(PathBuf::from(&config.file_path)),
+    })),
+    SMS_TRANSPORT_LOG => Ok(Arc::new(FileSmsSender { path: None })),
+    transport => Err(sms_error(format!("unknown sms transport {transport}"))),
+  }
+}
+
+/// Posts text messages as JSON to a provider's webhook, which delivers them.
+pub struct WebhookSmsSender {
+  url: reqwest::Url,
+  authorization: Option<String>,
+  client: reqwest::Client,
+}
+
+impl WebhookSmsSender {
+  pub fn new(url: &str, authorization: &str) -> Result<Self, InternalError> {
+    Ok(Self {
+      url: url.parse().map_err(sms_error)?,
+      authorization: Some(authorization.to_owned()).filter(|a| !a.is_empty()),
+      client: reqwest::Client::new(),
+    })
+  }
+}
+
+impl SmsSender for WebhookSmsSender {
+  fn send(&self, sms: Sms) -> BoxFuture<'_, Result<(), InternalError>> {
+    Box::pin(async move {
+      let mut request = self.client.post(self.url.clone()).json(&sms);
+      if let Some(authorization) = self.authorization.as_deref() {
+        request = request.header(AUTHORIZATION, authorization);
+      }
+      request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(sms_error)?;
+      Ok(())
+    })
+  }
+}
+
+/// Appends text messages as JSON lines to a file, or only logs them without one, for local
+/// development and tests.
+pub struct FileSmsSender {
+  path: Option<PathBuf>,
+}
+
+impl SmsSender for FileSmsSender {
+  fn send(&self, sms: Sms) -> BoxFuture<'_, Result<(), InternalError>> {
+    Box::pin(async move {
+      let Some(path) = self.path.as_ref() else {
+        log::info!("text message to {}: {}", sms.to, sms.body);
+        return Ok(());
+      };
+      append_json_line(path, &sms).await
+    })
+  }
+}
+
+fn sms_error(e: impl fmt::Display) -> InternalError {
+  log::error!("error sending text message: {}", e);
+  InternalError::internal_error().with_application_error(INTERNAL_ERROR)
+}
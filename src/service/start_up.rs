@@ -6,6 +6,7 @@ use crate::{
     encryption::encrypt_password,
     error::{InternalError, DATEBASE_ERROR, INTERNAL_ERROR},
   },
+  middleware::service_account_authorization::SERVICE_ACCOUNT_SCOPES,
   model::service_account::ServiceAccount,
   repository::service_account::{
     create_service_account, get_service_accounts, CreateServiceAccount,
@@ -53,6 +54,7 @@ pub async fn create_new_admin_service_account(
       encrypted_client_secret,
       name: "Admin".to_owned(),
       admin: true,
+      scopes: Some(SERVICE_ACCOUNT_SCOPES.join(" ")),
     },
   )
   .await
@@ -64,7 +66,7 @@ pub async fn create_new_admin_service_account(
     }
   };
   let mut service_account = ServiceAccount::from(service_account_row);
-  service_account.client_secret = Some(client_secret.clone());
+  service_account.client_secret = Some(client_secret);
   let service_account_json_string = match serde_json::to_string_pretty(&service_account) {
     Ok(json) => json,
     Err(e) => {
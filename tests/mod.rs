@@ -1,16 +1,50 @@
 use std::{path::Path, str::FromStr, sync::Arc};
 
 use auth::{
-  core::{config::Config, database::init_pool, error::InternalError},
+  core::{
+    config::Config,
+    database::init_pool,
+    encryption::{
+      decode_jwe_header, decrypt_jwe_rsa_oaep_256, encrypt_jwe_dir, encrypt_password,
+      generate_key_pair,
+    },
+    error::InternalError,
+  },
+  middleware::{
+    claims::{parse_jwt, parse_jwt_with_tenant_key, BasicClaims, Claims, TOKEN_SUB_TYPE_USER},
+    openid_claims::{OpenIdClaims, OpenIdProfile},
+    service_account_authorization::SERVICE_ACCOUNT_SCOPES,
+  },
+  model::tenant::Algorithm,
+  repository::{
+    self,
+    refresh_token::{
+      create_refresh_token_family, rotate_refresh_token, CreateRefreshTokenFamily,
+      RefreshTokenRotation,
+    },
+    tenant::TenantRow,
+  },
   router::{create_router, RouterState},
+  service::{mail::create_mail_sender, sms::create_sms_sender},
+};
+use axum::{
+  body::{to_bytes, Body},
+  Router,
+};
+use base64::{
+  prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+  Engine,
 };
-use axum::{body::Body, Router};
 use http::{Request, StatusCode};
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
 use scopeguard::defer;
+use sha2::{Digest, Sha256};
 use tokio::{fs::remove_file, runtime::Handle, task::block_in_place};
 use tower::ServiceExt;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+const DEFAULT_TENANT_CLIENT_ID: &str = "6fcf0235-cb11-4160-9df8-b9114f8dcdae";
+
 #[tokio::test(flavor = "multi_thread")]
 async fn not_found() -> Result<(), InternalError> {
   let (router, config, pool) = setup().await?;
@@ -26,12 +60,3697 @@ async fn not_found() -> Result<(), InternalError> {
   Ok(())
 }
 
+#[test]
+fn asymmetric_algorithms() {
+  let now = chrono::Utc::now().timestamp();
+  for algorithm in [
+    Algorithm::RS256,
+    Algorithm::PS384,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+  ] {
+    let (public_key, private_key) = algorithm.keys(None, None).unwrap();
+    let (derived_public_key, _) = algorithm.keys(None, Some(private_key.clone())).unwrap();
+    assert_eq!(public_key, derived_public_key);
+    assert!(algorithm
+      .keys(public_key.clone(), Some(private_key.clone()))
+      .is_ok());
+    let (other_public_key, _) = algorithm.keys(None, None).unwrap();
+    assert!(algorithm
+      .keys(other_public_key, Some(private_key.clone()))
+      .is_err());
+
+    let tenant = TenantRow {
+      id: 1,
+      application_id: 1,
+      client_id: uuid::Uuid::new_v4().to_string(),
+      issuer: "Test".to_owned(),
+      audience: None,
+      algorithm: algorithm.to_string(),
+      public_key,
+      private_key,
+      expires_in_seconds: 60,
+      refresh_expires_in_seconds: 60,
+      key_version: 1,
+      updated_at: now,
+      created_at: now,
+      encryption_public_key: None,
+      encryption_secret: None,
+      allowed_audiences: None,
+      encrypt_access_tokens: 0,
+      previous_encryption_secret: None,
+    };
+    let claims = BasicClaims {
+      r#type: "bearer".to_owned(),
+      exp: now + 60,
+      iat: now,
+      nbf: now,
+      iss: tenant.issuer.clone(),
+      sub: 1,
+      app: 1,
+      ..Default::default()
+    };
+    let jwt = claims.encode(&tenant).unwrap();
+    let token_data = parse_jwt_with_tenant_key::<BasicClaims>(&jwt, &tenant).unwrap();
+    assert_eq!(token_data.claims.sub, 1);
+  }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn jwks() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config, teardown_pool) }
+
+  let tenant = repository::tenant::get_tenant_by_id(&pool, 1, 1)
+    .await?
+    .unwrap();
+  let now = chrono::Utc::now().timestamp();
+  let jwt = BasicClaims {
+    r#type: "bearer".to_owned(),
+    exp: now + 60,
+    iat: now,
+    nbf: now,
+    iss: tenant.issuer.clone(),
+    sub: 1,
+    app: 1,
+    ..Default::default()
+  }
+  .encode(&tenant)
+  .unwrap();
+
+  let (public_key, private_key) = Algorithm::RS256.keys(None, None).unwrap();
+  let tenant = repository::tenant_key::rotate_tenant_key(
+    &pool,
+    1,
+    tenant.retiring_key_expires_at(),
+    Some(repository::tenant_key::CreateTenantKey {
+      algorithm: Algorithm::RS256.to_string(),
+      public_key,
+      private_key,
+    }),
+  )
+  .await?
+  .unwrap();
+  assert_eq!(tenant.key_version, 2);
+
+  let token_data = parse_jwt::<BasicClaims>(&pool, &jwt, &tenant)
+    .await
+    .unwrap();
+  assert_eq!(token_data.claims.sub, 1);
+
+  let response = router
+    .oneshot(
+      Request::builder()
+        .uri(format!(
+          "/.well-known/jwks.json?client_id={}",
+          DEFAULT_TENANT_CLIENT_ID
+        ))
+        .body(Body::empty())
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let jwks: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(jwks["keys"][0]["kty"], "RSA");
+  assert_eq!(jwks["keys"][0]["alg"], "RS256");
+  assert_eq!(jwks["keys"][0]["kid"], "1-1-2");
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tenant_key_rotation() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let service_account = service_account_token(&router, &config, &pool).await?;
+  let tenant_keys_request = |method: &str, uri: &str, body: Option<serde_json::Value>| {
+    let mut request = Request::builder()
+      .method(method)
+      .uri(format!("/tenants/1/keys{uri}"))
+      .header(
+        "Authorization",
+        format!(
+          "Bearer {}",
+          service_account["access_token"].as_str().unwrap()
+        ),
+      );
+    if body.is_some() {
+      request = request.header("Content-Type", "application/json");
+    }
+    router.clone().oneshot(
+      request
+        .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+        .unwrap(),
+    )
+  };
+  let jwks_kids = || async {
+    let response = router
+      .clone()
+      .oneshot(
+        Request::builder()
+          .uri(format!(
+            "/.well-known/jwks.json?client_id={DEFAULT_TENANT_CLIENT_ID}"
+          ))
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let jwks: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    jwks["keys"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|key| key["kid"].as_str().unwrap().to_owned())
+      .collect::<Vec<_>>()
+  };
+
+  let response = tenant_keys_request(
+    "POST",
+    "",
+    Some(serde_json::json!({ "algorithm": "RS256" })),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let key: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(key["state"], "next");
+  assert_eq!(key["version"], 2);
+  assert_eq!(jwks_kids().await, vec!["1-1-2"]);
+
+  let response = tenant_keys_request("POST", "/rotate", None).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let token = user_token(&router, &config, &pool).await?;
+  let access_token = token["access_token"].as_str().unwrap();
+  assert_eq!(jwt_status(&router, access_token).await, StatusCode::OK);
+
+  let response = tenant_keys_request("POST", "/rotate", None).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let tenant: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(tenant["algorithm"], "RS256");
+  assert_eq!(jwt_status(&router, access_token).await, StatusCode::OK);
+  assert_eq!(jwks_kids().await, vec!["1-1-3", "1-1-2"]);
+
+  let response = tenant_keys_request("GET", "", None).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let keys: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(
+    keys
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|key| (
+        key["version"].as_i64().unwrap(),
+        key["state"].as_str().unwrap()
+      ))
+      .collect::<Vec<_>>(),
+    vec![(3, "active"), (2, "retiring"), (1, "retiring")]
+  );
+
+  let response = tenant_keys_request("DELETE", "/3", None).await.unwrap();
+  assert_eq!(response.status(), StatusCode::NOT_FOUND);
+  let response = tenant_keys_request("DELETE", "/2", None).await.unwrap();
+  assert_eq!(response.status(), StatusCode::NO_CONTENT);
+  assert_eq!(
+    jwt_status(&router, access_token).await,
+    StatusCode::UNAUTHORIZED
+  );
+  assert_eq!(jwks_kids().await, vec!["1-1-3"]);
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn token_encryption() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let service_account = service_account_token(&router, &config, &pool).await?;
+  let update_tenant = |body: serde_json::Value| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("PUT")
+        .uri("/tenants/1")
+        .header(
+          "Authorization",
+          format!(
+            "Bearer {}",
+            service_account["access_token"].as_str().unwrap()
+          ),
+        )
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap(),
+    )
+  };
+  let refresh = |refresh_token: serde_json::Value| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token")
+        .header("Content-Type", "application/json")
+        .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+        .body(Body::from(
+          serde_json::json!({
+            "grant_type": "refresh-token",
+            "refresh_token": refresh_token,
+          })
+          .to_string(),
+        ))
+        .unwrap(),
+    )
+  };
+  let response = update_tenant(serde_json::json!({ "encryption_public_key": "invalid" }))
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+  let (public_key, private_key) = Algorithm::RS256.keys(None, None).unwrap();
+  let response = update_tenant(serde_json::json!({ "encryption_public_key": public_key }))
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  // only the ID token is encrypted, access tokens stay verifiable against the JWKS
+  let token = user_token_with_scope(&router, &config, &pool, Some("openid profile")).await?;
+  let access_token = token["access_token"].as_str().unwrap();
+  assert_eq!(access_token.split('.').count(), 3);
+  assert_eq!(jwt_status(&router, access_token).await, StatusCode::OK);
+
+  let id_token = token["id_token"].as_str().unwrap();
+  assert_eq!(decode_jwe_header(id_token).unwrap().alg, "RSA-OAEP-256");
+  let id_token = decrypt_jwe_rsa_oaep_256(id_token, &private_key).unwrap();
+  assert_eq!(jwt_payload(&id_token)["type"], "id");
+
+  let response = refresh(token["refresh_token"].clone()).await.unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+
+  let response = update_tenant(serde_json::json!({ "encrypt_access_tokens": true }))
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let encrypted_token = user_token(&router, &config, &pool).await?;
+  let encrypted_access_token = encrypted_token["access_token"].as_str().unwrap();
+  assert_eq!(
+    decode_jwe_header(encrypted_access_token).unwrap().alg,
+    "dir"
+  );
+  assert_eq!(
+    jwt_status(&router, encrypted_access_token).await,
+    StatusCode::OK
+  );
+
+  // the replaced secret keeps decrypting until it is rotated out as well
+  let response = update_tenant(serde_json::json!({ "rotate_encryption_secret": true }))
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(
+    jwt_status(&router, encrypted_access_token).await,
+    StatusCode::OK
+  );
+  let response = refresh(encrypted_token["refresh_token"].clone())
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let response = update_tenant(serde_json::json!({ "rotate_encryption_secret": true }))
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(
+    jwt_status(&router, encrypted_access_token).await,
+    StatusCode::UNAUTHORIZED
+  );
+
+  let response = update_tenant(serde_json::json!({
+    "encryption_public_key": "",
+    "encrypt_access_tokens": false,
+  }))
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let token = user_token_with_scope(&router, &config, &pool, Some("openid profile")).await?;
+  assert_eq!(
+    token["access_token"].as_str().unwrap().split('.').count(),
+    3
+  );
+  assert_eq!(token["id_token"].as_str().unwrap().split('.').count(), 3);
+  assert_eq!(jwt_status(&router, access_token).await, StatusCode::OK);
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn openid_configuration() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let tenant = repository::tenant::get_tenant_by_id(&pool, 1, 1)
+    .await?
+    .unwrap();
+  let openid_configuration = || async {
+    let response = router
+      .clone()
+      .oneshot(
+        Request::builder()
+          .uri("/.well-known/openid-configuration")
+          .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+  };
+  let configuration = openid_configuration().await;
+  let url = &config.server.url;
+  assert_eq!(configuration["issuer"], tenant.issuer);
+  assert_eq!(
+    configuration["jwks_uri"],
+    format!("{url}/.well-known/jwks.json?client_id={DEFAULT_TENANT_CLIENT_ID}")
+  );
+  assert_eq!(
+    configuration["authorization_endpoint"],
+    format!("{url}/authorize")
+  );
+  assert_eq!(configuration["token_endpoint"], format!("{url}/token"));
+  assert_eq!(
+    configuration["userinfo_endpoint"],
+    format!("{url}/userinfo")
+  );
+  assert_eq!(
+    configuration["device_authorization_endpoint"],
+    format!("{url}/device-authorization")
+  );
+  assert_eq!(
+    configuration["id_token_signing_alg_values_supported"],
+    serde_json::json!([tenant.algorithm])
+  );
+  assert!(configuration["code_challenge_methods_supported"]
+    .as_array()
+    .unwrap()
+    .contains(&serde_json::json!("S256")));
+  assert!(configuration["response_types_supported"]
+    .as_array()
+    .unwrap()
+    .contains(&serde_json::json!("code")));
+
+  // the retiring key's algorithm is listed until it is revoked
+  let (public_key, private_key) = Algorithm::ES256.keys(None, None).unwrap();
+  repository::tenant_key::rotate_tenant_key(
+    &pool,
+    1,
+    tenant.retiring_key_expires_at(),
+    Some(repository::tenant_key::CreateTenantKey {
+      algorithm: Algorithm::ES256.to_string(),
+      public_key,
+      private_key,
+    }),
+  )
+  .await?
+  .unwrap();
+  let configuration = openid_configuration().await;
+  assert_eq!(
+    configuration["id_token_signing_alg_values_supported"],
+    serde_json::json!(["ES256", tenant.algorithm])
+  );
+  repository::tenant_key::revoke_tenant_key(&pool, 1, tenant.key_version)
+    .await?
+    .unwrap();
+  let configuration = openid_configuration().await;
+  assert_eq!(
+    configuration["id_token_signing_alg_values_supported"],
+    serde_json::json!(["ES256"])
+  );
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn openid_configuration_issuer() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let service_account = service_account_token(&router, &config, &pool).await?;
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/tenants")
+        .header(
+          "Authorization",
+          format!(
+            "Bearer {}",
+            service_account["access_token"].as_str().unwrap()
+          ),
+        )
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+          serde_json::json!({
+            "audience": "https://example.com",
+            "algorithm": "RS256",
+          })
+          .to_string(),
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let tenant: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let url = &config.server.url;
+  let issuer = tenant["issuer"].as_str().unwrap();
+  assert_eq!(
+    issuer,
+    format!("{url}/tenants/{}", tenant["client_id"].as_str().unwrap())
+  );
+
+  // clients configure themselves from the issuer alone
+  let get = |uri: String| {
+    router.clone().oneshot(
+      Request::builder()
+        .uri(uri.strip_prefix(url.as_str()).unwrap().to_owned())
+        .body(Body::empty())
+        .unwrap(),
+    )
+  };
+  let response = get(format!("{issuer}/.well-known/openid-configuration"))
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let configuration: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(configuration["issuer"], issuer);
+  assert_eq!(
+    configuration["jwks_uri"],
+    format!("{issuer}/.well-known/jwks.json")
+  );
+  assert_eq!(
+    configuration["id_token_signing_alg_values_supported"],
+    serde_json::json!(["RS256"])
+  );
+
+  let response = get(configuration["jwks_uri"].as_str().unwrap().to_owned())
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let jwks: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let keys = jwks["keys"].as_array().unwrap();
+  assert_eq!(keys.len(), 1);
+  assert_eq!(keys[0]["alg"], "RS256");
+
+  let response = get(format!(
+    "{url}/tenants/{}/.well-known/openid-configuration",
+    uuid::Uuid::new_v4()
+  ))
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn resource_audiences() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let service_account = service_account_token(&router, &config, &pool).await?;
+  let update_allowed_audiences = |allowed_audiences: serde_json::Value| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("PUT")
+        .uri("/tenants/1")
+        .header(
+          "Authorization",
+          format!(
+            "Bearer {}",
+            service_account["access_token"].as_str().unwrap()
+          ),
+        )
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+          serde_json::json!({ "allowed_audiences": allowed_audiences }).to_string(),
+        ))
+        .unwrap(),
+    )
+  };
+  let response = update_allowed_audiences(serde_json::json!(["https://api example.com"]))
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+  let response = update_allowed_audiences(serde_json::json!([
+    "https://api.example.com",
+    "https://billing.example.com"
+  ]))
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  let username = format!("user-{}", uuid::Uuid::new_v4());
+  repository::user::create_user_with_password(
+    &pool,
+    &config,
+    1,
+    repository::user::CreateUserWithPassword {
+      username: username.clone(),
+      password: "password".to_owned(),
+    },
+  )
+  .await?;
+  let token_request = |body: serde_json::Value| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token")
+        .header("Content-Type", "application/json")
+        .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+        .body(Body::from(body.to_string()))
+        .unwrap(),
+    )
+  };
+  let response = token_request(serde_json::json!({
+    "grant_type": "password",
+    "username": username,
+    "password": "password",
+    "resource": "https://other.example.com",
+  }))
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let errors: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(errors["resource"][0]["code"], "invalid");
+
+  let response = token_request(serde_json::json!({
+    "grant_type": "password",
+    "username": username,
+    "password": "password",
+    "resource": "https://billing.example.com",
+  }))
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let token: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let access_token = token["access_token"].as_str().unwrap();
+  assert_eq!(jwt_payload(access_token)["aud"], "https://billing.example.com");
+  assert_eq!(jwt_status(&router, access_token).await, StatusCode::OK);
+
+  let response = token_request(serde_json::json!({
+    "grant_type": "refresh-token",
+    "refresh_token": token["refresh_token"],
+  }))
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let refreshed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(
+    jwt_payload(refreshed["access_token"].as_str().unwrap())["aud"],
+    "https://billing.example.com"
+  );
+
+  let (client_id, client_secret) = create_service_account(&config, &pool, None).await?;
+  let form_token_request = |resource: &str| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token")
+        .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(Body::from(format!(
+          "grant_type=client_credentials&client_id={client_id}&client_secret={client_secret}\
+           &resource={}",
+          urlencoding::encode(resource)
+        )))
+        .unwrap(),
+    )
+  };
+  let response = form_token_request("https://api.example.com").await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let token: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(
+    jwt_payload(token["access_token"].as_str().unwrap())["aud"],
+    "https://api.example.com"
+  );
+  let response = form_token_request("https://other.example.com")
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(error["error"], "invalid_target");
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refresh_token_reuse() -> Result<(), InternalError> {
+  let (_router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config, teardown_pool) }
+
+  let expires_at = chrono::Utc::now().timestamp() + 60;
+  let first = create_refresh_token_family(
+    &pool,
+    CreateRefreshTokenFamily {
+      tenant_id: 1,
+      sub_type: TOKEN_SUB_TYPE_USER.to_owned(),
+      sub: 1,
+      expires_at,
+      mfa_validated: true,
+    },
+  )
+  .await?;
+
+  let second = match rotate_refresh_token(&pool, &first.jti, None).await? {
+    RefreshTokenRotation::Rotated {
+      family,
+      refresh_token,
+    } => {
+      assert!(family.is_mfa_validated());
+      refresh_token
+    }
+    _ => panic!("expected refresh token to rotate"),
+  };
+  assert_ne!(first.jti, second.jti);
+  assert_eq!(second.expires_at, expires_at);
+
+  assert!(matches!(
+    rotate_refresh_token(&pool, &first.jti, None).await?,
+    RefreshTokenRotation::Reused
+  ));
+  assert!(matches!(
+    rotate_refresh_token(&pool, &second.jti, None).await?,
+    RefreshTokenRotation::Invalid
+  ));
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn revoke_token() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let token = user_token(&router, &config, &pool).await?;
+  let access_token = token["access_token"].as_str().unwrap();
+  let refresh_token = token["refresh_token"].as_str().unwrap();
+  let service_account_access_token = service_account_token(&router, &config, &pool).await?
+    ["access_token"]
+    .as_str()
+    .unwrap()
+    .to_owned();
+
+  assert_eq!(jwt_status(&router, access_token).await, StatusCode::OK);
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token/revoke")
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(Body::from(format!("token={access_token}")))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token/revoke")
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(Body::from(format!(
+          "token={service_account_access_token}&client_id={DEFAULT_TENANT_CLIENT_ID}"
+        )))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(error["error"], "unauthorized_client");
+  assert_eq!(
+    jwt_status(&router, &service_account_access_token).await,
+    StatusCode::OK
+  );
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token/revoke")
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(Body::from(format!(
+          "token={access_token}&client_id={DEFAULT_TENANT_CLIENT_ID}"
+        )))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(
+    jwt_status(&router, access_token).await,
+    StatusCode::UNAUTHORIZED
+  );
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token/revoke")
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+          serde_json::json!({
+            "token": refresh_token,
+            "client_id": DEFAULT_TENANT_CLIENT_ID,
+          })
+          .to_string(),
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token")
+        .header("Content-Type", "application/json")
+        .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+        .body(Body::from(
+          serde_json::json!({
+            "grant_type": "refresh-token",
+            "refresh_token": refresh_token,
+          })
+          .to_string(),
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn revoke_subject_tokens() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let service_account = service_account_token(&router, &config, &pool).await?;
+  let token = user_token(&router, &config, &pool).await?;
+  let access_token = token["access_token"].as_str().unwrap();
+  let iat = jwt_payload(access_token)["iat"].as_i64().unwrap();
+  let sub = jwt_payload(access_token)["sub"].as_i64().unwrap();
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri(format!("/users/{sub}/revoke-tokens"))
+        .header(
+          "Authorization",
+          format!(
+            "Bearer {}",
+            service_account["access_token"].as_str().unwrap()
+          ),
+        )
+        .body(Body::empty())
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::NO_CONTENT);
+  assert_eq!(
+    jwt_status(&router, access_token).await,
+    StatusCode::UNAUTHORIZED
+  );
+
+  // logging in right after the revocation issues a token that is not revoked
+  let (username,): (String,) = sqlx::query_as("SELECT username FROM users WHERE id = $1;")
+    .bind(sub)
+    .fetch_one(&pool)
+    .await?;
+  let token = password_token(&router, &username).await;
+  let new_access_token = token["access_token"].as_str().unwrap();
+  assert!(jwt_payload(new_access_token)["iat"].as_i64().unwrap() > iat);
+  assert_eq!(jwt_status(&router, new_access_token).await, StatusCode::OK);
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn introspect_token() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let token = service_account_token(&router, &config, &pool).await?;
+  let access_token = token["access_token"].as_str().unwrap();
+
+  let introspect = |token: String| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token/introspect")
+        .header("Authorization", format!("Bearer {access_token}"))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(Body::from(format!("token={token}")))
+        .unwrap(),
+    )
+  };
+
+  let response = introspect(access_token.to_owned()).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let introspection: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(introspection["active"], true);
+  assert_eq!(introspection["client_id"], DEFAULT_TENANT_CLIENT_ID);
+  assert_eq!(introspection["token_type"], "bearer");
+
+  let response = introspect("invalid".to_owned()).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let introspection: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(introspection, serde_json::json!({ "active": false }));
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn authorize_code_flow() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let service_account = service_account_token(&router, &config, &pool).await?;
+  let user = user_token(&router, &config, &pool).await?;
+  let client_id = &public_oauth2_client(&router, &service_account).await;
+
+  let (code_challenge, code_verifier) = oauth2::PkceCodeChallenge::new_random_sha256();
+  let authorize_query = |scope: &str, code_challenge_method: &str| {
+    form_urlencoded::Serializer::new(String::new())
+      .append_pair("response_type", "code")
+      .append_pair("client_id", client_id)
+      .append_pair("scope", scope)
+      .append_pair("state", "custom-state")
+      .append_pair("nonce", "custom-nonce")
+      .append_pair("code_challenge", code_challenge.as_str())
+      .append_pair("code_challenge_method", code_challenge_method)
+      .finish()
+  };
+  let authorize = |query: String, cookie: Option<String>| {
+    let mut request = Request::builder().uri(format!("/authorize?{query}"));
+    if let Some(cookie) = cookie {
+      request = request.header("Cookie", cookie);
+    }
+    router.clone().oneshot(request.body(Body::empty()).unwrap())
+  };
+  let error_of = |response: axum::response::Response| {
+    let location = reqwest::Url::parse(response.headers()["Location"].to_str().unwrap()).unwrap();
+    location
+      .query_pairs()
+      .find(|(key, _)| key == "error")
+      .map(|(_, error)| error.into_owned())
+  };
+
+  let query = authorize_query("openid", "S256");
+  let response = authorize(query.clone(), None).await.unwrap();
+  assert_eq!(response.status(), StatusCode::FOUND);
+  let location = reqwest::Url::parse(response.headers()["Location"].to_str().unwrap()).unwrap();
+  assert!(location.as_str().starts_with(&config.oauth2.login_uri));
+  let return_to = location
+    .query_pairs()
+    .find(|(key, _)| key == "return_to")
+    .map(|(_, return_to)| return_to.into_owned())
+    .unwrap();
+  assert_eq!(
+    return_to,
+    format!("{}/authorize?{query}", config.server.url)
+  );
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/authorize/session")
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(Body::from(
+          form_urlencoded::Serializer::new(String::new())
+            .append_pair("access_token", user["access_token"].as_str().unwrap())
+            .append_pair("return_to", "https://example.com/elsewhere")
+            .finish(),
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/authorize/session")
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header(
+          "Origin",
+          reqwest::Url::parse(&config.oauth2.login_uri)
+            .unwrap()
+            .origin()
+            .ascii_serialization(),
+        )
+        .body(Body::from(
+          form_urlencoded::Serializer::new(String::new())
+            .append_pair("access_token", user["access_token"].as_str().unwrap())
+            .append_pair("return_to", &return_to)
+            .finish(),
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::SEE_OTHER);
+  assert_eq!(response.headers()["Location"], return_to.as_str());
+  let cookie = response.headers()["Set-Cookie"]
+    .to_str()
+    .unwrap()
+    .split(';')
+    .next()
+    .unwrap()
+    .to_owned();
+  assert!(cookie.starts_with("authorize_session="));
+
+  // the session id is in every token, a cookie made from it is not a browser session
+  let sid = jwt_payload(user["access_token"].as_str().unwrap())["sid"]
+    .as_str()
+    .unwrap()
+    .to_owned();
+  assert!(!cookie.contains(&sid));
+  let response = authorize(query.clone(), Some(format!("authorize_session={sid}")))
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::FOUND);
+  let location = reqwest::Url::parse(response.headers()["Location"].to_str().unwrap()).unwrap();
+  assert!(location.as_str().starts_with(&config.oauth2.login_uri));
+
+  // other sites can not log the browser in to an account of their choosing
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/authorize/session")
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Origin", "https://attacker.example.com")
+        .body(Body::from(
+          form_urlencoded::Serializer::new(String::new())
+            .append_pair("access_token", user["access_token"].as_str().unwrap())
+            .append_pair("return_to", &return_to)
+            .finish(),
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+  let response = authorize(authorize_query("openid", "plain"), Some(cookie.clone()))
+    .await
+    .unwrap();
+  assert_eq!(error_of(response).as_deref(), Some("invalid_request"));
+  let response = authorize(
+    authorize_query("openid email", "S256"),
+    Some(cookie.clone()),
+  )
+  .await
+  .unwrap();
+  assert_eq!(error_of(response).as_deref(), Some("invalid_scope"));
+  let response = authorize(format!("{query}&acr_values=2"), Some(cookie.clone()))
+    .await
+    .unwrap();
+  assert_eq!(
+    error_of(response).as_deref(),
+    Some("unmet_authentication_requirements")
+  );
+
+  let response = authorize(query.clone(), Some(cookie.clone()))
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::FOUND);
+  let location = reqwest::Url::parse(response.headers()["Location"].to_str().unwrap()).unwrap();
+  assert!(location
+    .as_str()
+    .starts_with("https://example.com/callback?"));
+  let params = location
+    .query_pairs()
+    .into_owned()
+    .collect::<std::collections::HashMap<_, _>>();
+  assert_eq!(params["state"], "custom-state");
+  let code = &params["code"];
+
+  let redeem = |code_verifier: &str| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token")
+        .header("Content-Type", "application/json")
+        .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+        .body(Body::from(
+          serde_json::json!({
+            "grant_type": "authorization-code",
+            "code": code,
+            "client_id": client_id,
+            "code_verifier": code_verifier,
+          })
+          .to_string(),
+        ))
+        .unwrap(),
+    )
+  };
+  // a failed redemption does not use up the code
+  let response = redeem("wrong-code-verifier").await.unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+  let response = redeem(code_verifier.secret()).await.unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let token: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let id_claims = jwt_payload(token["id_token"].as_str().unwrap());
+  assert_eq!(id_claims["nonce"], "custom-nonce");
+  assert_eq!(id_claims["amr"], serde_json::json!(["pwd"]));
+  assert_eq!(id_claims["acr"], "1");
+  assert_eq!(
+    id_claims["auth_time"],
+    jwt_payload(user["access_token"].as_str().unwrap())["auth_time"]
+  );
+
+  let response = redeem(code_verifier.secret()).await.unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+  // the refresh token is only refreshed by the client it was issued to
+  let refresh = |client_id: Option<&str>| {
+    let mut payload = serde_json::json!({
+      "grant_type": "refresh-token",
+      "refresh_token": token["refresh_token"],
+    });
+    if let Some(client_id) = client_id {
+      payload["client_id"] = client_id.into();
+    }
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token")
+        .header("Content-Type", "application/json")
+        .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+        .body(Body::from(payload.to_string()))
+        .unwrap(),
+    )
+  };
+  let response = refresh(None).await.unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+  let response = refresh(Some(DEFAULT_TENANT_CLIENT_ID)).await.unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+  let response = refresh(Some(client_id)).await.unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+
+  // logins older than max_age go back to the login page
+  sqlx::query("UPDATE user_sessions SET auth_time = auth_time - 3600;")
+    .execute(&pool)
+    .await?;
+  let response = authorize(format!("{query}&max_age=60"), Some(cookie))
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::FOUND);
+  let location = reqwest::Url::parse(response.headers()["Location"].to_str().unwrap()).unwrap();
+  assert!(location.as_str().starts_with(&config.oauth2.login_uri));
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn step_up_authentication() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let user = user_token(&router, &config, &pool).await?;
+  let access_claims = jwt_payload(user["access_token"].as_str().unwrap());
+  assert_eq!(access_claims["amr"], serde_json::json!(["pwd"]));
+  assert_eq!(access_claims["acr"], "1");
+  let auth_time = access_claims["auth_time"].as_i64().unwrap();
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token")
+        .header("Content-Type", "application/json")
+        .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+        .body(Body::from(
+          serde_json::json!({
+            "grant_type": "refresh-token",
+            "refresh_token": user["refresh_token"],
+          })
+          .to_string(),
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let refreshed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let refreshed_claims = jwt_payload(refreshed["access_token"].as_str().unwrap());
+  assert_eq!(refreshed_claims["amr"], serde_json::json!(["pwd"]));
+  assert_eq!(refreshed_claims["auth_time"], auth_time);
+
+  let tenant = repository::tenant::get_tenant_by_id(&pool, 1, 1)
+    .await?
+    .unwrap();
+  let mut stale_claims: BasicClaims = serde_json::from_value(refreshed_claims).unwrap();
+  stale_claims.auth_time = Some(auth_time - 60 * 60);
+  let stale_access_token = stale_claims.encode(&tenant).unwrap();
+
+  let reset_password = |access_token: String| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/current-user/reset-password")
+        .header("Authorization", format!("Bearer {access_token}"))
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+          serde_json::json!({
+            "current_password": "password",
+            "password": "new-password",
+            "password_confirmation": "new-password",
+          })
+          .to_string(),
+        ))
+        .unwrap(),
+    )
+  };
+  let response = reset_password(stale_access_token).await.unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let errors: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(
+    errors["Authorization"][0]["code"],
+    "insufficient-authentication"
+  );
+  assert_eq!(
+    errors["Authorization"][0]["parameters"]["max_age"],
+    config.user.step_up_max_age_in_seconds
+  );
+
+  let response = reset_password(refreshed["access_token"].as_str().unwrap().to_owned())
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn email_mfa() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let username = format!("user-{}", uuid::Uuid::new_v4());
+  let user = repository::user::create_user_with_password(
+    &pool,
+    &config,
+    1,
+    repository::user::CreateUserWithPassword {
+      username: username.clone(),
+      password: "password".to_owned(),
+    },
+  )
+  .await?;
+  repository::user_email::create_user_email(
+    &pool,
+    user.id,
+    repository::user_email::CreateUserEmail {
+      email: format!("{username}@example.com"),
+      primary: Some(true),
+      verified: Some(true),
+    },
+  )
+  .await?;
+  repository::user_config::update_user_config(
+    &pool,
+    1,
+    user.id,
+    repository::user_config::UserConfigUpdate {
+      mfa_type: Some("email".to_owned()),
+    },
+  )
+  .await?;
+
+  let mfa_token = password_token(&router, &username).await;
+  assert_eq!(mfa_token["token_type"], "mfa-email");
+  let mfa_access_token = mfa_token["access_token"].as_str().unwrap().to_owned();
+
+  let mails = tokio::fs::read_to_string(&config.mail.file_path).await?;
+  let mail: serde_json::Value = serde_json::from_str(mails.lines().last().unwrap()).unwrap();
+  assert_eq!(mail["to"], format!("{username}@example.com"));
+  let code = mail["body"]
+    .as_str()
+    .unwrap()
+    .split(|c: char| !c.is_ascii_digit())
+    .find(|part| part.len() == 6)
+    .unwrap()
+    .to_owned();
+  let wrong_code = format!("{:06}", (code.parse::<u32>().unwrap() + 1) % 1_000_000);
+
+  let verify = |code: String| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/mfa")
+        .header("Authorization", format!("Bearer {mfa_access_token}"))
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+          serde_json::json!({ "type": "email", "code": code }).to_string(),
+        ))
+        .unwrap(),
+    )
+  };
+  let response = verify(wrong_code).await.unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+  let response = verify(code.clone()).await.unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let token: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let claims = jwt_payload(token["access_token"].as_str().unwrap());
+  assert_eq!(claims["amr"], serde_json::json!(["pwd", "otp", "mfa"]));
+
+  let response = verify(code).await.unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn text_mfa() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let username = format!("user-{}", uuid::Uuid::new_v4());
+  let user = repository::user::create_user_with_password(
+    &pool,
+    &config,
+    1,
+    repository::user::CreateUserWithPassword {
+      username: username.clone(),
+      password: "password".to_owned(),
+    },
+  )
+  .await?;
+  repository::user_phone_number::create_user_phone_number(
+    &pool,
+    user.id,
+    repository::user_phone_number::CreateUserPhoneNumber {
+      phone_number: "+15555550100".to_owned(),
+      primary: Some(true),
+      verified: Some(true),
+    },
+  )
+  .await?;
+  repository::user_config::update_user_config(
+    &pool,
+    1,
+    user.id,
+    repository::user_config::UserConfigUpdate {
+      mfa_type: Some("text".to_owned()),
+    },
+  )
+  .await?;
+
+  let mfa_token = password_token(&router, &username).await;
+  assert_eq!(mfa_token["token_type"], "mfa-text");
+  let mfa_access_token = mfa_token["access_token"].as_str().unwrap().to_owned();
+
+  let messages = tokio::fs::read_to_string(&config.sms.file_path).await?;
+  let sms: serde_json::Value = serde_json::from_str(messages.lines().last().unwrap()).unwrap();
+  assert_eq!(sms["to"], "+15555550100");
+  let code = sms["body"]
+    .as_str()
+    .unwrap()
+    .split(|c: char| !c.is_ascii_digit())
+    .find(|part| part.len() == 6)
+    .unwrap()
+    .to_owned();
+
+  let verify = |r#type: &str| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/mfa")
+        .header("Authorization", format!("Bearer {mfa_access_token}"))
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+          serde_json::json!({ "type": r#type, "code": code }).to_string(),
+        ))
+        .unwrap(),
+    )
+  };
+  let response = verify("email").await.unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+  let response = verify("text").await.unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let token: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let claims = jwt_payload(token["access_token"].as_str().unwrap());
+  assert_eq!(claims["amr"], serde_json::json!(["pwd", "otp", "mfa"]));
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn totp_mfa() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let username = format!("user-{}", uuid::Uuid::new_v4());
+  let user = repository::user::create_user_with_password(
+    &pool,
+    &config,
+    1,
+    repository::user::CreateUserWithPassword {
+      username: username.clone(),
+      password: "password".to_owned(),
+    },
+  )
+  .await?;
+  let access_token = password_token(&router, &username).await["access_token"]
+    .as_str()
+    .unwrap()
+    .to_owned();
+  let request = |method: &str, uri: &str, authorization: &str, body: Option<serde_json::Value>| {
+    router.clone().oneshot(
+      Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Authorization", format!("Bearer {authorization}"))
+        .header("Content-Type", "application/json")
+        .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+        .unwrap(),
+    )
+  };
+
+  let response = request(
+    "POST",
+    "/current-user/totp",
+    &access_token,
+    Some(serde_json::json!({})),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let user_totp: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(user_totp["active"], false);
+  let secret = user_totp["secret"].as_str().unwrap().to_owned();
+  assert_eq!(
+    user_totp["uri"],
+    format!(
+      "otpauth://totp/Auth:{username}?secret={secret}&issuer=Auth&algorithm=SHA1&digits=6&period=30"
+    )
+  );
+
+  let response = request("GET", "/current-user/totp/qr-code", &access_token, None)
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.headers()["content-type"], "image/svg+xml");
+
+  assert!(repository::user_config::update_user_config(
+    &pool,
+    1,
+    user.id,
+    repository::user_config::UserConfigUpdate {
+      mfa_type: Some("totp".to_owned()),
+    },
+  )
+  .await
+  .is_err());
+
+  let totp = totp_rs::TOTP::new(
+    totp_rs::Algorithm::SHA1,
+    6,
+    0,
+    30,
+    totp_rs::Secret::Encoded(secret).to_bytes().unwrap(),
+  )
+  .unwrap();
+  let now = chrono::Utc::now().timestamp() as u64;
+  let code = totp.generate(now);
+  let wrong_code = format!("{:06}", (code.parse::<u32>().unwrap() + 1) % 1_000_000);
+  let response = request(
+    "POST",
+    "/current-user/totp/confirm",
+    &access_token,
+    Some(serde_json::json!({ "code": wrong_code })),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+  let response = request(
+    "POST",
+    "/current-user/totp/confirm",
+    &access_token,
+    Some(serde_json::json!({ "code": code })),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let user_totp: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(user_totp["active"], true);
+  assert_eq!(user_totp["secret"], serde_json::Value::Null);
+
+  let response = request("GET", "/current-user/totp/qr-code", &access_token, None)
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+  repository::user_config::update_user_config(
+    &pool,
+    1,
+    user.id,
+    repository::user_config::UserConfigUpdate {
+      mfa_type: Some("totp".to_owned()),
+    },
+  )
+  .await?;
+  let mfa_token = password_token(&router, &username).await;
+  assert_eq!(mfa_token["token_type"], "mfa-totp");
+  let mfa_access_token = mfa_token["access_token"].as_str().unwrap().to_owned();
+
+  // the code that confirmed the TOTP can not be used again
+  let response = request(
+    "POST",
+    "/mfa",
+    &mfa_access_token,
+    Some(serde_json::json!({ "type": "totp", "code": code })),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+  let next_code = totp.generate(now + 30);
+  let response = request(
+    "POST",
+    "/mfa",
+    &mfa_access_token,
+    Some(serde_json::json!({ "type": "totp", "code": next_code })),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let token: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let claims = jwt_payload(token["access_token"].as_str().unwrap());
+  assert_eq!(claims["amr"], serde_json::json!(["pwd", "otp", "mfa"]));
+
+  let response = request(
+    "POST",
+    "/mfa",
+    &mfa_access_token,
+    Some(serde_json::json!({ "type": "totp", "code": next_code })),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+  // with the replayed code that makes five wrong codes, after which even the right code is
+  // refused until the window ends
+  for _ in 0..4 {
+    let response = request(
+      "POST",
+      "/mfa",
+      &mfa_access_token,
+      Some(serde_json::json!({ "type": "totp", "code": wrong_code })),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+  }
+  sqlx::query("UPDATE user_totps SET last_used_step = NULL WHERE user_id = $1;")
+    .bind(user.id)
+    .execute(&pool)
+    .await?;
+  let response = request(
+    "POST",
+    "/mfa",
+    &mfa_access_token,
+    Some(serde_json::json!({ "type": "totp", "code": next_code })),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+  sqlx::query("UPDATE key_values SET expires_at = 0 WHERE \"key\" = $1;")
+    .bind(format!("mfa-totp-attempts:{}", user.id))
+    .execute(&pool)
+    .await?;
+  let response = request(
+    "POST",
+    "/mfa",
+    &mfa_access_token,
+    Some(serde_json::json!({ "type": "totp", "code": next_code })),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn service_account_mfa() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let username = format!("user-{}", uuid::Uuid::new_v4());
+  let user = repository::user::create_user_with_password(
+    &pool,
+    &config,
+    1,
+    repository::user::CreateUserWithPassword {
+      username: username.clone(),
+      password: "password".to_owned(),
+    },
+  )
+  .await?;
+  repository::user_totp::create_user_totp(
+    &pool,
+    user.id,
+    repository::user_totp::CreateUserTOTP {
+      algorithm: "SHA1".to_owned(),
+      digits: 6,
+      step: 30,
+      secret: totp_rs::Secret::generate_secret().to_encoded().to_string(),
+    },
+  )
+  .await?;
+  repository::user_totp::confirm_user_totp(&pool, user.id, 0).await?;
+  repository::user_config::update_user_config(
+    &pool,
+    1,
+    user.id,
+    repository::user_config::UserConfigUpdate {
+      mfa_type: Some("totp".to_owned()),
+    },
+  )
+  .await?;
+  let mfa_token = password_token(&router, &username).await;
+  let mfa_access_token = mfa_token["access_token"].as_str().unwrap().to_owned();
+  let verify = |service_account_access_token: String| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/mfa")
+        .header("Authorization", format!("Bearer {mfa_access_token}"))
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+          serde_json::json!({ "type": "service-account", "code": service_account_access_token })
+            .to_string(),
+        ))
+        .unwrap(),
+    )
+  };
+
+  let token = service_account_token_with_scopes(&router, &config, &pool, Some("users:read")).await?;
+  let response = verify(token["access_token"].as_str().unwrap().to_owned())
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+  // a service account of another application can not complete MFA for this application's users
+  let application = repository::application::create_application(
+    &pool,
+    repository::application::CreateApplication {
+      name: "Other".to_owned(),
+    },
+  )
+  .await?;
+  let tenant_client_id = uuid::Uuid::new_v4().to_string();
+  repository::tenant::create_tenant(
+    &pool,
+    application.id,
+    repository::tenant::CreateTenant {
+      client_id: tenant_client_id.clone(),
+      issuer: "Other".to_owned(),
+      audience: "Other".to_owned(),
+      algorithm: "HS256".to_owned(),
+      public_key: None,
+      private_key: generate_key_pair(jsonwebtoken::Algorithm::HS256).unwrap().1,
+      expires_in_seconds: 3600,
+      refresh_expires_in_seconds: 86400,
+      encryption_public_key: None,
+      encryption_secret: None,
+      encrypt_access_tokens: false,
+      allowed_audiences: Vec::new(),
+    },
+  )
+  .await?;
+  let client_id = uuid::Uuid::new_v4();
+  let client_secret = uuid::Uuid::new_v4();
+  repository::service_account::create_service_account(
+    &pool,
+    application.id,
+    repository::service_account::CreateServiceAccount {
+      client_id: client_id.to_string(),
+      encrypted_client_secret: encrypt_password(&config, &client_secret.to_string()).unwrap(),
+      name: "Other".to_owned(),
+      admin: false,
+      scopes: Some("users:write".to_owned()),
+    },
+  )
+  .await?;
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token")
+        .header("Content-Type", "application/json")
+        .header("Tenant-ID", &tenant_client_id)
+        .body(Body::from(
+          serde_json::json!({
+            "grant_type": "service-account",
+            "client_id": client_id,
+            "client_secret": client_secret,
+          })
+          .to_string(),
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let token: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let response = verify(token["access_token"].as_str().unwrap().to_owned())
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+  let token = service_account_token(&router, &config, &pool).await?;
+  let response = verify(token["access_token"].as_str().unwrap().to_owned())
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let token: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let claims = jwt_payload(token["access_token"].as_str().unwrap());
+  assert_eq!(claims["amr"], serde_json::json!(["pwd", "mfa"]));
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hotp_mfa() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let username = format!("user-{}", uuid::Uuid::new_v4());
+  let user = repository::user::create_user_with_password(
+    &pool,
+    &config,
+    1,
+    repository::user::CreateUserWithPassword {
+      username: username.clone(),
+      password: "password".to_owned(),
+    },
+  )
+  .await?;
+  let token = service_account_token(&router, &config, &pool).await?;
+  let service_account_access_token = token["access_token"].as_str().unwrap().to_owned();
+  let request = |uri: String, authorization: &str, body: serde_json::Value| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("Authorization", format!("Bearer {authorization}"))
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap(),
+    )
+  };
+
+  // the RFC 4226 test seed "12345678901234567890"
+  let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+  let response = request(
+    format!("/users/{}/hotp", user.id),
+    &service_account_access_token,
+    serde_json::json!({ "secret": secret }),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let user_hotp: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(user_hotp["counter"], 0);
+  assert_eq!(user_hotp["secret"], serde_json::Value::Null);
+
+  let response = request(
+    format!("/users/{}/hotp", user.id),
+    &service_account_access_token,
+    serde_json::json!({ "secret": secret }),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::CONFLICT);
+
+  repository::user_config::update_user_config(
+    &pool,
+    1,
+    user.id,
+    repository::user_config::UserConfigUpdate {
+      mfa_type: Some("hotp".to_owned()),
+    },
+  )
+  .await?;
+  let mfa_token = password_token(&router, &username).await;
+  assert_eq!(mfa_token["token_type"], "mfa-hotp");
+  let mfa_access_token = mfa_token["access_token"].as_str().unwrap().to_owned();
+  let verify = |code: String| {
+    request(
+      "/mfa".to_owned(),
+      &mfa_access_token,
+      serde_json::json!({ "type": "hotp", "code": code }),
+    )
+  };
+
+  // counters 0 and 1 were pressed without reaching the server
+  let response = verify("359152".to_owned()).await.unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let token: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let claims = jwt_payload(token["access_token"].as_str().unwrap());
+  assert_eq!(claims["amr"], serde_json::json!(["pwd", "otp", "mfa"]));
+
+  let response = verify("359152".to_owned()).await.unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+  let response = verify("287082".to_owned()).await.unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+  let hotp = totp_rs::TOTP::new(
+    totp_rs::Algorithm::SHA1,
+    6,
+    0,
+    1,
+    totp_rs::Secret::Encoded(secret.to_owned())
+      .to_bytes()
+      .unwrap(),
+  )
+  .unwrap();
+  let response = verify(hotp.generate(40)).await.unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+  let response = request(
+    format!("/users/{}/hotp/resync", user.id),
+    &service_account_access_token,
+    serde_json::json!({ "code": hotp.generate(40), "next_code": hotp.generate(42) }),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+  let response = request(
+    format!("/users/{}/hotp/resync", user.id),
+    &service_account_access_token,
+    serde_json::json!({ "code": hotp.generate(40), "next_code": hotp.generate(41) }),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let user_hotp: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(user_hotp["counter"], 42);
+
+  let response = verify(hotp.generate(42)).await.unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+
+  // wrong codes are limited, after the limit even the right code is refused until the window ends
+  for _ in 0..5 {
+    let response = verify("000000".to_owned()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+  }
+  let response = verify(hotp.generate(43)).await.unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+  let response = request(
+    format!("/users/{}/hotp/resync", user.id),
+    &service_account_access_token,
+    serde_json::json!({ "code": hotp.generate(50), "next_code": hotp.generate(51) }),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+  sqlx::query("UPDATE key_values SET expires_at = 0 WHERE \"key\" = $1;")
+    .bind(format!("mfa-hotp-attempts:{}", user.id))
+    .execute(&pool)
+    .await?;
+  let response = verify(hotp.generate(43)).await.unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn recovery_code_mfa() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let username = format!("user-{}", uuid::Uuid::new_v4());
+  let user = repository::user::create_user_with_password(
+    &pool,
+    &config,
+    1,
+    repository::user::CreateUserWithPassword {
+      username: username.clone(),
+      password: "password".to_owned(),
+    },
+  )
+  .await?;
+  let access_token = password_token(&router, &username).await["access_token"]
+    .as_str()
+    .unwrap()
+    .to_owned();
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/current-user/recovery-codes")
+        .header("Authorization", format!("Bearer {access_token}"))
+        .body(Body::empty())
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let recovery_codes: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let codes = recovery_codes["codes"].as_array().unwrap();
+  assert_eq!(codes.len(), 10);
+  let code = codes[0].as_str().unwrap().to_owned();
+  // each code is found by its lookup, so checking one costs a single hash
+  let lookups: i64 = sqlx::query_scalar(
+    "SELECT COUNT(DISTINCT lookup) FROM user_recovery_codes WHERE user_id = $1;",
+  )
+  .bind(user.id)
+  .fetch_one(&pool)
+  .await?;
+  assert_eq!(lookups, 10);
+
+  repository::user_totp::create_user_totp(
+    &pool,
+    user.id,
+    repository::user_totp::CreateUserTOTP {
+      algorithm: "SHA1".to_owned(),
+      digits: 6,
+      step: 30,
+      secret: totp_rs::Secret::generate_secret().to_encoded().to_string(),
+    },
+  )
+  .await?;
+  repository::user_totp::confirm_user_totp(&pool, user.id, 0).await?;
+  repository::user_config::update_user_config(
+    &pool,
+    1,
+    user.id,
+    repository::user_config::UserConfigUpdate {
+      mfa_type: Some("totp".to_owned()),
+    },
+  )
+  .await?;
+  let mfa_token = password_token(&router, &username).await;
+  assert_eq!(mfa_token["token_type"], "mfa-totp");
+  let mfa_access_token = mfa_token["access_token"].as_str().unwrap().to_owned();
+
+  let verify = |code: String| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/mfa")
+        .header("Authorization", format!("Bearer {mfa_access_token}"))
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+          serde_json::json!({ "type": "recovery-code", "code": code }).to_string(),
+        ))
+        .unwrap(),
+    )
+  };
+  for _ in 0..5 {
+    let response = verify("aaaaa-aaaaa".to_owned()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+  }
+  // guessing is limited like the other MFA types, the right code is refused until the window ends
+  let response = verify(code.clone()).await.unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+  sqlx::query("UPDATE key_values SET expires_at = 0 WHERE \"key\" = $1;")
+    .bind(format!("mfa-recovery-code-attempts:{}", user.id))
+    .execute(&pool)
+    .await?;
+
+  let response = verify(code.to_uppercase().replace('-', " ")).await.unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let token: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let claims = jwt_payload(token["access_token"].as_str().unwrap());
+  assert_eq!(claims["amr"], serde_json::json!(["pwd", "otp", "mfa"]));
+
+  let response = verify(code).await.unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("GET")
+        .uri("/current-user")
+        .header(
+          "Authorization",
+          format!("Bearer {}", token["access_token"].as_str().unwrap()),
+        )
+        .body(Body::empty())
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let current_user: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(current_user["recovery_codes_remaining"], 9);
+
+  let delete_recovery_codes = |access_token: String| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("DELETE")
+        .uri("/current-user/recovery-codes")
+        .header("Authorization", format!("Bearer {access_token}"))
+        .body(Body::empty())
+        .unwrap(),
+    )
+  };
+  // the token from before TOTP was enabled did not pass multi-factor authentication
+  let response = delete_recovery_codes(access_token).await.unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+  let response = delete_recovery_codes(token["access_token"].as_str().unwrap().to_owned())
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn webauthn() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let username = format!("user-{}", uuid::Uuid::new_v4());
+  let user = repository::user::create_user_with_password(
+    &pool,
+    &config,
+    1,
+    repository::user::CreateUserWithPassword {
+      username: username.clone(),
+      password: "password".to_owned(),
+    },
+  )
+  .await?;
+  let access_token = password_token(&router, &username).await["access_token"]
+    .as_str()
+    .unwrap()
+    .to_owned();
+  let post = |uri: &str, authorization: Option<&str>, body: serde_json::Value| {
+    let mut request = Request::builder()
+      .method("POST")
+      .uri(uri)
+      .header("Content-Type", "application/json")
+      .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID);
+    if let Some(authorization) = authorization {
+      request = request.header("Authorization", format!("Bearer {authorization}"));
+    }
+    router
+      .clone()
+      .oneshot(request.body(Body::from(body.to_string())).unwrap())
+  };
+
+  let response = post(
+    "/current-user/webauthn/options",
+    Some(&access_token),
+    serde_json::json!({}),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let options: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(options["rp"]["id"], config.webauthn.rp_id);
+
+  let signing_key = SigningKey::random(&mut rsa::rand_core::OsRng);
+  let point = signing_key.verifying_key().to_encoded_point(false);
+  let mut cose_key = Vec::new();
+  ciborium::into_writer(
+    &ciborium::Value::Map(vec![
+      (1.into(), 2.into()),
+      (3.into(), (-7).into()),
+      ((-1).into(), 1.into()),
+      ((-2).into(), point.x().unwrap().to_vec().into()),
+      ((-3).into(), point.y().unwrap().to_vec().into()),
+    ]),
+    &mut cose_key,
+  )
+  .unwrap();
+  let credential_id = uuid::Uuid::new_v4().as_bytes().to_vec();
+  let mut auth_data = webauthn_authenticator_data(&config.webauthn.rp_id, 0x45, 0);
+  auth_data.extend_from_slice(&[0; 16]);
+  auth_data.extend_from_slice(&(credential_id.len() as u16).to_be_bytes());
+  auth_data.extend_from_slice(&credential_id);
+  auth_data.extend_from_slice(&cose_key);
+  let mut attestation_object = Vec::new();
+  ciborium::into_writer(
+    &ciborium::Value::Map(vec![
+      ("fmt".into(), "none".into()),
+      ("attStmt".into(), ciborium::Value::Map(vec![])),
+      ("authData".into(), auth_data.into()),
+    ]),
+    &mut attestation_object,
+  )
+  .unwrap();
+  let client_data_json = serde_json::json!({
+    "type": "webauthn.create",
+    "challenge": options["challenge"],
+    "origin": config.webauthn.origins[0],
+  })
+  .to_string();
+  let response = post(
+    "/current-user/webauthn",
+    Some(&access_token),
+    serde_json::json!({
+      "name": "Laptop",
+      "client_data_json": BASE64_URL_SAFE_NO_PAD.encode(client_data_json),
+      "attestation_object": BASE64_URL_SAFE_NO_PAD.encode(attestation_object),
+    }),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let credential: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(credential["name"], "Laptop");
+  assert_eq!(credential["last_used_at"], serde_json::Value::Null);
+
+  let response = post("/token/webauthn-options", None, serde_json::json!({}))
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let options: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let mut assertion = webauthn_assertion(
+    &config,
+    &signing_key,
+    &credential_id,
+    options["challenge"].as_str().unwrap(),
+    0x05,
+    1,
+  );
+  assertion["grant_type"] = "webauthn".into();
+  let response = post("/token", None, assertion.clone()).await.unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let token: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let claims = jwt_payload(token["access_token"].as_str().unwrap());
+  assert_eq!(claims["sub"], user.id);
+  assert_eq!(claims["amr"], serde_json::json!(["hwk", "mfa"]));
+
+  let response = post("/token", None, assertion).await.unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+  repository::user_config::update_user_config(
+    &pool,
+    1,
+    user.id,
+    repository::user_config::UserConfigUpdate {
+      mfa_type: Some("webauthn".to_owned()),
+    },
+  )
+  .await?;
+  let mfa_token = password_token(&router, &username).await;
+  assert_eq!(mfa_token["token_type"], "mfa-webauthn");
+  let mfa_access_token = mfa_token["access_token"].as_str().unwrap();
+  let response = post(
+    "/mfa/webauthn-options",
+    Some(mfa_access_token),
+    serde_json::json!({}),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let options: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(
+    options["allowCredentials"][0]["id"],
+    BASE64_URL_SAFE_NO_PAD.encode(&credential_id)
+  );
+  let challenge = options["challenge"].as_str().unwrap();
+
+  let mut assertion =
+    webauthn_assertion(&config, &signing_key, &credential_id, challenge, 0x01, 1);
+  assertion["type"] = "webauthn".into();
+  let response = post("/mfa", Some(mfa_access_token), assertion)
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+  let response = post(
+    "/mfa/webauthn-options",
+    Some(mfa_access_token),
+    serde_json::json!({}),
+  )
+  .await
+  .unwrap();
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let options: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let mut assertion = webauthn_assertion(
+    &config,
+    &signing_key,
+    &credential_id,
+    options["challenge"].as_str().unwrap(),
+    0x01,
+    2,
+  );
+  assertion["type"] = "webauthn".into();
+  let response = post("/mfa", Some(mfa_access_token), assertion)
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let token: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let claims = jwt_payload(token["access_token"].as_str().unwrap());
+  assert_eq!(claims["amr"], serde_json::json!(["pwd", "hwk", "mfa"]));
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn oauth2_token_request() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let (client_id, client_secret) = create_service_account(&config, &pool, None).await?;
+
+  let token_request = |authorization: String, body: &'static str| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token")
+        .header("Authorization", authorization)
+        .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(Body::from(body))
+        .unwrap(),
+    )
+  };
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token")
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(Body::from(format!(
+          "grant_type=client_credentials&client_id={client_id}&client_secret={client_secret}"
+        )))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(error["error"], "invalid_request");
+
+  let response = token_request(
+    format!(
+      "Basic {}",
+      BASE64_STANDARD.encode(format!("{client_id}:{client_secret}"))
+    ),
+    "grant_type=client_credentials&scope=unknown",
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(error["error"], "invalid_scope");
+  assert!(error["error_description"]
+    .as_str()
+    .unwrap()
+    .starts_with("scope"));
+
+  let response = token_request(
+    format!(
+      "Basic {}",
+      BASE64_STANDARD.encode(format!("{client_id}:{client_secret}"))
+    ),
+    "grant_type=client_credentials",
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  assert_eq!(response.headers()["Cache-Control"], "no-store");
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let token: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(
+    jwt_status(&router, token["access_token"].as_str().unwrap()).await,
+    StatusCode::OK
+  );
+
+  let response = token_request(
+    format!(
+      "Basic {}",
+      BASE64_STANDARD.encode(format!("{client_id}:{}", uuid::Uuid::new_v4()))
+    ),
+    "grant_type=client_credentials",
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(error["error"], "invalid_client");
+
+  let response = token_request(
+    format!(
+      "Basic {}",
+      BASE64_STANDARD.encode(format!("{client_id}:{client_secret}"))
+    ),
+    "grant_type=implicit",
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(error["error"], "unsupported_grant_type");
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn token_exchange() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let impersonating_token = service_account_token_with_scopes(
+    &router,
+    &config,
+    &pool,
+    Some("users:impersonate openid profile"),
+  )
+  .await?;
+  let user_token = user_token(&router, &config, &pool).await?;
+  let user_id = jwt_payload(user_token["access_token"].as_str().unwrap())["sub"].clone();
+
+  let exchange = |body: serde_json::Value| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token")
+        .header("Content-Type", "application/json")
+        .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+        .body(Body::from(body.to_string()))
+        .unwrap(),
+    )
+  };
+
+  let response = exchange(serde_json::json!({
+    "grant_type": "urn:ietf:params:oauth:grant-type:token-exchange",
+    "subject_token": impersonating_token["access_token"],
+    "subject_token_type": "urn:ietf:params:oauth:token-type:access_token",
+    "requested_subject": user_id,
+    "scope": "openid email",
+  }))
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let token: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert!(token.get("refresh_token").is_none());
+  let access_token = token["access_token"].as_str().unwrap();
+  let claims = jwt_payload(access_token);
+  assert_eq!(claims["sub"], user_id);
+  assert_eq!(claims["act"]["sub_type"], "service-account");
+  assert_eq!(claims["scopes"], serde_json::json!(["openid"]));
+  assert_eq!(jwt_status(&router, access_token).await, StatusCode::OK);
+
+  // impersonation and delegation need users:impersonate granted explicitly
+  let unscoped_service_account_token =
+    service_account_token_with_scopes(&router, &config, &pool, Some("users:read")).await?;
+  let response = exchange(serde_json::json!({
+    "grant_type": "urn:ietf:params:oauth:grant-type:token-exchange",
+    "subject_token": unscoped_service_account_token["access_token"],
+    "subject_token_type": "urn:ietf:params:oauth:token-type:access_token",
+    "requested_subject": user_id,
+  }))
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+  let response = exchange(serde_json::json!({
+    "grant_type": "urn:ietf:params:oauth:grant-type:token-exchange",
+    "subject_token": user_token["access_token"],
+    "subject_token_type": "urn:ietf:params:oauth:token-type:access_token",
+    "actor_token": unscoped_service_account_token["access_token"],
+    "actor_token_type": "urn:ietf:params:oauth:token-type:access_token",
+  }))
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+  let response = exchange(serde_json::json!({
+    "grant_type": "urn:ietf:params:oauth:grant-type:token-exchange",
+    "subject_token": user_token["access_token"],
+    "subject_token_type": "urn:ietf:params:oauth:token-type:access_token",
+    "actor_token": impersonating_token["access_token"],
+    "actor_token_type": "urn:ietf:params:oauth:token-type:access_token",
+  }))
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token")
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+        .body(Body::from(format!(
+          "grant_type=urn:ietf:params:oauth:grant-type:token-exchange&subject_token={}&subject_token_type=urn:ietf:params:oauth:token-type:access_token&audience=https://example.com&client_id={DEFAULT_TENANT_CLIENT_ID}",
+          user_token["access_token"].as_str().unwrap()
+        )))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(error["error"], "invalid_target");
+
+  let response = exchange(serde_json::json!({
+    "grant_type": "urn:ietf:params:oauth:grant-type:token-exchange",
+    "subject_token": user_token["access_token"],
+    "subject_token_type": "urn:ietf:params:oauth:token-type:access_token",
+    "scope": "openid profile",
+  }))
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn device_authorization_flow() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let service_account = service_account_token(&router, &config, &pool).await?;
+  let user = user_token(&router, &config, &pool).await?;
+  let client_id = public_oauth2_client(&router, &service_account).await;
+
+  let form_request = |uri: &'static str, body: String| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(Body::from(body))
+        .unwrap(),
+    )
+  };
+
+  let response = form_request(
+    "/device-authorization",
+    format!("client_id={client_id}&scope=openid%20email"),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+  let response = form_request(
+    "/device-authorization",
+    format!("client_id={client_id}&scope=openid"),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let device_authorization: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  let device_code = device_authorization["device_code"].as_str().unwrap();
+  let user_code = device_authorization["user_code"].as_str().unwrap();
+
+  let poll = || {
+    form_request(
+      "/token",
+      format!(
+        "grant_type=urn:ietf:params:oauth:grant-type:device_code&device_code={device_code}&client_id={client_id}"
+      ),
+    )
+  };
+  let poll_error = |response: axum::response::Response| async move {
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    error["error"].as_str().unwrap().to_owned()
+  };
+
+  assert_eq!(
+    poll_error(poll().await.unwrap()).await,
+    "authorization_pending"
+  );
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("GET")
+        .uri(format!(
+          "/device-authorization/approve?user_code={user_code}"
+        ))
+        .header(
+          "Authorization",
+          format!("Bearer {}", user["access_token"].as_str().unwrap()),
+        )
+        .body(Body::empty())
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let pending: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(pending["client_id"], client_id);
+  assert_eq!(pending["scopes"], serde_json::json!(["openid"]));
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/device-authorization/approve")
+        .header(
+          "Authorization",
+          format!("Bearer {}", user["access_token"].as_str().unwrap()),
+        )
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+          serde_json::json!({ "user_code": user_code.replace('-', "").to_lowercase() }).to_string(),
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+  let response = poll().await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let token: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(
+    jwt_payload(token["access_token"].as_str().unwrap())["sub"],
+    jwt_payload(user["access_token"].as_str().unwrap())["sub"]
+  );
+
+  assert_eq!(poll_error(poll().await.unwrap()).await, "expired_token");
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn userinfo() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let userinfo = |token: serde_json::Value| {
+    router.clone().oneshot(
+      Request::builder()
+        .uri("/userinfo")
+        .header(
+          "Authorization",
+          format!("Bearer {}", token["access_token"].as_str().unwrap()),
+        )
+        .body(Body::empty())
+        .unwrap(),
+    )
+  };
+
+  let token =
+    user_token_with_scope(&router, &config, &pool, Some("openid profile address")).await?;
+  let sub = jwt_payload(token["access_token"].as_str().unwrap())["sub"].clone();
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("PUT")
+        .uri("/current-user/info")
+        .header(
+          "Authorization",
+          format!("Bearer {}", token["access_token"].as_str().unwrap()),
+        )
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+          serde_json::json!({
+            "profile_picture": "https://example.com/picture.png",
+            "birthdate": "1990-05-17T00:00:00Z",
+            "zone_info": "Europe/Paris",
+            "address": "1 Main Street",
+          })
+          .to_string(),
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert!(response.status().is_success());
+  let response = userinfo(token).await.unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let userinfo_claims: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(userinfo_claims["sub"], sub.to_string());
+  assert!(userinfo_claims["preferred_username"]
+    .as_str()
+    .unwrap()
+    .starts_with("user-"));
+  assert_eq!(
+    userinfo_claims["picture"],
+    "https://example.com/picture.png"
+  );
+  assert_eq!(userinfo_claims["birthdate"], "1990-05-17");
+  assert_eq!(userinfo_claims["zoneinfo"], "Europe/Paris");
+  assert_eq!(
+    userinfo_claims["address"],
+    serde_json::json!({ "formatted": "1 Main Street" })
+  );
+  assert!(userinfo_claims.get("profile_picture").is_none());
+
+  let token = user_token(&router, &config, &pool).await?;
+  let response = userinfo(token).await.unwrap();
+  assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn service_account_scopes() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let admin = service_account_token(&router, &config, &pool).await?;
+  let request = |method: &str, uri: &str, token: &serde_json::Value, body: serde_json::Value| {
+    router.clone().oneshot(
+      Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(
+          "Authorization",
+          format!("Bearer {}", token["access_token"].as_str().unwrap()),
+        )
+        .header("Content-Type", "application/json")
+        .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+        .body(Body::from(body.to_string()))
+        .unwrap(),
+    )
+  };
+
+  let response = request(
+    "POST",
+    "/service-accounts",
+    &admin,
+    serde_json::json!({ "name": "Reader", "scopes": ["users:read"] }),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let reader: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(reader["scopes"], serde_json::json!(["users:read"]));
+
+  let service_account_token = |scope: Option<&str>| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token")
+        .header("Content-Type", "application/json")
+        .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+        .body(Body::from(
+          serde_json::json!({
+            "grant_type": "service-account",
+            "client_id": reader["client_id"],
+            "client_secret": reader["client_secret"],
+            "scope": scope,
+          })
+          .to_string(),
+        ))
+        .unwrap(),
+    )
+  };
+
+  let response = service_account_token(Some("users:write")).await.unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+  let response = service_account_token(None).await.unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let token: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(token["scope"], "users:read");
+
+  let response = request("GET", "/users", &token, serde_json::Value::Null)
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  let response = request("GET", "/service-accounts", &token, serde_json::Value::Null)
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+  // service accounts without scopes are allowed none
+  let unscoped = service_account_token_with_scopes(&router, &config, &pool, Some("")).await?;
+  let response = request("GET", "/users", &unscoped, serde_json::Value::Null)
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+  // a manager can neither grant scopes it lacks nor change an account holding them
+  let manager = service_account_token_with_scopes(
+    &router,
+    &config,
+    &pool,
+    Some("service-accounts:write users:read"),
+  )
+  .await?;
+  let response = request(
+    "POST",
+    "/service-accounts",
+    &manager,
+    serde_json::json!({ "name": "Writer", "scopes": ["users:write"] }),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+  let admin_id = jwt_payload(admin["access_token"].as_str().unwrap())["sub"].clone();
+  let response = request(
+    "PUT",
+    &format!("/service-accounts/{admin_id}"),
+    &manager,
+    serde_json::json!({ "client_secret": uuid::Uuid::new_v4() }),
+  )
+  .await
+  .unwrap();
+  assert!(response.status().is_client_error());
+
+  let response = request(
+    "PUT",
+    &format!("/service-accounts/{}", reader["id"]),
+    &manager,
+    serde_json::json!({ "client_secret": uuid::Uuid::new_v4() }),
+  )
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tenant_claim_mappings() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let service_account = service_account_token(&router, &config, &pool).await?;
+  let create_claim_mapping = |body: serde_json::Value| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/tenants/1/claim-mappings")
+        .header(
+          "Authorization",
+          format!(
+            "Bearer {}",
+            service_account["access_token"].as_str().unwrap()
+          ),
+        )
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap(),
+    )
+  };
+
+  for body in [
+    serde_json::json!({ "claim": "tier", "source": "static", "value": { "level": "gold" } }),
+    serde_json::json!({ "claim": "username", "source": "user", "value": "username" }),
+    serde_json::json!({ "claim": "app_name", "source": "application", "value": "name" }),
+    serde_json::json!({
+      "claim": "nickname",
+      "source": "user_info",
+      "value": "nickname",
+      "access_token": false,
+    }),
+  ] {
+    let response = create_claim_mapping(body).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+  }
+  let response = create_claim_mapping(serde_json::json!({
+    "claim": "sub",
+    "source": "user",
+    "value": "username",
+  }))
+  .await
+  .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+  let token = user_token(&router, &config, &pool).await?;
+  let claims = jwt_payload(token["access_token"].as_str().unwrap());
+  assert_eq!(claims["tier"], serde_json::json!({ "level": "gold" }));
+  assert!(claims["username"].as_str().unwrap().starts_with("user-"));
+  assert!(claims["app_name"].is_string());
+  assert!(claims.get("nickname").is_none());
+
+  Ok(())
+}
+
+#[test]
+fn openid_claims_round_trip() {
+  let mut custom = serde_json::Map::new();
+  custom.insert("department".to_owned(), serde_json::json!("sales"));
+  let claims = OpenIdClaims {
+    claims: BasicClaims {
+      r#type: "id".to_owned(),
+      sub_type: TOKEN_SUB_TYPE_USER.to_owned(),
+      sub: 1,
+      app: 1,
+      scopes: vec!["openid".to_owned(), "profile".to_owned()],
+      custom,
+      ..Default::default()
+    },
+    profile: OpenIdProfile {
+      name: Some("Test User".to_owned()),
+      email: Some("test@example.com".to_owned()),
+      ..Default::default()
+    },
+    nonce: Some("nonce".to_owned()),
+  };
+  let json = serde_json::to_value(&claims).unwrap();
+  let parsed: OpenIdClaims = serde_json::from_value(json.clone()).unwrap();
+  assert_eq!(
+    parsed.claims.custom.keys().collect::<Vec<_>>(),
+    vec!["department"]
+  );
+  assert_eq!(parsed.profile.name.as_deref(), Some("Test User"));
+  assert_eq!(parsed.nonce.as_deref(), Some("nonce"));
+  assert_eq!(serde_json::to_value(&parsed).unwrap(), json);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn user_sessions() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let token = user_token(&router, &config, &pool).await?;
+  let access_token = token["access_token"].as_str().unwrap().to_owned();
+  let sid = jwt_payload(&access_token)["sid"].clone();
+  assert!(sid.is_string());
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .uri("/current-user/sessions")
+        .header("Authorization", format!("Bearer {access_token}"))
+        .body(Body::empty())
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let sessions: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  assert_eq!(sessions.as_array().unwrap().len(), 1);
+  assert_eq!(sessions[0]["sid"], sid);
+  assert_eq!(sessions[0]["grant_type"], "password");
+
+  // forwarding headers are only trusted from the configured proxies
+  let username = format!("user-{}", uuid::Uuid::new_v4());
+  repository::user::create_user_with_password(
+    &pool,
+    &config,
+    1,
+    repository::user::CreateUserWithPassword {
+      username: username.clone(),
+      password: "password".to_owned(),
+    },
+  )
+  .await?;
+  let login_from = |peer_address: &str| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token")
+        .header("Content-Type", "application/json")
+        .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+        .header("X-Forwarded-For", "203.0.113.7, 127.0.0.1")
+        .extension(axum::extract::ConnectInfo(
+          std::net::SocketAddr::from_str(peer_address).unwrap(),
+        ))
+        .body(Body::from(
+          serde_json::json!({
+            "grant_type": "password",
+            "username": username,
+            "password": "password",
+          })
+          .to_string(),
+        ))
+        .unwrap(),
+    )
+  };
+  let mut ip_addresses = Vec::new();
+  for peer_address in ["127.0.0.1:4000", "198.51.100.2:4000"] {
+    let response = login_from(peer_address).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let login: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let sid = jwt_payload(login["access_token"].as_str().unwrap())["sid"].clone();
+    let session = repository::user_session::get_user_session_by_sid(&pool, sid.as_str().unwrap())
+      .await?
+      .unwrap();
+    ip_addresses.push(session.ip_address.unwrap());
+  }
+  assert_eq!(ip_addresses, vec!["203.0.113.7", "198.51.100.2"]);
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("DELETE")
+        .uri(format!("/current-user/sessions/{}", sessions[0]["id"]))
+        .header("Authorization", format!("Bearer {access_token}"))
+        .body(Body::empty())
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+  assert_eq!(
+    jwt_status(&router, &access_token).await,
+    StatusCode::UNAUTHORIZED
+  );
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn end_session() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let logout_tokens = Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+  let backchannel_logout_tokens = logout_tokens.clone();
+  let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let backchannel_logout_uri = format!(
+    "http://{}/backchannel-logout",
+    listener.local_addr().unwrap()
+  );
+  let backchannel_logout_router = Router::new().route(
+    "/backchannel-logout",
+    axum::routing::post(
+      move |axum::Form(form): axum::Form<std::collections::HashMap<String, String>>| async move {
+        backchannel_logout_tokens
+          .lock()
+          .unwrap()
+          .push(form["logout_token"].clone());
+      },
+    ),
+  );
+  tokio::spawn(async move { axum::serve(listener, backchannel_logout_router).await });
+
+  let service_account = service_account_token(&router, &config, &pool).await?;
+  let mut client_ids = Vec::new();
+  for name in ["Partner", "Other Partner"] {
+    let response = router
+      .clone()
+      .oneshot(
+        Request::builder()
+          .method("POST")
+          .uri("/tenants/1/oauth2-clients")
+          .header(
+            "Authorization",
+            format!(
+              "Bearer {}",
+              service_account["access_token"].as_str().unwrap()
+            ),
+          )
+          .header("Content-Type", "application/json")
+          .body(Body::from(
+            serde_json::json!({
+              "name": name,
+              "redirect_uris": ["https://example.com/callback"],
+              "post_logout_redirect_uris": ["https://example.com/logged-out"],
+              "backchannel_logout_uri": backchannel_logout_uri,
+              "scopes": ["openid", "profile"],
+              "public": true,
+            })
+            .to_string(),
+          ))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let oauth2_client: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    client_ids.push(oauth2_client["client_id"].as_str().unwrap().to_owned());
+  }
+  let client_id = client_ids[0].as_str();
+
+  let user = user_token(&router, &config, &pool).await?;
+  let authorize_query = |client_id: &str, code_challenge: &oauth2::PkceCodeChallenge| {
+    form_urlencoded::Serializer::new(String::new())
+      .append_pair("response_type", "code")
+      .append_pair("client_id", client_id)
+      .append_pair("scope", "openid profile")
+      .append_pair("code_challenge", code_challenge.as_str())
+      .append_pair("code_challenge_method", "S256")
+      .finish()
+  };
+  // the user logs in to both clients
+  let mut tokens = Vec::new();
+  for client_id in client_ids.iter() {
+    let (code_challenge, code_verifier) = oauth2::PkceCodeChallenge::new_random_sha256();
+    let response = router
+      .clone()
+      .oneshot(
+        Request::builder()
+          .uri(format!(
+            "/authorize?{}",
+            authorize_query(client_id, &code_challenge)
+          ))
+          .header(
+            "Authorization",
+            format!("Bearer {}", user["access_token"].as_str().unwrap()),
+          )
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::FOUND);
+    let location = reqwest::Url::parse(response.headers()["Location"].to_str().unwrap()).unwrap();
+    let code = location
+      .query_pairs()
+      .find(|(key, _)| key == "code")
+      .unwrap()
+      .1
+      .into_owned();
+    let response = router
+      .clone()
+      .oneshot(
+        Request::builder()
+          .method("POST")
+          .uri("/token")
+          .header("Content-Type", "application/json")
+          .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+          .body(Body::from(
+            serde_json::json!({
+              "grant_type": "authorization-code",
+              "code": code,
+              "client_id": client_id,
+              "code_verifier": code_verifier.secret(),
+            })
+            .to_string(),
+          ))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let token: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    tokens.push(token);
+  }
+  let id_token = tokens[0]["id_token"].as_str().unwrap();
+  let sid = jwt_payload(id_token)["sid"].clone();
+  assert!(sid.is_string());
+
+  // and has a browser session
+  let (code_challenge, _) = oauth2::PkceCodeChallenge::new_random_sha256();
+  let return_to = format!(
+    "{}/authorize?{}",
+    config.server.url,
+    authorize_query(client_id, &code_challenge)
+  );
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/authorize/session")
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(Body::from(
+          form_urlencoded::Serializer::new(String::new())
+            .append_pair("access_token", user["access_token"].as_str().unwrap())
+            .append_pair("return_to", &return_to)
+            .finish(),
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::SEE_OTHER);
+  let cookie = response.headers()["Set-Cookie"]
+    .to_str()
+    .unwrap()
+    .split(';')
+    .next()
+    .unwrap()
+    .to_owned();
+  let authorize_with_cookie = || {
+    router.clone().oneshot(
+      Request::builder()
+        .uri(return_to.trim_start_matches(&config.server.url))
+        .header("Cookie", &cookie)
+        .body(Body::empty())
+        .unwrap(),
+    )
+  };
+  let response = authorize_with_cookie().await.unwrap();
+  assert_eq!(response.status(), StatusCode::FOUND);
+  assert!(response.headers()["Location"]
+    .to_str()
+    .unwrap()
+    .starts_with("https://example.com/callback?"));
+
+  let query = form_urlencoded::Serializer::new(String::new())
+    .append_pair("id_token_hint", id_token)
+    .append_pair("client_id", client_id)
+    .append_pair("post_logout_redirect_uri", "https://example.com/logged-out")
+    .append_pair("state", "custom-state")
+    .finish();
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .uri(format!("/end-session?{query}"))
+        .body(Body::empty())
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::FOUND);
+  assert_eq!(
+    response.headers()["Location"],
+    "https://example.com/logged-out?state=custom-state"
+  );
+
+  // back-channel logouts are sent after responding, to every client the user logged in to
+  let mut delivered = Vec::new();
+  for _ in 0..50 {
+    delivered = logout_tokens.lock().unwrap().clone();
+    if delivered.len() >= 2 {
+      break;
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+  }
+  assert_eq!(delivered.len(), 2);
+  let mut logouts = delivered
+    .iter()
+    .map(|logout_token| {
+      let logout_claims = jwt_payload(logout_token);
+      assert_eq!(
+        logout_claims["sub"],
+        jwt_payload(id_token)["sub"].to_string()
+      );
+      assert!(logout_claims["events"]
+        .get("http://schemas.openid.net/event/backchannel-logout")
+        .is_some());
+      (logout_claims["aud"].clone(), logout_claims["sid"].clone())
+    })
+    .collect::<Vec<_>>();
+  logouts.sort_by_key(|(aud, _)| client_ids.iter().position(|client_id| aud == client_id));
+  assert_eq!(
+    logouts,
+    tokens
+      .iter()
+      .zip(client_ids.iter())
+      .map(|(token, client_id)| (
+        serde_json::json!(client_id),
+        jwt_payload(token["id_token"].as_str().unwrap())["sid"].clone()
+      ))
+      .collect::<Vec<_>>()
+  );
+  assert_eq!(logouts[0].1, sid);
+
+  // every session of the user ends, their login and browser session too
+  for token in tokens.iter().chain([&user]) {
+    assert_eq!(
+      jwt_status(&router, token["access_token"].as_str().unwrap()).await,
+      StatusCode::UNAUTHORIZED
+    );
+  }
+  let response = authorize_with_cookie().await.unwrap();
+  assert_eq!(response.status(), StatusCode::FOUND);
+  assert!(response.headers()["Location"]
+    .to_str()
+    .unwrap()
+    .starts_with(&config.oauth2.login_uri));
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .uri(format!("/end-session?{query}"))
+        .body(Body::empty())
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+  let (username,): (String,) = sqlx::query_as("SELECT username FROM users WHERE id = $1;")
+    .bind(jwt_payload(user["access_token"].as_str().unwrap())["sub"].as_i64())
+    .fetch_one(&pool)
+    .await?;
+  let user = password_token(&router, &username).await;
+
+  let tenant = repository::tenant::get_tenant_by_id(&pool, 1, 1)
+    .await?
+    .unwrap();
+  let now = chrono::Utc::now().timestamp();
+  let id_token_without_sid = |iat: i64| {
+    BasicClaims {
+      r#type: "id".to_owned(),
+      exp: now + 60,
+      iat,
+      nbf: iat,
+      iss: tenant.issuer.clone(),
+      sub_type: TOKEN_SUB_TYPE_USER.to_owned(),
+      sub: jwt_payload(user["access_token"].as_str().unwrap())["sub"]
+        .as_i64()
+        .unwrap(),
+      app: 1,
+      ..Default::default()
+    }
+    .encode(&tenant)
+    .unwrap()
+  };
+
+  let query = form_urlencoded::Serializer::new(String::new())
+    .append_pair(
+      "id_token_hint",
+      &id_token_without_sid(now - 60 * 60 * 24 * 2),
+    )
+    .finish();
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .uri(format!("/end-session?{query}"))
+        .body(Body::empty())
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+  let id_token_hint = id_token_without_sid(now);
+  let query = form_urlencoded::Serializer::new(String::new())
+    .append_pair("id_token_hint", &id_token_hint)
+    .append_pair("state", "custom-state")
+    .finish();
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .uri(format!("/end-session?{query}"))
+        .body(Body::empty())
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::FOUND);
+  let location = reqwest::Url::parse(response.headers()["Location"].to_str().unwrap()).unwrap();
+  assert!(location
+    .as_str()
+    .starts_with(&config.oauth2.logout_confirmation_uri));
+  assert!(location
+    .query_pairs()
+    .any(|(key, value)| key == "id_token_hint" && value == id_token_hint));
+  assert_eq!(
+    jwt_status(&router, user["access_token"].as_str().unwrap()).await,
+    StatusCode::OK
+  );
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/end-session")
+        .header(
+          "Authorization",
+          format!("Bearer {}", user["access_token"].as_str().unwrap()),
+        )
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+          serde_json::json!({ "id_token_hint": id_token_hint }).to_string(),
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::NO_CONTENT);
+  assert_eq!(
+    jwt_status(&router, user["access_token"].as_str().unwrap()).await,
+    StatusCode::UNAUTHORIZED
+  );
+
+  Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn end_session_encrypted_id_token_hint() -> Result<(), InternalError> {
+  let (router, config, pool) = setup().await?;
+  let teardown_pool = pool.clone();
+  defer! { teardown(config.clone(), teardown_pool) }
+
+  let service_account = service_account_token(&router, &config, &pool).await?;
+  let (public_key, private_key) = Algorithm::RS256.keys(None, None).unwrap();
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("PUT")
+        .uri("/tenants/1")
+        .header(
+          "Authorization",
+          format!(
+            "Bearer {}",
+            service_account["access_token"].as_str().unwrap()
+          ),
+        )
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+          serde_json::json!({
+            "encryption_public_key": public_key,
+            "encrypt_access_tokens": true,
+          })
+          .to_string(),
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let tenant = repository::tenant::get_tenant_by_id(&pool, 1, 1)
+    .await?
+    .unwrap();
+
+  let end_session = |access_token: String, id_token_hint: String| {
+    router.clone().oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/end-session")
+        .header("Authorization", format!("Bearer {access_token}"))
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+          serde_json::json!({ "id_token_hint": id_token_hint }).to_string(),
+        ))
+        .unwrap(),
+    )
+  };
+
+  // the client's encrypted id token can only be read with its private key
+  let token = user_token_with_scope(&router, &config, &pool, Some("openid profile")).await?;
+  let access_token = token["access_token"].as_str().unwrap().to_owned();
+  let encrypted_id_token = token["id_token"].as_str().unwrap().to_owned();
+  let response = end_session(access_token.clone(), encrypted_id_token.clone())
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+  let id_token = decrypt_jwe_rsa_oaep_256(&encrypted_id_token, &private_key).unwrap();
+  let response = end_session(access_token.clone(), id_token.clone())
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::NO_CONTENT);
+  assert_eq!(
+    jwt_status(&router, &access_token).await,
+    StatusCode::UNAUTHORIZED
+  );
+
+  // a hint encrypted with the tenant's secret is decrypted before it is verified
+  let token = user_token_with_scope(&router, &config, &pool, Some("openid profile")).await?;
+  let access_token = token["access_token"].as_str().unwrap().to_owned();
+  let id_token =
+    decrypt_jwe_rsa_oaep_256(token["id_token"].as_str().unwrap(), &private_key).unwrap();
+  let id_token_hint = encrypt_jwe_dir(
+    &id_token,
+    tenant.encryption_secret.as_deref().unwrap(),
+    jsonwebtoken::decode_header(&id_token).unwrap().kid,
+  )
+  .unwrap();
+  let response = end_session(access_token.clone(), id_token_hint)
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::NO_CONTENT);
+  assert_eq!(
+    jwt_status(&router, &access_token).await,
+    StatusCode::UNAUTHORIZED
+  );
+
+  Ok(())
+}
+
+async fn public_oauth2_client(router: &Router, service_account: &serde_json::Value) -> String {
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/tenants/1/oauth2-clients")
+        .header(
+          "Authorization",
+          format!(
+            "Bearer {}",
+            service_account["access_token"].as_str().unwrap()
+          ),
+        )
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+          serde_json::json!({
+            "name": "Partner",
+            "redirect_uris": ["https://example.com/callback"],
+            "scopes": ["openid", "profile"],
+            "public": true,
+          })
+          .to_string(),
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  let oauth2_client: serde_json::Value = serde_json::from_slice(&body).unwrap();
+  oauth2_client["client_id"].as_str().unwrap().to_owned()
+}
+
+async fn create_service_account(
+  config: &Config,
+  pool: &sqlx::AnyPool,
+  scopes: Option<&str>,
+) -> Result<(uuid::Uuid, uuid::Uuid), InternalError> {
+  let client_id = uuid::Uuid::new_v4();
+  let client_secret = uuid::Uuid::new_v4();
+  repository::service_account::create_service_account(
+    pool,
+    1,
+    repository::service_account::CreateServiceAccount {
+      client_id: client_id.to_string(),
+      encrypted_client_secret: encrypt_password(config, &client_secret.to_string()).unwrap(),
+      name: "Test".to_owned(),
+      admin: true,
+      scopes: Some(
+        scopes
+          .map(ToOwned::to_owned)
+          .unwrap_or_else(|| SERVICE_ACCOUNT_SCOPES.join(" ")),
+      ),
+    },
+  )
+  .await?;
+  Ok((client_id, client_secret))
+}
+
+async fn service_account_token(
+  router: &Router,
+  config: &Config,
+  pool: &sqlx::AnyPool,
+) -> Result<serde_json::Value, InternalError> {
+  service_account_token_with_scopes(router, config, pool, None).await
+}
+
+async fn service_account_token_with_scopes(
+  router: &Router,
+  config: &Config,
+  pool: &sqlx::AnyPool,
+  scopes: Option<&str>,
+) -> Result<serde_json::Value, InternalError> {
+  let (client_id, client_secret) = create_service_account(config, pool, scopes).await?;
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token")
+        .header("Content-Type", "application/json")
+        .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+        .body(Body::from(
+          serde_json::json!({
+            "grant_type": "service-account",
+            "client_id": client_id,
+            "client_secret": client_secret,
+          })
+          .to_string(),
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  Ok(serde_json::from_slice(&body).unwrap())
+}
+
+async fn user_token(
+  router: &Router,
+  config: &Config,
+  pool: &sqlx::AnyPool,
+) -> Result<serde_json::Value, InternalError> {
+  user_token_with_scope(router, config, pool, None).await
+}
+
+async fn user_token_with_scope(
+  router: &Router,
+  config: &Config,
+  pool: &sqlx::AnyPool,
+  scope: Option<&str>,
+) -> Result<serde_json::Value, InternalError> {
+  let username = format!("user-{}", uuid::Uuid::new_v4());
+  let password = "password";
+  repository::user::create_user_with_password(
+    pool,
+    config,
+    1,
+    repository::user::CreateUserWithPassword {
+      username: username.clone(),
+      password: password.to_owned(),
+    },
+  )
+  .await?;
+
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token")
+        .header("Content-Type", "application/json")
+        .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+        .body(Body::from(
+          serde_json::json!({
+            "grant_type": "password",
+            "username": username,
+            "password": password,
+            "scope": scope,
+          })
+          .to_string(),
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  Ok(serde_json::from_slice(&body).unwrap())
+}
+
+async fn password_token(router: &Router, username: &str) -> serde_json::Value {
+  let response = router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .method("POST")
+        .uri("/token")
+        .header("Content-Type", "application/json")
+        .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+        .body(Body::from(
+          serde_json::json!({
+            "grant_type": "password",
+            "username": username,
+            "password": "password",
+          })
+          .to_string(),
+        ))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::CREATED);
+  let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  serde_json::from_slice(&body).unwrap()
+}
+
+fn webauthn_authenticator_data(rp_id: &str, flags: u8, sign_count: u32) -> Vec<u8> {
+  let mut authenticator_data = Sha256::digest(rp_id.as_bytes()).to_vec();
+  authenticator_data.push(flags);
+  authenticator_data.extend_from_slice(&sign_count.to_be_bytes());
+  authenticator_data
+}
+
+fn webauthn_assertion(
+  config: &Config,
+  signing_key: &SigningKey,
+  credential_id: &[u8],
+  challenge: &str,
+  flags: u8,
+  sign_count: u32,
+) -> serde_json::Value {
+  let authenticator_data = webauthn_authenticator_data(&config.webauthn.rp_id, flags, sign_count);
+  let client_data_json = serde_json::json!({
+    "type": "webauthn.get",
+    "challenge": challenge,
+    "origin": config.webauthn.origins[0],
+  })
+  .to_string();
+  let mut signed = authenticator_data.clone();
+  signed.extend_from_slice(&Sha256::digest(&client_data_json));
+  let signature: Signature = signing_key.sign(&signed);
+  serde_json::json!({
+    "credential_id": BASE64_URL_SAFE_NO_PAD.encode(credential_id),
+    "client_data_json": BASE64_URL_SAFE_NO_PAD.encode(&client_data_json),
+    "authenticator_data": BASE64_URL_SAFE_NO_PAD.encode(authenticator_data),
+    "signature": BASE64_URL_SAFE_NO_PAD.encode(signature.to_der()),
+  })
+}
+
+fn jwt_payload(token: &str) -> serde_json::Value {
+  serde_json::from_slice(
+    &BASE64_URL_SAFE_NO_PAD
+      .decode(token.split('.').nth(1).unwrap())
+      .unwrap(),
+  )
+  .unwrap()
+}
+
+async fn jwt_status(router: &Router, access_token: &str) -> StatusCode {
+  router
+    .clone()
+    .oneshot(
+      Request::builder()
+        .uri("/jwt")
+        .header("Authorization", format!("Bearer {access_token}"))
+        .header("Tenant-ID", DEFAULT_TENANT_CLIENT_ID)
+        .body(Body::empty())
+        .unwrap(),
+    )
+    .await
+    .unwrap()
+    .status()
+}
+
 pub async fn setup() -> Result<(Router, Arc<Config>, sqlx::AnyPool), InternalError> {
   dotenvy::from_path("./.env.test").ok();
   sqlx::any::install_default_drivers();
 
   let mut config = Config::new("./config.test.json").await?;
-  config.database.url = format!("sqlite:tests/.dbs/auth-{}-test.db", uuid::Uuid::new_v4());
+  let test_id = uuid::Uuid::new_v4();
+  config.database.url = format!("sqlite:tests/.dbs/auth-{}-test.db", test_id);
+  config.mail.transport = "file".to_owned();
+  config.mail.file_path = format!("tests/.dbs/auth-{}-mails.jsonl", test_id);
+  config.sms.transport = "file".to_owned();
+  config.sms.file_path = format!("tests/.dbs/auth-{}-sms.jsonl", test_id);
   let config = Arc::new(config);
 
   let level = tracing::Level::from_str(&config.log_level).unwrap_or(tracing::Level::DEBUG);
@@ -47,10 +3766,13 @@ pub async fn setup() -> Result<(Router, Arc<Config>, sqlx::AnyPool), InternalErr
       }),
     )
     .with(tracing_subscriber::fmt::layer())
-    .init();
+    .try_init()
+    .ok();
 
   let pool = init_pool(config.as_ref()).await?;
   let router = create_router(RouterState {
+    mail_sender: create_mail_sender(&config.mail)?,
+    sms_sender: create_sms_sender(&config.sms)?,
     config: config.clone(),
     pool: pool.clone(),
   });
@@ -65,8 +3787,10 @@ pub fn teardown(config: Arc<Config>, pool: sqlx::AnyPool) {
         let path = Path::new(&config.database.url["sqlite:".len()..]);
         remove_file(path)
           .await
-          .expect(&format!("failed to delete: {:?}", path));
+          .unwrap_or_else(|_| panic!("failed to delete: {:?}", path));
       }
+      remove_file(&config.mail.file_path).await.ok();
+      remove_file(&config.sms.file_path).await.ok();
     });
   });
 }